@@ -0,0 +1,361 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `ApiSchema` derive.
+
+use proc_macro2::TokenStream;
+use syn::{self, Attribute, Data, DeriveInput, Field, Fields, GenericParam, Generics, Lit, Meta,
+          NestedMeta, Type, Variant, WhereClause, WherePredicate};
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+
+/// Attributes of a container, a variant or a field affecting the schema.
+#[derive(Default)]
+struct Attrs {
+    rename: Option<String>,
+    rename_all: Option<RenameRule>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+    default: bool,
+    skip: bool,
+    skip_serializing_if: bool,
+    flatten: bool,
+    with: Option<Type>,
+    bound: Option<Vec<WherePredicate>>,
+}
+
+impl Attrs {
+    fn new(attrs: &[Attribute]) -> Attrs {
+        let mut result = Attrs::default();
+        for attr in attrs {
+            let list = match attr.interpret_meta() {
+                Some(Meta::List(list)) => list,
+                _ => continue,
+            };
+            let is_serde = if list.ident == "serde" {
+                true
+            } else if list.ident == "api_schema" {
+                false
+            } else {
+                continue;
+            };
+            for nested in &list.nested {
+                match *nested {
+                    NestedMeta::Meta(Meta::Word(ref word)) if is_serde => {
+                        match word.to_string().as_str() {
+                            "untagged" => result.untagged = true,
+                            "default" => result.default = true,
+                            "skip" | "skip_serializing" => result.skip = true,
+                            "flatten" => result.flatten = true,
+                            _ => {}
+                        }
+                    }
+                    NestedMeta::Meta(Meta::NameValue(ref pair)) => {
+                        let value = match pair.lit {
+                            Lit::Str(ref value) => value,
+                            _ => continue,
+                        };
+                        match (is_serde, pair.ident.to_string().as_str()) {
+                            (true, "rename") => result.rename = Some(value.value()),
+                            (true, "rename_all") => {
+                                result.rename_all = Some(RenameRule::new(&value.value()))
+                            }
+                            (true, "tag") => result.tag = Some(value.value()),
+                            (true, "content") => result.content = Some(value.value()),
+                            (true, "default") => result.default = true,
+                            (true, "skip_serializing_if") => result.skip_serializing_if = true,
+                            (false, "with") => {
+                                let ty: Type = value
+                                    .parse()
+                                    .expect("Invalid type in `api_schema(with)` attribute");
+                                result.with = Some(ty);
+                            }
+                            (false, "bound") if value.value().trim().is_empty() => {
+                                result.bound = Some(Vec::new());
+                            }
+                            (false, "bound") => {
+                                let clause = format!("where {}", value.value());
+                                let clause: WhereClause = syn::parse_str(&clause)
+                                    .expect("Invalid `api_schema(bound)` attribute");
+                                result.bound = Some(clause.predicates.into_iter().collect());
+                            }
+                            (false, name) => panic!("Unknown `api_schema` attribute: {}", name),
+                            _ => {}
+                        }
+                    }
+                    // `rename(serialize = "...", deserialize = "...")`
+                    NestedMeta::Meta(Meta::List(ref list)) if is_serde && list.ident == "rename" => {
+                        for nested in &list.nested {
+                            if let NestedMeta::Meta(Meta::NameValue(ref pair)) = *nested {
+                                if let Lit::Str(ref value) = pair.lit {
+                                    if pair.ident == "serialize" {
+                                        result.rename = Some(value.value());
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        result
+    }
+}
+
+/// `serde` rules of renaming fields and variants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RenameRule {
+    LowerCase,
+    UpperCase,
+    PascalCase,
+    CamelCase,
+    SnakeCase,
+    ScreamingSnakeCase,
+    KebabCase,
+    ScreamingKebabCase,
+}
+
+impl RenameRule {
+    fn new(rule: &str) -> RenameRule {
+        match rule {
+            "lowercase" => RenameRule::LowerCase,
+            "UPPERCASE" => RenameRule::UpperCase,
+            "PascalCase" => RenameRule::PascalCase,
+            "camelCase" => RenameRule::CamelCase,
+            "snake_case" => RenameRule::SnakeCase,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnakeCase,
+            "kebab-case" => RenameRule::KebabCase,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebabCase,
+            _ => panic!("Unknown `rename_all` rule: {}", rule),
+        }
+    }
+
+    /// Renames a `PascalCase` variant.
+    fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::PascalCase => variant.to_owned(),
+            RenameRule::LowerCase => variant.to_ascii_lowercase(),
+            RenameRule::UpperCase => variant.to_ascii_uppercase(),
+            RenameRule::CamelCase => variant[..1].to_ascii_lowercase() + &variant[1..],
+            RenameRule::SnakeCase => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                snake
+            }
+            RenameRule::ScreamingSnakeCase => RenameRule::SnakeCase
+                .apply_to_variant(variant)
+                .to_ascii_uppercase(),
+            RenameRule::KebabCase => RenameRule::SnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+            RenameRule::ScreamingKebabCase => RenameRule::ScreamingSnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+
+    /// Renames a `snake_case` field.
+    fn apply_to_field(self, field: &str) -> String {
+        match self {
+            RenameRule::LowerCase | RenameRule::SnakeCase => field.to_owned(),
+            RenameRule::UpperCase | RenameRule::ScreamingSnakeCase => field.to_ascii_uppercase(),
+            RenameRule::PascalCase => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                pascal
+            }
+            RenameRule::CamelCase => {
+                let pascal = RenameRule::PascalCase.apply_to_field(field);
+                pascal[..1].to_ascii_lowercase() + &pascal[1..]
+            }
+            RenameRule::KebabCase => field.replace('_', "-"),
+            RenameRule::ScreamingKebabCase => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+pub fn impl_api_schema(input: &DeriveInput) -> TokenStream {
+    let attrs = Attrs::new(&input.attrs);
+    let schema = match input.data {
+        Data::Struct(ref data) => fields_schema(&data.fields, &attrs),
+        Data::Enum(ref data) => enum_schema(&data.variants, &attrs),
+        Data::Union(_) => panic!("`ApiSchema` cannot be derived for unions"),
+    };
+
+    let name = &input.ident;
+    let generics = with_bounds(&input.generics, &attrs);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::exonum::api::schema::ApiSchema for #name #ty_generics
+            #where_clause
+        {
+            fn schema() -> ::exonum::api::schema::__private::Value {
+                #schema
+            }
+        }
+    }
+}
+
+/// Adds `ApiSchema` bounds on the type parameters, or the bounds from the `bound` attribute.
+fn with_bounds(generics: &Generics, attrs: &Attrs) -> Generics {
+    let mut generics = generics.clone();
+    let predicates: Vec<WherePredicate> = match attrs.bound {
+        Some(ref bound) => bound.clone(),
+        None => generics
+            .params
+            .iter()
+            .filter_map(|param| match *param {
+                GenericParam::Type(ref param) => {
+                    let ident = &param.ident;
+                    let predicate = quote!(#ident: ::exonum::api::schema::ApiSchema);
+                    Some(syn::parse2(predicate).expect("Cannot build a bound"))
+                }
+                _ => None,
+            })
+            .collect(),
+    };
+    {
+        let where_clause = generics.make_where_clause();
+        for predicate in predicates {
+            where_clause.predicates.push(predicate);
+        }
+    }
+    generics
+}
+
+fn field_type(field: &Field, attrs: &Attrs) -> TokenStream {
+    let ty = attrs.with.as_ref().unwrap_or(&field.ty);
+    quote!(<#ty as ::exonum::api::schema::ApiSchema>::schema())
+}
+
+/// Returns the schema of the fields of a struct or a variant.
+fn fields_schema(fields: &Fields, container: &Attrs) -> TokenStream {
+    match *fields {
+        Fields::Named(ref fields) => {
+            let properties = fields.named.iter().filter_map(|field| {
+                let attrs = Attrs::new(&field.attrs);
+                if attrs.skip {
+                    return None;
+                }
+                if attrs.flatten {
+                    panic!("`ApiSchema` cannot be derived for flattened fields");
+                }
+                let ident = field.ident.as_ref().expect("Named field without a name");
+                let name = match (attrs.rename.as_ref(), container.rename_all) {
+                    (Some(name), _) => name.clone(),
+                    (None, Some(rule)) => rule.apply_to_field(&ident.to_string()),
+                    (None, None) => ident.to_string(),
+                };
+                let optional = attrs.default || attrs.skip_serializing_if || container.default;
+                let schema = field_type(field, &attrs);
+                Some(quote!((#name, #schema, #optional)))
+            });
+            quote!(::exonum::api::schema::__private::object(vec![#(#properties),*]))
+        }
+        Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+            let field = fields.unnamed.iter().next().expect("Newtype without a field");
+            field_type(field, &Attrs::new(&field.attrs))
+        }
+        Fields::Unnamed(ref fields) => {
+            let items = fields
+                .unnamed
+                .iter()
+                .map(|field| field_type(field, &Attrs::new(&field.attrs)));
+            quote!(::exonum::api::schema::__private::tuple(vec![#(#items),*]))
+        }
+        Fields::Unit => quote!(::exonum::api::schema::__private::unit()),
+    }
+}
+
+/// Returns the schema of an enum in the representation chosen by the `serde` attributes.
+fn enum_schema(variants: &Punctuated<Variant, Comma>, container: &Attrs) -> TokenStream {
+    let mut unit_names = Vec::new();
+    let mut schemas = Vec::new();
+    for variant in variants {
+        let attrs = Attrs::new(&variant.attrs);
+        if attrs.skip {
+            continue;
+        }
+        let name = match (attrs.rename.as_ref(), container.rename_all) {
+            (Some(name), _) => name.clone(),
+            (None, Some(rule)) => rule.apply_to_variant(&variant.ident.to_string()),
+            (None, None) => variant.ident.to_string(),
+        };
+        let is_unit = match variant.fields {
+            Fields::Unit => true,
+            _ => false,
+        };
+        let content = fields_schema(&variant.fields, &attrs);
+
+        let schema = match (
+            container.tag.as_ref(),
+            container.content.as_ref(),
+            container.untagged,
+        ) {
+            (_, _, true) => content,
+            (Some(tag), _, false) if is_unit => {
+                quote!(::exonum::api::schema::__private::with_tag(#tag, #name, None))
+            }
+            (Some(tag), None, false) => {
+                quote!(::exonum::api::schema::__private::with_tag(#tag, #name, Some(#content)))
+            }
+            (Some(tag), Some(content_field), false) => quote!(
+                ::exonum::api::schema::__private::adjacently_tagged(
+                    #tag,
+                    #name,
+                    #content_field,
+                    #content,
+                )
+            ),
+            (None, _, false) if is_unit => {
+                unit_names.push(name);
+                continue;
+            }
+            (None, _, false) => {
+                quote!(::exonum::api::schema::__private::externally_tagged(#name, #content))
+            }
+        };
+        schemas.push(schema);
+    }
+
+    let unit_names = if unit_names.is_empty() {
+        None
+    } else {
+        Some(quote!(::exonum::api::schema::__private::string_enum(vec![#(#unit_names),*])))
+    };
+    match unit_names {
+        Some(unit_names) if schemas.is_empty() => unit_names,
+        unit_names => {
+            let schemas = unit_names.into_iter().chain(schemas);
+            quote!(::exonum::api::schema::__private::one_of(vec![#(#schemas),*]))
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Custom derives for the [Exonum] framework.
+//!
+//! [Exonum]: https://github.com/exonum/exonum
+
+#![recursion_limit = "128"]
+#![deny(missing_debug_implementations, missing_docs)]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+mod api_schema;
+
+use proc_macro::TokenStream;
+
+/// Derives `exonum::api::ApiSchema` describing the JSON form of the type produced
+/// by its `Serialize` derive.
+///
+/// The schema follows the `serde` attributes of the type:
+///
+/// - `rename` and `rename_all` on containers, variants and fields,
+/// - `tag`, `content` and `untagged` enum representations,
+/// - `skip` and `skip_serializing` on variants and fields,
+/// - `default` and `skip_serializing_if`, which make fields optional. Fields of
+///   the `Option` types are optional as well.
+///
+/// Flattened fields are not supported.
+///
+/// The derive has its own attributes:
+///
+/// - `#[api_schema(with = "Type")]` on a field describes the field with the schema
+///   of `Type`, which is useful for the fields serialized with `serde(with)`,
+/// - `#[api_schema(bound = "T: Trait")]` on a container replaces the `T: ApiSchema`
+///   bounds added to the type parameters of the implementation.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[macro_use]
+/// extern crate exonum_derive;
+///
+/// #[derive(Serialize, Deserialize, ApiSchema)]
+/// struct WalletQuery {
+///     pub_key: PublicKey,
+///     #[serde(default)]
+///     with_history: bool,
+/// }
+/// ```
+#[proc_macro_derive(ApiSchema, attributes(api_schema))]
+pub fn api_schema(input: TokenStream) -> TokenStream {
+    let input: syn::DeriveInput = syn::parse(input).expect("Cannot parse the type definition");
+    api_schema::impl_api_schema(&input).into()
+}
@@ -28,6 +28,7 @@ fn node_config() -> NodeConfig {
     let validator_keys = ValidatorKeys {
         consensus_key: consensus_public_key,
         service_key: service_public_key,
+        weight: ValidatorKeys::DEFAULT_WEIGHT,
     };
     let genesis = GenesisConfig::new(vec![validator_keys].into_iter());
 
@@ -42,6 +43,8 @@ fn node_config() -> NodeConfig {
     NodeConfig {
         listen_address: peer_address,
         peers: vec![],
+        peer_list: None,
+        keystore: None,
         service_public_key,
         service_secret_key,
         consensus_public_key,
@@ -0,0 +1,170 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Authentication of the API requests.
+
+use futures::future;
+use http::{Method, StatusCode};
+use http::header::AUTHORIZATION;
+
+use std::fmt;
+
+use crypto::{hash, Hash};
+use super::ApiError;
+use super::handler::{AsyncHandler, HandlerFuture, HttpRequest, HttpResponse};
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Authentication options of the private API.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApiAuthConfig {
+    /// Tokens accepted in the `Authorization: Bearer <token>` header of the requests.
+    /// If the list is empty, all requests are rejected.
+    pub bearer_tokens: Vec<String>,
+}
+
+/// Handler rejecting the requests without valid credentials with `ApiError::Unauthorized`
+/// and passing the other requests to the wrapped handler.
+///
+/// Browsers send CORS preflight requests without credentials, so `OPTIONS` requests
+/// without credentials are answered with an empty `204 No Content` response. The wrapped
+/// handler is never called for the requests without valid credentials.
+pub struct AuthHandler<H> {
+    // Tokens are compared by their hashes, so that the time of the comparison
+    // does not depend on the length of the matching prefix.
+    token_hashes: Vec<Hash>,
+    handler: H,
+}
+
+impl<H: AsyncHandler> AuthHandler<H> {
+    /// Wraps the handler, accepting the credentials from the given options.
+    pub fn new(config: &ApiAuthConfig, handler: H) -> AuthHandler<H> {
+        AuthHandler {
+            token_hashes: config
+                .bearer_tokens
+                .iter()
+                .map(|token| hash(token.as_bytes()))
+                .collect(),
+            handler,
+        }
+    }
+
+    fn is_authorized(&self, request: &HttpRequest) -> bool {
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                if value.starts_with(BEARER_PREFIX) {
+                    Some(value[BEARER_PREFIX.len()..].trim())
+                } else {
+                    None
+                }
+            })
+            .map_or(false, |token| {
+                self.token_hashes.contains(&hash(token.as_bytes()))
+            })
+    }
+}
+
+impl<H: AsyncHandler> AsyncHandler for AuthHandler<H> {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        if self.is_authorized(&request) {
+            self.handler.call(request)
+        } else if *request.method() == Method::OPTIONS {
+            let mut response = HttpResponse::new(Vec::new());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            Box::new(future::ok(response))
+        } else {
+            Box::new(future::err(ApiError::Unauthorized))
+        }
+    }
+}
+
+impl<H> fmt::Debug for AuthHandler<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuthHandler")
+            .field("tokens", &self.token_hashes.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use http::{self, StatusCode};
+
+    use api::{AsyncMount, HandlerFuture, HttpRequest, HttpResponse};
+    use api::handler::AsyncHandler;
+    use api::private::MetricsApi;
+    use helpers::metrics::Registry;
+    use super::{ApiAuthConfig, AuthHandler};
+
+    fn request(method: &str, authorization: Option<&str>) -> HttpRequest {
+        let mut builder = http::Request::builder();
+        builder.method(method).uri("/v1/shutdown");
+        if let Some(authorization) = authorization {
+            builder.header("Authorization", authorization);
+        }
+        builder.body(Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_auth_handler() {
+        let config = ApiAuthConfig {
+            bearer_tokens: vec!["secret".to_owned()],
+        };
+        let handler = AuthHandler::new(&config, |_: HttpRequest| -> HandlerFuture {
+            Box::new(future::ok(HttpResponse::new(b"Ok".to_vec())))
+        });
+
+        let status = |request| match handler.call(request).wait() {
+            Ok(response) => response.status(),
+            Err(e) => HttpResponse::from(e).status(),
+        };
+        assert_eq!(status(request("POST", Some("Bearer secret"))), StatusCode::OK);
+        assert_eq!(status(request("OPTIONS", None)), StatusCode::NO_CONTENT);
+        assert_eq!(
+            status(request("OPTIONS", Some("Bearer secret"))),
+            StatusCode::OK
+        );
+        assert_eq!(status(request("POST", None)), StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(request("POST", Some("Bearer other"))),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(status(request("POST", Some("secret"))), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_auth_handler_options_metrics() {
+        let config = ApiAuthConfig {
+            bearer_tokens: vec!["secret".to_owned()],
+        };
+        let registry = Registry::new();
+        registry.counter("api.requests", &[]).inc();
+        let mut mount = AsyncMount::new();
+        mount.mount("metrics", MetricsApi::new(registry));
+        let handler = AuthHandler::new(&config, mount);
+
+        let request = http::Request::builder()
+            .method("OPTIONS")
+            .uri("/metrics")
+            .body(Vec::new())
+            .unwrap();
+        let response = handler.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(response.body().is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compatibility layer between Iron handlers and asynchronous API handlers.
+
+use futures::Future;
+use http;
+use http::header::{CONTENT_LENGTH, HOST, TRANSFER_ENCODING};
+use iron::{Handler, IronError, IronResult, Protocol, Request, Response};
+use iron::status;
+use iron_hyper;
+use iron_hyper::buffer::BufReader;
+use iron_hyper::net::NetworkStream;
+
+use std::{fmt, io};
+use std::io::{Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::ApiError;
+use super::handler::{spawn_blocking, AsyncHandler, HandlerFuture, HttpRequest, HttpResponse,
+                     LocalAddr, RemoteAddr};
+
+/// Adapts an Iron handler to the `AsyncHandler` interface.
+///
+/// Iron handlers block the thread they are called on, so the adapter runs them on
+/// the thread pool shared by the API handlers. The addresses of the connection are taken
+/// from the `RemoteAddr` and `LocalAddr` request extensions if they are present.
+pub struct IronAdapter<H> {
+    handler: Arc<H>,
+}
+
+impl<H: Handler> IronAdapter<H> {
+    /// Wraps the Iron handler.
+    pub fn new(handler: H) -> IronAdapter<H> {
+        IronAdapter {
+            handler: Arc::new(handler),
+        }
+    }
+}
+
+impl<H: Handler> AsyncHandler for IronAdapter<H> {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        let handler = Arc::clone(&self.handler);
+        spawn_blocking(move || call_iron(&*handler, request))
+    }
+}
+
+impl<H> fmt::Debug for IronAdapter<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IronAdapter").finish()
+    }
+}
+
+/// Default address used if the request has no address extensions.
+fn unspecified_addr() -> SocketAddr {
+    ([127, 0, 0, 1], 0).into()
+}
+
+fn call_iron<H: Handler>(handler: &H, request: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let remote_addr = request
+        .extensions()
+        .get::<RemoteAddr>()
+        .map_or_else(unspecified_addr, |addr| addr.0);
+    let local_addr = request
+        .extensions()
+        .get::<LocalAddr>()
+        .map_or_else(unspecified_addr, |addr| addr.0);
+
+    let mut stream = MemoryStream::new(serialize_request(request, local_addr), remote_addr);
+    let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+    let request = iron_hyper::server::Request::new(&mut reader, remote_addr)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let mut request = Request::from_http(request, local_addr, &Protocol::http())
+        .map_err(ApiError::BadRequest)?;
+
+    let response = match handler.handle(&mut request) {
+        Ok(response) | Err(IronError { response, .. }) => response,
+    };
+    from_iron_response(response)
+}
+
+/// Serializes the request in the HTTP/1.1 format parsed by Iron's `hyper`.
+fn serialize_request(request: HttpRequest, local_addr: SocketAddr) -> Vec<u8> {
+    let (parts, body) = request.into_parts();
+    let mut data = format!(
+        "{} {} HTTP/1.1\r\n",
+        parts.method,
+        parts.uri.path_and_query().map_or("/", |path| path.as_str())
+    ).into_bytes();
+    if !parts.headers.contains_key(HOST) {
+        data.extend_from_slice(format!("host: {}\r\n", local_addr).as_bytes());
+    }
+    for (name, value) in &parts.headers {
+        // The body is already read into memory, so its length is set explicitly.
+        if *name == CONTENT_LENGTH || *name == TRANSFER_ENCODING {
+            continue;
+        }
+        data.extend_from_slice(name.as_str().as_bytes());
+        data.extend_from_slice(b": ");
+        data.extend_from_slice(value.as_bytes());
+        data.extend_from_slice(b"\r\n");
+    }
+    data.extend_from_slice(format!("content-length: {}\r\n\r\n", body.len()).as_bytes());
+    data.extend_from_slice(&body);
+    data
+}
+
+/// In-memory stream providing the serialized request to `hyper`.
+struct MemoryStream {
+    input: Cursor<Vec<u8>>,
+    peer_addr: SocketAddr,
+}
+
+impl MemoryStream {
+    fn new(input: Vec<u8>, peer_addr: SocketAddr) -> MemoryStream {
+        MemoryStream {
+            input: Cursor::new(input),
+            peer_addr,
+        }
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NetworkStream for MemoryStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serves an asynchronous handler with Iron.
+///
+/// The Iron worker thread waits for the response future to complete.
+pub struct IronBackend {
+    handler: Box<AsyncHandler>,
+}
+
+impl IronBackend {
+    /// Wraps the asynchronous handler.
+    pub fn new<H: AsyncHandler>(handler: H) -> IronBackend {
+        IronBackend {
+            handler: Box::new(handler),
+        }
+    }
+}
+
+impl Handler for IronBackend {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let request = to_http_request(request)?;
+        let response = self.handler.call(request).wait()?;
+        Ok(to_iron_response(response))
+    }
+}
+
+impl fmt::Debug for IronBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IronBackend").finish()
+    }
+}
+
+fn to_http_request(request: &mut Request) -> Result<HttpRequest, ApiError> {
+    let mut body = Vec::new();
+    request.body.read_to_end(&mut body)?;
+
+    let mut uri = format!("/{}", request.url.path().join("/"));
+    if let Some(query) = request.url.query() {
+        uri.push('?');
+        uri.push_str(query);
+    }
+
+    let mut builder = http::Request::builder();
+    builder.method(request.method.as_ref()).uri(uri.as_str());
+    for header in request.headers.iter() {
+        builder.header(header.name(), header.value_string().as_str());
+    }
+    let mut http_request = builder
+        .body(body)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    http_request
+        .extensions_mut()
+        .insert(RemoteAddr(request.remote_addr));
+    http_request
+        .extensions_mut()
+        .insert(LocalAddr(request.local_addr));
+    Ok(http_request)
+}
+
+fn from_iron_response(response: Response) -> Result<HttpResponse, ApiError> {
+    let mut builder = http::Response::builder();
+    // Iron responds with `404 Not Found` if the status is not set.
+    builder.status(response.status.unwrap_or(status::NotFound).to_u16());
+    for header in response.headers.iter() {
+        builder.header(header.name(), header.value_string().as_str());
+    }
+    let mut body = Vec::new();
+    if let Some(mut writer) = response.body {
+        writer.write_body(&mut body)?;
+    }
+    builder
+        .body(body)
+        .map_err(|e| ApiError::InternalError(Box::new(e)))
+}
+
+fn to_iron_response(response: HttpResponse) -> Response {
+    let (parts, body) = response.into_parts();
+    let mut response = Response::with((status::Status::from_u16(parts.status.as_u16()), body));
+    for (name, value) in &parts.headers {
+        response
+            .headers
+            .append_raw(name.as_str().to_owned(), value.as_bytes().to_vec());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use http;
+    use iron::{IronResult, Request, Response};
+    use iron::headers::Headers;
+    use iron::status;
+    use iron_test::{request, response};
+    use http::StatusCode;
+
+    use std::io::Read;
+
+    use api::ApiError;
+    use super::{IronAdapter, IronBackend};
+    use super::super::handler::{AsyncHandler, HandlerFuture, HttpRequest, HttpResponse,
+                                LocalAddr, RemoteAddr};
+
+    fn echo(request: &mut Request) -> IronResult<Response> {
+        let mut body = String::new();
+        request.body.read_to_string(&mut body).unwrap();
+        let path = request.url.path().join("/");
+        Ok(Response::with((status::Ok, format!("{} {}", path, body))))
+    }
+
+    #[test]
+    fn test_iron_adapter() {
+        let adapter = IronAdapter::new(echo);
+        let request = http::Request::builder()
+            .method("POST")
+            .uri("/v1/wallets")
+            .body(b"{}".to_vec())
+            .unwrap();
+        let response = adapter.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.into_body(), b"v1/wallets {}".to_vec());
+    }
+
+    #[test]
+    fn test_iron_adapter_request_info() {
+        let adapter = IronAdapter::new(|request: &mut Request| -> IronResult<Response> {
+            let mut body = Vec::new();
+            request.body.read_to_end(&mut body).unwrap();
+            let info = format!(
+                "{} {} {} {:?}",
+                request.remote_addr,
+                request.local_addr,
+                request.url.host(),
+                body
+            );
+            Ok(Response::with((status::Ok, info)))
+        });
+
+        let mut request = http::Request::builder()
+            .method("POST")
+            .uri("/v1/wallets")
+            .body(vec![0xff, 0x00])
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(RemoteAddr("10.0.0.1:52000".parse().unwrap()));
+        request
+            .extensions_mut()
+            .insert(LocalAddr("127.0.0.1:8200".parse().unwrap()));
+        let response = adapter.call(request).wait().unwrap();
+        assert_eq!(
+            String::from_utf8(response.into_body()).unwrap(),
+            "10.0.0.1:52000 127.0.0.1:8200 127.0.0.1 [255, 0]"
+        );
+    }
+
+    #[test]
+    fn test_iron_backend() {
+        let backend = IronBackend::new(|request: HttpRequest| -> HandlerFuture {
+            if request.uri().path() == "/v1/wallets" {
+                Box::new(future::ok(HttpResponse::new(request.into_body())))
+            } else {
+                Box::new(future::err(ApiError::NotFound("Unknown path".to_owned())))
+            }
+        });
+
+        let response = request::post(
+            "http://localhost:3000/v1/wallets",
+            Headers::new(),
+            "{}",
+            &backend,
+        ).unwrap();
+        assert_eq!(response::extract_body_to_string(response), "{}");
+
+        let error = request::get("http://localhost:3000/v1/blocks", Headers::new(), &backend)
+            .err()
+            .unwrap();
+        assert_eq!(error.response.status, Some(status::NotFound));
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cross-origin resource sharing ([CORS][cors]) for the asynchronous API handlers.
+//!
+//! [cors]: https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
+
+use futures::{future, Future};
+use http::Method;
+use http::header::{HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+                   ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+                   ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY};
+
+use std::fmt;
+
+use node::AllowOrigin;
+use super::ApiError;
+use super::handler::{AsyncHandler, HandlerFuture, HttpRequest, HttpResponse};
+
+/// Methods allowed in the cross-origin requests.
+const ALLOWED_METHODS: &str = "GET, POST, OPTIONS";
+/// Time in seconds the browsers may cache the responses to the preflight requests.
+const PREFLIGHT_MAX_AGE: &str = "86400";
+
+/// Handler answering the CORS preflight requests and adding the
+/// `Access-Control-Allow-Origin` header to the responses of the wrapped handler.
+///
+/// Requests from origins which are not allowed are passed to the wrapped handler
+/// as is, so that browsers reject the responses.
+pub struct CorsHandler<H> {
+    allow_origin: AllowOrigin,
+    handler: H,
+}
+
+impl<H: AsyncHandler> CorsHandler<H> {
+    /// Wraps the handler, allowing the requests from the given origins.
+    pub fn new(allow_origin: AllowOrigin, handler: H) -> CorsHandler<H> {
+        CorsHandler {
+            allow_origin,
+            handler,
+        }
+    }
+
+    /// Returns the value of the `Access-Control-Allow-Origin` header for the request,
+    /// or `None` if the origin of the request is not allowed.
+    fn allowed_origin(&self, request: &HttpRequest) -> Option<HeaderValue> {
+        let origin = request.headers().get(ORIGIN)?;
+        match self.allow_origin {
+            AllowOrigin::Any => Some(HeaderValue::from_static("*")),
+            AllowOrigin::Whitelist(ref hosts) => {
+                if hosts.iter().any(|host| host.as_bytes() == origin.as_bytes()) {
+                    Some(origin.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl<H: AsyncHandler> AsyncHandler for CorsHandler<H> {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        let origin = match self.allowed_origin(&request) {
+            Some(origin) => origin,
+            None => return self.handler.call(request),
+        };
+        let vary_origin = self.allow_origin != AllowOrigin::Any;
+
+        let is_preflight = *request.method() == Method::OPTIONS
+            && request.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            let mut response = HttpResponse::new(Vec::new());
+            {
+                let headers = response.headers_mut();
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+                headers.insert(
+                    ACCESS_CONTROL_ALLOW_METHODS,
+                    HeaderValue::from_static(ALLOWED_METHODS),
+                );
+                if let Some(requested) = request.headers().get(ACCESS_CONTROL_REQUEST_HEADERS) {
+                    headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+                }
+                headers.insert(
+                    ACCESS_CONTROL_MAX_AGE,
+                    HeaderValue::from_static(PREFLIGHT_MAX_AGE),
+                );
+                if vary_origin {
+                    headers.insert(VARY, HeaderValue::from_static("Origin"));
+                }
+            }
+            return Box::new(future::ok(response));
+        }
+
+        // Error responses are provided with the CORS headers as well, so that browsers
+        // expose them to the clients.
+        Box::new(self.handler.call(request).then(move |result| -> Result<_, ApiError> {
+            let mut response = result.unwrap_or_else(HttpResponse::from);
+            response
+                .headers_mut()
+                .insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            if vary_origin {
+                response
+                    .headers_mut()
+                    .insert(VARY, HeaderValue::from_static("Origin"));
+            }
+            Ok(response)
+        }))
+    }
+}
+
+impl<H> fmt::Debug for CorsHandler<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CorsHandler")
+            .field("allow_origin", &self.allow_origin)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use http::{self, StatusCode};
+
+    use api::{ApiError, HandlerFuture, HttpRequest};
+    use api::handler::AsyncHandler;
+    use node::AllowOrigin;
+    use super::CorsHandler;
+
+    fn not_found(_: HttpRequest) -> HandlerFuture {
+        Box::new(future::err(ApiError::NotFound("Nothing here".to_owned())))
+    }
+
+    fn request(method: &str, origin: &str) -> HttpRequest {
+        http::Request::builder()
+            .method(method)
+            .uri("/v1/blocks")
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .body(Vec::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cors_handler() {
+        let allow_origin = AllowOrigin::Whitelist(vec!["http://example.com".to_owned()]);
+        let handler = CorsHandler::new(allow_origin, not_found);
+
+        let response = handler
+            .call(request("OPTIONS", "http://example.com"))
+            .wait()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "http://example.com"
+        );
+        assert_eq!(
+            response.headers()["access-control-allow-methods"],
+            "GET, POST, OPTIONS"
+        );
+
+        let response = handler
+            .call(request("GET", "http://example.com"))
+            .wait()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "http://example.com"
+        );
+
+        let error = handler
+            .call(request("GET", "http://other.com"))
+            .wait()
+            .unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND.as_u16());
+    }
+}
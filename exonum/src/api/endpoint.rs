@@ -0,0 +1,566 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed API endpoints.
+//!
+//! Endpoints are defined by the query (or request body) and response types, which are
+//! described with `ApiSchema`. The descriptions are used to generate the OpenAPI document
+//! of the node.
+
+use futures::{future, Future};
+use http::Method;
+use http::header::{HeaderValue, CONTENT_TYPE};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use serde_urlencoded;
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::ApiError;
+use super::handler::{path_segments, spawn_blocking, AsyncHandler, HandlerFuture, HttpRequest,
+                     HttpResponse};
+use super::schema::ApiSchema;
+
+/// Query of the endpoints without parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ApiSchema)]
+pub struct NoQuery {}
+
+/// Description of a typed endpoint.
+#[derive(Debug, Clone)]
+pub(crate) struct EndpointSpec {
+    pub method: Method,
+    pub path: String,
+    /// Schema of the query for `GET` endpoints and of the request body for `POST` endpoints.
+    pub request: Value,
+    pub response: Value,
+}
+
+/// Error returned by the handler of a typed endpoint.
+///
+/// `ApiError`s are converted into responses by the server. Other errors may be converted
+/// into responses with custom bodies.
+pub trait EndpointError: 'static {
+    /// Converts the error into the response to the request.
+    fn into_response(self) -> Result<HttpResponse, ApiError>;
+}
+
+impl EndpointError for ApiError {
+    fn into_response(self) -> Result<HttpResponse, ApiError> {
+        Err(self)
+    }
+}
+
+type EndpointHandler = Box<Fn(&HttpRequest, Vec<(&str, &str)>) -> HandlerFuture + Send + Sync>;
+
+struct Endpoint {
+    spec: EndpointSpec,
+    handler: EndpointHandler,
+}
+
+impl EndpointSpec {
+    /// Returns the names of the path parameters.
+    pub fn path_params(&self) -> Vec<&str> {
+        path_segments(&self.path)
+            .into_iter()
+            .filter_map(path_param)
+            .collect()
+    }
+
+    /// Matches the path of the request against the path of the endpoint, returning
+    /// the values of the path parameters.
+    fn match_path<'a>(&'a self, path: &'a str) -> Option<Vec<(&'a str, &'a str)>> {
+        let segments = path_segments(path);
+        let template = path_segments(&self.path);
+        if segments.len() != template.len() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        for (expected, actual) in template.into_iter().zip(segments) {
+            match path_param(expected) {
+                Some(name) => params.push((name, actual)),
+                None if expected == actual => {}
+                None => return None,
+            }
+        }
+        Some(params)
+    }
+}
+
+/// Set of typed endpoints mounted on the same path.
+///
+/// `GET` endpoints take the query of the request, and `POST` endpoints take the JSON
+/// body of the request. Responses are serialized to JSON.
+///
+/// # Examples
+///
+/// ```
+/// use exonum::api::{ApiError, ApiScope, NoQuery};
+///
+/// let mut scope = ApiScope::new();
+/// scope
+///     .endpoint("v1/counter", |_: NoQuery| -> Result<u64, ApiError> { Ok(42) })
+///     .endpoint_mut("v1/counter", |value: u64| -> Result<u64, ApiError> { Ok(value) });
+/// ```
+#[derive(Default)]
+pub struct ApiScope {
+    endpoints: Vec<Endpoint>,
+    fallback: Option<Box<AsyncHandler>>,
+}
+
+impl ApiScope {
+    /// Creates a scope without endpoints.
+    pub fn new() -> ApiScope {
+        ApiScope::default()
+    }
+
+    /// Adds a `GET` endpoint taking the query of the request.
+    ///
+    /// The path may contain parameters in braces, such as `v1/blocks/{height}`. Each parameter
+    /// matches a single path segment, which is deserialized as the query field with the name
+    /// of the parameter.
+    ///
+    /// The handler is called on the thread pool shared by the API handlers.
+    pub fn endpoint<Q, R, E, F>(&mut self, path: &str, handler: F) -> &mut ApiScope
+    where
+        Q: DeserializeOwned + ApiSchema + Send + 'static,
+        R: Serialize + ApiSchema,
+        E: EndpointError,
+        F: Fn(Q) -> Result<R, E> + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        let handler = move |request: &HttpRequest, params: Vec<(&str, &str)>| -> HandlerFuture {
+            match parse_query(request, params) {
+                Ok(query) => {
+                    let handler = Arc::clone(&handler);
+                    spawn_blocking(move || endpoint_response(handler(query)))
+                }
+                Err(e) => Box::new(future::err(e)),
+            }
+        };
+        self.add(Method::GET, path, Q::schema(), R::schema(), Box::new(handler))
+    }
+
+    /// Adds a `POST` endpoint taking the JSON body of the request. An empty body is treated
+    /// as an empty JSON object.
+    ///
+    /// The handler is called on the thread pool shared by the API handlers.
+    ///
+    /// # Panics
+    ///
+    /// If the path contains parameters, which are supported by `GET` endpoints only.
+    pub fn endpoint_mut<Q, R, E, F>(&mut self, path: &str, handler: F) -> &mut ApiScope
+    where
+        Q: DeserializeOwned + ApiSchema + Send + 'static,
+        R: Serialize + ApiSchema,
+        E: EndpointError,
+        F: Fn(Q) -> Result<R, E> + Send + Sync + 'static,
+    {
+        assert_no_path_params(path);
+        let handler = Arc::new(handler);
+        let handler = move |request: &HttpRequest, _: Vec<(&str, &str)>| -> HandlerFuture {
+            match parse_body(request) {
+                Ok(query) => {
+                    let handler = Arc::clone(&handler);
+                    spawn_blocking(move || endpoint_response(handler(query)))
+                }
+                Err(e) => Box::new(future::err(e)),
+            }
+        };
+        self.add(Method::POST, path, Q::schema(), R::schema(), Box::new(handler))
+    }
+
+    /// Adds a `POST` endpoint taking the JSON body of the request and responding
+    /// asynchronously. An empty body is treated as an empty JSON object.
+    ///
+    /// # Panics
+    ///
+    /// If the path contains parameters, which are supported by `GET` endpoints only.
+    pub fn endpoint_mut_async<Q, R, E, F>(&mut self, path: &str, handler: F) -> &mut ApiScope
+    where
+        Q: DeserializeOwned + ApiSchema,
+        R: Serialize + ApiSchema + 'static,
+        E: EndpointError,
+        F: Fn(Q) -> Box<Future<Item = R, Error = E> + Send> + Send + Sync + 'static,
+    {
+        assert_no_path_params(path);
+        let handler = move |request: &HttpRequest, _: Vec<(&str, &str)>| -> HandlerFuture {
+            match parse_body(request) {
+                Ok(query) => Box::new(handler(query).then(endpoint_response)),
+                Err(e) => Box::new(future::err(e)),
+            }
+        };
+        self.add(Method::POST, path, Q::schema(), R::schema(), Box::new(handler))
+    }
+
+    /// Sets the handler of the requests not matching any endpoint of the scope.
+    pub fn set_fallback<H: AsyncHandler>(&mut self, handler: H) -> &mut ApiScope {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns `true` if the scope has neither endpoints nor a fallback handler.
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty() && self.fallback.is_none()
+    }
+
+    pub(crate) fn specs(&self) -> Vec<&EndpointSpec> {
+        self.endpoints.iter().map(|endpoint| &endpoint.spec).collect()
+    }
+
+    fn add(
+        &mut self,
+        method: Method,
+        path: &str,
+        request: Value,
+        response: Value,
+        handler: EndpointHandler,
+    ) -> &mut ApiScope {
+        let path = format!("/{}", path.trim_matches('/'));
+        self.endpoints
+            .retain(|endpoint| endpoint.spec.method != method || endpoint.spec.path != path);
+        self.endpoints.push(Endpoint {
+            spec: EndpointSpec {
+                method,
+                path,
+                request,
+                response,
+            },
+            handler,
+        });
+        self
+    }
+}
+
+impl AsyncHandler for ApiScope {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        let response = self.endpoints
+            .iter()
+            .filter(|endpoint| endpoint.spec.method == *request.method())
+            .filter_map(|endpoint| {
+                endpoint
+                    .spec
+                    .match_path(request.uri().path())
+                    .map(|params| (endpoint.handler)(&request, params))
+            })
+            .next();
+        match (response, &self.fallback) {
+            (Some(response), _) => response,
+            (None, &Some(ref fallback)) => fallback.call(request),
+            (None, &None) => {
+                let path = request.uri().path().to_owned();
+                Box::new(future::err(ApiError::NotFound(format!(
+                    "Unknown endpoint {}",
+                    path
+                ))))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let endpoints = self.endpoints
+            .iter()
+            .map(|endpoint| format!("{} {}", endpoint.spec.method, endpoint.spec.path))
+            .collect::<Vec<_>>();
+        f.debug_struct("ApiScope")
+            .field("endpoints", &endpoints)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// Typed endpoints of a service, see `Service::wire_api`.
+#[derive(Debug, Default)]
+pub struct ServiceApiBuilder {
+    public_scope: ApiScope,
+    private_scope: ApiScope,
+}
+
+impl ServiceApiBuilder {
+    /// Creates a builder without endpoints.
+    pub fn new() -> ServiceApiBuilder {
+        ServiceApiBuilder::default()
+    }
+
+    /// Returns the endpoints served by the public API.
+    pub fn public_scope(&mut self) -> &mut ApiScope {
+        &mut self.public_scope
+    }
+
+    /// Returns the endpoints served by the private API.
+    pub fn private_scope(&mut self) -> &mut ApiScope {
+        &mut self.private_scope
+    }
+
+    pub(crate) fn into_scopes(self) -> (ApiScope, ApiScope) {
+        (self.public_scope, self.private_scope)
+    }
+}
+
+/// Returns the response with the value serialized to JSON.
+pub(crate) fn json_response<T: Serialize>(value: &T) -> Result<HttpResponse, ApiError> {
+    let body =
+        serde_json::to_vec_pretty(value).map_err(|e| ApiError::InternalError(Box::new(e)))?;
+    let mut response = HttpResponse::new(body);
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(response)
+}
+
+/// Deserializes the query of the `GET` endpoint from the path parameters and the query
+/// of the request.
+fn parse_query<Q: DeserializeOwned>(
+    request: &HttpRequest,
+    params: Vec<(&str, &str)>,
+) -> Result<Q, ApiError> {
+    let mut query = params
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, escape_query_value(value)))
+        .collect::<Vec<_>>();
+    query.extend(request.uri().query().map(str::to_owned));
+    serde_urlencoded::from_str(&query.join("&")).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+/// Deserializes the JSON body of the `POST` endpoint.
+fn parse_body<Q: DeserializeOwned>(request: &HttpRequest) -> Result<Q, ApiError> {
+    let body = if request.body().is_empty() {
+        &b"{}"[..]
+    } else {
+        request.body().as_slice()
+    };
+    serde_json::from_slice(body).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+/// Converts the result returned by the endpoint handler into the response.
+fn endpoint_response<R: Serialize, E: EndpointError>(
+    result: Result<R, E>,
+) -> Result<HttpResponse, ApiError> {
+    match result {
+        Ok(response) => json_response(&response),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn assert_no_path_params(path: &str) {
+    assert!(
+        !path.contains('{'),
+        "Path parameters are not supported by POST endpoints: {}",
+        path
+    );
+}
+
+/// Returns the name of the parameter if the path segment is a parameter.
+fn path_param(segment: &str) -> Option<&str> {
+    if segment.len() > 2 && segment.starts_with('{') && segment.ends_with('}') {
+        Some(&segment[1..segment.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Escapes the characters of the path segment which have a special meaning in the query.
+fn escape_query_value(value: &str) -> String {
+    value
+        .replace('&', "%26")
+        .replace('=', "%3D")
+        .replace('+', "%2B")
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use futures::sync::oneshot;
+    use http::{self, StatusCode};
+    use serde_json::{self, Value};
+
+    use std::sync::Mutex;
+    use std::thread;
+
+    use api::ApiError;
+    use api::handler::{AsyncHandler, HttpRequest, HttpResponse};
+    use api::schema::ApiSchema;
+    use super::{json_response, ApiScope, EndpointError, NoQuery};
+
+    #[derive(Debug, Serialize, Deserialize, ApiSchema)]
+    struct BlocksQuery {
+        count: u64,
+        latest: Option<u64>,
+    }
+
+    fn request(method: &str, uri: &str, body: &str) -> HttpRequest {
+        http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(body.as_bytes().to_vec())
+            .unwrap()
+    }
+
+    fn call(scope: &ApiScope, request: HttpRequest) -> Result<Value, ApiError> {
+        scope
+            .call(request)
+            .wait()
+            .map(|response| serde_json::from_slice(response.body()).unwrap())
+    }
+
+    #[test]
+    fn test_no_query_schema() {
+        assert_eq!(
+            NoQuery::schema(),
+            json!({ "type": "object", "properties": {} })
+        );
+    }
+
+    #[test]
+    fn test_api_scope() {
+        let mut scope = ApiScope::new();
+        scope
+            .endpoint("v1/blocks", |query: BlocksQuery| -> Result<Vec<u64>, ApiError> {
+                let latest = query.latest.unwrap_or(10);
+                Ok((0..query.count).map(|i| latest - i).collect())
+            })
+            .endpoint_mut("/v1/echo/", |value: Value| -> Result<Value, ApiError> {
+                Ok(value)
+            })
+            .endpoint("v1/error", |_: NoQuery| -> Result<u64, ApiError> {
+                Err(ApiError::NotFound("Nothing here".to_owned()))
+            });
+
+        assert_eq!(
+            call(&scope, request("GET", "/v1/blocks?count=2", "")).unwrap(),
+            json!([10, 9])
+        );
+        assert_eq!(
+            call(&scope, request("GET", "/v1/blocks?count=2&latest=5", "")).unwrap(),
+            json!([5, 4])
+        );
+        assert_eq!(
+            call(&scope, request("POST", "/v1/echo", r#"{ "a": 1 }"#)).unwrap(),
+            json!({ "a": 1 })
+        );
+
+        let error = call(&scope, request("GET", "/v1/blocks", "")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST.as_u16());
+        let error = call(&scope, request("POST", "/v1/echo", "not json")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST.as_u16());
+        let error = call(&scope, request("GET", "/v1/error", "")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND.as_u16());
+        let error = call(&scope, request("POST", "/v1/blocks", "")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND.as_u16());
+
+        scope.set_fallback(scope_with_single_endpoint());
+        assert_eq!(
+            call(&scope, request("GET", "/v1/other", "")).unwrap(),
+            json!("fallback")
+        );
+    }
+
+    struct Missing;
+
+    impl EndpointError for Missing {
+        fn into_response(self) -> Result<HttpResponse, ApiError> {
+            let mut response = json_response(&json!({ "type": "unknown" }))?;
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            Ok(response)
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, ApiSchema)]
+    struct BlockQuery {
+        height: u64,
+        #[serde(default)]
+        with_txs: bool,
+    }
+
+    #[test]
+    fn test_api_scope_path_params() {
+        let mut scope = ApiScope::new();
+        scope
+            .endpoint("v1/blocks/{height}", |query: BlockQuery| {
+                if query.height > 10 {
+                    Err(Missing)
+                } else {
+                    Ok(json!([query.height, query.with_txs]))
+                }
+            })
+            .endpoint_mut("v1/shutdown", |_: NoQuery| -> Result<bool, ApiError> { Ok(true) });
+
+        assert_eq!(
+            call(&scope, request("GET", "/v1/blocks/5", "")).unwrap(),
+            json!([5, false])
+        );
+        assert_eq!(
+            call(&scope, request("GET", "/v1/blocks/5?with_txs=true", "")).unwrap(),
+            json!([5, true])
+        );
+        assert_eq!(
+            call(&scope, request("POST", "/v1/shutdown", "")).unwrap(),
+            json!(true)
+        );
+
+        let response = scope
+            .call(request("GET", "/v1/blocks/11", ""))
+            .wait()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            serde_json::from_slice::<Value>(response.body()).unwrap(),
+            json!({ "type": "unknown" })
+        );
+
+        let error = call(&scope, request("GET", "/v1/blocks/five", "")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST.as_u16());
+        let error = call(&scope, request("GET", "/v1/blocks/5/txs", "")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND.as_u16());
+        assert_eq!(scope.specs()[0].path_params(), vec!["height"]);
+    }
+
+    #[test]
+    fn test_api_scope_async_endpoint() {
+        let (tx, rx) = oneshot::channel::<u64>();
+        let rx = Mutex::new(Some(rx));
+        let mut scope = ApiScope::new();
+        scope.endpoint_mut_async("v1/wait", move |_: NoQuery| {
+            let response: Box<Future<Item = u64, Error = ApiError> + Send> =
+                match rx.lock().unwrap().take() {
+                    Some(rx) => Box::new(rx.map_err(|e| ApiError::InternalError(Box::new(e)))),
+                    None => Box::new(future::err(ApiError::NotFound("Received".to_owned()))),
+                };
+            response
+        });
+
+        let response = scope.call(request("POST", "/v1/wait", ""));
+        thread::spawn(move || tx.send(5).unwrap());
+        let response = response.wait().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(response.body()).unwrap(),
+            json!(5)
+        );
+
+        let error = call(&scope, request("POST", "/v1/wait", "")).unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND.as_u16());
+    }
+
+    fn scope_with_single_endpoint() -> ApiScope {
+        let mut scope = ApiScope::new();
+        scope.endpoint("v1/other", |_: NoQuery| -> Result<String, ApiError> {
+            Ok("fallback".to_owned())
+        });
+        scope
+    }
+}
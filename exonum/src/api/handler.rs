@@ -0,0 +1,237 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Asynchronous API handlers independent of the HTTP server.
+//!
+//! Handlers take requests with the body read into memory and return a future resolving
+//! into the response, so that they can be served by any HTTP server running on the node
+//! event loop. Requests and responses are represented by the types of the `http` crate.
+//! The node serves the handlers with `HttpServer`, which puts the `RemoteAddr` and
+//! `LocalAddr` of the connection into the extensions of the requests.
+//!
+//! Handlers must not block the event loop: blocking work, such as reading the storage,
+//! is run on the thread pool shared by the API handlers with `spawn_blocking`.
+//!
+//! Iron handlers are adapted to this abstraction with `IronAdapter`, and asynchronous
+//! handlers can be embedded into Iron applications with `IronBackend`.
+
+use futures::{future, Future};
+use futures_cpupool::CpuPool;
+use http;
+use num_cpus;
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use super::ApiError;
+
+/// HTTP request with the body read into memory.
+pub type HttpRequest = http::Request<Vec<u8>>;
+
+/// HTTP response with the body in memory.
+pub type HttpResponse = http::Response<Vec<u8>>;
+
+/// Future resolving into the response to a request.
+///
+/// Errors are converted into responses with the corresponding status codes
+/// by the server.
+pub type HandlerFuture = Box<Future<Item = HttpResponse, Error = ApiError> + Send>;
+
+lazy_static! {
+    // As in Iron, the number of threads is a multiple of the number of CPUs,
+    // since the handlers mostly wait for the storage.
+    static ref BLOCKING_POOL: CpuPool = CpuPool::new_builder()
+        .name_prefix("api-worker-")
+        .pool_size(8 * num_cpus::get())
+        .create();
+}
+
+/// Runs the blocking function on the thread pool shared by the API handlers and returns
+/// the future resolving into its result.
+pub fn spawn_blocking<F>(f: F) -> HandlerFuture
+where
+    F: FnOnce() -> Result<HttpResponse, ApiError> + Send + 'static,
+{
+    Box::new(BLOCKING_POOL.spawn_fn(f))
+}
+
+/// Address of the client which has sent the request, stored in the request extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteAddr(pub SocketAddr);
+
+/// Address of the server which has received the request, stored in the request extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalAddr(pub SocketAddr);
+
+/// Asynchronous API handler.
+pub trait AsyncHandler: Send + Sync + 'static {
+    /// Handles the request.
+    fn call(&self, request: HttpRequest) -> HandlerFuture;
+}
+
+impl<F> AsyncHandler for F
+where
+    F: Fn(HttpRequest) -> HandlerFuture + Send + Sync + 'static,
+{
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        self(request)
+    }
+}
+
+impl AsyncHandler for Box<AsyncHandler> {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        (**self).call(request)
+    }
+}
+
+/// Dispatches requests to the handlers mounted on path prefixes.
+///
+/// The handler with the longest prefix matching the request path is called with
+/// the request path stripped of the prefix.
+#[derive(Default)]
+pub struct AsyncMount {
+    handlers: Vec<(Vec<String>, Box<AsyncHandler>)>,
+}
+
+impl AsyncMount {
+    /// Creates a mount without handlers.
+    pub fn new() -> AsyncMount {
+        AsyncMount::default()
+    }
+
+    /// Mounts the handler on the path prefix, replacing the handler previously mounted
+    /// on the same prefix.
+    pub fn mount<H: AsyncHandler>(&mut self, prefix: &str, handler: H) -> &mut AsyncMount {
+        let prefix = path_segments(prefix)
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        self.handlers.retain(|&(ref mounted, _)| *mounted != prefix);
+        self.handlers.push((prefix, Box::new(handler)));
+        // Longer prefixes are tried first.
+        self.handlers
+            .sort_by(|&(ref a, _), &(ref b, _)| b.len().cmp(&a.len()));
+        self
+    }
+}
+
+impl AsyncHandler for AsyncMount {
+    fn call(&self, mut request: HttpRequest) -> HandlerFuture {
+        let (uri, handler) = {
+            let segments = path_segments(request.uri().path());
+            let mounted = self.handlers.iter().find(|&&(ref prefix, _)| {
+                prefix.len() <= segments.len()
+                    && prefix.iter().zip(&segments).all(|(a, b)| a == b)
+            });
+            let (prefix, handler) = match mounted {
+                Some(&(ref prefix, ref handler)) => (prefix, handler),
+                None => {
+                    let path = request.uri().path().to_owned();
+                    return Box::new(future::err(ApiError::NotFound(format!(
+                        "Unknown endpoint {}",
+                        path
+                    ))));
+                }
+            };
+
+            let mut uri = format!("/{}", segments[prefix.len()..].join("/"));
+            if let Some(query) = request.uri().query() {
+                uri.push('?');
+                uri.push_str(query);
+            }
+            (uri, handler)
+        };
+
+        match uri.parse() {
+            Ok(uri) => {
+                *request.uri_mut() = uri;
+                handler.call(request)
+            }
+            Err(e) => Box::new(future::err(ApiError::BadRequest(e.to_string()))),
+        }
+    }
+}
+
+impl fmt::Debug for AsyncMount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefixes = self.handlers
+            .iter()
+            .map(|&(ref prefix, _)| prefix.join("/"))
+            .collect::<Vec<_>>();
+        f.debug_struct("AsyncMount")
+            .field("prefixes", &prefixes)
+            .finish()
+    }
+}
+
+pub(super) fn path_segments(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use http::StatusCode;
+
+    use api::ApiError;
+    use super::{AsyncHandler, AsyncMount, HandlerFuture, HttpRequest, HttpResponse};
+
+    fn echo_path(request: HttpRequest) -> HandlerFuture {
+        let body = request.uri().to_string().into_bytes();
+        Box::new(future::ok(HttpResponse::new(body)))
+    }
+
+    fn call(mount: &AsyncMount, uri: &str) -> Result<String, ApiError> {
+        let mut request = HttpRequest::new(Vec::new());
+        *request.uri_mut() = uri.parse().unwrap();
+        mount
+            .call(request)
+            .wait()
+            .map(|response| String::from_utf8(response.into_body()).unwrap())
+    }
+
+    #[test]
+    fn test_async_mount() {
+        let mut mount = AsyncMount::new();
+        mount
+            .mount("api/services", echo_path)
+            .mount("api/services/cryptocurrency", echo_path)
+            .mount("", |_: HttpRequest| -> HandlerFuture {
+                Box::new(future::ok(HttpResponse::new(b"root".to_vec())))
+            });
+
+        assert_eq!(
+            call(&mount, "/api/services/cryptocurrency/v1/wallets?count=1").unwrap(),
+            "/v1/wallets?count=1"
+        );
+        assert_eq!(call(&mount, "/api/services/time/v1/time").unwrap(), "/time/v1/time");
+        assert_eq!(call(&mount, "/api/system").unwrap(), "root");
+    }
+
+    #[test]
+    fn test_async_mount_not_found() {
+        let mut mount = AsyncMount::new();
+        mount.mount("api/services", echo_path);
+
+        let error = call(&mount, "/api/system/v1/healthcheck").unwrap_err();
+        match error {
+            ApiError::NotFound(_) => {}
+            ref e => panic!("Unexpected error: {}", e),
+        }
+        let response = HttpResponse::from(error);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
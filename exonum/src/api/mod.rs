@@ -14,12 +14,28 @@
 
 //! `RESTful` API and corresponding utilities.
 
+pub use self::auth::{ApiAuthConfig, AuthHandler};
+pub use self::compat::{IronAdapter, IronBackend};
+pub use self::cors::CorsHandler;
+pub use self::endpoint::{ApiScope, EndpointError, NoQuery, ServiceApiBuilder};
+pub use self::handler::{spawn_blocking, AsyncHandler, AsyncMount, HandlerFuture, HttpRequest,
+                        HttpResponse, LocalAddr, RemoteAddr};
+pub use self::openapi::OpenApi;
+pub use self::schema::{object_schema, ApiSchema};
+pub use self::server::HttpServer;
+pub use self::shutdown::ShutdownHandler;
+pub use self::websocket::{ClientMessage, Notification, Subscription, Subscriptions,
+                          WebSocketConfig, WebSocketServer};
+
 pub mod public;
 pub mod private;
+pub mod schema;
 
 use iron::{status, IronError, headers::Cookie};
+use iron::headers::{ContentType, SetCookie};
 use iron::prelude::*;
-use hyper::header::{ContentType, SetCookie};
+use http::StatusCode;
+use http::header::{HeaderValue, CONTENT_TYPE};
 use cookie::Cookie as CookiePair;
 use router::Router;
 use params;
@@ -39,6 +55,15 @@ use crypto::{PublicKey, SecretKey};
 use encoding::serialize::{encode_hex, FromHex, FromHexError, ToHex};
 use storage;
 
+mod auth;
+mod compat;
+mod cors;
+mod endpoint;
+mod handler;
+mod openapi;
+mod server;
+mod shutdown;
+mod websocket;
 #[cfg(test)]
 mod tests;
 
@@ -68,6 +93,10 @@ pub enum ApiError {
     /// Unauthorized error.
     #[fail(display = "Unauthorized")]
     Unauthorized,
+
+    /// The node is shutting down and does not accept the request.
+    #[fail(display = "Service unavailable: {}", _0)]
+    ServiceUnavailable(String),
 }
 
 impl From<io::Error> for ApiError {
@@ -82,34 +111,56 @@ impl From<storage::Error> for ApiError {
     }
 }
 
-impl From<ApiError> for IronError {
-    fn from(e: ApiError) -> IronError {
-        let code = match e {
-            // Note that `status::Unauthorized` does not fit here, because
+impl ApiError {
+    /// Returns the HTTP status code of the response with this error.
+    pub fn status_code(&self) -> u16 {
+        match *self {
+            // Note that `401 Unauthorized` does not fit here, because
             //
             // > A server generating a 401 (Unauthorized) response MUST send a
             // > WWW-Authenticate header field containing at least one challenge.
             //
             // https://tools.ietf.org/html/rfc7235#section-4.1
-            ApiError::Unauthorized => status::Forbidden,
+            ApiError::Unauthorized => 403,
 
-            ApiError::BadRequest(..) => status::BadRequest,
-            ApiError::NotFound(..) => status::NotFound,
+            ApiError::BadRequest(..) => 400,
+            ApiError::NotFound(..) => 404,
 
-            ApiError::Storage(..) | ApiError::Io(..) | ApiError::InternalError(..) => {
-                status::InternalServerError
-            }
-        };
-        let body = {
-            let mut map = BTreeMap::new();
-            map.insert("debug", format!("{:?}", e));
-            map.insert("description", e.to_string());
-            serde_json::to_string_pretty(&map).unwrap()
-        };
+            ApiError::Storage(..) | ApiError::Io(..) | ApiError::InternalError(..) => 500,
+            ApiError::ServiceUnavailable(..) => 503,
+        }
+    }
+
+    /// Returns the JSON body of the response with this error.
+    pub fn response_body(&self) -> String {
+        let mut map = BTreeMap::new();
+        map.insert("debug", format!("{:?}", self));
+        map.insert("description", self.to_string());
+        serde_json::to_string_pretty(&map).unwrap()
+    }
+}
+
+impl From<ApiError> for IronError {
+    fn from(e: ApiError) -> IronError {
+        let code = status::Status::from_u16(e.status_code());
+        let body = e.response_body();
         IronError::new(e.compat(), (code, body))
     }
 }
 
+impl From<ApiError> for HttpResponse {
+    fn from(e: ApiError) -> HttpResponse {
+        let mut response = HttpResponse::new(e.response_body().into_bytes());
+        *response.status_mut() =
+            StatusCode::from_u16(e.status_code()).expect("Invalid status code");
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        response
+    }
+}
+
 /// `Field` that is serialized/deserialized from/to hex.
 #[derive(Clone, Debug)]
 struct HexField<T: AsRef<[u8]> + Clone>(pub T);
@@ -235,39 +286,31 @@ pub trait Api {
     }
 
     /// Loads hex value from the cookies.
+    #[deprecated(since = "0.8.0",
+                 note = "secret keys should not be sent over HTTP; sign with the node keystore \
+                         via `ApiContext::sign` instead")]
     fn load_hex_value_from_cookie<'a>(
         &self,
         request: &'a Request,
         key: &str,
     ) -> storage::Result<Vec<u8>> {
-        if let Some(&Cookie(ref cookies)) = request.headers.get() {
-            for cookie in cookies.iter() {
-                if let Ok(c) = CookiePair::parse(cookie.as_str()) {
-                    if c.name() == key {
-                        if let Ok(value) = FromHex::from_hex(c.value()) {
-                            return Ok(value);
-                        }
-                    }
-                }
-            }
-        }
-        Err(storage::Error::new(format!(
-            "Unable to find value with given key {}",
-            key
-        )))
+        hex_value_from_cookie(request, key)
     }
 
     /// Loads public and secret key from the cookies.
+    #[deprecated(since = "0.8.0",
+                 note = "secret keys should not be sent over HTTP; sign with the node keystore \
+                         via `ApiContext::sign` instead")]
     fn load_keypair_from_cookies(
         &self,
         request: &Request,
     ) -> Result<(PublicKey, SecretKey), ApiError> {
         let public_key = PublicKey::from_slice(
-            self.load_hex_value_from_cookie(request, "public_key")?
+            hex_value_from_cookie(request, "public_key")?
                 .as_ref(),
         );
         let secret_key = SecretKey::from_slice(
-            self.load_hex_value_from_cookie(request, "secret_key")?
+            hex_value_from_cookie(request, "secret_key")?
                 .as_ref(),
         );
 
@@ -276,47 +319,68 @@ pub trait Api {
         Ok((public_key, secret_key))
     }
 
-    //TODO: Remove duplicate code
     /// Returns NotFound and some response with cookies.
+    #[deprecated(since = "0.8.0",
+                 note = "use `not_found_response` instead; sessions should not be kept in cookies")]
     fn not_found_response_with_cookies(
         &self,
         json: &serde_json::Value,
         cookies: Option<Vec<String>>,
     ) -> IronResult<Response> {
-        let mut resp = Response::with((
-            status::NotFound,
-            serde_json::to_string_pretty(json).unwrap(),
-        ));
-        resp.headers.set(ContentType::json());
-        if let Some(cookies) = cookies {
-            resp.headers.set(SetCookie(cookies));
-        }
-        Ok(resp)
+        json_response(status::NotFound, json, cookies)
     }
 
     /// Returns OK and some response with cookies.
+    #[deprecated(since = "0.8.0",
+                 note = "use `ok_response` instead; sessions should not be kept in cookies")]
     fn ok_response_with_cookies(
         &self,
         json: &serde_json::Value,
         cookies: Option<Vec<String>>,
     ) -> IronResult<Response> {
-        let mut resp = Response::with((status::Ok, serde_json::to_string_pretty(json).unwrap()));
-        resp.headers.set(ContentType::json());
-        if let Some(cookies) = cookies {
-            resp.headers.set(SetCookie(cookies));
-        }
-        Ok(resp)
+        json_response(status::Ok, json, cookies)
     }
 
     /// Returns OK and some response.
     fn ok_response(&self, json: &serde_json::Value) -> IronResult<Response> {
-        self.ok_response_with_cookies(json, None)
+        json_response(status::Ok, json, None)
     }
     /// Returns NotFound and some response.
     fn not_found_response(&self, json: &serde_json::Value) -> IronResult<Response> {
-        self.not_found_response_with_cookies(json, None)
+        json_response(status::NotFound, json, None)
     }
 
     /// Used to extend Api.
     fn wire<'b>(&self, router: &'b mut Router);
 }
+
+fn hex_value_from_cookie(request: &Request, key: &str) -> storage::Result<Vec<u8>> {
+    if let Some(&Cookie(ref cookies)) = request.headers.get() {
+        for cookie in cookies.iter() {
+            if let Ok(c) = CookiePair::parse(cookie.as_str()) {
+                if c.name() == key {
+                    if let Ok(value) = FromHex::from_hex(c.value()) {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+    Err(storage::Error::new(format!(
+        "Unable to find value with given key {}",
+        key
+    )))
+}
+
+fn json_response(
+    code: status::Status,
+    json: &serde_json::Value,
+    cookies: Option<Vec<String>>,
+) -> IronResult<Response> {
+    let mut resp = Response::with((code, serde_json::to_string_pretty(json).unwrap()));
+    resp.headers.set(ContentType::json());
+    if let Some(cookies) = cookies {
+        resp.headers.set(SetCookie(cookies));
+    }
+    Ok(resp)
+}
@@ -0,0 +1,187 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [OpenAPI][openapi] document describing the typed endpoints of the node.
+//!
+//! [openapi]: https://github.com/OAI/OpenAPI-Specification/blob/master/versions/3.0.0.md
+
+use futures::future;
+use http::Method;
+use serde_json::{Map, Value};
+
+use super::endpoint::{json_response, ApiScope, EndpointSpec};
+use super::handler::{AsyncHandler, HandlerFuture, HttpRequest};
+
+/// Version of the OpenAPI specification used by the document.
+const OPENAPI_VERSION: &str = "3.0.0";
+
+/// Builder of the OpenAPI document. The document itself is served by the builder
+/// as an `AsyncHandler`.
+#[derive(Debug, Clone, Default)]
+pub struct OpenApi {
+    paths: Map<String, Value>,
+}
+
+impl OpenApi {
+    /// Creates a document without endpoints.
+    pub fn new() -> OpenApi {
+        OpenApi::default()
+    }
+
+    /// Adds the endpoints of the scope mounted on the given path prefix.
+    pub fn add_scope(&mut self, prefix: &str, scope: &ApiScope) -> &mut OpenApi {
+        for spec in scope.specs() {
+            let path = format!("/{}{}", prefix.trim_matches('/'), spec.path);
+            let item = self.paths
+                .entry(path)
+                .or_insert_with(|| Value::Object(Map::new()));
+            item[spec.method.as_str().to_lowercase()] = operation(spec);
+        }
+        self
+    }
+
+    /// Returns the OpenAPI document.
+    pub fn document(&self) -> Value {
+        json!({
+            "openapi": OPENAPI_VERSION,
+            "info": {
+                "title": "Exonum node API",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": self.paths,
+        })
+    }
+}
+
+impl AsyncHandler for OpenApi {
+    fn call(&self, _: HttpRequest) -> HandlerFuture {
+        Box::new(future::result(json_response(&self.document())))
+    }
+}
+
+fn operation(spec: &EndpointSpec) -> Value {
+    let mut operation = json!({
+        "responses": {
+            "200": {
+                "description": "Successful response",
+                "content": { "application/json": { "schema": spec.response } },
+            },
+        },
+    });
+    if spec.method == Method::GET {
+        let path_params = spec.path_params();
+        operation["parameters"] = Value::Array(query_parameters(&spec.request, &path_params));
+    } else {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": spec.request } },
+        });
+    }
+    operation
+}
+
+// Converts the properties of the query object schema to the parameters of the operation.
+// Properties bound to the segments of the endpoint path become the required path parameters.
+fn query_parameters(schema: &Value, path_params: &[&str]) -> Vec<Value> {
+    let required = schema["required"].as_array().cloned().unwrap_or_default();
+    schema["properties"]
+        .as_object()
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, schema)| {
+                    let in_path = path_params.contains(&name.as_str());
+                    json!({
+                        "name": name,
+                        "in": if in_path { "path" } else { "query" },
+                        "required": in_path || required.contains(&Value::String(name.clone())),
+                        "schema": schema,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use api::{ApiError, ApiScope, NoQuery};
+    use super::OpenApi;
+
+    #[test]
+    fn test_openapi_document() {
+        let mut scope = ApiScope::new();
+        scope
+            .endpoint("v1/height", |_: NoQuery| -> Result<u64, ApiError> { Ok(0) })
+            .endpoint_mut("v1/height", |height: u64| -> Result<u64, ApiError> {
+                Ok(height)
+            });
+        let mut openapi = OpenApi::new();
+        openapi.add_scope("api/services/counter/", &scope);
+
+        let document = openapi.document();
+        assert_eq!(document["openapi"], "3.0.0");
+        let path = &document["paths"]["/api/services/counter/v1/height"];
+        assert_eq!(path["get"]["parameters"], json!([]));
+        assert_eq!(
+            path["get"]["responses"]["200"]["content"]["application/json"]["schema"],
+            json!({ "type": "integer" })
+        );
+        assert_eq!(
+            path["post"]["requestBody"]["content"]["application/json"]["schema"],
+            json!({ "type": "integer" })
+        );
+        assert_eq!(document["paths"].as_object().map(|paths| paths.len()), Some(1));
+        assert_eq!(path["put"], Value::Null);
+    }
+
+    #[test]
+    fn test_openapi_path_params() {
+        #[derive(Deserialize, ApiSchema)]
+        struct BlockQuery {
+            height: u64,
+            #[serde(default)]
+            with_txs: bool,
+        }
+
+        let mut scope = ApiScope::new();
+        scope.endpoint("v1/blocks/{height}", |query: BlockQuery| -> Result<bool, ApiError> {
+            Ok(query.with_txs && query.height > 0)
+        });
+        let mut openapi = OpenApi::new();
+        openapi.add_scope("api/explorer", &scope);
+
+        let document = openapi.document();
+        let block = &document["paths"]["/api/explorer/v1/blocks/{height}"];
+        assert_eq!(
+            block["get"]["parameters"],
+            json!([
+                {
+                    "name": "height",
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer" },
+                },
+                {
+                    "name": "with_txs",
+                    "in": "query",
+                    "required": false,
+                    "schema": { "type": "boolean" },
+                },
+            ])
+        );
+    }
+}
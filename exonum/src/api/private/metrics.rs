@@ -0,0 +1,48 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the node metrics.
+
+use futures::future;
+use http::header::{HeaderValue, CONTENT_TYPE};
+
+use api::{AsyncHandler, HandlerFuture, HttpRequest, HttpResponse};
+use helpers::metrics::Registry;
+
+/// Content type of the Prometheus text format.
+const CONTENT_TYPE_PROMETHEUS: &str = "text/plain; version=0.0.4";
+
+/// Handler returning the metrics of the node in the Prometheus text format.
+#[derive(Debug, Clone)]
+pub struct MetricsApi {
+    registry: Registry,
+}
+
+impl MetricsApi {
+    /// Creates the handler for the given metrics registry.
+    pub fn new(registry: Registry) -> Self {
+        MetricsApi { registry }
+    }
+}
+
+impl AsyncHandler for MetricsApi {
+    fn call(&self, _: HttpRequest) -> HandlerFuture {
+        let mut response = HttpResponse::new(self.registry.prometheus_text().into_bytes());
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(CONTENT_TYPE_PROMETHEUS),
+        );
+        Box::new(future::ok(response))
+    }
+}
@@ -14,6 +14,8 @@
 
 //! Private part of the Exonum rest api.
 
-pub use self::system::{NodeInfo, SystemApi};
+pub use self::metrics::MetricsApi;
+pub use self::system::{ConsensusStatus, NodeInfo, RoundVotes, SystemApi, ValidatorStatus};
 
+mod metrics;
 mod system;
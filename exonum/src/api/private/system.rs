@@ -12,28 +12,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use serde_json;
-use router::Router;
-use iron::prelude::*;
+use futures::{future, Future, sync::oneshot};
 
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, net::SocketAddr, time::SystemTime};
 
-use crypto::PublicKey;
-use node::{ApiSender, ExternalMessage};
+use crypto::{Hash, PublicKey};
+use node::{ApiSender, ExternalMessage, RemoveTransactionError, State};
+use node::state::{VoteMessage, Votes};
 use blockchain::{Blockchain, Service, SharedNodeState};
-use api::{Api, ApiError};
-use messages::PROTOCOL_MAJOR_VERSION;
+use events::lanes::QueueDepths;
+use api::{ApiError, ApiScope, NoQuery};
+use messages::{PROTOCOL_FEATURES, PROTOCOL_MAJOR_VERSION, PROTOCOL_VERSIONS};
+use helpers::{Height, Milliseconds, Round, ValidatorId};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Number of round timeouts without a new block after which consensus is considered stalled.
+const STALLED_ROUNDS: u64 = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ApiSchema)]
 struct ServiceInfo {
     name: String,
     id: u16,
 }
 
 /// `DTO` is used to transfer information about node.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ApiSchema)]
 pub struct NodeInfo {
     protocol_version: u8,
+    #[serde(default)]
+    protocol_versions: Vec<u8>,
+    #[serde(default)]
+    protocol_features: u64,
     services: Vec<ServiceInfo>,
 }
 
@@ -45,6 +53,8 @@ impl NodeInfo {
     {
         NodeInfo {
             protocol_version: PROTOCOL_MAJOR_VERSION,
+            protocol_versions: PROTOCOL_VERSIONS.to_vec(),
+            protocol_features: PROTOCOL_FEATURES,
             services: services
                 .into_iter()
                 .map(|s| ServiceInfo {
@@ -56,12 +66,12 @@ impl NodeInfo {
     }
 }
 
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, ApiSchema)]
 struct ReconnectInfo {
     delay: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ApiSchema)]
 #[serde(tag = "type")]
 enum IncomingConnectionState {
     Active,
@@ -74,16 +84,186 @@ impl Default for IncomingConnectionState {
     }
 }
 
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, ApiSchema)]
 struct IncomingConnection {
     public_key: Option<PublicKey>,
     state: IncomingConnectionState,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ApiSchema)]
+struct BannedPeer {
+    public_key: PublicKey,
+    address: SocketAddr,
+    banned_until: SystemTime,
+}
+
+#[derive(Serialize, ApiSchema)]
 struct PeersInfo {
     incoming_connections: Vec<SocketAddr>,
     outgoing_connections: HashMap<SocketAddr, IncomingConnection>,
+    banned_peers: Vec<BannedPeer>,
+    queue_depths: HashMap<SocketAddr, QueueDepths>,
+}
+
+#[derive(Serialize, ApiSchema)]
+struct NetworkInfo {
+    protocol_version: u8,
+    protocol_versions: Vec<u8>,
+    protocol_features: u64,
+    services: Vec<ServiceInfo>,
+    banned_peers: Vec<BannedPeer>,
+    queue_depths: HashMap<SocketAddr, QueueDepths>,
+}
+
+/// Votes collected by the node for a propose or a block in a round of the current height.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ApiSchema)]
+pub struct RoundVotes {
+    /// Round of the votes.
+    pub round: Round,
+    /// Hash of the propose for pre-votes, or hash of the block for pre-commits.
+    pub hash: Hash,
+    /// Validators which have sent the votes.
+    pub validators: Vec<ValidatorId>,
+    /// Total voting power of the validators.
+    pub weight: u64,
+}
+
+impl RoundVotes {
+    fn collect<T: VoteMessage>(votes: &HashMap<(Round, Hash), Votes<T>>) -> Vec<RoundVotes> {
+        let mut votes = votes
+            .iter()
+            .map(|(&(round, hash), votes)| RoundVotes {
+                round,
+                hash,
+                validators: votes
+                    .validators()
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, voted)| voted)
+                    .map(|(id, _)| ValidatorId(id as u16))
+                    .collect(),
+                weight: votes.weight(),
+            })
+            .collect::<Vec<_>>();
+        votes.sort_by(|a, b| (a.round, &a.hash).cmp(&(b.round, &b.hash)));
+        votes
+    }
+}
+
+/// Height of a validator as known to the node.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ApiSchema)]
+pub struct ValidatorStatus {
+    /// Identifier of the validator.
+    pub id: ValidatorId,
+    /// Consensus key of the validator.
+    pub consensus_key: PublicKey,
+    /// The latest height reported by the validator in `Status` messages, or the height
+    /// of the node itself.
+    pub height: Height,
+}
+
+/// Consensus state of the node, used to check whether the blockchain makes progress.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ApiSchema)]
+pub struct ConsensusStatus {
+    /// Current height.
+    pub height: Height,
+    /// Current round.
+    pub round: Round,
+    /// Identifier of the node if it is a validator.
+    pub validator_id: Option<ValidatorId>,
+    /// Leader of the current round.
+    pub leader: ValidatorId,
+    /// Whether the node is the leader of the current round.
+    pub is_leader: bool,
+    /// Round in which the node has locked on a propose.
+    pub locked_round: Round,
+    /// Hash of the propose the node has locked on.
+    pub locked_propose: Option<Hash>,
+    /// Voting power sufficient for the majority.
+    pub majority_weight: u64,
+    /// Pre-votes collected at the current height.
+    pub prevotes: Vec<RoundVotes>,
+    /// Pre-commits collected at the current height.
+    pub precommits: Vec<RoundVotes>,
+    /// Heights of the validators.
+    pub validators: Vec<ValidatorStatus>,
+    /// Time when the latest block was committed by the node.
+    pub last_commit_time: SystemTime,
+    /// Milliseconds elapsed since the latest block was committed.
+    pub since_last_commit: Milliseconds,
+    /// Interval without new blocks after which consensus is considered stalled.
+    pub stall_timeout: Milliseconds,
+    /// Whether no blocks have been committed for `stall_timeout`.
+    pub stalled: bool,
+}
+
+impl ConsensusStatus {
+    /// Takes the consensus state from the node state.
+    pub(crate) fn new(state: &State) -> ConsensusStatus {
+        let validators = state
+            .validators()
+            .iter()
+            .enumerate()
+            .map(|(id, keys)| {
+                let id = ValidatorId(id as u16);
+                let height = if state.validator_id() == Some(id) {
+                    state.height()
+                } else {
+                    state.node_height(&keys.consensus_key)
+                };
+                ValidatorStatus {
+                    id,
+                    consensus_key: keys.consensus_key,
+                    height,
+                }
+            })
+            .collect();
+        let config = state.consensus_config();
+        let stall_timeout = config.round_timeout * STALLED_ROUNDS
+            + config.max_empty_block_interval.unwrap_or(0);
+
+        ConsensusStatus {
+            height: state.height(),
+            round: state.round(),
+            validator_id: state.validator_id(),
+            leader: state.leader(state.round()),
+            is_leader: state.is_leader(),
+            locked_round: state.locked_round(),
+            locked_propose: state.locked_propose(),
+            majority_weight: state.majority_count(),
+            prevotes: RoundVotes::collect(state.collected_prevotes()),
+            precommits: RoundVotes::collect(state.collected_precommits()),
+            validators,
+            last_commit_time: state.height_start_time(),
+            since_last_commit: 0,
+            stall_timeout,
+            stalled: false,
+        }
+    }
+
+    /// Updates the time elapsed since the latest commit.
+    pub(crate) fn at(mut self, now: SystemTime) -> ConsensusStatus {
+        let elapsed = now.duration_since(self.last_commit_time).unwrap_or_default();
+        self.since_last_commit =
+            elapsed.as_secs() * 1_000 + u64::from(elapsed.subsec_nanos()) / 1_000_000;
+        self.stalled = self.since_last_commit >= self.stall_timeout;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, ApiSchema)]
+struct PeerAddInfo {
+    ip: SocketAddr,
+}
+
+#[derive(Serialize, Deserialize, ApiSchema)]
+struct EnabledInfo {
+    enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, ApiSchema)]
+struct RemoveTransactionInfo {
+    tx_hash: Hash,
 }
 
 /// Private system API.
@@ -135,96 +315,137 @@ impl SystemApi {
         PeersInfo {
             incoming_connections: self.shared_api_state.incoming_connections(),
             outgoing_connections,
+            banned_peers: self.banned_peers(),
+            queue_depths: self.shared_api_state.queue_depths(),
         }
     }
 
-    fn handle_peers_info(self, router: &mut Router) {
-        let peers_info = move |_: &mut Request| -> IronResult<Response> {
-            let info = self.peers_info();
-            self.ok_response(&serde_json::to_value(info).unwrap())
-        };
-
-        router.get("/v1/peers", peers_info, "peers_info");
+    fn banned_peers(&self) -> Vec<BannedPeer> {
+        self.shared_api_state
+            .banned_peers()
+            .into_iter()
+            .map(|(public_key, address, banned_until)| BannedPeer {
+                public_key,
+                address,
+                banned_until,
+            })
+            .collect()
     }
 
-    fn handle_peer_add(self, router: &mut Router) {
-        let peer_add = move |request: &mut Request| -> IronResult<Response> {
-            #[derive(Serialize, Deserialize, Clone, Debug)]
-            struct PeerAddInfo {
-                ip: SocketAddr,
-            }
+    fn network_info(&self) -> NetworkInfo {
+        NetworkInfo {
+            protocol_version: self.info.protocol_version,
+            protocol_versions: self.info.protocol_versions.clone(),
+            protocol_features: self.info.protocol_features,
+            services: self.info.services.clone(),
+            banned_peers: self.banned_peers(),
+            queue_depths: self.shared_api_state.queue_depths(),
+        }
+    }
 
-            let PeerAddInfo { ip } = self.parse_body(request)?;
-            self.node_channel.peer_add(ip).map_err(ApiError::from)?;
-            self.ok_response(&serde_json::to_value("Ok").unwrap())
-        };
+    fn handle_peers_info(self, scope: &mut ApiScope) {
+        scope.endpoint("v1/peers", move |_: NoQuery| -> Result<_, ApiError> {
+            Ok(self.peers_info())
+        });
+    }
 
-        router.post("/v1/peers", peer_add, "peer_add");
+    fn handle_peer_add(self, scope: &mut ApiScope) {
+        scope.endpoint_mut(
+            "v1/peers",
+            move |info: PeerAddInfo| -> Result<_, ApiError> {
+                self.node_channel.peer_add(info.ip)?;
+                Ok("Ok")
+            },
+        );
     }
 
-    fn handle_network(self, router: &mut Router) {
-        let network = move |_: &mut Request| -> IronResult<Response> {
-            let info = self.info.clone();
-            self.ok_response(&serde_json::to_value(info).unwrap())
-        };
+    fn handle_network(self, scope: &mut ApiScope) {
+        scope.endpoint("v1/network", move |_: NoQuery| -> Result<_, ApiError> {
+            Ok(self.network_info())
+        });
+    }
 
-        router.get("/v1/network", network, "network_info");
+    fn handle_is_consensus_enabled(self, scope: &mut ApiScope) {
+        scope.endpoint(
+            "v1/consensus_enabled",
+            move |_: NoQuery| -> Result<_, ApiError> { Ok(self.shared_api_state.is_enabled()) },
+        );
     }
 
-    fn handle_is_consensus_enabled(self, router: &mut Router) {
-        let consensus_enabled_info = move |_: &mut Request| -> IronResult<Response> {
-            let info = self.shared_api_state.is_enabled();
-            self.ok_response(&serde_json::to_value(info).unwrap())
-        };
+    fn handle_set_consensus_enabled(self, scope: &mut ApiScope) {
+        scope.endpoint_mut(
+            "v1/consensus_enabled",
+            move |info: EnabledInfo| -> Result<_, ApiError> {
+                self.node_channel
+                    .send_external_message(ExternalMessage::Enable(info.enabled))?;
+                Ok("Ok")
+            },
+        );
+    }
 
-        router.get(
-            "/v1/consensus_enabled",
-            consensus_enabled_info,
-            "consensus_enabled_info",
+    fn handle_consensus_status(self, scope: &mut ApiScope) {
+        scope.endpoint(
+            "v1/consensus_status",
+            move |_: NoQuery| -> Result<_, ApiError> {
+                self.shared_api_state.consensus_status().ok_or_else(|| {
+                    ApiError::NotFound("Consensus status is not available yet".to_owned())
+                })
+            },
         );
     }
 
-    fn handle_set_consensus_enabled(self, router: &mut Router) {
-        let consensus_enabled_set = move |request: &mut Request| -> IronResult<Response> {
-            #[derive(Serialize, Deserialize, Clone, Debug)]
-            struct EnabledInfo {
-                enabled: bool,
-            }
+    /// Removes the transaction from the pool. The response is sent once the node has
+    /// handled the removal.
+    fn remove_transaction(
+        &self,
+        tx_hash: Hash,
+    ) -> Box<Future<Item = &'static str, Error = ApiError> + Send> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let message = ExternalMessage::RemoveTransaction(tx_hash, result_tx);
+        if let Err(e) = self.node_channel.send_external_message(message) {
+            return Box::new(future::err(e.into()));
+        }
+        let response = result_rx.then(move |result| match result {
+            Ok(Ok(())) => Ok("Ok"),
+            Ok(Err(RemoveTransactionError::NotInPool)) => Err(ApiError::NotFound(format!(
+                "Transaction {} is not in the pool",
+                tx_hash
+            ))),
+            Ok(Err(RemoveTransactionError::Proposed)) => Err(ApiError::BadRequest(format!(
+                "Transaction {} is included in a propose",
+                tx_hash
+            ))),
+            Err(oneshot::Canceled) => Err(ApiError::ServiceUnavailable(
+                "The node is shutting down".to_owned(),
+            )),
+        });
+        Box::new(response)
+    }
 
-            let EnabledInfo { enabled } = self.parse_body(request)?;
-            let message = ExternalMessage::Enable(enabled);
-            self.node_channel
-                .send_external_message(message)
-                .map_err(ApiError::from)?;
-            self.ok_response(&serde_json::to_value("Ok").unwrap())
-        };
-
-        router.post(
-            "/v1/consensus_enabled",
-            consensus_enabled_set,
-            "consensus_enabled_set",
+    fn handle_remove_transaction(self, scope: &mut ApiScope) {
+        scope.endpoint_mut_async(
+            "v1/mempool/remove",
+            move |info: RemoveTransactionInfo| self.remove_transaction(info.tx_hash),
         );
     }
 
-    fn handle_shutdown(self, router: &mut Router) {
-        let shutdown = move |_: &mut Request| -> IronResult<Response> {
+    fn handle_shutdown(self, scope: &mut ApiScope) {
+        scope.endpoint_mut("v1/shutdown", move |_: NoQuery| -> Result<_, ApiError> {
             self.node_channel
-                .send_external_message(ExternalMessage::Shutdown)
-                .map_err(ApiError::from)?;
-            self.ok_response(&serde_json::to_value("Ok").unwrap())
-        };
-
-        router.post("/v1/shutdown", shutdown, "shutdown");
+                .send_external_message(ExternalMessage::Shutdown)?;
+            Ok("Ok")
+        });
     }
-}
 
-impl Api for SystemApi {
-    fn wire(&self, router: &mut Router) {
-        self.clone().handle_peers_info(router);
-        self.clone().handle_peer_add(router);
-        self.clone().handle_network(router);
-        self.clone().handle_is_consensus_enabled(router);
-        self.clone().handle_set_consensus_enabled(router);
-        self.clone().handle_shutdown(router);
+    /// Adds the endpoints of the private system API to the scope.
+    pub fn wire(&self, scope: &mut ApiScope) {
+        self.clone().handle_peers_info(scope);
+        self.clone().handle_peer_add(scope);
+        self.clone().handle_network(scope);
+        self.clone().handle_is_consensus_enabled(scope);
+        self.clone().handle_set_consensus_enabled(scope);
+        self.clone().handle_consensus_status(scope);
+        self.clone().handle_remove_transaction(scope);
+        self.clone().handle_shutdown(scope);
     }
 }
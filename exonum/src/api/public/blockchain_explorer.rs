@@ -12,21 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use router::Router;
-use iron::prelude::*;
+use byteorder::{ByteOrder, LittleEndian};
+use futures::{future, Future};
+use http::StatusCode;
+use tokio_timer::Timer;
 
+use std::{cmp, fmt};
 use std::ops::Range;
+use std::time::Duration;
 
-use blockchain::{Block, Blockchain};
-use explorer::{BlockchainExplorer, TransactionInfo};
-use api::{Api, ApiError};
-use crypto::Hash;
-use helpers::Height;
+use blockchain::{Block, Blockchain, Schema, Transaction, TransactionError, TransactionErrorType};
+use explorer::{BlockchainExplorer, CommittedTransaction, SerializeContent, TransactionInfo};
+use api::{ApiError, ApiScope, EndpointError, HttpResponse};
+use api::endpoint::json_response;
+use crypto::{Hash, SIGNATURE_LENGTH};
+use encoding::serialize::FromHex;
+use helpers::{Height, Milliseconds};
+use messages::{Message, Precommit, RawMessage, HEADER_LENGTH};
+use node::ExternalMessage;
 
 const MAX_BLOCKS_PER_REQUEST: usize = 1000;
+const MAX_TRANSACTIONS_PER_REQUEST: usize = 1000;
+/// Maximal time to wait for the commit of a submitted transaction.
+const MAX_COMMIT_TIMEOUT: Milliseconds = 60_000;
 
 /// Information on blocks coupled with the corresponding range in the blockchain.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
 pub struct BlocksRange {
     /// Exclusive range of blocks.
     pub range: Range<Height>,
@@ -34,16 +45,197 @@ pub struct BlocksRange {
     pub blocks: Vec<Block>,
 }
 
+/// Committed transactions coupled with the range of blocks they were searched in.
+///
+/// The type parameter corresponds to some representation of `Box<Transaction>`,
+/// see [`CommittedTransaction`].
+///
+/// [`CommittedTransaction`]: ../../explorer/struct.CommittedTransaction.html
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+#[serde(bound(serialize = "T: SerializeContent"))]
+#[api_schema(bound = "")]
+pub struct TransactionsRange<T = Box<Transaction>> {
+    /// Exclusive range of the searched blocks. Transactions committed in the earlier blocks
+    /// can be requested with `latest` set to the height preceding the start of the range.
+    pub range: Range<Height>,
+    /// Matching transactions, the latest first.
+    pub transactions: Vec<CommittedTransaction<T>>,
+}
+
+/// Transaction submitted to the node.
+#[derive(Debug, Clone, Serialize, Deserialize, ApiSchema)]
+pub struct TransactionSubmission {
+    /// Hex-encoded serialized transaction.
+    pub tx_body: String,
+    /// Maximal time in milliseconds to wait for the commit of the transaction. The time is
+    /// capped at one minute. If not set, the node responds right after accepting
+    /// the transaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_for_commit: Option<Milliseconds>,
+}
+
+/// Transaction accepted by the node.
+///
+/// The type parameter corresponds to some representation of `Box<Transaction>`,
+/// see [`CommittedTransaction`].
+///
+/// [`CommittedTransaction`]: ../../explorer/struct.CommittedTransaction.html
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+#[serde(bound(serialize = "T: SerializeContent"))]
+#[api_schema(bound = "")]
+pub struct SubmittedTransaction<T = Box<Transaction>> {
+    /// Hash of the transaction.
+    pub tx_hash: Hash,
+    /// The transaction with its execution status if the client has waited for the commit
+    /// and the transaction has been committed in time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committed: Option<CommittedTransaction<T>>,
+}
+
+/// Reason of rejecting a submitted transaction. Returned with `400 Bad Request`.
+///
+/// # JSON presentation
+///
+/// The error is represented as an object with the `type` field equal to the kebab-cased
+/// variant name and the `description` field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ApiSchema)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SubmissionError {
+    /// The transaction is not a hex-encoded message of the valid length.
+    Malformed {
+        /// Error description.
+        description: String,
+    },
+    /// The message is not a transaction of any service of the blockchain.
+    UnknownTransaction {
+        /// Error description.
+        description: String,
+    },
+    /// `Transaction::verify` has returned `false` for the transaction.
+    VerificationFailed {
+        /// Error description.
+        description: String,
+    },
+    /// The transaction is already in the pool or committed.
+    Duplicate {
+        /// Error description.
+        description: String,
+    },
+}
+
+impl EndpointError for SubmissionError {
+    fn into_response(self) -> Result<HttpResponse, ApiError> {
+        let mut response = json_response(&self)?;
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        Ok(response)
+    }
+}
+
+/// Transaction which is neither committed nor in the pool, returned with `404 Not Found`.
+#[derive(Debug)]
+struct UnknownTransaction;
+
+impl EndpointError for UnknownTransaction {
+    fn into_response(self) -> Result<HttpResponse, ApiError> {
+        let mut response = json_response(&json!({ "type": "unknown" }))?;
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        Ok(response)
+    }
+}
+
+/// Execution status of the listed transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ApiSchema)]
+#[serde(rename_all = "kebab-case")]
+enum StatusFilter {
+    Success,
+    Failure,
+    Error,
+    Panic,
+    BatchRollback,
+    Expired,
+    InvalidNonce,
+}
+
+impl StatusFilter {
+    fn matches(self, status: Result<(), &TransactionError>) -> bool {
+        use self::TransactionErrorType::*;
+
+        match (self, status) {
+            (StatusFilter::Success, Ok(())) | (StatusFilter::Failure, Err(_)) => true,
+            (_, Ok(())) => false,
+            (filter, Err(e)) => match (filter, e.error_type()) {
+                (StatusFilter::Error, Code(_))
+                | (StatusFilter::Panic, Panic)
+                | (StatusFilter::BatchRollback, BatchRollback)
+                | (StatusFilter::Expired, Expired)
+                | (StatusFilter::InvalidNonce, InvalidNonce) => true,
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+struct BlocksQuery {
+    count: usize,
+    latest: Option<u64>,
+    #[serde(default)]
+    skip_empty_blocks: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+struct BlockQuery {
+    height: u64,
+}
+
+/// Block with its precommits and the hashes of its transactions.
+#[derive(Debug, Serialize, ApiSchema)]
+struct BlockDetails {
+    block: Block,
+    #[api_schema(with = "Vec<::serde_json::Value>")]
+    precommits: Vec<Precommit>,
+    txs: Vec<Hash>,
+}
+
+/// Parameters of the transactions listing.
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+struct TransactionsQuery {
+    count: usize,
+    latest: Option<u64>,
+    earliest: Option<u64>,
+    service_id: Option<u16>,
+    message_type: Option<u16>,
+    status: Option<StatusFilter>,
+}
+
+impl TransactionsQuery {
+    fn matches(&self, tx: &CommittedTransaction) -> bool {
+        let raw = tx.content().raw();
+        self.service_id.map_or(true, |id| raw.service_id() == id)
+            && self.message_type.map_or(true, |id| raw.message_type() == id)
+            && self.status.map_or(true, |status| status.matches(tx.status()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+struct TransactionQuery {
+    hash: Hash,
+}
+
 /// Public explorer API.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct ExplorerApi {
     blockchain: Blockchain,
+    timer: Timer,
 }
 
 impl ExplorerApi {
     /// Creates a new `ExplorerApi` instance.
     pub fn new(blockchain: Blockchain) -> Self {
-        ExplorerApi { blockchain }
+        ExplorerApi {
+            blockchain,
+            timer: Timer::default(),
+        }
     }
 
     fn explorer(&self) -> BlockchainExplorer {
@@ -89,51 +281,232 @@ impl ExplorerApi {
         })
     }
 
+    fn transactions(&self, query: &TransactionsQuery) -> Result<TransactionsRange, ApiError> {
+        if query.count > MAX_TRANSACTIONS_PER_REQUEST {
+            return Err(ApiError::BadRequest(format!(
+                "Max transaction count per request exceeded ({})",
+                MAX_TRANSACTIONS_PER_REQUEST
+            )));
+        }
+
+        let explorer = self.explorer();
+        let upper = query.latest.map_or(explorer.height(), |latest| {
+            cmp::min(Height(latest), explorer.height())
+        });
+        let lower = Height(query.earliest.unwrap_or(0));
+
+        // Whole blocks are searched, so the response may contain more than `count`
+        // transactions if the last searched block has several matching ones.
+        let mut start = upper.next();
+        let mut transactions = Vec::new();
+        let blocks = explorer
+            .blocks(lower..upper.next())
+            .rev()
+            .take(MAX_BLOCKS_PER_REQUEST);
+        for block in blocks {
+            if transactions.len() >= query.count {
+                break;
+            }
+            start = block.height();
+            let mut matching: Vec<_> = block.iter().filter(|tx| query.matches(tx)).collect();
+            matching.reverse();
+            transactions.extend(matching);
+        }
+
+        Ok(TransactionsRange {
+            range: start..upper.next(),
+            transactions,
+        })
+    }
+
+    fn validate_transaction(&self, tx_body: &str) -> Result<Box<Transaction>, SubmissionError> {
+        let malformed = |description: String| SubmissionError::Malformed { description };
+
+        let bytes = Vec::<u8>::from_hex(tx_body)
+            .map_err(|e| malformed(format!("Invalid hex encoding: {}", e)))?;
+        if bytes.len() < HEADER_LENGTH + SIGNATURE_LENGTH
+            || LittleEndian::read_u32(&bytes[6..10]) as usize != bytes.len()
+        {
+            return Err(malformed("Message length does not match the header".to_owned()));
+        }
+        let snapshot = self.blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        let max_message_len = schema.actual_configuration().consensus.max_message_len;
+        if bytes.len() > max_message_len as usize {
+            return Err(malformed(format!(
+                "Message is too long, maximum allowed length is {} bytes",
+                max_message_len
+            )));
+        }
+
+        let raw = RawMessage::from_vec(bytes);
+        let tx_hash = raw.hash();
+        let tx = self.blockchain
+            .tx_from_raw(raw)
+            .map_err(|e| SubmissionError::UnknownTransaction {
+                description: e.to_string(),
+            })?;
+        if !tx.verify() {
+            return Err(SubmissionError::VerificationFailed {
+                description: "Unable to verify transaction".to_owned(),
+            });
+        }
+        if schema.transactions().contains(&tx_hash) {
+            return Err(SubmissionError::Duplicate {
+                description: format!("Transaction {} is already known", tx_hash),
+            });
+        }
+        Ok(tx)
+    }
+
+    fn submit_transaction(
+        &self,
+        submission: &TransactionSubmission,
+    ) -> Box<Future<Item = SubmittedTransaction, Error = SubmitError> + Send> {
+        let tx = match self.validate_transaction(&submission.tx_body) {
+            Ok(tx) => tx,
+            Err(e) => return Box::new(future::err(e.into())),
+        };
+        let tx_hash = tx.hash();
+        // The commit notification is requested before sending the transaction to the node,
+        // so that the commit cannot be missed.
+        let commit = submission
+            .wait_for_commit
+            .map(|timeout| (self.blockchain.subscriptions().wait_for_commit(tx_hash), timeout));
+        if let Err(e) = self.blockchain
+            .api_sender()
+            .send_external_message(ExternalMessage::Transaction(tx))
+        {
+            return Box::new(future::err(ApiError::from(e).into()));
+        }
+
+        let (commit, timeout) = match commit {
+            Some(commit) => commit,
+            None => {
+                return Box::new(future::ok(SubmittedTransaction {
+                    tx_hash,
+                    committed: None,
+                }))
+            }
+        };
+        let timeout = Duration::from_millis(cmp::min(timeout, MAX_COMMIT_TIMEOUT));
+        let deadline = self.timer.sleep(timeout).map_err(drop);
+        let explorer = self.clone();
+        let response = commit
+            .map_err(drop)
+            .select(deadline)
+            .then(move |_| -> Result<_, SubmitError> {
+                let committed = match explorer.transaction_info(&tx_hash) {
+                    Some(TransactionInfo::Committed(tx)) => Some(tx),
+                    _ => None,
+                };
+                Ok(SubmittedTransaction { tx_hash, committed })
+            });
+        Box::new(response)
+    }
+
     fn transaction_info(&self, hash: &Hash) -> Option<TransactionInfo> {
         self.explorer().transaction(hash)
     }
 
-    fn set_blocks_response(self, router: &mut Router) {
-        let blocks = move |req: &mut Request| -> IronResult<Response> {
-            let count: usize = self.required_param(req, "count")?;
-            let latest: Option<u64> = self.optional_param(req, "latest")?;
-            let skip_empty_blocks: bool = self.optional_param(req, "skip_empty_blocks")?
-                .unwrap_or(false);
-            let info = self.blocks(count, latest.map(Height), skip_empty_blocks)?;
-            self.ok_response(&::serde_json::to_value(info).unwrap())
-        };
+    fn block_details(&self, height: Height) -> Option<BlockDetails> {
+        let explorer = self.explorer();
+        explorer.block(height).map(|block| BlockDetails {
+            block: block.header().clone(),
+            precommits: block.precommits().to_vec(),
+            txs: block.transaction_hashes().to_vec(),
+        })
+    }
 
-        router.get("/v1/blocks", blocks, "blocks");
+    fn set_blocks_response(self, scope: &mut ApiScope) {
+        scope.endpoint("v1/blocks", move |query: BlocksQuery| {
+            self.blocks(
+                query.count,
+                query.latest.map(Height),
+                query.skip_empty_blocks,
+            )
+        });
     }
 
-    fn set_block_response(self, router: &mut Router) {
-        let block = move |req: &mut Request| -> IronResult<Response> {
-            let height: Height = self.url_fragment(req, "height")?;
-            let explorer = self.explorer();
-            self.ok_response(&::serde_json::to_value(explorer.block(height)).unwrap())
-        };
+    fn set_block_response(self, scope: &mut ApiScope) {
+        scope.endpoint(
+            "v1/blocks/{height}",
+            move |query: BlockQuery| -> Result<_, ApiError> {
+                Ok(self.block_details(Height(query.height)))
+            },
+        );
+    }
 
-        router.get("/v1/blocks/:height", block, "height");
+    fn set_transactions_response(self, scope: &mut ApiScope) {
+        scope.endpoint("v1/transactions", move |query: TransactionsQuery| {
+            self.transactions(&query)
+        });
     }
 
-    fn set_transaction_info_response(self, router: &mut Router) {
-        let transaction = move |req: &mut Request| -> IronResult<Response> {
-            let hash: Hash = self.url_fragment(req, "hash")?;
+    fn set_submit_transaction_response(self, scope: &mut ApiScope) {
+        scope.endpoint_mut_async(
+            "v1/transactions",
+            move |submission: TransactionSubmission| self.submit_transaction(&submission),
+        );
+    }
 
-            match self.transaction_info(&hash) {
-                None => self.not_found_response(&json!({ "type": "unknown" })),
-                Some(info) => self.ok_response(&::serde_json::to_value(info).unwrap()),
-            }
-        };
+    fn set_transaction_info_response(self, scope: &mut ApiScope) {
+        scope.endpoint(
+            "v1/transactions/{hash}",
+            move |query: TransactionQuery| {
+                self.transaction_info(&query.hash).ok_or(UnknownTransaction)
+            },
+        );
+    }
+
+    /// Adds the endpoints of the explorer API to the scope.
+    pub fn wire(&self, scope: &mut ApiScope) {
+        self.clone().set_blocks_response(scope);
+        self.clone().set_block_response(scope);
+        self.clone().set_transactions_response(scope);
+        self.wire_submission(scope);
+        self.clone().set_transaction_info_response(scope);
+    }
+
+    /// Adds only the `POST v1/transactions` endpoint submitting transactions to the scope.
+    /// The endpoint is served even if the blockchain explorer is disabled.
+    pub fn wire_submission(&self, scope: &mut ApiScope) {
+        self.clone().set_submit_transaction_response(scope);
+    }
+}
 
-        router.get("/v1/transactions/:hash", transaction, "hash");
+impl fmt::Debug for ExplorerApi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExplorerApi")
+            .field("blockchain", &self.blockchain)
+            .finish()
     }
 }
 
-impl Api for ExplorerApi {
-    fn wire(&self, router: &mut Router) {
-        self.clone().set_blocks_response(router);
-        self.clone().set_block_response(router);
-        self.clone().set_transaction_info_response(router);
+/// Error of the transaction submission.
+#[derive(Debug)]
+enum SubmitError {
+    Rejected(SubmissionError),
+    Api(ApiError),
+}
+
+impl From<SubmissionError> for SubmitError {
+    fn from(e: SubmissionError) -> SubmitError {
+        SubmitError::Rejected(e)
+    }
+}
+
+impl From<ApiError> for SubmitError {
+    fn from(e: ApiError) -> SubmitError {
+        SubmitError::Api(e)
+    }
+}
+
+impl EndpointError for SubmitError {
+    fn into_response(self) -> Result<HttpResponse, ApiError> {
+        match self {
+            SubmitError::Rejected(e) => e.into_response(),
+            SubmitError::Api(e) => Err(e),
+        }
     }
 }
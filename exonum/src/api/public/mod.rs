@@ -14,8 +14,9 @@
 
 //! Public part of the Exonum rest api.
 
-pub use self::system::{HealthCheckInfo, SystemApi};
-pub use self::blockchain_explorer::{BlocksRange, ExplorerApi};
+pub use self::system::{HealthCheckInfo, MemPoolTransaction, MemPoolTransactions, SystemApi};
+pub use self::blockchain_explorer::{BlocksRange, ExplorerApi, SubmissionError, SubmittedTransaction,
+                                    TransactionSubmission, TransactionsRange};
 
 mod system;
 mod blockchain_explorer;
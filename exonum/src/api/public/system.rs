@@ -12,21 +12,58 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use router::Router;
-use iron::prelude::*;
-use serde_json;
+use serde_json::Value;
+
+use std::time::SystemTime;
 
 use blockchain::{Blockchain, Schema, SharedNodeState};
-use api::Api;
-use helpers::user_agent;
+use api::{ApiError, ApiScope, NoQuery};
+use crypto::Hash;
+use storage::Snapshot;
+use encoding::serialize::json::ExonumJson;
+use helpers::{user_agent, Milliseconds};
+
+const MAX_TRANSACTIONS_PER_REQUEST: usize = 1000;
 
-#[derive(Serialize, Deserialize, PartialEq)]
+#[derive(Serialize, Deserialize, PartialEq, ApiSchema)]
 struct MemPoolInfo {
     pub size: usize,
 }
 
+/// Transaction in the pool.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ApiSchema)]
+pub struct MemPoolTransaction {
+    /// Hash of the transaction.
+    pub tx_hash: Hash,
+    /// JSON representation of the transaction.
+    pub content: Value,
+    /// Time in milliseconds the transaction has been waiting in the pool, or `None`
+    /// if the transaction has been added to the pool before the node start.
+    pub waiting_time: Option<Milliseconds>,
+}
+
+/// Transactions in the pool ordered by their hashes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ApiSchema)]
+pub struct MemPoolTransactions {
+    /// Transactions in the pool.
+    pub transactions: Vec<MemPoolTransaction>,
+    /// Hash to start the next page from, or `None` if there are no more transactions.
+    pub next: Option<Hash>,
+}
+
+#[derive(Serialize, Deserialize, ApiSchema)]
+struct MemPoolTransactionsQuery {
+    count: usize,
+    from: Option<Hash>,
+}
+
+#[derive(Serialize, Deserialize, ApiSchema)]
+struct MemPoolTransactionQuery {
+    hash: Hash,
+}
+
 #[doc(hidden)]
-#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, ApiSchema)]
 pub struct HealthCheckInfo {
     pub connectivity: bool,
 }
@@ -39,7 +76,7 @@ pub struct SystemApi {
 }
 
 impl SystemApi {
-    /// Creates a new `private::SystemApi` instance.
+    /// Creates a new `public::SystemApi` instance.
     pub fn new(blockchain: Blockchain, shared_api_state: SharedNodeState) -> SystemApi {
         SystemApi {
             blockchain,
@@ -47,41 +84,112 @@ impl SystemApi {
         }
     }
 
-    fn mempool_info(self, router: &mut Router) {
-        let mempool = move |_: &mut Request| -> IronResult<Response> {
+    fn mempool_info(self, scope: &mut ApiScope) {
+        scope.endpoint("v1/mempool", move |_: NoQuery| -> Result<_, ApiError> {
             let snapshot = self.blockchain.snapshot();
             let schema = Schema::new(&snapshot);
-            let info = MemPoolInfo {
+            Ok(MemPoolInfo {
                 size: schema.transactions_pool_len(),
-            };
-            self.ok_response(&serde_json::to_value(info).unwrap())
-        };
-        router.get("/v1/mempool", mempool, "mempool");
+            })
+        });
     }
 
-    fn healthcheck_info(self, router: &mut Router) {
-        let healthcheck = move |_: &mut Request| -> IronResult<Response> {
-            let info = HealthCheckInfo {
+    fn pool_transaction<T: AsRef<Snapshot>>(
+        &self,
+        schema: &Schema<T>,
+        tx_hash: Hash,
+    ) -> Result<MemPoolTransaction, ApiError> {
+        let raw = schema.transactions().get(&tx_hash).ok_or_else(|| {
+            ApiError::InternalError(format!("Transaction {} is not found", tx_hash).into())
+        })?;
+        let content = self.blockchain
+            .tx_from_raw(raw)
+            .map_err(|e| ApiError::InternalError(e.to_string().into()))?
+            .serialize_field()
+            .map_err(ApiError::InternalError)?;
+        let waiting_time = self.shared_api_state
+            .pool_arrival_time(&tx_hash)
+            .and_then(|arrival| SystemTime::now().duration_since(arrival).ok())
+            .map(|duration| {
+                duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
+            });
+        Ok(MemPoolTransaction {
+            tx_hash,
+            content,
+            waiting_time,
+        })
+    }
+
+    fn mempool_transactions(self, scope: &mut ApiScope) {
+        scope.endpoint(
+            "v1/mempool/transactions",
+            move |query: MemPoolTransactionsQuery| -> Result<_, ApiError> {
+                if query.count > MAX_TRANSACTIONS_PER_REQUEST {
+                    return Err(ApiError::BadRequest(format!(
+                        "Max transaction count per request exceeded ({})",
+                        MAX_TRANSACTIONS_PER_REQUEST
+                    )));
+                }
+
+                let snapshot = self.blockchain.snapshot();
+                let schema = Schema::new(&snapshot);
+                let pool = schema.transactions_pool();
+                let mut hashes: Vec<_> = match query.from {
+                    Some(ref from) => pool.iter_from(from).take(query.count + 1).collect(),
+                    None => pool.iter().take(query.count + 1).collect(),
+                };
+                let next = if hashes.len() > query.count {
+                    hashes.pop()
+                } else {
+                    None
+                };
+
+                let transactions = hashes
+                    .into_iter()
+                    .map(|hash| self.pool_transaction(&schema, hash))
+                    .collect::<Result<_, _>>()?;
+                Ok(MemPoolTransactions { transactions, next })
+            },
+        );
+    }
+
+    fn mempool_transaction(self, scope: &mut ApiScope) {
+        scope.endpoint(
+            "v1/mempool/transaction",
+            move |query: MemPoolTransactionQuery| -> Result<_, ApiError> {
+                let snapshot = self.blockchain.snapshot();
+                let schema = Schema::new(&snapshot);
+                if !schema.transactions_pool().contains(&query.hash) {
+                    return Err(ApiError::NotFound(format!(
+                        "Transaction {} is not in the pool",
+                        query.hash
+                    )));
+                }
+                self.pool_transaction(&schema, query.hash)
+            },
+        );
+    }
+
+    fn healthcheck_info(self, scope: &mut ApiScope) {
+        scope.endpoint("v1/healthcheck", move |_: NoQuery| -> Result<_, ApiError> {
+            Ok(HealthCheckInfo {
                 connectivity: !self.shared_api_state.peers_info().is_empty(),
-            };
-            self.ok_response(&serde_json::to_value(info).unwrap())
-        };
-        router.get("/v1/healthcheck", healthcheck, "healthcheck");
+            })
+        });
     }
 
-    fn user_agent_info(self, router: &mut Router) {
-        let user_agent = move |_: &mut Request| -> IronResult<Response> {
-            let info = user_agent::get();
-            self.ok_response(&serde_json::to_value(info).unwrap())
-        };
-        router.get("/v1/user_agent", user_agent, "user_agent");
+    fn user_agent_info(scope: &mut ApiScope) {
+        scope.endpoint("v1/user_agent", |_: NoQuery| -> Result<_, ApiError> {
+            Ok(user_agent::get())
+        });
     }
-}
 
-impl Api for SystemApi {
-    fn wire(&self, router: &mut Router) {
-        self.clone().mempool_info(router);
-        self.clone().healthcheck_info(router);
-        self.clone().user_agent_info(router);
+    /// Adds the endpoints of the public system API to the scope.
+    pub fn wire(&self, scope: &mut ApiScope) {
+        self.clone().mempool_info(scope);
+        self.clone().mempool_transactions(scope);
+        self.clone().mempool_transaction(scope);
+        self.clone().healthcheck_info(scope);
+        SystemApi::user_agent_info(scope);
     }
 }
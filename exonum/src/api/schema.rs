@@ -0,0 +1,435 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON schemas of the types used by the API endpoints.
+//!
+//! Schemas are generated by `#[derive(ApiSchema)]` from the `exonum_derive` crate,
+//! which follows the `serde` attributes of the type, so that the schema matches
+//! the serialized form of the type.
+
+use serde_json::Value;
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::time::SystemTime;
+
+use blockchain::{Block, TxLocation};
+use crypto::{Hash, PublicKey, Signature};
+use helpers::{Height, Round, ValidatorId};
+
+/// Type with a JSON schema describing its serialized form.
+///
+/// The trait should be derived with `#[derive(ApiSchema)]` from the `exonum_derive` crate.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate exonum;
+/// #[macro_use] extern crate exonum_derive;
+/// #[macro_use] extern crate serde_derive;
+/// #[macro_use] extern crate serde_json;
+/// use exonum::api::ApiSchema;
+/// use exonum::crypto::PublicKey;
+///
+/// #[derive(Serialize, Deserialize, ApiSchema)]
+/// struct WalletQuery {
+///     pub_key: PublicKey,
+///     #[serde(default)]
+///     with_history: bool,
+/// }
+///
+/// # fn main() {
+/// assert_eq!(
+///     WalletQuery::schema(),
+///     json!({
+///         "type": "object",
+///         "properties": {
+///             "pub_key": { "type": "string", "format": "hex" },
+///             "with_history": { "type": "boolean" },
+///         },
+///         "required": ["pub_key"],
+///     })
+/// );
+/// # }
+/// ```
+pub trait ApiSchema {
+    /// Returns the JSON schema of the type in the form used by OpenAPI.
+    fn schema() -> Value;
+}
+
+macro_rules! impl_api_schema {
+    ($($ty:ty),* => $schema:tt) => {
+        $(
+            impl ApiSchema for $ty {
+                fn schema() -> Value {
+                    json!($schema)
+                }
+            }
+        )*
+    };
+}
+
+impl_api_schema!(bool => { "type": "boolean" });
+impl_api_schema!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize => { "type": "integer" });
+impl_api_schema!(f32, f64 => { "type": "number" });
+impl_api_schema!(str, String, SocketAddr => { "type": "string" });
+impl_api_schema!(Hash, PublicKey, Signature => { "type": "string", "format": "hex" });
+impl_api_schema!(Height, Round, ValidatorId => { "type": "integer" });
+impl_api_schema!(Value => {});
+
+impl ApiSchema for SystemTime {
+    fn schema() -> Value {
+        object_schema(vec![
+            ("secs_since_epoch", u64::schema()),
+            ("nanos_since_epoch", u32::schema()),
+        ])
+    }
+}
+
+// Fields of `encoding_struct!`s are serialized with `ExonumJson`, which represents
+// 64-bit integers as strings.
+fn uint64_string() -> Value {
+    json!({ "type": "string", "format": "uint64" })
+}
+
+impl ApiSchema for Block {
+    fn schema() -> Value {
+        object_schema(vec![
+            ("schema_version", u16::schema()),
+            ("proposer_id", ValidatorId::schema()),
+            ("height", uint64_string()),
+            ("tx_count", u32::schema()),
+            ("prev_hash", Hash::schema()),
+            ("tx_hash", Hash::schema()),
+            ("state_hash", Hash::schema()),
+        ])
+    }
+}
+
+impl ApiSchema for TxLocation {
+    fn schema() -> Value {
+        object_schema(vec![
+            ("block_height", uint64_string()),
+            ("position_in_block", uint64_string()),
+        ])
+    }
+}
+
+impl<'a, T: ApiSchema + ?Sized> ApiSchema for &'a T {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: ApiSchema + ?Sized> ApiSchema for Box<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Option<T> {
+    fn schema() -> Value {
+        let mut schema = T::schema();
+        if let Value::Object(ref mut object) = schema {
+            object.insert("nullable".to_owned(), Value::Bool(true));
+        }
+        schema
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for [T] {
+    fn schema() -> Value {
+        Vec::<T>::schema()
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Range<T> {
+    fn schema() -> Value {
+        object_schema(vec![("start", T::schema()), ("end", T::schema())])
+    }
+}
+
+// Keys of the maps are serialized as strings.
+impl<K, T: ApiSchema> ApiSchema for BTreeMap<K, T> {
+    fn schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::schema() })
+    }
+}
+
+impl<K, T: ApiSchema, S> ApiSchema for HashMap<K, T, S> {
+    fn schema() -> Value {
+        BTreeMap::<K, T>::schema()
+    }
+}
+
+/// Returns the schema of an object with the given properties. Properties with nullable
+/// schemas are optional, the others are required.
+///
+/// The function is useful for the types with custom `Serialize` implementations;
+/// the schemas of other types should be derived.
+pub fn object_schema(properties: Vec<(&str, Value)>) -> Value {
+    __private::object(
+        properties
+            .into_iter()
+            .map(|(name, schema)| (name, schema, false))
+            .collect(),
+    )
+}
+
+/// Functions used by the code generated by `#[derive(ApiSchema)]`.
+#[doc(hidden)]
+pub mod __private {
+    pub use serde_json::Value;
+
+    use serde_json::Map;
+
+    use std::mem;
+
+    /// Object with the properties described by their names, schemas and whether
+    /// they may be omitted. Properties with nullable schemas are optional as well.
+    pub fn object(properties: Vec<(&str, Value, bool)>) -> Value {
+        let required = properties
+            .iter()
+            .filter(|&&(_, ref schema, optional)| {
+                !optional && schema.get("nullable") != Some(&Value::Bool(true))
+            })
+            .map(|&(name, _, _)| Value::String(name.to_owned()))
+            .collect::<Vec<_>>();
+        let properties = properties
+            .into_iter()
+            .map(|(name, schema, _)| (name.to_owned(), schema))
+            .collect::<Map<_, _>>();
+
+        let mut schema = json!({ "type": "object", "properties": properties });
+        // OpenAPI does not allow empty lists of the required properties.
+        if !required.is_empty() {
+            schema["required"] = Value::Array(required);
+        }
+        schema
+    }
+
+    /// Tuple, serialized as an array.
+    pub fn tuple(items: Vec<Value>) -> Value {
+        let len = items.len();
+        json!({
+            "type": "array",
+            "items": { "anyOf": items },
+            "minItems": len,
+            "maxItems": len,
+        })
+    }
+
+    /// Unit struct, serialized as `null`.
+    pub fn unit() -> Value {
+        json!({ "enum": [null], "nullable": true })
+    }
+
+    /// String equal to one of the names.
+    pub fn string_enum(names: Vec<&str>) -> Value {
+        json!({ "type": "string", "enum": names })
+    }
+
+    /// Value matching exactly one of the schemas.
+    pub fn one_of(schemas: Vec<Value>) -> Value {
+        json!({ "oneOf": schemas })
+    }
+
+    /// Variant of an internally tagged enum: the tag is added to the object
+    /// with the content of the variant.
+    pub fn with_tag(tag: &str, name: &str, content: Option<Value>) -> Value {
+        let tag_property = (tag, string_enum(vec![name]), false);
+        let mut content = match content {
+            Some(content) => content,
+            None => return object(vec![tag_property]),
+        };
+        if content["type"] != "object" || !content["properties"].is_object() {
+            return json!({ "allOf": [content, object(vec![tag_property])] });
+        }
+
+        content["properties"][tag] = tag_property.1;
+        let mut required = match mem::replace(&mut content["required"], Value::Null) {
+            Value::Array(required) => required,
+            _ => Vec::new(),
+        };
+        required.insert(0, Value::String(tag.to_owned()));
+        content["required"] = Value::Array(required);
+        content
+    }
+
+    /// Variant of an adjacently tagged enum.
+    pub fn adjacently_tagged(tag: &str, name: &str, content_field: &str, content: Value) -> Value {
+        object(vec![
+            (tag, string_enum(vec![name]), false),
+            (content_field, content, false),
+        ])
+    }
+
+    /// Variant of an externally tagged enum: an object with the single property
+    /// named after the variant.
+    pub fn externally_tagged(name: &str, content: Value) -> Value {
+        object(vec![(name, content, false)])
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use serde_json::Value;
+
+    use crypto::Hash;
+    use super::ApiSchema;
+
+    #[derive(Serialize, ApiSchema)]
+    struct BlocksQuery {
+        count: u64,
+        latest: Option<u64>,
+        #[serde(rename = "skip_empty", default)]
+        skip_empty_blocks: bool,
+        #[serde(skip)]
+        cache: Vec<u8>,
+    }
+
+    #[derive(Serialize, ApiSchema)]
+    struct Wrapper(Hash);
+
+    #[derive(Serialize, ApiSchema)]
+    #[serde(rename_all = "kebab-case")]
+    enum Status {
+        Success,
+        BatchRollback,
+    }
+
+    #[derive(Serialize, ApiSchema)]
+    #[serde(tag = "type", rename_all = "kebab-case")]
+    enum Event {
+        Started,
+        Stopped { reason: String },
+        Block(Block),
+    }
+
+    #[derive(Serialize, ApiSchema)]
+    struct Block {
+        height: u64,
+    }
+
+    #[derive(Serialize, ApiSchema)]
+    enum Message {
+        Ping,
+        Text(String),
+    }
+
+    #[derive(Serialize, ApiSchema)]
+    struct Page<T> {
+        items: Vec<T>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[api_schema(with = "Option<Hash>")]
+        next: Option<String>,
+    }
+
+    #[test]
+    fn test_derive_struct() {
+        assert_eq!(
+            BlocksQuery::schema(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "count": { "type": "integer" },
+                    "latest": { "type": "integer", "nullable": true },
+                    "skip_empty": { "type": "boolean" },
+                },
+                "required": ["count"],
+            })
+        );
+        assert_eq!(Wrapper::schema(), Hash::schema());
+        assert_eq!(
+            Page::<u64>::schema(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "items": { "type": "array", "items": { "type": "integer" } },
+                    "next": { "type": "string", "format": "hex", "nullable": true },
+                },
+                "required": ["items"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_derive_enum() {
+        assert_eq!(
+            Status::schema(),
+            json!({ "type": "string", "enum": ["success", "batch-rollback"] })
+        );
+        assert_eq!(
+            Event::schema(),
+            json!({
+                "oneOf": [
+                    {
+                        "type": "object",
+                        "properties": { "type": { "type": "string", "enum": ["started"] } },
+                        "required": ["type"],
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "type": { "type": "string", "enum": ["stopped"] },
+                            "reason": { "type": "string" },
+                        },
+                        "required": ["type", "reason"],
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "type": { "type": "string", "enum": ["block"] },
+                            "height": { "type": "integer" },
+                        },
+                        "required": ["type", "height"],
+                    },
+                ],
+            })
+        );
+        assert_eq!(
+            Message::schema(),
+            json!({
+                "oneOf": [
+                    { "type": "string", "enum": ["Ping"] },
+                    {
+                        "type": "object",
+                        "properties": { "Text": { "type": "string" } },
+                        "required": ["Text"],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_collection_schemas() {
+        assert_eq!(
+            Vec::<Hash>::schema(),
+            json!({ "type": "array", "items": { "type": "string", "format": "hex" } })
+        );
+        assert_eq!(
+            Option::<Value>::schema(),
+            json!({ "nullable": true })
+        );
+    }
+}
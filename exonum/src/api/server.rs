@@ -0,0 +1,238 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `hyper` server running asynchronous API handlers on a `tokio-core` event loop.
+
+use futures::{future, Future, Stream};
+use http;
+use hyper::{self, Chunk};
+use hyper::server::{Http, Service};
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Handle;
+
+use std::{fmt, io, net};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use super::ApiError;
+use super::handler::{AsyncHandler, HandlerFuture, HttpResponse, LocalAddr, RemoteAddr};
+
+/// Maximal length of a request body.
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+/// HTTP server running an asynchronous handler on a `tokio-core` event loop.
+///
+/// Request bodies are read into memory before the handler is called. Errors returned
+/// by the handler are converted into responses with the corresponding status codes.
+/// The remote and local addresses of the connection are put into the request extensions
+/// as `RemoteAddr` and `LocalAddr`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate exonum;
+/// # extern crate futures;
+/// # extern crate tokio_core;
+/// use exonum::api::{ApiError, ApiScope, HttpServer, NoQuery};
+/// use tokio_core::reactor::Core;
+///
+/// # fn main() {
+/// let mut scope = ApiScope::new();
+/// scope.endpoint("v1/ping", |_: NoQuery| -> Result<String, ApiError> {
+///     Ok("pong".to_owned())
+/// });
+///
+/// let server = HttpServer::bind(&"127.0.0.1:8080".parse().unwrap(), scope).unwrap();
+/// let mut core = Core::new().unwrap();
+/// let server = server.run(&core.handle()).unwrap();
+/// core.run(server).unwrap();
+/// # }
+/// ```
+pub struct HttpServer {
+    listener: net::TcpListener,
+    handler: Arc<Box<AsyncHandler>>,
+}
+
+impl HttpServer {
+    /// Binds the server to the address. The server accepts connections once it is run.
+    pub fn bind<H: AsyncHandler>(address: &SocketAddr, handler: H) -> io::Result<HttpServer> {
+        Ok(HttpServer {
+            listener: net::TcpListener::bind(address)?,
+            handler: Arc::new(Box::new(handler)),
+        })
+    }
+
+    /// Returns the address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns the future serving connections on the event loop. Connections are
+    /// spawned on the event loop as separate tasks.
+    pub fn run(self, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let local_addr = self.listener.local_addr()?;
+        let listener = TcpListener::from_listener(self.listener, &local_addr, handle)?;
+        let handler = self.handler;
+        let handle = handle.clone();
+        let http = Http::<Chunk>::new();
+
+        let server = listener
+            .incoming()
+            .then(|accepted| {
+                if let Err(ref e) = accepted {
+                    warn!("Cannot accept API connection: {}", e);
+                }
+                Ok::<_, io::Error>(accepted.ok())
+            })
+            .filter_map(|accepted| accepted)
+            .for_each(move |(socket, remote_addr)| {
+                socket.set_nodelay(true).ok();
+                let service = ApiService {
+                    handler: Arc::clone(&handler),
+                    remote_addr,
+                    local_addr,
+                };
+                let connection = http.serve_connection(socket, service)
+                    .map(drop)
+                    .map_err(move |e| debug!("API connection with {} failed: {}", remote_addr, e));
+                handle.spawn(connection);
+                Ok(())
+            });
+        Ok(Box::new(server))
+    }
+}
+
+impl fmt::Debug for HttpServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpServer")
+            .field("listener", &self.listener)
+            .finish()
+    }
+}
+
+/// `hyper` service passing the requests received over a single connection to the handler.
+struct ApiService {
+    handler: Arc<Box<AsyncHandler>>,
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+}
+
+impl Service for ApiService {
+    type Request = hyper::Request;
+    type Response = hyper::Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = hyper::Response, Error = hyper::Error>>;
+
+    fn call(&self, request: hyper::Request) -> Self::Future {
+        let (mut parts, body) = http::Request::<hyper::Body>::from(request).into_parts();
+        parts.extensions.insert(RemoteAddr(self.remote_addr));
+        parts.extensions.insert(LocalAddr(self.local_addr));
+        let handler = Arc::clone(&self.handler);
+
+        // The body exceeding the limit is read to the end, so that the connection
+        // can be reused, but is not kept in memory.
+        let body = body.fold(Some(Vec::new()), |body, chunk| {
+            let body = body.and_then(|mut body| {
+                if body.len() + chunk.len() > MAX_BODY_LEN {
+                    None
+                } else {
+                    body.extend_from_slice(&chunk);
+                    Some(body)
+                }
+            });
+            Ok::<_, hyper::Error>(body)
+        });
+        let response = body.and_then(move |body| {
+            let response: HandlerFuture = match body {
+                Some(body) => handler.call(http::Request::from_parts(parts, body)),
+                None => Box::new(future::err(ApiError::BadRequest(format!(
+                    "Request body exceeds {} bytes",
+                    MAX_BODY_LEN
+                )))),
+            };
+            response.then(|response| {
+                let (parts, body) = response.unwrap_or_else(HttpResponse::from).into_parts();
+                let response = http::Response::from_parts(parts, hyper::Body::from(body));
+                Ok::<_, hyper::Error>(hyper::Response::from(response))
+            })
+        });
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use http::StatusCode;
+    use tokio_core::net::TcpStream;
+    use tokio_core::reactor::Core;
+    use tokio_io::io::{read_to_end, write_all};
+
+    use api::{ApiError, HandlerFuture, HttpRequest, HttpResponse, RemoteAddr};
+    use super::HttpServer;
+
+    fn echo(request: HttpRequest) -> HandlerFuture {
+        match request.uri().path() {
+            "/echo" => Box::new(future::ok(HttpResponse::new(request.into_body()))),
+            "/remote" => {
+                let remote = request.extensions().get::<RemoteAddr>().map(|addr| addr.0);
+                let body = format!("{:?}", remote).into_bytes();
+                Box::new(future::ok(HttpResponse::new(body)))
+            }
+            path => Box::new(future::err(ApiError::NotFound(path.to_owned()))),
+        }
+    }
+
+    #[test]
+    fn test_http_server() {
+        let server = HttpServer::bind(&"127.0.0.1:0".parse().unwrap(), echo).unwrap();
+        let address = server.local_addr().unwrap();
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        handle.spawn(server.run(&handle).unwrap().map_err(|e| panic!("{}", e)));
+
+        let request = b"POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+                        POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                        3\r\nbye\r\n0\r\n\r\n\
+                        GET /other HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let client = TcpStream::connect(&address, &handle)
+            .and_then(|socket| write_all(socket, &request[..]))
+            .and_then(|(socket, _)| read_to_end(socket, Vec::new()));
+        let (_, response) = core.run(client).unwrap();
+        let response = String::from_utf8(response).unwrap().to_lowercase();
+
+        let hello = response.find("http/1.1 200 ok\r\n").unwrap();
+        let bye = hello + 1 + response[hello + 1..].find("http/1.1 200 ok\r\n").unwrap();
+        let not_found = response
+            .find(&format!("http/1.1 {} not found\r\n", StatusCode::NOT_FOUND.as_u16()))
+            .unwrap();
+        assert!(hello < bye && bye < not_found);
+        assert!(response[hello..bye].contains("content-length: 5\r\n"));
+        assert!(response[hello..bye].ends_with("\r\n\r\nhello"));
+        assert!(response[bye..not_found].ends_with("\r\n\r\nbye"));
+
+        let request = b"GET /remote HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let client = TcpStream::connect(&address, &handle)
+            .and_then(|socket| {
+                let local_addr = socket.local_addr().unwrap();
+                write_all(socket, &request[..]).map(move |(socket, _)| (socket, local_addr))
+            })
+            .and_then(|(socket, local_addr)| {
+                read_to_end(socket, Vec::new()).map(move |(_, response)| (response, local_addr))
+            });
+        let (response, local_addr) = core.run(client).unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.ends_with(&format!("Some({:?})", local_addr)));
+    }
+}
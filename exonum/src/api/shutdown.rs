@@ -0,0 +1,95 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rejection of the modifying requests while the node is shutting down.
+
+use futures::future;
+use http::Method;
+
+use std::fmt;
+
+use blockchain::SharedNodeState;
+use super::ApiError;
+use super::handler::{AsyncHandler, HandlerFuture, HttpRequest};
+
+/// Handler responding to the `POST` requests with `503 Service Unavailable` once the node
+/// is shutting down, so that clients do not submit transactions which would be lost.
+/// Other requests are passed to the wrapped handler.
+pub struct ShutdownHandler<H> {
+    node_state: SharedNodeState,
+    handler: H,
+}
+
+impl<H: AsyncHandler> ShutdownHandler<H> {
+    /// Wraps the handler, checking the shutdown flag of the given node state.
+    pub fn new(node_state: SharedNodeState, handler: H) -> ShutdownHandler<H> {
+        ShutdownHandler {
+            node_state,
+            handler,
+        }
+    }
+}
+
+impl<H: AsyncHandler> AsyncHandler for ShutdownHandler<H> {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        if *request.method() == Method::POST && self.node_state.is_shutting_down() {
+            return Box::new(future::err(ApiError::ServiceUnavailable(
+                "The node is shutting down".to_owned(),
+            )));
+        }
+        self.handler.call(request)
+    }
+}
+
+impl<H> fmt::Debug for ShutdownHandler<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShutdownHandler").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use http::{self, StatusCode};
+
+    use api::{HandlerFuture, HttpRequest, HttpResponse};
+    use api::handler::AsyncHandler;
+    use blockchain::SharedNodeState;
+    use node::ShutdownReason;
+    use super::ShutdownHandler;
+
+    fn ok(_: HttpRequest) -> HandlerFuture {
+        Box::new(future::ok(HttpResponse::new(Vec::new())))
+    }
+
+    fn request(method: &str) -> HttpRequest {
+        http::Request::builder()
+            .method(method)
+            .uri("/v1/transactions")
+            .body(Vec::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shutdown_handler() {
+        let node_state = SharedNodeState::new(10_000);
+        let handler = ShutdownHandler::new(node_state.clone(), ok);
+        assert!(handler.call(request("POST")).wait().is_ok());
+
+        node_state.request_shutdown(ShutdownReason::Requested);
+        let error = handler.call(request("POST")).wait().unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::SERVICE_UNAVAILABLE.as_u16());
+        assert!(handler.call(request("GET")).wait().is_ok());
+    }
+}
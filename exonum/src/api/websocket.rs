@@ -0,0 +1,552 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WebSocket API pushing notifications about committed blocks, transactions and
+//! service events to the subscribed clients.
+//!
+//! Iron handlers cannot take over the underlying connection, so the WebSocket API
+//! listens on its own address set in `NodeApiConfig::websocket`.
+//!
+//! Clients manage subscriptions with JSON messages:
+//!
+//! ```text
+//! { "action": "subscribe", "subscription": { "type": "blocks" } }
+//! { "action": "subscribe", "subscription": { "type": "transaction", "tx_hash": "..." } }
+//! { "action": "subscribe", "subscription": { "type": "service_events", "service_name": "..." } }
+//! { "action": "unsubscribe", "subscription": { "type": "blocks" } }
+//! ```
+//!
+//! Notifications are sent as JSON objects with the `type` field equal to `block_committed`,
+//! `transaction_committed`, `service_event` or `error`. A transaction subscription is
+//! removed once the transaction is committed; if the transaction is already committed
+//! at the moment of the subscription, the notification is sent right away.
+//!
+//! Notifications are not buffered beyond `WebSocketConfig::max_pending_bytes` for each
+//! client: clients that do not read notifications fast enough are disconnected. The number
+//! of transaction subscriptions of a client is limited by
+//! `WebSocketConfig::max_transaction_subscriptions`.
+//!
+//! Block and transaction notifications are prepared in a separate thread of the server,
+//! which learns the heights of the committed blocks over a channel, so that the clients
+//! do not slow down the block commit.
+
+use futures::sync::oneshot;
+use serde::Serialize;
+use serde_json::{self, Value};
+use ws::{self, Handshake, Message, Settings};
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use blockchain::{Block, Blockchain, Schema, TransactionResult, TxLocation};
+use crypto::Hash;
+use explorer::TxStatus;
+use helpers::Height;
+use storage::Snapshot;
+
+/// WebSocket API options.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WebSocketConfig {
+    /// Listen address of the WebSocket API.
+    pub address: SocketAddr,
+    /// Maximum number of simultaneously connected clients.
+    #[serde(default = "WebSocketConfig::default_max_connections")]
+    pub max_connections: usize,
+    /// Maximum size in bytes of the notifications queued for a client. Clients exceeding
+    /// this limit are disconnected.
+    #[serde(default = "WebSocketConfig::default_max_pending_bytes")]
+    pub max_pending_bytes: usize,
+    /// Maximum number of transactions a client can be subscribed to at the same time.
+    #[serde(default = "WebSocketConfig::default_max_transaction_subscriptions")]
+    pub max_transaction_subscriptions: usize,
+}
+
+impl WebSocketConfig {
+    /// Creates the configuration with the given listen address and default limits.
+    pub fn new(address: SocketAddr) -> WebSocketConfig {
+        WebSocketConfig {
+            address,
+            max_connections: Self::default_max_connections(),
+            max_pending_bytes: Self::default_max_pending_bytes(),
+            max_transaction_subscriptions: Self::default_max_transaction_subscriptions(),
+        }
+    }
+
+    fn default_max_connections() -> usize {
+        128
+    }
+
+    fn default_max_pending_bytes() -> usize {
+        1 << 20
+    }
+
+    fn default_max_transaction_subscriptions() -> usize {
+        1_000
+    }
+}
+
+/// Events a client can subscribe to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Subscription {
+    /// Commits of the new blocks.
+    Blocks,
+    /// Commit of the transaction with the given hash.
+    Transaction {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+    },
+    /// Events published by the service with the given name.
+    ServiceEvents {
+        /// Name of the service.
+        service_name: String,
+    },
+}
+
+/// Message sent by a client to manage its subscriptions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "subscription", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Adds the subscription.
+    Subscribe(Subscription),
+    /// Removes the subscription.
+    Unsubscribe(Subscription),
+}
+
+/// Notification sent to the subscribed clients.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    /// A new block has been committed.
+    BlockCommitted {
+        /// Hash of the block.
+        block_hash: Hash,
+        /// Header of the block.
+        block: Block,
+    },
+    /// A transaction has been committed.
+    TransactionCommitted {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Location of the transaction in the blockchain. Not set for the transactions
+        /// executed as a part of a batch.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        location: Option<TxLocation>,
+        /// Execution status of the transaction.
+        #[serde(serialize_with = "TxStatus::serialize")]
+        status: TransactionResult,
+    },
+    /// A service has published an event.
+    ServiceEvent {
+        /// Name of the service.
+        service_name: String,
+        /// Event data.
+        event: Value,
+    },
+    /// A client message could not be processed.
+    Error {
+        /// Description of the error.
+        description: String,
+    },
+}
+
+impl Notification {
+    fn to_message(&self) -> String {
+        serde_json::to_string(self).expect("Unable to serialize notification")
+    }
+}
+
+#[derive(Debug)]
+struct Client {
+    sender: ws::Sender,
+    blocks: bool,
+    transactions: HashSet<Hash>,
+    services: HashSet<String>,
+}
+
+impl Client {
+    fn new(sender: ws::Sender) -> Client {
+        Client {
+            sender,
+            blocks: false,
+            transactions: HashSet::new(),
+            services: HashSet::new(),
+        }
+    }
+
+    fn is_subscribed(&self) -> bool {
+        self.blocks || !self.transactions.is_empty() || !self.services.is_empty()
+    }
+}
+
+/// Clients of the WebSocket API and their subscriptions, along with the API requests
+/// waiting for the commit of transactions.
+///
+/// The subscriptions are shared between the clones of `Blockchain`, which notifies
+/// the subscribers on the block commit.
+#[derive(Debug, Clone, Default)]
+pub struct Subscriptions {
+    clients: Arc<Mutex<HashMap<u32, Client>>>,
+    commit_waiters: Arc<Mutex<HashMap<Hash, Vec<oneshot::Sender<()>>>>>,
+    // Heights of the committed blocks are sent to the thread notifying the clients.
+    notifier: Arc<Mutex<Option<mpsc::Sender<Height>>>>,
+}
+
+impl Subscriptions {
+    /// Creates an empty set of subscriptions.
+    pub fn new() -> Subscriptions {
+        Subscriptions::default()
+    }
+
+    /// Sends the event published by the service to the clients subscribed
+    /// to the events of this service.
+    pub fn publish_event<T: Serialize>(&self, service_name: &str, event: &T) {
+        let mut clients = self.clients.lock().unwrap();
+        if !clients
+            .values()
+            .any(|client| client.services.contains(service_name))
+        {
+            return;
+        }
+
+        let event = match serde_json::to_value(event) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Unable to serialize event of service {}: {}", service_name, e);
+                return;
+            }
+        };
+        let message = Notification::ServiceEvent {
+            service_name: service_name.to_owned(),
+            event,
+        }.to_message();
+        send_to(&mut clients, &message, |client| {
+            client.services.contains(service_name)
+        });
+    }
+
+    /// Returns the receiver resolved once a block with the transaction is committed.
+    ///
+    /// The receiver is not resolved if the transaction has been committed before the call,
+    /// so the callers should check the transaction status after subscribing.
+    pub fn wait_for_commit(&self, tx_hash: Hash) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.commit_waiters
+            .lock()
+            .unwrap()
+            .entry(tx_hash)
+            .or_insert_with(Vec::new)
+            .push(tx);
+        rx
+    }
+
+    /// Notifies the subscribers about the last committed block. The WebSocket clients are
+    /// notified asynchronously by the thread of the WebSocket server.
+    pub(crate) fn notify_commit(&self, snapshot: &Snapshot) {
+        self.notify_commit_waiters(snapshot);
+
+        if let Some(ref notifier) = *self.notifier.lock().unwrap() {
+            let height = Schema::new(snapshot).height();
+            if notifier.send(height).is_err() {
+                warn!("WebSocket notifier has stopped");
+            }
+        }
+    }
+
+    fn set_notifier(&self, notifier: Option<mpsc::Sender<Height>>) {
+        *self.notifier.lock().unwrap() = notifier;
+    }
+
+    // Sends the notifications about the block at the given height and the transactions
+    // committed up to the snapshot to the subscribed clients.
+    fn notify_clients(&self, snapshot: &Snapshot, height: Height) {
+        let mut clients = self.clients.lock().unwrap();
+        if !clients.values().any(Client::is_subscribed) {
+            return;
+        }
+
+        let schema = Schema::new(snapshot);
+        if clients.values().any(|client| client.blocks) {
+            if let Some(block_hash) = schema.block_hash_by_height(height) {
+                let message = Notification::BlockCommitted {
+                    block_hash,
+                    block: schema.blocks().get(&block_hash).unwrap(),
+                }.to_message();
+                send_to(&mut clients, &message, |client| client.blocks);
+            }
+        }
+
+        let committed = clients
+            .values()
+            .flat_map(|client| client.transactions.iter())
+            .filter_map(|tx_hash| {
+                schema
+                    .transaction_results()
+                    .get(tx_hash)
+                    .map(|status| (*tx_hash, status))
+            })
+            .collect::<HashMap<_, _>>();
+        for (tx_hash, status) in committed {
+            let message = Notification::TransactionCommitted {
+                tx_hash,
+                location: schema.transactions_locations().get(&tx_hash),
+                status,
+            }.to_message();
+            send_to(&mut clients, &message, |client| {
+                client.transactions.remove(&tx_hash)
+            });
+        }
+    }
+
+    // Resolves the receivers waiting for the committed transactions and drops the ones
+    // which are no longer awaited.
+    fn notify_commit_waiters(&self, snapshot: &Snapshot) {
+        let mut waiters = self.commit_waiters.lock().unwrap();
+        if waiters.is_empty() {
+            return;
+        }
+
+        let schema = Schema::new(snapshot);
+        let results = schema.transaction_results();
+        waiters.retain(|tx_hash, senders| {
+            if results.contains(tx_hash) {
+                for sender in senders.drain(..) {
+                    let _ = sender.send(());
+                }
+                false
+            } else {
+                senders.retain(|sender| !sender.is_canceled());
+                !senders.is_empty()
+            }
+        });
+    }
+
+    fn add_client(&self, sender: ws::Sender) {
+        let id = sender.connection_id();
+        self.clients.lock().unwrap().insert(id, Client::new(sender));
+    }
+
+    fn remove_client(&self, id: u32) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    // Adds the subscription of the client. Returns `false` if the client is already
+    // subscribed to the maximum number of transactions.
+    fn subscribe(&self, id: u32, subscription: &Subscription, max_transactions: usize) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+        let client = match clients.get_mut(&id) {
+            Some(client) => client,
+            None => return true,
+        };
+        match *subscription {
+            Subscription::Blocks => client.blocks = true,
+            Subscription::Transaction { tx_hash } => {
+                if client.transactions.len() >= max_transactions
+                    && !client.transactions.contains(&tx_hash)
+                {
+                    return false;
+                }
+                client.transactions.insert(tx_hash);
+            }
+            Subscription::ServiceEvents { ref service_name } => {
+                client.services.insert(service_name.clone());
+            }
+        }
+        true
+    }
+
+    fn unsubscribe(&self, id: u32, subscription: &Subscription) {
+        let mut clients = self.clients.lock().unwrap();
+        let client = match clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        match *subscription {
+            Subscription::Blocks => client.blocks = false,
+            Subscription::Transaction { ref tx_hash } => {
+                client.transactions.remove(tx_hash);
+            }
+            Subscription::ServiceEvents { ref service_name } => {
+                client.services.remove(service_name);
+            }
+        }
+    }
+
+    // Removes the transaction subscription of the client, returning `true` if it was present.
+    fn take_transaction(&self, id: u32, tx_hash: &Hash) -> bool {
+        self.clients
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .map_or(false, |client| client.transactions.remove(tx_hash))
+    }
+}
+
+// Sends the message to the clients matching the predicate. Clients which do not read
+// the notifications are disconnected by the server once their output buffer is full.
+fn send_to<F>(clients: &mut HashMap<u32, Client>, message: &str, mut predicate: F)
+where
+    F: FnMut(&mut Client) -> bool,
+{
+    for (id, client) in clients.iter_mut() {
+        if predicate(client) {
+            if let Err(e) = client.sender.send(message) {
+                warn!("Unable to notify WebSocket client {}: {}", id, e);
+            }
+        }
+    }
+}
+
+struct ClientHandler {
+    sender: ws::Sender,
+    blockchain: Blockchain,
+    max_transaction_subscriptions: usize,
+}
+
+impl ClientHandler {
+    fn subscriptions(&self) -> &Subscriptions {
+        self.blockchain.subscriptions()
+    }
+
+    fn subscribe(&self, subscription: &Subscription) -> ws::Result<()> {
+        let id = self.sender.connection_id();
+        if !self.subscriptions()
+            .subscribe(id, subscription, self.max_transaction_subscriptions)
+        {
+            let notification = Notification::Error {
+                description: format!(
+                    "Too many transaction subscriptions, at most {} are allowed",
+                    self.max_transaction_subscriptions
+                ),
+            };
+            return self.sender.send(notification.to_message());
+        }
+
+        // The transaction could have been committed before the subscription.
+        if let Subscription::Transaction { tx_hash } = *subscription {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            if let Some(status) = schema.transaction_results().get(&tx_hash) {
+                if self.subscriptions().take_transaction(id, &tx_hash) {
+                    let notification = Notification::TransactionCommitted {
+                        tx_hash,
+                        location: schema.transactions_locations().get(&tx_hash),
+                        status,
+                    };
+                    return self.sender.send(notification.to_message());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ws::Handler for ClientHandler {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        self.subscriptions().add_client(self.sender.clone());
+        Ok(())
+    }
+
+    fn on_message(&mut self, message: Message) -> ws::Result<()> {
+        let id = self.sender.connection_id();
+        match serde_json::from_str(message.as_text()?) {
+            Ok(ClientMessage::Subscribe(subscription)) => self.subscribe(&subscription),
+            Ok(ClientMessage::Unsubscribe(subscription)) => {
+                self.subscriptions().unsubscribe(id, &subscription);
+                Ok(())
+            }
+            Err(e) => {
+                let notification = Notification::Error {
+                    description: format!("Invalid message: {}", e),
+                };
+                self.sender.send(notification.to_message())
+            }
+        }
+    }
+}
+
+impl Drop for ClientHandler {
+    fn drop(&mut self) {
+        self.subscriptions()
+            .remove_client(self.sender.connection_id());
+    }
+}
+
+/// Running WebSocket API server.
+#[derive(Debug)]
+pub struct WebSocketServer {
+    broadcaster: ws::Sender,
+    thread: JoinHandle<()>,
+    subscriptions: Subscriptions,
+    notifier_thread: JoinHandle<()>,
+}
+
+impl WebSocketServer {
+    /// Starts the WebSocket API server with the given options in a separate thread.
+    pub fn start(config: &WebSocketConfig, blockchain: Blockchain) -> io::Result<WebSocketServer> {
+        let settings = Settings {
+            max_connections: config.max_connections,
+            out_buffer_capacity: config.max_pending_bytes,
+            out_buffer_grow: false,
+            ..Settings::default()
+        };
+        let max_transaction_subscriptions = config.max_transaction_subscriptions;
+        let handler_blockchain = blockchain.clone();
+        let server = ws::Builder::new()
+            .with_settings(settings)
+            .build(move |sender| ClientHandler {
+                sender,
+                blockchain: handler_blockchain.clone(),
+                max_transaction_subscriptions,
+            })
+            .and_then(|server| server.bind(config.address))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let broadcaster = server.broadcaster();
+        let thread = thread::spawn(move || {
+            if let Err(e) = server.run() {
+                error!("WebSocket API server failed: {}", e);
+            }
+        });
+
+        let subscriptions = blockchain.subscriptions().clone();
+        let (notifier, heights) = mpsc::channel();
+        subscriptions.set_notifier(Some(notifier));
+        let notifier_thread = thread::spawn(move || {
+            for height in heights {
+                blockchain
+                    .subscriptions()
+                    .notify_clients(&*blockchain.snapshot(), height);
+            }
+        });
+        Ok(WebSocketServer {
+            broadcaster,
+            thread,
+            subscriptions,
+            notifier_thread,
+        })
+    }
+
+    /// Disconnects the clients and stops the server.
+    pub fn shutdown(self) {
+        // Dropping the sender stops the notifier thread.
+        self.subscriptions.set_notifier(None);
+        self.notifier_thread.join().unwrap();
+        if self.broadcaster.shutdown().is_ok() {
+            self.thread.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transaction batches, i.e., signed bundles of transactions committed atomically.
+
+use serde_json::Value;
+
+use std::collections::HashSet;
+use std::error::Error;
+
+use crypto::CryptoHash;
+use encoding::{self, Offset};
+use encoding::serialize::WriteBufferWrapper;
+use encoding::serialize::json::ExonumJson;
+use messages::{Message, RawMessage, TransactionBatch, CONSENSUS as CORE_SERVICE,
+               TRANSACTION_BATCH_MESSAGE_ID};
+use storage::Fork;
+use super::{ExecutionResult, Transaction};
+
+/// Parsed `TransactionBatch` message together with the inner transactions.
+#[derive(Debug)]
+pub(crate) struct Batch {
+    message: TransactionBatch,
+    transactions: Vec<Box<Transaction>>,
+}
+
+impl Batch {
+    /// Checks whether the raw message is a `TransactionBatch`.
+    pub(crate) fn is_batch(raw: &RawMessage) -> bool {
+        raw.service_id() == CORE_SERVICE && raw.message_type() == TRANSACTION_BATCH_MESSAGE_ID
+    }
+
+    /// Creates a batch from the message and the parsed inner transactions.
+    pub(crate) fn new(message: TransactionBatch, transactions: Vec<Box<Transaction>>) -> Self {
+        Batch {
+            message,
+            transactions,
+        }
+    }
+
+    /// Returns the inner transactions in the order of their execution.
+    pub(crate) fn transactions(&self) -> &[Box<Transaction>] {
+        &self.transactions
+    }
+}
+
+impl Message for Batch {
+    fn from_raw(_raw: RawMessage) -> Result<Self, encoding::Error> {
+        Err(encoding::Error::Basic(
+            "Batch should be parsed with `Blockchain::tx_from_raw`".into(),
+        ))
+    }
+
+    fn raw(&self) -> &RawMessage {
+        self.message.raw()
+    }
+}
+
+impl ExonumJson for Batch {
+    fn deserialize_field<B: WriteBufferWrapper>(
+        _value: &Value,
+        _buffer: &mut B,
+        _from: Offset,
+        _to: Offset,
+    ) -> Result<(), Box<Error>> {
+        Err("Batch cannot be deserialized as a field".into())
+    }
+
+    fn serialize_field(&self) -> Result<Value, Box<Error + Send + Sync>> {
+        let mut value = self.message.serialize_field()?;
+        let transactions = self.transactions
+            .iter()
+            .map(|tx| tx.serialize_field())
+            .collect::<Result<Vec<_>, _>>()?;
+        value["body"]["transactions"] = Value::Array(transactions);
+        Ok(value)
+    }
+}
+
+impl Transaction for Batch {
+    fn verify(&self) -> bool {
+        if self.transactions.is_empty() || !self.message.verify_signature(self.message.from()) {
+            return false;
+        }
+
+        let mut hashes = HashSet::new();
+        self.transactions.iter().all(|tx| {
+            hashes.insert(tx.hash()) && tx.raw().verify_signature(self.message.from())
+                && tx.verify()
+        })
+    }
+
+    /// Applies the inner transactions in order, stopping at the first error.
+    ///
+    /// Note that the blockchain does not call this method directly: it executes inner
+    /// transactions one by one in order to record the result of each of them.
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        for tx in &self.transactions {
+            tx.execute(fork)?;
+        }
+        Ok(())
+    }
+}
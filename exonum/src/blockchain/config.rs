@@ -23,12 +23,13 @@
 
 use serde::de::Error;
 use serde_json::{self, Error as JsonError};
+use byteorder::{ByteOrder, LittleEndian};
 
 use std::collections::{BTreeMap, HashSet};
 
 use storage::StorageValue;
 use crypto::{hash, CryptoHash, Hash, PublicKey};
-use helpers::{Height, Milliseconds};
+use helpers::{Height, Milliseconds, Round, ValidatorId};
 
 /// Public keys of a validator. Each validator has two public keys: the
 /// `consensus_key` is used for internal operations in the consensus process,
@@ -40,6 +41,24 @@ pub struct ValidatorKeys {
     /// Service key is used for services, for example, the configuration
     /// updater service, the anchoring service, etc.
     pub service_key: PublicKey,
+    /// Voting power of the validator. Consensus and configuration majorities are
+    /// computed as the sum of weights of the voted validators. Defaults to `1`.
+    #[serde(default = "ValidatorKeys::default_weight",
+            skip_serializing_if = "ValidatorKeys::is_default_weight")]
+    pub weight: u64,
+}
+
+impl ValidatorKeys {
+    /// Default voting power of a validator.
+    pub const DEFAULT_WEIGHT: u64 = 1;
+
+    fn default_weight() -> u64 {
+        Self::DEFAULT_WEIGHT
+    }
+
+    fn is_default_weight(weight: &u64) -> bool {
+        *weight == Self::DEFAULT_WEIGHT
+    }
 }
 
 /// Exonum blockchain global configuration. Services
@@ -63,10 +82,10 @@ pub struct StoredConfiguration {
     pub validator_keys: Vec<ValidatorKeys>,
     /// Consensus algorithm parameters.
     pub consensus: ConsensusConfig,
-    /// Number of votes required to commit the new configuration.
+    /// Voting power required to commit the new configuration.
     /// This value should be greater than 2/3 and less or equal to the
-    /// validators count.
-    pub majority_count: Option<u16>,
+    /// total weight of validators.
+    pub majority_count: Option<u64>,
     /// Services specific variables.
     /// Keys are `service_name` from the `Service` trait and values are the serialized JSON.
     #[serde(default)]
@@ -122,6 +141,147 @@ pub struct ConsensusConfig {
     /// in a block if the transaction pool is almost empty, and create blocks faster when there are
     /// enough transactions in the pool.
     pub propose_timeout_threshold: u32,
+    /// Strategy used to choose the leader of each round.
+    ///
+    /// Round-robin election is used if the field is omitted.
+    #[serde(default, skip_serializing_if = "LeaderElection::is_round_robin")]
+    pub leader_election: LeaderElection,
+    /// Maximal interval between blocks while the transactions pool is empty.
+    ///
+    /// If set, the leader doesn't propose empty blocks until this interval elapses since
+    /// the start of the current height, so that an empty block is created only as a heartbeat.
+    /// Once a transaction arrives, the block is proposed after `min_propose_timeout`, so that
+    /// transactions arriving together are committed in the same block. `Status` messages and
+    /// peer exchange requests are still sent every `status_timeout` and `peers_timeout`.
+    /// If the field is omitted, blocks are created in each round regardless of the pool size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_empty_block_interval: Option<Milliseconds>,
+    /// Number of the latest blocks which are sent in the compact form.
+    ///
+    /// If set, a node responds to `BlockRequest` for one of the latest `compact_block_depth`
+    /// blocks with `CompactBlockResponse`, which contains only transaction hashes. Lagging
+    /// nodes take the transactions from their pools and request only the missing ones.
+    /// If the field is omitted, blocks are always sent with the transaction bodies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compact_block_depth: Option<u64>,
+    /// Consensus protocol version required from the peers, see `messages::PROTOCOL_VERSIONS`.
+    ///
+    /// The version takes effect at the `actual_from` height of the configuration, so that
+    /// validators switch to the new behaviour simultaneously once all of them are upgraded.
+    /// Peers which don't support the version are disconnected. If the field is omitted,
+    /// a peer only needs to have a common protocol version with the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u8>,
+}
+
+/// Strategy of the leader election.
+///
+/// The leader of a round is the only validator allowed to send a `Propose` in it. Every
+/// strategy is deterministic given the committed state of the blockchain, so all validators
+/// agree on the leader of each round. The strategy is a part of the `ConsensusConfig` and
+/// can be changed with the configuration updater service.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LeaderElection {
+    /// Validators take turns based on the height and round.
+    RoundRobin,
+    /// Round-robin over validators which have not missed their proposals recently.
+    ///
+    /// A validator is considered to have missed a proposal if it was the leader of a round
+    /// preceding the round in which the block has been committed, and has not proposed
+    /// the block itself. Such validators are skipped
+    /// until `window` blocks are committed without them missing a proposal again. If all
+    /// validators are skipped, the strategy falls back to the plain round-robin.
+    SkipMissed {
+        /// Number of latest blocks taken into account.
+        window: u64,
+    },
+    /// Leaders are chosen pseudo-randomly with the probability proportional to the `weight`
+    /// of validators.
+    Weighted,
+}
+
+impl LeaderElection {
+    /// Returns `true` if this is the round-robin strategy.
+    pub fn is_round_robin(&self) -> bool {
+        *self == LeaderElection::RoundRobin
+    }
+
+    /// Returns the leader of the given round at the given height.
+    ///
+    /// `inactive` contains consensus keys of validators which have missed their proposals
+    /// recently; it is used only by the `SkipMissed` strategy.
+    pub fn leader(
+        &self,
+        validators: &[ValidatorKeys],
+        inactive: &HashSet<PublicKey>,
+        height: Height,
+        round: Round,
+    ) -> ValidatorId {
+        let slot = height.0 + u64::from(round.0);
+        let round_robin = ValidatorId((slot % validators.len() as u64) as u16);
+
+        match *self {
+            LeaderElection::RoundRobin => round_robin,
+            LeaderElection::SkipMissed { .. } => {
+                let active = validators
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, keys)| !inactive.contains(&keys.consensus_key))
+                    .map(|(id, _)| ValidatorId(id as u16))
+                    .collect::<Vec<_>>();
+                if active.is_empty() {
+                    round_robin
+                } else {
+                    active[(slot % active.len() as u64) as usize]
+                }
+            }
+            LeaderElection::Weighted => {
+                let total = validators
+                    .iter()
+                    .fold(Some(0_u64), |acc, keys| acc.and_then(|x| x.checked_add(keys.weight)));
+                // Weights are checked during the configuration validation, but the strategy
+                // should not panic on a configuration built by hand.
+                let total = match total {
+                    Some(0) | None => return round_robin,
+                    Some(total) => total,
+                };
+                let seed = {
+                    let mut bytes = [0; 12];
+                    LittleEndian::write_u64(&mut bytes[0..8], height.0);
+                    LittleEndian::write_u32(&mut bytes[8..12], round.0);
+                    hash(&bytes)
+                };
+                let mut point = LittleEndian::read_u64(&seed.as_ref()[0..8]) % total;
+                for (id, keys) in validators.iter().enumerate() {
+                    if point < keys.weight {
+                        return ValidatorId(id as u16);
+                    }
+                    point -= keys.weight;
+                }
+                round_robin
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            LeaderElection::RoundRobin | LeaderElection::Weighted => Ok(()),
+            LeaderElection::SkipMissed { window } => {
+                if window == 0 {
+                    Err("SkipMissed leader election window should not be equal to zero".into())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+impl Default for LeaderElection {
+    fn default() -> Self {
+        LeaderElection::RoundRobin
+    }
 }
 
 impl ConsensusConfig {
@@ -152,6 +312,16 @@ impl ConsensusConfig {
                 self.txs_block_limit, MIN_TXS_BLOCK_LIMIT, MAX_TXS_BLOCK_LIMIT
             );
         }
+
+        if let Some(interval) = self.max_empty_block_interval {
+            if interval <= self.round_timeout {
+                warn!(
+                    "max_empty_block_interval ({}) does not exceed round_timeout ({}), \
+                     so empty blocks are not actually suppressed",
+                    interval, self.round_timeout
+                );
+            }
+        }
     }
 }
 
@@ -166,11 +336,20 @@ impl Default for ConsensusConfig {
             min_propose_timeout: 10,
             max_propose_timeout: 200,
             propose_timeout_threshold: 500,
+            leader_election: LeaderElection::RoundRobin,
+            max_empty_block_interval: None,
+            compact_block_depth: None,
+            protocol_version: None,
         }
     }
 }
 
 impl StoredConfiguration {
+    /// Returns the total voting power of validators.
+    pub fn total_weight(&self) -> u64 {
+        self.validator_keys.iter().map(|keys| keys.weight).sum()
+    }
+
     /// Tries to serialize the given configuration into a UTF-8 encoded JSON.
     /// The method returns either the result of execution or an error.
     pub fn try_serialize(&self) -> Result<Vec<u8>, JsonError> {
@@ -197,6 +376,22 @@ impl StoredConfiguration {
             }
         }
 
+        // Check validator weights.
+        {
+            let mut total_weight = 0_u64;
+            for k in &config.validator_keys {
+                if k.weight == 0 {
+                    return Err(JsonError::custom(format!(
+                        "Validator weight should be positive: consensus_key = {:?}",
+                        k.consensus_key
+                    )));
+                }
+                total_weight = total_weight.checked_add(k.weight).ok_or_else(|| {
+                    JsonError::custom("Total weight of validators overflows u64")
+                })?;
+            }
+        }
+
         // Check timeouts.
         if config.consensus.min_propose_timeout > config.consensus.max_propose_timeout {
             return Err(JsonError::custom(format!(
@@ -220,6 +415,35 @@ impl StoredConfiguration {
             ));
         }
 
+        // Check leader election.
+        config
+            .consensus
+            .leader_election
+            .validate()
+            .map_err(JsonError::custom)?;
+
+        // Check empty blocks suppression.
+        if let Some(interval) = config.consensus.max_empty_block_interval {
+            if interval == 0 {
+                return Err(JsonError::custom(
+                    "max_empty_block_interval should not be equal to zero",
+                ));
+            }
+            if let LeaderElection::SkipMissed { .. } = config.consensus.leader_election {
+                return Err(JsonError::custom(
+                    "SkipMissed leader election cannot be used together with \
+                     max_empty_block_interval, because leaders skip rounds with an empty pool",
+                ));
+            }
+        }
+
+        // Check compact blocks.
+        if config.consensus.compact_block_depth == Some(0) {
+            return Err(JsonError::custom(
+                "compact_block_depth should not be equal to zero",
+            ));
+        }
+
         Ok(config)
     }
 }
@@ -304,10 +528,40 @@ mod tests {
         configuration.validator_keys.push(ValidatorKeys {
             consensus_key: PublicKey::zero(),
             service_key: PublicKey::zero(),
+            weight: ValidatorKeys::DEFAULT_WEIGHT,
         });
         serialize_deserialize(&configuration);
     }
 
+    #[test]
+    fn validator_weights() {
+        let mut configuration = create_test_configuration();
+        let serialized = String::from_utf8(configuration.try_serialize().unwrap()).unwrap();
+        assert!(!serialized.contains("weight"));
+        assert_eq!(configuration.total_weight(), 3);
+
+        configuration.validator_keys[0].weight = 5;
+        let deserialized = serialize_deserialize(&configuration);
+        assert_eq!(deserialized, configuration);
+        assert_eq!(deserialized.total_weight(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "Validator weight should be positive")]
+    fn zero_validator_weight() {
+        let mut configuration = create_test_configuration();
+        configuration.validator_keys[1].weight = 0;
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    #[should_panic(expected = "Total weight of validators overflows u64")]
+    fn validator_weights_overflow() {
+        let mut configuration = create_test_configuration();
+        configuration.validator_keys[0].weight = u64::max_value();
+        serialize_deserialize(&configuration);
+    }
+
     #[test]
     #[should_panic(expected = "Invalid propose timeouts: min_propose_timeout should be less or")]
     fn min_max_propose_timeouts() {
@@ -334,11 +588,141 @@ mod tests {
         serialize_deserialize(&configuration);
     }
 
+    #[test]
+    fn leader_election_toml() {
+        let mut original = create_test_configuration();
+        original.consensus.leader_election = LeaderElection::Weighted;
+        let toml = toml::to_string(&original).unwrap();
+        let deserialized: StoredConfiguration = toml::from_str(&toml).unwrap();
+        assert_eq!(original, deserialized);
+
+        original.consensus.leader_election = LeaderElection::SkipMissed { window: 10 };
+        assert_eq!(original, serialize_deserialize(&original));
+    }
+
+    #[test]
+    #[should_panic(expected = "SkipMissed leader election window should not be equal to zero")]
+    fn invalid_skip_missed_window() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.leader_election = LeaderElection::SkipMissed { window: 0 };
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    fn max_empty_block_interval_toml() {
+        let mut original = create_test_configuration();
+        original.consensus.max_empty_block_interval = Some(60_000);
+        let toml = toml::to_string(&original).unwrap();
+        let deserialized: StoredConfiguration = toml::from_str(&toml).unwrap();
+        assert_eq!(original, deserialized);
+        assert_eq!(original, serialize_deserialize(&original));
+    }
+
+    #[test]
+    #[should_panic(expected = "max_empty_block_interval should not be equal to zero")]
+    fn invalid_max_empty_block_interval() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.max_empty_block_interval = Some(0);
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    fn compact_block_depth_toml() {
+        let mut original = create_test_configuration();
+        original.consensus.compact_block_depth = Some(2);
+        let toml = toml::to_string(&original).unwrap();
+        let deserialized: StoredConfiguration = toml::from_str(&toml).unwrap();
+        assert_eq!(original, deserialized);
+        assert_eq!(original, serialize_deserialize(&original));
+    }
+
+    #[test]
+    fn protocol_version_toml() {
+        let mut original = create_test_configuration();
+        original.consensus.protocol_version = Some(1);
+        let toml = toml::to_string(&original).unwrap();
+        let deserialized: StoredConfiguration = toml::from_str(&toml).unwrap();
+        assert_eq!(original, deserialized);
+        assert_eq!(original, serialize_deserialize(&original));
+
+        // The field is omitted if not set, so that hashes of the existing configurations
+        // don't change.
+        let original = create_test_configuration();
+        assert!(!toml::to_string(&original).unwrap().contains("protocol_version"));
+    }
+
+    #[test]
+    #[should_panic(expected = "compact_block_depth should not be equal to zero")]
+    fn invalid_compact_block_depth() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.compact_block_depth = Some(0);
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    #[should_panic(expected = "SkipMissed leader election cannot be used together with")]
+    fn max_empty_block_interval_with_skip_missed() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.max_empty_block_interval = Some(60_000);
+        configuration.consensus.leader_election = LeaderElection::SkipMissed { window: 10 };
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    fn leader_election_strategies() {
+        let validators = create_test_configuration().validator_keys;
+        let mut inactive = HashSet::new();
+
+        let round_robin = LeaderElection::RoundRobin;
+        assert_eq!(
+            round_robin.leader(&validators, &inactive, Height(1), Round(1)),
+            ValidatorId(2)
+        );
+        assert_eq!(
+            round_robin.leader(&validators, &inactive, Height(1), Round(2)),
+            ValidatorId(0)
+        );
+
+        let skip_missed = LeaderElection::SkipMissed { window: 1 };
+        inactive.insert(validators[0].consensus_key);
+        for round in Round::first().iter_to(Round(10)) {
+            assert_ne!(
+                skip_missed.leader(&validators, &inactive, Height(1), round),
+                ValidatorId(0)
+            );
+        }
+        inactive.extend(validators.iter().map(|keys| keys.consensus_key));
+        assert_eq!(
+            skip_missed.leader(&validators, &inactive, Height(1), Round(2)),
+            ValidatorId(0)
+        );
+
+        let mut validators = validators;
+        validators[1].weight = u64::max_value() / 2;
+        let weighted = LeaderElection::Weighted;
+        for round in Round::first().iter_to(Round(10)) {
+            assert_eq!(
+                weighted.leader(&validators, &inactive, Height(1), round),
+                ValidatorId(1)
+            );
+        }
+
+        // Zero weights fall back to the round-robin.
+        for keys in &mut validators {
+            keys.weight = 0;
+        }
+        assert_eq!(
+            weighted.leader(&validators, &inactive, Height(1), Round(1)),
+            ValidatorId(2)
+        );
+    }
+
     fn create_test_configuration() -> StoredConfiguration {
         let validator_keys = (1..4)
             .map(|i| ValidatorKeys {
                 consensus_key: gen_keypair_from_seed(&Seed::new([i; 32])).0,
                 service_key: gen_keypair_from_seed(&Seed::new([i * 10; 32])).0,
+                weight: ValidatorKeys::DEFAULT_WEIGHT,
             })
             .collect();
 
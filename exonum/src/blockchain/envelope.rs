@@ -0,0 +1,116 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transaction envelopes, i.e., transactions with an expiration height and a nonce.
+
+use serde_json::Value;
+
+use std::error::Error;
+
+use encoding::{self, Offset};
+use encoding::serialize::WriteBufferWrapper;
+use encoding::serialize::json::ExonumJson;
+use helpers::Height;
+use messages::{Message, RawMessage, TransactionEnvelope, CONSENSUS as CORE_SERVICE,
+               TRANSACTION_ENVELOPE_MESSAGE_ID};
+use storage::Fork;
+use super::{ExecutionResult, Transaction};
+
+/// Parsed `TransactionEnvelope` message together with the wrapped transaction.
+#[derive(Debug)]
+pub(crate) struct Envelope {
+    message: TransactionEnvelope,
+    transaction: Box<Transaction>,
+}
+
+impl Envelope {
+    /// Checks whether the raw message is a `TransactionEnvelope`.
+    pub(crate) fn is_envelope(raw: &RawMessage) -> bool {
+        raw.service_id() == CORE_SERVICE && raw.message_type() == TRANSACTION_ENVELOPE_MESSAGE_ID
+    }
+
+    /// Returns the last height at which the transaction can be committed, or `None`
+    /// if the raw message is not a valid `TransactionEnvelope`.
+    pub(crate) fn valid_until(raw: &RawMessage) -> Option<Height> {
+        if !Self::is_envelope(raw) {
+            return None;
+        }
+        TransactionEnvelope::from_raw(raw.clone())
+            .ok()
+            .map(|envelope| envelope.valid_until())
+    }
+
+    /// Creates an envelope from the message and the parsed wrapped transaction.
+    pub(crate) fn new(message: TransactionEnvelope, transaction: Box<Transaction>) -> Self {
+        Envelope {
+            message,
+            transaction,
+        }
+    }
+
+    /// Returns the envelope message.
+    pub(crate) fn message(&self) -> &TransactionEnvelope {
+        &self.message
+    }
+
+    /// Returns the wrapped transaction.
+    pub(crate) fn transaction(&self) -> &Transaction {
+        self.transaction.as_ref()
+    }
+}
+
+impl Message for Envelope {
+    fn from_raw(_raw: RawMessage) -> Result<Self, encoding::Error> {
+        Err(encoding::Error::Basic(
+            "Envelope should be parsed with `Blockchain::tx_from_raw`".into(),
+        ))
+    }
+
+    fn raw(&self) -> &RawMessage {
+        self.message.raw()
+    }
+}
+
+impl ExonumJson for Envelope {
+    fn deserialize_field<B: WriteBufferWrapper>(
+        _value: &Value,
+        _buffer: &mut B,
+        _from: Offset,
+        _to: Offset,
+    ) -> Result<(), Box<Error>> {
+        Err("Envelope cannot be deserialized as a field".into())
+    }
+
+    fn serialize_field(&self) -> Result<Value, Box<Error + Send + Sync>> {
+        let mut value = self.message.serialize_field()?;
+        value["body"]["transaction"] = self.transaction.serialize_field()?;
+        Ok(value)
+    }
+}
+
+impl Transaction for Envelope {
+    fn verify(&self) -> bool {
+        self.message.verify_signature(self.message.from())
+            && self.transaction.raw().verify_signature(self.message.from())
+            && self.transaction.verify()
+    }
+
+    /// Executes the wrapped transaction.
+    ///
+    /// Note that the blockchain does not call this method directly: the expiration height
+    /// and the nonce of the envelope are checked before executing the wrapped transaction.
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        self.transaction.execute(fork)
+    }
+}
@@ -35,7 +35,7 @@
 pub use self::block::{Block, BlockProof, SCHEMA_MAJOR_VERSION};
 pub use self::schema::{Schema, TxLocation};
 pub use self::genesis::GenesisConfig;
-pub use self::config::{ConsensusConfig, StoredConfiguration, ValidatorKeys};
+pub use self::config::{ConsensusConfig, LeaderElection, StoredConfiguration, ValidatorKeys};
 pub use self::service::{ApiContext, Service, ServiceContext, SharedNodeState};
 pub use self::transaction::{ExecutionError, ExecutionResult, Transaction, TransactionError,
                             TransactionErrorType, TransactionResult, TransactionSet};
@@ -49,17 +49,25 @@ use failure;
 
 use std::{fmt, iter, mem, panic};
 use std::sync::Arc;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::SocketAddr;
 use std::error::Error as StdError;
+use std::time::Instant;
 
 use crypto::{self, CryptoHash, Hash, PublicKey, SecretKey};
-use messages::{CONSENSUS as CORE_SERVICE, Connect, Precommit, RawMessage};
+use messages::{CONSENSUS as CORE_SERVICE, Connect, Message, Precommit, RawMessage,
+               TransactionBatch, TransactionEnvelope};
 use storage::{Database, Error, Fork, Patch, Snapshot};
-use helpers::{Height, Round, ValidatorId};
-use node::ApiSender;
+use helpers::{metrics, Height, Round, ValidatorId};
+use api::{ApiScope, AsyncMount, IronBackend, OpenApi, ServiceApiBuilder, Subscriptions};
+use node::{ApiSender, Keystore};
 use encoding::Error as MessageError;
+use self::batch::Batch;
 
+pub(crate) use self::envelope::Envelope;
+
+mod batch;
+mod envelope;
 mod block;
 mod schema;
 mod genesis;
@@ -78,6 +86,10 @@ pub struct Blockchain {
     service_map: Arc<VecMap<Box<Service>>>,
     service_keypair: (PublicKey, SecretKey),
     api_sender: ApiSender,
+    keystore: Keystore,
+    subscriptions: Subscriptions,
+    metrics: metrics::Registry,
+    tx_execution_seconds: Arc<VecMap<metrics::Histogram>>,
 }
 
 impl Blockchain {
@@ -101,11 +113,25 @@ impl Blockchain {
             service_map.insert(id, service);
         }
 
+        let metrics = metrics::Registry::new();
+        let tx_execution_seconds = service_map
+            .keys()
+            .map(|id| {
+                let service_id = id.to_string();
+                let labels = [("service_id", service_id.as_str())];
+                (id, metrics.histogram("blockchain.tx_execution_seconds", &labels))
+            })
+            .collect();
+
         Blockchain {
             db: storage.into(),
             service_map: Arc::new(service_map),
             service_keypair: (service_public_key, service_secret_key),
             api_sender,
+            keystore: Keystore::new(),
+            subscriptions: Subscriptions::new(),
+            metrics,
+            tx_execution_seconds: Arc::new(tx_execution_seconds),
         }
     }
 
@@ -142,7 +168,21 @@ impl Blockchain {
     ///
     /// - Blockchain has a service with the `service_id` of the given raw message.
     /// - Service can deserialize the given raw message.
+    ///
+    /// `TransactionBatch` and `TransactionEnvelope` messages are parsed by the blockchain
+    /// itself; the wrapped transactions are parsed by the corresponding services.
     pub fn tx_from_raw(&self, raw: RawMessage) -> Result<Box<Transaction>, MessageError> {
+        if Batch::is_batch(&raw) {
+            return self.batch_from_raw(raw).map(|batch| Box::new(batch) as Box<Transaction>);
+        }
+        if Envelope::is_envelope(&raw) {
+            return self.envelope_from_raw(raw)
+                .map(|envelope| Box::new(envelope) as Box<Transaction>);
+        }
+        self.service_tx_from_raw(raw)
+    }
+
+    fn service_tx_from_raw(&self, raw: RawMessage) -> Result<Box<Transaction>, MessageError> {
         let id = raw.service_id() as usize;
         let service = self.service_map
             .get(id)
@@ -150,12 +190,80 @@ impl Blockchain {
         service.tx_from_raw(raw)
     }
 
+    fn batch_from_raw(&self, raw: RawMessage) -> Result<Batch, MessageError> {
+        let message = TransactionBatch::from_raw(raw)?;
+        let transactions = message
+            .transactions()
+            .into_iter()
+            .map(|raw| {
+                if Batch::is_batch(&raw) || Envelope::is_envelope(&raw) {
+                    Err(MessageError::from(
+                        "Batches cannot contain batches or envelopes.",
+                    ))
+                } else {
+                    self.tx_from_raw(raw)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Batch::new(message, transactions))
+    }
+
+    fn envelope_from_raw(&self, raw: RawMessage) -> Result<Envelope, MessageError> {
+        let message = TransactionEnvelope::from_raw(raw)?;
+        let raw = message.transaction();
+        if Batch::is_batch(&raw) || Envelope::is_envelope(&raw) {
+            return Err(MessageError::from(
+                "Envelopes cannot contain batches or envelopes.",
+            ));
+        }
+        let transaction = self.service_tx_from_raw(raw)?;
+        Ok(Envelope::new(message, transaction))
+    }
+
+    /// Returns the hashes of the transactions wrapped into the batch or the envelope.
+    /// Other transactions do not wrap any transactions.
+    pub(crate) fn wrapped_tx_hashes(raw: &RawMessage) -> Vec<Hash> {
+        if Batch::is_batch(raw) {
+            TransactionBatch::from_raw(raw.clone())
+                .map(|batch| batch.transactions().iter().map(RawMessage::hash).collect())
+                .unwrap_or_default()
+        } else if Envelope::is_envelope(raw) {
+            TransactionEnvelope::from_raw(raw.clone())
+                .map(|envelope| vec![envelope.transaction().hash()])
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns a transaction which is included into the list both directly and within
+    /// a batch or an envelope from the same list, if any. Such a list cannot be executed
+    /// as a block, since the transaction would be recorded in the block twice.
+    pub(crate) fn find_wrapped_duplicate(&self, tx_hashes: &[Hash]) -> Option<Hash> {
+        let snapshot = self.snapshot();
+        let transactions = Schema::new(&snapshot).transactions();
+        let direct = tx_hashes.iter().collect::<HashSet<_>>();
+        tx_hashes
+            .iter()
+            .filter_map(|hash| transactions.get(hash))
+            .flat_map(|raw| Blockchain::wrapped_tx_hashes(&raw))
+            .find(|hash| direct.contains(hash))
+    }
+
     /// Commits changes from the patch to the blockchain storage.
     /// See [`Fork`](../storage/struct.Fork.html) for details.
     pub fn merge(&mut self, patch: Patch) -> Result<(), Error> {
         self.db.merge(patch)
     }
 
+    /// Commits changes from the patch to the blockchain storage and waits until they are
+    /// written to the disk.
+    /// See [`Database::merge_sync`](../storage/trait.Database.html#tymethod.merge_sync)
+    /// for details.
+    pub fn merge_sync(&mut self, patch: Patch) -> Result<(), Error> {
+        self.db.merge_sync(patch)
+    }
+
     /// Returns the hash of the latest committed block.
     ///
     /// # Panics
@@ -269,8 +377,8 @@ impl Blockchain {
             // Save & execute transactions.
             for (index, hash) in tx_hashes.iter().enumerate() {
                 self.execute_transaction(*hash, height, index, &mut fork)
-                    // Execution could fail if the transaction
-                    // cannot be deserialized or it isn't in the pool.
+                    // Execution could fail if the transaction cannot be deserialized,
+                    // it isn't in the pool or it has already been committed in the block.
                     .expect("Transaction not found in the database.");
             }
 
@@ -354,62 +462,175 @@ impl Blockchain {
         index: usize,
         fork: &mut Fork,
     ) -> Result<(), failure::Error> {
-        let tx = {
-            let schema = Schema::new(&fork);
-
-            let tx = schema
-                .transactions()
-                .get(&tx_hash)
-                .ok_or_else(|| failure::err_msg("BUG: Cannot find transaction in database."))?;
-
-            self.tx_from_raw(tx).or_else(|error| {
-                Err(failure::err_msg(format!(
-                    "{}, tx: {:?}",
-                    error.description(),
-                    tx_hash
-                )))
-            })?
-        };
-
-        fork.checkpoint();
+        let raw = Schema::new(&fork)
+            .transactions()
+            .get(&tx_hash)
+            .ok_or_else(|| failure::err_msg("BUG: Cannot find transaction in database."))?;
+        let location = TxLocation::new(height, index as u64);
 
-        let catch_result = panic::catch_unwind(panic::AssertUnwindSafe(|| tx.execute(fork)));
+        // Proposes including a transaction both directly and within a batch or an envelope
+        // are rejected by the validators, see `find_wrapped_duplicate`.
+        if Schema::new(&fork)
+            .transactions_locations()
+            .contains(&tx_hash)
+        {
+            bail!("BUG: Transaction {:?} has already been committed.", tx_hash);
+        }
 
-        let tx_result = match catch_result {
-            Ok(execution_result) => {
-                match execution_result {
-                    Ok(()) => {
-                        fork.commit();
-                    }
-                    Err(ref e) => {
-                        // Unlike panic, transaction failure isn't that rare, so logging the
-                        // whole transaction body is an overkill: it can be relatively big.
-                        info!("{:?} transaction execution failed: {:?}", tx_hash, e);
-                        fork.rollback();
-                    }
-                }
-                execution_result.map_err(TransactionError::from)
-            }
-            Err(err) => {
-                if err.is::<Error>() {
-                    // Continue panic unwind if the reason is StorageError.
-                    panic::resume_unwind(err);
-                }
+        let tx_result = if Batch::is_batch(&raw) {
+            let batch = self.batch_from_raw(raw)
+                .map_err(|error| parse_error(&error, &tx_hash))?;
+            self.execute_batch(&batch, location, fork)
+        } else if Envelope::is_envelope(&raw) {
+            let envelope = self.envelope_from_raw(raw)
+                .map_err(|error| parse_error(&error, &tx_hash))?;
+            self.execute_envelope(&envelope, location, fork)
+        } else {
+            let tx = self.tx_from_raw(raw)
+                .map_err(|error| parse_error(&error, &tx_hash))?;
+
+            fork.checkpoint();
+            let tx_result = self.execute_catching_panics(tx.as_ref(), fork);
+            if tx_result.is_ok() {
+                fork.commit();
+            } else {
                 fork.rollback();
-                error!("{:?} transaction execution panicked: {:?}", tx, err);
-                Err(TransactionError::from_panic(&err))
             }
+            tx_result
         };
 
         let mut schema = Schema::new(fork);
         schema.transaction_results_mut().put(&tx_hash, tx_result);
         schema.commit_transaction(&tx_hash);
         schema.block_transactions_mut(height).push(tx_hash);
-        let location = TxLocation::new(height, index as u64);
         schema.transactions_locations_mut().put(&tx_hash, location);
         Ok(())
     }
 
+    // Executes inner transactions of the batch under a single checkpoint and records
+    // their results. Returns the result of the batch as a whole.
+    fn execute_batch(
+        &self,
+        batch: &Batch,
+        location: TxLocation,
+        fork: &mut Fork,
+    ) -> TransactionResult {
+        let hashes = batch
+            .transactions()
+            .iter()
+            .map(|tx| tx.hash())
+            .collect::<Vec<_>>();
+
+        let committed = {
+            let schema = Schema::new(&fork);
+            let locations = schema.transactions_locations();
+            hashes.iter().find(|hash| locations.contains(hash)).cloned()
+        };
+        if let Some(hash) = committed {
+            info!(
+                "{:?} batch contains already committed transaction {:?}",
+                batch.hash(),
+                hash
+            );
+            return Err(TransactionError::batch_rollback(Some(format!(
+                "Transaction {:?} is already committed",
+                hash
+            ))));
+        }
+
+        fork.checkpoint();
+        let mut results = Vec::with_capacity(hashes.len());
+        let mut batch_result = Ok(());
+        for tx in batch.transactions() {
+            let tx_result = self.execute_catching_panics(tx.as_ref(), fork);
+            if let Err(ref e) = tx_result {
+                batch_result = Err(e.clone());
+            }
+            results.push(tx_result);
+            if batch_result.is_err() {
+                break;
+            }
+        }
+
+        if batch_result.is_ok() {
+            fork.commit();
+        } else {
+            fork.rollback();
+        }
+
+        let mut schema = Schema::new(fork);
+        for (index, hash) in hashes.iter().enumerate() {
+            let tx_result = match (results.get(index), &batch_result) {
+                (Some(&Err(ref e)), _) => Err(e.clone()),
+                (_, &Err(_)) => Err(TransactionError::batch_rollback(None)),
+                (_, &Ok(())) => Ok(()),
+            };
+            let raw = batch.transactions()[index].raw().clone();
+            schema.transactions_mut().put(hash, raw);
+            schema.transaction_results_mut().put(hash, tx_result);
+            schema.commit_transaction(hash);
+            schema.transactions_locations_mut().put(hash, location);
+        }
+        batch_result
+    }
+
+    // Checks the expiration height and the nonce of the envelope, then executes
+    // the wrapped transaction and records its result.
+    fn execute_envelope(
+        &self,
+        envelope: &Envelope,
+        location: TxLocation,
+        fork: &mut Fork,
+    ) -> TransactionResult {
+        let message = envelope.message();
+        let tx = envelope.transaction();
+        let hash = tx.hash();
+
+        {
+            let schema = Schema::new(&fork);
+            if location.block_height() > message.valid_until() {
+                return Err(TransactionError::expired(Some(format!(
+                    "Transaction is valid until height {}",
+                    message.valid_until()
+                ))));
+            }
+            let expected_nonce = schema.next_envelope_nonce(message.from());
+            if message.nonce() != expected_nonce {
+                return Err(TransactionError::invalid_nonce(Some(format!(
+                    "Expected nonce {}, got {}",
+                    expected_nonce,
+                    message.nonce()
+                ))));
+            }
+            if schema.transactions_locations().contains(&hash) {
+                return Err(TransactionError::invalid_nonce(Some(format!(
+                    "Transaction {:?} is already committed",
+                    hash
+                ))));
+            }
+        }
+
+        // The nonce is used up regardless of the execution result.
+        Schema::new(&mut *fork)
+            .envelope_nonces_mut()
+            .put(message.from(), message.nonce() + 1);
+
+        fork.checkpoint();
+        let tx_result = self.execute_catching_panics(tx, fork);
+        if tx_result.is_ok() {
+            fork.commit();
+        } else {
+            fork.rollback();
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.transactions_mut().put(&hash, tx.raw().clone());
+        schema.transaction_results_mut().put(&hash, tx_result.clone());
+        schema.commit_transaction(&hash);
+        schema.transactions_locations_mut().put(&hash, location);
+        tx_result
+    }
+
     /// Commits to the blockchain a new block with the indicated changes (patch),
     /// hash and Precommit messages. After that invokes `handle_commit`
     /// for each service in the increasing order of their identifiers.
@@ -432,10 +653,22 @@ impl Blockchain {
 
             {
                 let mut schema = Schema::new(&mut fork);
+                let mut round = None;
                 for precommit in precommits {
+                    round = Some(precommit.round());
                     schema.precommits_mut(&block_hash).push(precommit.clone());
                 }
 
+                // Remember validators which have not proposed a block before the round
+                // in which the block has been committed.
+                if let Some(round) = round {
+                    let block = schema
+                        .blocks()
+                        .get(&block_hash)
+                        .expect("Committed block is not in the patch.");
+                    schema.record_missed_proposals(block.proposer_id(), block.height(), round);
+                }
+
                 // Consensus messages cache is useful only during one height, so it should be
                 // cleared when a new height is achieved.
                 schema.consensus_messages_cache_mut().clear();
@@ -449,38 +682,81 @@ impl Blockchain {
             self.service_keypair.1.clone(),
             self.api_sender.clone(),
             self.fork(),
-        );
+        ).with_subscriptions(self.subscriptions.clone());
         // Invokes `handle_commit` for each service in order of their identifiers
         for service in self.service_map.values() {
             service.handle_commit(&context);
         }
+        self.subscriptions.notify_commit(&*self.snapshot());
         Ok(())
     }
 
-    /// Returns the `Mount` object that aggregates public API handlers.
-    pub fn mount_public_api(&self) -> Mount {
+    /// Returns the handler that aggregates public API handlers of the services.
+    pub fn public_api(&self) -> AsyncMount {
         let context = self.api_context();
-        let mut mount = Mount::new();
+        let mut mount = AsyncMount::new();
         for service in self.service_map.values() {
-            if let Some(handler) = service.public_api_handler(&context) {
-                mount.mount(service.service_name(), handler);
+            let mut scope = self.service_api_scopes(service.as_ref(), &context).0;
+            if let Some(handler) = service.public_api(&context) {
+                scope.set_fallback(handler);
+            }
+            if !scope.is_empty() {
+                mount.mount(service.service_name(), scope);
             }
         }
         mount
     }
 
-    /// Returns the `Mount` object that aggregates private API handlers.
-    pub fn mount_private_api(&self) -> Mount {
+    /// Returns the handler that aggregates private API handlers of the services.
+    pub fn private_api(&self) -> AsyncMount {
         let context = self.api_context();
-        let mut mount = Mount::new();
+        let mut mount = AsyncMount::new();
         for service in self.service_map.values() {
-            if let Some(handler) = service.private_api_handler(&context) {
-                mount.mount(service.service_name(), handler);
+            let mut scope = self.service_api_scopes(service.as_ref(), &context).1;
+            if let Some(handler) = service.private_api(&context) {
+                scope.set_fallback(handler);
+            }
+            if !scope.is_empty() {
+                mount.mount(service.service_name(), scope);
             }
         }
         mount
     }
 
+    /// Adds the typed public endpoints of the services mounted on the given path prefix
+    /// to the OpenAPI document.
+    pub fn describe_public_api(&self, prefix: &str, openapi: &mut OpenApi) {
+        let context = self.api_context();
+        for service in self.service_map.values() {
+            let scope = self.service_api_scopes(service.as_ref(), &context).0;
+            openapi.add_scope(&format!("{}/{}", prefix, service.service_name()), &scope);
+        }
+    }
+
+    fn service_api_scopes(
+        &self,
+        service: &Service,
+        context: &ApiContext,
+    ) -> (ApiScope, ApiScope) {
+        let mut builder = ServiceApiBuilder::new();
+        service.wire_api(context, &mut builder);
+        builder.into_scopes()
+    }
+
+    /// Returns the `Mount` object that aggregates public API handlers.
+    pub fn mount_public_api(&self) -> Mount {
+        let mut mount = Mount::new();
+        mount.mount("", IronBackend::new(self.public_api()));
+        mount
+    }
+
+    /// Returns the `Mount` object that aggregates private API handlers.
+    pub fn mount_private_api(&self) -> Mount {
+        let mut mount = Mount::new();
+        mount.mount("", IronBackend::new(self.private_api()));
+        mount
+    }
+
     fn api_context(&self) -> ApiContext {
         ApiContext::from_parts(
             self,
@@ -490,6 +766,31 @@ impl Blockchain {
         )
     }
 
+    /// Returns the keystore with the signing keys available to the service APIs.
+    pub fn keystore(&self) -> &Keystore {
+        &self.keystore
+    }
+
+    /// Returns the subscriptions of the WebSocket API clients.
+    pub fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
+    /// Returns the metrics of the node exported by the private API.
+    pub fn metrics(&self) -> &metrics::Registry {
+        &self.metrics
+    }
+
+    /// Returns the sender of the requests to the node.
+    pub(crate) fn api_sender(&self) -> &ApiSender {
+        &self.api_sender
+    }
+
+    /// Sets the keystore with the signing keys available to the service APIs.
+    pub fn set_keystore(&mut self, keystore: Keystore) {
+        self.keystore = keystore;
+    }
+
     /// Saves the `Connect` message from a peer to the cache.
     pub fn save_peer(&mut self, pubkey: &PublicKey, peer: Connect) {
         let mut fork = self.fork();
@@ -550,6 +851,41 @@ impl Blockchain {
         self.merge(fork.into_patch())
             .expect("Unable to save messages to the consensus cache");
     }
+
+    // Executes the transaction catching panics. The caller is responsible for discarding
+    // changes of the failed transaction.
+    fn execute_catching_panics(&self, tx: &Transaction, fork: &mut Fork) -> TransactionResult {
+        let tx_hash = tx.hash();
+        let start = Instant::now();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| tx.execute(fork)));
+        if let Some(histogram) = self.tx_execution_seconds
+            .get(tx.raw().service_id() as usize)
+        {
+            histogram.observe(metrics::seconds(start.elapsed()));
+        }
+        match result {
+            Ok(execution_result) => {
+                if let Err(ref e) = execution_result {
+                    // Unlike panic, transaction failure isn't that rare, so logging the
+                    // whole transaction body is an overkill: it can be relatively big.
+                    info!("{:?} transaction execution failed: {:?}", tx_hash, e);
+                }
+                execution_result.map_err(TransactionError::from)
+            }
+            Err(err) => {
+                if err.is::<Error>() {
+                    // Continue panic unwind if the reason is StorageError.
+                    panic::resume_unwind(err);
+                }
+                error!("{:?} transaction execution panicked: {:?}", tx, err);
+                Err(TransactionError::from_panic(&err))
+            }
+        }
+    }
+}
+
+fn parse_error(error: &MessageError, tx_hash: &Hash) -> failure::Error {
+    failure::err_msg(format!("{}, tx: {:?}", error.description(), tx_hash))
 }
 
 fn service_execute(service: &Service, fork: &mut Fork) {
@@ -584,6 +920,10 @@ impl Clone for Blockchain {
             service_map: Arc::clone(&self.service_map),
             api_sender: self.api_sender.clone(),
             service_keypair: self.service_keypair.clone(),
+            keystore: self.keystore.clone(),
+            subscriptions: self.subscriptions.clone(),
+            metrics: self.metrics.clone(),
+            tx_execution_seconds: Arc::clone(&self.tx_execution_seconds),
         }
     }
 }
@@ -12,13 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+
 use crypto::{CryptoHash, Hash, PublicKey};
 use messages::{Connect, Precommit, RawMessage};
 use storage::{Entry, Fork, KeySetIndex, ListIndex, MapIndex, MapProof, ProofListIndex,
               ProofMapIndex, Snapshot};
-use helpers::{Height, Round};
-use super::{Block, BlockProof, Blockchain, TransactionResult};
-use super::config::StoredConfiguration;
+use helpers::{Height, Round, ValidatorId};
+use super::{Block, BlockProof, Blockchain, Envelope, TransactionResult};
+use super::config::{LeaderElection, StoredConfiguration};
 
 /// Defines `&str` constants with given name and value.
 macro_rules! define_names {
@@ -46,6 +48,10 @@ define_names!(
     PEERS_CACHE => "peers_cache";
     CONSENSUS_MESSAGES_CACHE => "consensus_messages_cache";
     CONSENSUS_ROUND => "consensus_round";
+    MISSED_PROPOSALS => "missed_proposals";
+    ENVELOPE_NONCES => "envelope_nonces";
+    TRANSACTIONS_POOL_EXPIRY => "transactions_pool_expiry";
+    TRANSACTIONS_POOL_EXPIRY_HEIGHTS => "transactions_pool_expiry_heights";
 );
 
 encoding_struct! (
@@ -197,6 +203,35 @@ where
             .unwrap_or_else(Round::first)
     }
 
+    /// Returns a table that keeps the latest height at which a validator, identified by its
+    /// consensus key, has missed a round in which it was the leader.
+    ///
+    /// The table is maintained only while the `SkipMissed` leader election is used.
+    pub fn missed_proposals(&self) -> MapIndex<&T, PublicKey, u64> {
+        MapIndex::new(MISSED_PROPOSALS, &self.view)
+    }
+
+    /// Returns consensus keys of validators that have missed their proposals within
+    /// `window` blocks preceding the given height.
+    pub fn inactive_validators(&self, height: Height, window: u64) -> HashSet<PublicKey> {
+        self.missed_proposals()
+            .iter()
+            .filter(|&(_, missed_at)| missed_at.saturating_add(window) >= height.0)
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Returns a table that keeps the nonce expected in the next transaction envelope
+    /// of the author. Authors which have not committed any envelopes are absent from the table.
+    pub fn envelope_nonces(&self) -> MapIndex<&T, PublicKey, u64> {
+        MapIndex::new(ENVELOPE_NONCES, &self.view)
+    }
+
+    /// Returns the nonce expected in the next transaction envelope of the author.
+    pub fn next_envelope_nonce(&self, author: &PublicKey) -> u64 {
+        self.envelope_nonces().get(author).unwrap_or(0)
+    }
+
     /// Returns the block hash for the given height.
     pub fn block_hash_by_height(&self, height: Height) -> Option<Hash> {
         self.block_hashes_by_height().get(height.into())
@@ -468,6 +503,68 @@ impl<'a> Schema<&'a mut Fork> {
         ListIndex::new(CONSENSUS_MESSAGES_CACHE, self.view)
     }
 
+    /// Mutable reference to the [`missed_proposals`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.missed_proposals
+    pub(crate) fn missed_proposals_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, u64> {
+        MapIndex::new(MISSED_PROPOSALS, self.view)
+    }
+
+    /// Mutable reference to the [`envelope_nonces`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.envelope_nonces
+    pub(crate) fn envelope_nonces_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, u64> {
+        MapIndex::new(ENVELOPE_NONCES, self.view)
+    }
+
+    /// Returns a set of hashes of the transaction envelopes which have been added to the pool
+    /// with the given `valid_until` height. The set is cleared once the height expires,
+    /// so it may contain envelopes which have already left the pool.
+    fn transactions_pool_expiry_mut(
+        &mut self,
+        valid_until: Height,
+    ) -> KeySetIndex<&mut Fork, Hash> {
+        let valid_until: u64 = valid_until.into();
+        KeySetIndex::new_in_family(TRANSACTIONS_POOL_EXPIRY, &valid_until, self.view)
+    }
+
+    /// Returns a set of `valid_until` heights of the transaction envelopes added to the pool.
+    fn transactions_pool_expiry_heights_mut(&mut self) -> KeySetIndex<&mut Fork, u64> {
+        KeySetIndex::new(TRANSACTIONS_POOL_EXPIRY_HEIGHTS, self.view)
+    }
+
+    /// Records the leaders of the rounds preceding the round in which the block has been
+    /// committed as validators that have missed their proposals at the given height.
+    /// The block proposer is not recorded, even if it has led one of these rounds.
+    ///
+    /// Does nothing unless the `SkipMissed` leader election is actual for the height.
+    pub(crate) fn record_missed_proposals(
+        &mut self,
+        proposer_id: ValidatorId,
+        height: Height,
+        round: Round,
+    ) {
+        let config = self.configuration_by_height(height);
+        let window = match config.consensus.leader_election {
+            LeaderElection::SkipMissed { window } => window,
+            _ => return,
+        };
+
+        let validators = &config.validator_keys;
+        let inactive = self.inactive_validators(height, window);
+        let mut missed_proposals = self.missed_proposals_mut();
+        for round in Round::first().iter_to(round) {
+            let leader = config
+                .consensus
+                .leader_election
+                .leader(validators, &inactive, height, round);
+            if leader != proposer_id {
+                let id: usize = leader.into();
+                missed_proposals.put(&validators[id].consensus_key, height.0);
+            }
+        }
+    }
+
     /// Saves the given consensus round value into the storage.
     pub(crate) fn set_consensus_round(&mut self, round: Round) {
         let mut entry: Entry<&mut Fork, _> = Entry::new(CONSENSUS_ROUND, self.view);
@@ -515,10 +612,45 @@ impl<'a> Schema<&'a mut Fork> {
     /// Adds transaction into the persistent pool.
     #[doc(hidden)]
     pub fn add_transaction_into_pool(&mut self, tx: RawMessage) {
+        if let Some(valid_until) = Envelope::valid_until(&tx) {
+            self.transactions_pool_expiry_mut(valid_until)
+                .insert(tx.hash());
+            self.transactions_pool_expiry_heights_mut()
+                .insert(valid_until.0);
+        }
         self.transactions_pool_mut().insert(tx.hash());
         self.transactions_mut().put(&tx.hash(), tx);
     }
 
+    /// Removes transaction envelopes which cannot be committed at the given height
+    /// from the pool. Returns the number of the evicted transactions.
+    pub(crate) fn evict_expired_transactions(&mut self, height: Height) -> usize {
+        let expired_heights = self.transactions_pool_expiry_heights_mut()
+            .iter()
+            .take_while(|valid_until| *valid_until < height.0)
+            .collect::<Vec<_>>();
+
+        let mut evicted = 0;
+        for valid_until in expired_heights {
+            let valid_until = Height(valid_until);
+            let hashes = self.transactions_pool_expiry_mut(valid_until)
+                .iter()
+                .collect::<Vec<_>>();
+            for hash in &hashes {
+                // Committed envelopes have already left the pool.
+                if self.transactions_pool().contains(hash) {
+                    self.reject_transaction(hash)
+                        .expect("Expired transaction is not found in the pool");
+                    evicted += 1;
+                }
+            }
+            self.transactions_pool_expiry_mut(valid_until).clear();
+            self.transactions_pool_expiry_heights_mut()
+                .remove(&valid_until.0);
+        }
+        evicted
+    }
+
     /// Changes the transaction status from `in_pool`, to `committed`.
     pub(crate) fn commit_transaction(&mut self, hash: &Hash) {
         self.transactions_pool_mut().remove(hash)
@@ -15,6 +15,7 @@
 //! This module defines the Exonum services interfaces. Like smart contracts in some other
 //! blockchain platforms, Exonum services encapsulate business logic of the blockchain application.
 
+use serde::Serialize;
 use serde_json::Value;
 use iron::Handler;
 
@@ -22,12 +23,16 @@ use std::fmt;
 use std::sync::{Arc, RwLock};
 use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::time::SystemTime;
 
-use crypto::{Hash, PublicKey, SecretKey};
+use api::{AsyncHandler, IronAdapter, ServiceApiBuilder, Subscriptions};
+use api::private::ConsensusStatus;
+use crypto::{Hash, PublicKey, SecretKey, Signature};
 use storage::{Fork, Snapshot};
 use messages::RawTransaction;
 use encoding::Error as MessageError;
-use node::{ApiSender, Node, State, TransactionSend};
+use events::lanes::{QueueDepths, SharedQueueDepths};
+use node::{ApiSender, Keystore, Node, ShutdownReason, State, TransactionSend};
 use blockchain::{Blockchain, ConsensusConfig, Schema, StoredConfiguration, ValidatorKeys};
 use helpers::{Height, Milliseconds, ValidatorId};
 use super::transaction::Transaction;
@@ -184,6 +189,10 @@ pub trait Service: Send + Sync + 'static {
     /// `execute` for the service with the smallest ID is invoked first up to the largest one.
     /// Effectively, this means that services should not rely on a particular ordering of
     /// Service::execute invocations.
+    ///
+    /// Note that if empty blocks are suppressed (see `max_empty_block_interval` in
+    /// `ConsensusConfig`), blocks are created only when there are transactions or
+    /// as heartbeats, so `execute` may be invoked rarely on a network with low traffic.
     fn execute(&self, fork: &mut Fork) {}
 
     /// Handles block commit. This handler is invoked for each service after commit of the block.
@@ -193,22 +202,52 @@ pub trait Service: Send + Sync + 'static {
     /// *Try not to perform long operations in this handler*.
     fn handle_commit(&self, context: &ServiceContext) {}
 
+    /// Defines typed endpoints of the service. Public and private endpoints are mounted on
+    /// the `/api/services/{service_name}` path at the corresponding listen addresses and
+    /// precede the handlers returned by `public_api` and `private_api`.
+    ///
+    /// Public endpoints are described in the OpenAPI document of the node served
+    /// at `/api/openapi.json`.
+    fn wire_api(&self, context: &ApiContext, builder: &mut ServiceApiBuilder) {}
+
+    /// Returns an Iron API handler for public requests.
+    ///
+    /// The handler is served through `public_api`, which adapts it to the asynchronous
+    /// interface by default. New services should implement `public_api` instead.
+    fn public_api_handler(&self, context: &ApiContext) -> Option<Box<Handler>> {
+        None
+    }
+
+    /// Returns an Iron API handler for private requests.
+    ///
+    /// The handler is served through `private_api`, which adapts it to the asynchronous
+    /// interface by default. New services should implement `private_api` instead.
+    fn private_api_handler(&self, context: &ApiContext) -> Option<Box<Handler>> {
+        None
+    }
+
     /// Returns an API handler for public requests. The handler is mounted on
     /// the `/api/services/{service_name}` path at [the public listen address][pub-addr]
     /// of all full nodes in the blockchain network.
     ///
+    /// By default, the handler returned by `public_api_handler` is adapted with `IronAdapter`.
+    ///
     /// [pub-addr]: ../node/struct.NodeApiConfig.html#structfield.public_api_address
-    fn public_api_handler(&self, context: &ApiContext) -> Option<Box<Handler>> {
-        None
+    fn public_api(&self, context: &ApiContext) -> Option<Box<AsyncHandler>> {
+        self.public_api_handler(context)
+            .map(|handler| Box::new(IronAdapter::new(handler)) as Box<AsyncHandler>)
     }
 
     /// Returns an API handler for private requests. The handler is mounted on
     /// the `/api/services/{service_name}` path at [the private listen address][private-addr]
     /// of all full nodes in the blockchain network.
     ///
+    /// By default, the handler returned by `private_api_handler` is adapted with `IronAdapter`.
+    ///
     /// [private-addr]: ../node/struct.NodeApiConfig.html#structfield.private_api_address
-    fn private_api_handler(&self, context: &ApiContext) -> Option<Box<Handler>> {
-        None
+    fn private_api(&self, context: &ApiContext) -> Option<Box<AsyncHandler>> {
+        self.private_api_handler(context)
+            .map(|handler| Box::new(IronAdapter::new(handler)) as Box<AsyncHandler>)
     }
 }
 
@@ -224,6 +263,7 @@ pub struct ServiceContext {
     fork: Fork,
     stored_configuration: StoredConfiguration,
     height: Height,
+    subscriptions: Subscriptions,
 }
 
 impl ServiceContext {
@@ -257,9 +297,15 @@ impl ServiceContext {
             fork,
             stored_configuration,
             height,
+            subscriptions: Subscriptions::new(),
         }
     }
 
+    pub(crate) fn with_subscriptions(mut self, subscriptions: Subscriptions) -> ServiceContext {
+        self.subscriptions = subscriptions;
+        self
+    }
+
     /// If the current node is a validator, returns its identifier.
     /// For other nodes return `None`.
     pub fn validator_id(&self) -> Option<ValidatorId> {
@@ -312,6 +358,12 @@ impl ServiceContext {
     pub fn stored_configuration(&self) -> &StoredConfiguration {
         &self.stored_configuration
     }
+
+    /// Sends the event to the WebSocket API clients subscribed to the events
+    /// of the given service.
+    pub fn publish_event<T: Serialize>(&self, service_name: &str, event: &T) {
+        self.subscriptions.publish_event(service_name, event);
+    }
 }
 
 #[derive(Debug, Default)]
@@ -321,7 +373,11 @@ pub struct ApiNodeState {
     reconnects_timeout: HashMap<SocketAddr, Milliseconds>,
     //TODO: update on event?
     peers_info: HashMap<SocketAddr, PublicKey>,
+    banned_peers: HashMap<PublicKey, (SocketAddr, SystemTime)>,
     is_enabled: bool,
+    shutdown_reason: Option<ShutdownReason>,
+    pool_arrivals: HashMap<Hash, SystemTime>,
+    consensus_status: Option<ConsensusStatus>,
 }
 
 impl ApiNodeState {
@@ -337,6 +393,7 @@ impl ApiNodeState {
 #[derive(Clone, Debug)]
 pub struct SharedNodeState {
     state: Arc<RwLock<ApiNodeState>>,
+    queue_depths: SharedQueueDepths,
     /// Timeout to update API state.
     pub state_update_timeout: Milliseconds,
 }
@@ -346,6 +403,7 @@ impl SharedNodeState {
     pub fn new(state_update_timeout: Milliseconds) -> SharedNodeState {
         SharedNodeState {
             state: Arc::new(RwLock::new(ApiNodeState::new())),
+            queue_depths: SharedQueueDepths::new(),
             state_update_timeout,
         }
     }
@@ -392,6 +450,30 @@ impl SharedNodeState {
             .map(|(c, e)| (*c, *e))
             .collect()
     }
+    /// Returns a list of public keys and addresses of temporarily banned peers along with
+    /// the time when their bans expire.
+    pub fn banned_peers(&self) -> Vec<(PublicKey, SocketAddr, SystemTime)> {
+        let now = SystemTime::now();
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .banned_peers
+            .iter()
+            .filter(|&(_, &(_, until))| until > now)
+            .map(|(p, &(a, until))| (*p, a, until))
+            .collect()
+    }
+    /// Returns numbers of the messages waiting to be sent to the peers in each priority lane
+    /// of the outgoing connections.
+    pub fn queue_depths(&self) -> HashMap<SocketAddr, QueueDepths> {
+        self.queue_depths.depths()
+    }
+
+    /// Returns the queue depths shared with the network part of the node.
+    pub(crate) fn shared_queue_depths(&self) -> &SharedQueueDepths {
+        &self.queue_depths
+    }
+
     /// Updates internal state, from `State` of a blockchain node.
     pub fn update_node_state(&self, state: &State) {
         for (p, c) in state.peers().iter() {
@@ -401,6 +483,21 @@ impl SharedNodeState {
                 .peers_info
                 .insert(c.addr(), *p);
         }
+        self.state
+            .write()
+            .expect("Expected write lock.")
+            .consensus_status = Some(ConsensusStatus::new(state));
+    }
+
+    /// Returns the consensus state of the node as of the latest update of the API state,
+    /// or `None` if the state hasn't been updated yet. The time since the latest commit
+    /// is computed at the moment of the call.
+    pub fn consensus_status(&self) -> Option<ConsensusStatus> {
+        let state = self.state.read().expect("Expected read lock.");
+        state
+            .consensus_status
+            .clone()
+            .map(|status| status.at(SystemTime::now()))
     }
 
     /// Returns a boolean value which indicates whether the node is enabled
@@ -417,6 +514,29 @@ impl SharedNodeState {
         state.is_enabled = is_enabled;
     }
 
+    /// Returns the reason of the shutdown if the node is shutting down.
+    pub fn shutdown_reason(&self) -> Option<ShutdownReason> {
+        let state = self.state.read().expect("Expected read lock.");
+        state.shutdown_reason
+    }
+
+    /// Returns `true` if the node is shutting down. The API does not accept transactions
+    /// in this case.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown_reason().is_some()
+    }
+
+    /// Marks the node as shutting down. Returns `false` if the shutdown has already
+    /// been requested, in which case the previous reason is preserved.
+    pub fn request_shutdown(&self, reason: ShutdownReason) -> bool {
+        let mut state = self.state.write().expect("Expected write lock.");
+        if state.shutdown_reason.is_some() {
+            return false;
+        }
+        state.shutdown_reason = Some(reason);
+        true
+    }
+
     /// Returns the value of the `state_update_timeout`.
     pub fn state_update_timeout(&self) -> Milliseconds {
         self.state_update_timeout
@@ -470,6 +590,36 @@ impl SharedNodeState {
             .insert(addr, timeout)
     }
 
+    /// Adds a banned peer into the state.
+    pub fn add_banned_peer(&self, public_key: PublicKey, addr: SocketAddr, until: SystemTime) {
+        let mut state = self.state.write().expect("Expected write lock");
+        let now = SystemTime::now();
+        state.banned_peers.retain(|_, &mut (_, until)| until > now);
+        state.banned_peers.insert(public_key, (addr, until));
+    }
+
+    /// Returns the time when the transaction has been added to the pool, or `None` if
+    /// the transaction is not in the pool or has been added before the node start.
+    pub fn pool_arrival_time(&self, tx_hash: &Hash) -> Option<SystemTime> {
+        let state = self.state.read().expect("Expected read lock.");
+        state.pool_arrivals.get(tx_hash).cloned()
+    }
+
+    /// Records the time when the transaction has been added to the pool.
+    pub(crate) fn add_pool_arrival(&self, tx_hash: Hash, time: SystemTime) {
+        let mut state = self.state.write().expect("Expected write lock");
+        state.pool_arrivals.insert(tx_hash, time);
+    }
+
+    /// Forgets the arrival times of the transactions for which the predicate returns `false`.
+    pub(crate) fn retain_pool_arrivals<F>(&self, mut predicate: F)
+    where
+        F: FnMut(&Hash) -> bool,
+    {
+        let mut state = self.state.write().expect("Expected write lock");
+        state.pool_arrivals.retain(|hash, _| predicate(hash));
+    }
+
     /// Removes the reconnect timeout and returns the previous value.
     pub fn remove_reconnect_timeout(&self, addr: &SocketAddr) -> Option<Milliseconds> {
         self.state
@@ -486,6 +636,7 @@ pub struct ApiContext {
     node_channel: ApiSender,
     public_key: PublicKey,
     secret_key: SecretKey,
+    keystore: Keystore,
 }
 
 /// Provides the current node state to API handlers.
@@ -498,6 +649,7 @@ impl ApiContext {
             node_channel: node.channel(),
             public_key: *node.state().service_public_key(),
             secret_key: node.state().service_secret_key().clone(),
+            keystore: handler.blockchain.keystore().clone(),
         }
     }
 
@@ -513,6 +665,7 @@ impl ApiContext {
             node_channel,
             public_key: *public_key,
             secret_key: secret_key.clone(),
+            keystore: blockchain.keystore().clone(),
         }
     }
 
@@ -535,6 +688,23 @@ impl ApiContext {
     pub fn secret_key(&self) -> &SecretKey {
         &self.secret_key
     }
+
+    /// Returns the public key with the given identifier from the node keystore.
+    pub fn signing_key(&self, key_id: &str) -> Option<&PublicKey> {
+        self.keystore.public_key(key_id)
+    }
+
+    /// Signs the data with the key with the given identifier from the node keystore.
+    /// Returns `None` if there is no such key.
+    pub fn sign(&self, key_id: &str, data: &[u8]) -> Option<Signature> {
+        self.keystore.sign(key_id, data)
+    }
+
+    /// Sends the event to the WebSocket API clients subscribed to the events
+    /// of the given service.
+    pub fn publish_event<T: Serialize>(&self, service_name: &str, event: &T) {
+        self.blockchain.subscriptions().publish_event(service_name, event);
+    }
 }
 
 impl ::std::fmt::Debug for ApiContext {
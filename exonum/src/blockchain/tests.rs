@@ -18,11 +18,13 @@ use rand::{thread_rng, Rng};
 use serde_json;
 use chrono::{DateTime, TimeZone, Utc};
 
-use blockchain::{Blockchain, ExecutionResult, Schema, Service, Transaction};
+use blockchain::{Blockchain, ExecutionResult, Schema, Service, Transaction, TransactionErrorType,
+                 TxLocation};
 use crypto::{gen_keypair, CryptoHash, Hash};
 use storage::{Database, Error, Fork, ListIndex, Snapshot};
-use messages::{Message, RawTransaction};
+use messages::{Message, RawTransaction, TransactionBatch, TransactionEnvelope};
 use encoding::Error as MessageError;
+use encoding::serialize::json::ExonumJson;
 use helpers::{Height, ValidatorId};
 
 const IDX_NAME: &'static str = "idx_name";
@@ -267,6 +269,248 @@ fn handling_tx_panic_storage_error(blockchain: &mut Blockchain) {
     );
 }
 
+fn handling_tx_batch(blockchain: &mut Blockchain) {
+    let (pub_key, sec_key) = gen_keypair();
+
+    let tx1 = Tx::new(3, &sec_key);
+    let tx2 = Tx::new(4, &sec_key);
+    let batch = TransactionBatch::new(
+        &pub_key,
+        vec![tx1.raw().clone(), tx2.raw().clone()],
+        &sec_key,
+    );
+
+    let parsed = blockchain.tx_from_raw(batch.raw().clone()).unwrap();
+    assert!(parsed.verify());
+    let json = parsed.serialize_field().unwrap();
+    assert_eq!(json["body"]["transactions"][1]["body"]["value"], "4");
+
+    let patch = {
+        let mut fork = blockchain.fork();
+        Schema::new(&mut fork).add_transaction_into_pool(batch.raw().clone());
+        fork.into_patch()
+    };
+    blockchain.merge(patch).unwrap();
+
+    let (_, patch) =
+        blockchain.create_patch(ValidatorId::zero(), Height::zero(), &[batch.hash()]);
+    blockchain.merge(patch).unwrap();
+
+    let snapshot = blockchain.snapshot();
+    let schema = Schema::new(&snapshot);
+    let location = TxLocation::new(Height::zero(), 0);
+    for hash in &[batch.hash(), tx1.hash(), tx2.hash()] {
+        assert_eq!(schema.transaction_results().get(hash), Some(Ok(())));
+        assert_eq!(schema.transactions_locations().get(hash), Some(location.clone()));
+        assert!(!schema.transactions_pool().contains(hash));
+    }
+    assert_eq!(schema.block_transactions(Height::zero()).len(), 1);
+
+    let index = ListIndex::new(IDX_NAME, &snapshot);
+    assert_eq!(index.iter().collect::<Vec<u64>>(), vec![3, 14, 4, 10]);
+}
+
+fn handling_failed_tx_batch(blockchain: &mut Blockchain) {
+    let (pub_key, sec_key) = gen_keypair();
+
+    let tx_ok = Tx::new(3, &sec_key);
+    let tx_failed = Tx::new(0, &sec_key);
+    let tx_skipped = Tx::new(4, &sec_key);
+    let batch = TransactionBatch::new(
+        &pub_key,
+        vec![
+            tx_ok.raw().clone(),
+            tx_failed.raw().clone(),
+            tx_skipped.raw().clone(),
+        ],
+        &sec_key,
+    );
+
+    let patch = {
+        let mut fork = blockchain.fork();
+        Schema::new(&mut fork).add_transaction_into_pool(batch.raw().clone());
+        fork.into_patch()
+    };
+    blockchain.merge(patch).unwrap();
+
+    let (_, patch) =
+        blockchain.create_patch(ValidatorId::zero(), Height::zero(), &[batch.hash()]);
+    blockchain.merge(patch).unwrap();
+
+    let snapshot = blockchain.snapshot();
+    let schema = Schema::new(&snapshot);
+    let error_type = |hash: &Hash| {
+        schema
+            .transaction_results()
+            .get(hash)
+            .unwrap()
+            .unwrap_err()
+            .error_type()
+    };
+    assert_eq!(error_type(&batch.hash()), TransactionErrorType::Panic);
+    assert_eq!(error_type(&tx_ok.hash()), TransactionErrorType::BatchRollback);
+    assert_eq!(error_type(&tx_failed.hash()), TransactionErrorType::Panic);
+    assert_eq!(
+        error_type(&tx_skipped.hash()),
+        TransactionErrorType::BatchRollback
+    );
+
+    let index: ListIndex<_, u64> = ListIndex::new(IDX_NAME, &snapshot);
+    assert!(index.is_empty());
+}
+
+fn verifying_tx_batch(blockchain: &Blockchain) {
+    let (pub_key, sec_key) = gen_keypair();
+    let (_, other_sec_key) = gen_keypair();
+
+    let tx = Tx::new(3, &sec_key);
+    let foreign_tx = Tx::new(4, &other_sec_key);
+
+    let verify = |transactions: Vec<RawTransaction>| {
+        let batch = TransactionBatch::new(&pub_key, transactions, &sec_key);
+        blockchain
+            .tx_from_raw(batch.raw().clone())
+            .map(|tx| tx.verify())
+            .unwrap_or(false)
+    };
+
+    assert!(verify(vec![tx.raw().clone()]));
+    assert!(!verify(vec![]));
+    assert!(!verify(vec![tx.raw().clone(), tx.raw().clone()]));
+    assert!(!verify(vec![tx.raw().clone(), foreign_tx.raw().clone()]));
+
+    let nested = TransactionBatch::new(&pub_key, vec![tx.raw().clone()], &sec_key);
+    assert!(!verify(vec![nested.raw().clone()]));
+}
+
+fn commit_transactions(
+    blockchain: &mut Blockchain,
+    height: Height,
+    transactions: &[RawTransaction],
+) {
+    let patch = {
+        let mut fork = blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            for raw in transactions {
+                schema.add_transaction_into_pool(raw.clone());
+            }
+        }
+        fork.into_patch()
+    };
+    blockchain.merge(patch).unwrap();
+
+    let hashes = transactions.iter().map(|raw| raw.hash()).collect::<Vec<_>>();
+    let (_, patch) = blockchain.create_patch(ValidatorId::zero(), height, &hashes);
+    blockchain.merge(patch).unwrap();
+}
+
+fn handling_tx_envelopes(blockchain: &mut Blockchain) {
+    let (pub_key, sec_key) = gen_keypair();
+    let envelope = |nonce: u64, valid_until: Height, value: u64| {
+        let tx = Tx::new(value, &sec_key);
+        TransactionEnvelope::new(&pub_key, valid_until, nonce, tx.raw().clone(), &sec_key)
+    };
+
+    let first = envelope(0, Height(1), 1);
+    let replayed = envelope(0, Height(1), 2);
+    let out_of_order = envelope(2, Height(1), 3);
+    let second = envelope(1, Height(1), 4);
+    commit_transactions(
+        blockchain,
+        Height::zero(),
+        &[
+            first.raw().clone(),
+            replayed.raw().clone(),
+            out_of_order.raw().clone(),
+            second.raw().clone(),
+        ],
+    );
+
+    let expired = envelope(2, Height(0), 5);
+    let failed = envelope(2, Height(1), 0);
+    commit_transactions(
+        blockchain,
+        Height(1),
+        &[expired.raw().clone(), failed.raw().clone()],
+    );
+
+    let snapshot = blockchain.snapshot();
+    let schema = Schema::new(&snapshot);
+    let error_type = |hash: &Hash| {
+        schema
+            .transaction_results()
+            .get(hash)
+            .unwrap()
+            .err()
+            .map(|e| e.error_type())
+    };
+
+    assert_eq!(error_type(&first.hash()), None);
+    assert_eq!(error_type(&first.transaction().hash()), None);
+    assert_eq!(
+        error_type(&replayed.hash()),
+        Some(TransactionErrorType::InvalidNonce)
+    );
+    assert_eq!(
+        error_type(&out_of_order.hash()),
+        Some(TransactionErrorType::InvalidNonce)
+    );
+    assert_eq!(error_type(&second.hash()), None);
+    assert_eq!(
+        error_type(&expired.hash()),
+        Some(TransactionErrorType::Expired)
+    );
+    // The nonce is used up even if the wrapped transaction fails.
+    assert_eq!(
+        error_type(&failed.hash()),
+        Some(TransactionErrorType::Panic)
+    );
+    assert_eq!(schema.next_envelope_nonce(&pub_key), 3);
+
+    // Only the wrapped transactions of the successfully checked envelopes are committed.
+    assert_eq!(
+        schema.transactions_locations().get(&second.transaction().hash()),
+        Some(TxLocation::new(Height::zero(), 3))
+    );
+    assert!(!schema
+        .transactions_locations()
+        .contains(&replayed.transaction().hash()));
+
+    let index = ListIndex::new(IDX_NAME, &snapshot);
+    assert_eq!(index.iter().collect::<Vec<u64>>(), vec![1, 42, 4, 10]);
+}
+
+fn verifying_tx_envelope(blockchain: &Blockchain) {
+    let (pub_key, sec_key) = gen_keypair();
+    let (_, other_sec_key) = gen_keypair();
+
+    let tx = Tx::new(3, &sec_key);
+    let foreign_tx = Tx::new(4, &other_sec_key);
+
+    let wrap = |transaction: &RawTransaction| {
+        TransactionEnvelope::new(&pub_key, Height(1), 0, transaction.clone(), &sec_key)
+    };
+    let verify = |envelope: &TransactionEnvelope| {
+        blockchain
+            .tx_from_raw(envelope.raw().clone())
+            .map(|tx| tx.verify())
+            .unwrap_or(false)
+    };
+
+    let envelope = wrap(tx.raw());
+    assert!(verify(&envelope));
+    // The wrapped transaction is carried unchanged.
+    assert!(envelope.transaction() == *tx.raw());
+    assert_eq!(envelope.transaction().hash(), tx.hash());
+
+    assert!(!verify(&wrap(foreign_tx.raw())));
+
+    let batch = TransactionBatch::new(&pub_key, vec![tx.raw().clone()], &sec_key);
+    assert!(!verify(&wrap(batch.raw())));
+    assert!(!verify(&wrap(envelope.raw())));
+}
+
 mod transactions_tests {
     use blockchain::{ExecutionResult, Transaction, TransactionSet};
     use storage::Fork;
@@ -501,6 +745,36 @@ mod memorydb_tests {
         super::handling_tx_panic_storage_error(&mut blockchain);
     }
 
+    #[test]
+    fn test_handling_tx_batch() {
+        let mut blockchain = create_blockchain();
+        super::handling_tx_batch(&mut blockchain);
+    }
+
+    #[test]
+    fn test_handling_failed_tx_batch() {
+        let mut blockchain = create_blockchain();
+        super::handling_failed_tx_batch(&mut blockchain);
+    }
+
+    #[test]
+    fn test_verifying_tx_batch() {
+        let blockchain = create_blockchain();
+        super::verifying_tx_batch(&blockchain);
+    }
+
+    #[test]
+    fn test_handling_tx_envelopes() {
+        let mut blockchain = create_blockchain();
+        super::handling_tx_envelopes(&mut blockchain);
+    }
+
+    #[test]
+    fn test_verifying_tx_envelope() {
+        let blockchain = create_blockchain();
+        super::verifying_tx_envelope(&blockchain);
+    }
+
     #[test]
     fn test_service_execute() {
         let blockchain = create_blockchain_with_service(Box::new(ServiceGood));
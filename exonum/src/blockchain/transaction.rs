@@ -36,6 +36,12 @@ const MAX_ERROR_CODE: u16 = u8::MAX as u16;
 const TRANSACTION_STATUS_OK: u16 = MAX_ERROR_CODE + 1;
 // `Err(TransactionErrorType::Panic)`.
 const TRANSACTION_STATUS_PANIC: u16 = TRANSACTION_STATUS_OK + 1;
+// `Err(TransactionErrorType::BatchRollback)`.
+const TRANSACTION_STATUS_BATCH_ROLLBACK: u16 = TRANSACTION_STATUS_PANIC + 1;
+// `Err(TransactionErrorType::Expired)`.
+const TRANSACTION_STATUS_EXPIRED: u16 = TRANSACTION_STATUS_BATCH_ROLLBACK + 1;
+// `Err(TransactionErrorType::InvalidNonce)`.
+const TRANSACTION_STATUS_INVALID_NONCE: u16 = TRANSACTION_STATUS_EXPIRED + 1;
 
 /// Returns a result of the `Transaction` `execute` method. This result may be
 /// either an empty unit type, in case of success, or an `ExecutionError`, if execution has
@@ -191,6 +197,14 @@ impl ExecutionError {
 pub enum TransactionErrorType {
     /// Panic occurred during transaction execution.
     Panic,
+    /// The transaction is a part of a batch and its changes have been discarded
+    /// because another transaction of the same batch has failed.
+    BatchRollback,
+    /// The transaction envelope has been committed after its `valid_until` height.
+    Expired,
+    /// The nonce of the transaction envelope is not the next nonce of its author,
+    /// i.e., the envelope is either replayed or out of order.
+    InvalidNonce,
     /// User-defined error code. Can have different meanings for different transactions and
     /// services.
     Code(u8),
@@ -206,6 +220,10 @@ pub enum TransactionErrorType {
 ///   implementation for the details).
 /// - `TransactionErrorType::Panic` is set by the framework if panic is raised during transaction
 ///   execution.
+/// - `TransactionErrorType::BatchRollback` is set by the framework for the transactions of
+///   a failed batch.
+/// - `TransactionErrorType::Expired` and `TransactionErrorType::InvalidNonce` are set by the
+///   framework for the transaction envelopes which cannot be executed.
 /// - `TransactionError` implements `Display` which can be used for obtaining a simple error
 ///   description.
 ///
@@ -263,6 +281,21 @@ impl TransactionError {
         Self::new(TransactionErrorType::Panic, description)
     }
 
+    /// Creates a new `TransactionError` representing a rolled back transaction of a batch.
+    pub(crate) fn batch_rollback(description: Option<String>) -> Self {
+        Self::new(TransactionErrorType::BatchRollback, description)
+    }
+
+    /// Creates a new `TransactionError` representing an expired transaction envelope.
+    pub(crate) fn expired(description: Option<String>) -> Self {
+        Self::new(TransactionErrorType::Expired, description)
+    }
+
+    /// Creates a new `TransactionError` representing a transaction envelope with an invalid nonce.
+    pub(crate) fn invalid_nonce(description: Option<String>) -> Self {
+        Self::new(TransactionErrorType::InvalidNonce, description)
+    }
+
     /// Creates a new `TransactionError` instance from `std::thread::Result`'s `Err`.
     pub(crate) fn from_panic(panic: &Box<Any + Send>) -> Self {
         Self::panic(panic_description(panic))
@@ -290,6 +323,9 @@ impl fmt::Display for TransactionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.error_type {
             TransactionErrorType::Panic => write!(f, "Panic during execution")?,
+            TransactionErrorType::BatchRollback => write!(f, "Batch rolled back")?,
+            TransactionErrorType::Expired => write!(f, "Transaction expired")?,
+            TransactionErrorType::InvalidNonce => write!(f, "Invalid nonce")?,
             TransactionErrorType::Code(c) => write!(f, "Error code: {}", c)?,
         }
 
@@ -345,6 +381,9 @@ impl StorageValue for TransactionResult {
             value @ 0...MAX_ERROR_CODE => Err(TransactionError::code(value as u8, description)),
             TRANSACTION_STATUS_OK => Ok(()),
             TRANSACTION_STATUS_PANIC => Err(TransactionError::panic(description)),
+            TRANSACTION_STATUS_BATCH_ROLLBACK => Err(TransactionError::batch_rollback(description)),
+            TRANSACTION_STATUS_EXPIRED => Err(TransactionError::expired(description)),
+            TRANSACTION_STATUS_INVALID_NONCE => Err(TransactionError::invalid_nonce(description)),
             value => panic!("Invalid TransactionResult value: {}", value),
         }
     }
@@ -355,6 +394,9 @@ fn status_as_u16(status: &TransactionResult) -> u16 {
         Ok(()) => TRANSACTION_STATUS_OK,
         Err(ref e) => match e.error_type {
             TransactionErrorType::Panic => TRANSACTION_STATUS_PANIC,
+            TransactionErrorType::BatchRollback => TRANSACTION_STATUS_BATCH_ROLLBACK,
+            TransactionErrorType::Expired => TRANSACTION_STATUS_EXPIRED,
+            TransactionErrorType::InvalidNonce => TRANSACTION_STATUS_INVALID_NONCE,
             TransactionErrorType::Code(c) => u16::from(c),
         },
     }
@@ -701,6 +743,10 @@ mod tests {
         let values = [
             (TransactionErrorType::Panic, None),
             (TransactionErrorType::Panic, Some("panic")),
+            (TransactionErrorType::BatchRollback, None),
+            (TransactionErrorType::BatchRollback, Some("rollback")),
+            (TransactionErrorType::Expired, None),
+            (TransactionErrorType::InvalidNonce, Some("expected nonce 1")),
             (TransactionErrorType::Code(0), None),
             (TransactionErrorType::Code(1), Some("")),
             (TransactionErrorType::Code(100), None),
@@ -744,6 +790,14 @@ mod tests {
             Err(TransactionError::panic(Some(
                 "Panic error description".to_owned(),
             ))),
+            Err(TransactionError::batch_rollback(None)),
+            Err(TransactionError::batch_rollback(Some(
+                "Batch error description".to_owned(),
+            ))),
+            Err(TransactionError::expired(None)),
+            Err(TransactionError::invalid_nonce(Some(
+                "Nonce error description".to_owned(),
+            ))),
             Err(TransactionError::code(0, None)),
             Err(TransactionError::code(
                 0,
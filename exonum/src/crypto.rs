@@ -844,6 +844,36 @@ impl CryptoHash for Decimal {
     }
 }
 
+pub mod x25519 {
+    //! Conversion of Ed25519 keys into Curve25519 keys.
+    //!
+    //! Network connections are secured with the Noise protocol, which requires
+    //! Diffie-Hellman keys. Converting the consensus keys of the node allows to
+    //! bind the transport identity of the peer to its consensus identity.
+
+    use sodiumoxide::crypto::sign::ed25519::{convert_ed_pk_to_curve25519,
+                                             convert_ed_sk_to_curve25519};
+
+    use super::{PublicKey, SecretKey, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH};
+
+    /// Length of a Curve25519 key in bytes.
+    pub const KEY_LENGTH: usize = 32;
+
+    /// Converts an Ed25519 public key into a Curve25519 public key.
+    pub fn into_x25519_public_key(public_key: &PublicKey) -> [u8; KEY_LENGTH] {
+        let mut ed_public_key = [0; PUBLIC_KEY_LENGTH];
+        ed_public_key.copy_from_slice(&public_key[..]);
+        convert_ed_pk_to_curve25519(&ed_public_key)
+    }
+
+    /// Converts an Ed25519 secret key into a Curve25519 secret key.
+    pub fn into_x25519_secret_key(secret_key: &SecretKey) -> [u8; KEY_LENGTH] {
+        let mut ed_secret_key = [0; SECRET_KEY_LENGTH];
+        ed_secret_key.copy_from_slice(&secret_key[..]);
+        convert_ed_sk_to_curve25519(&ed_secret_key)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json;
@@ -860,6 +890,18 @@ mod tests {
         assert_eq!(*h.as_ref(), [0; 32]);
     }
 
+    #[test]
+    fn test_x25519_keys() {
+        use sodiumoxide::crypto::scalarmult::curve25519::{scalarmult_base, Scalar};
+        use super::x25519::{into_x25519_public_key, into_x25519_secret_key};
+
+        let (p, s) = gen_keypair();
+        let public_key = into_x25519_public_key(&p);
+        let secret_key = into_x25519_secret_key(&s);
+        assert_eq!(scalarmult_base(&Scalar(secret_key)).0, public_key);
+        assert_ne!(into_x25519_public_key(&gen_keypair().0), public_key);
+    }
+
     #[test]
     fn test_keys() {
         let (p, s) = gen_keypair();
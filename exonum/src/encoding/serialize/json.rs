@@ -324,6 +324,25 @@ impl<'a> ExonumJson for &'a [u8] {
     }
 }
 
+impl ExonumJson for RawMessage {
+    fn deserialize_field<B: WriteBufferWrapper>(
+        value: &Value,
+        buffer: &mut B,
+        from: Offset,
+        to: Offset,
+    ) -> Result<(), Box<Error>> {
+        use messages::MessageBuffer;
+        let string = value.as_str().ok_or("Can't cast json as string")?;
+        let str_hex = <Vec<u8> as FromHex>::from_hex(string)?;
+        buffer.write(from, to, RawMessage::new(MessageBuffer::from_vec(str_hex)));
+        Ok(())
+    }
+
+    fn serialize_field(&self) -> Result<Value, Box<Error + Send + Sync>> {
+        Ok(Value::String(::encoding::serialize::encode_hex(self)))
+    }
+}
+
 impl ExonumJson for Vec<RawMessage> {
     fn deserialize_field<B: WriteBufferWrapper>(
         value: &Value,
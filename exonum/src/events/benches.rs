@@ -17,9 +17,8 @@ use test::Bencher;
 use std::net::SocketAddr;
 use std::thread;
 
-use node::EventsPoolCapacity;
 use events::network::NetworkConfiguration;
-use events::tests::{connect_message, raw_message, TestEvents};
+use events::tests::{raw_message, TestEvents};
 
 struct BenchConfig {
     times: usize,
@@ -33,9 +32,8 @@ fn test_events(cfg: &BenchConfig, listen_address: SocketAddr) -> TestEvents {
         ..Default::default()
     };
     TestEvents {
-        listen_address,
         network_config,
-        events_config: EventsPoolCapacity::default(),
+        ..TestEvents::with_addr(listen_address)
     }
 }
 
@@ -46,11 +44,13 @@ fn bench_network(b: &mut Bencher, addrs: [SocketAddr; 2], cfg: &BenchConfig) {
         let first = addrs[0];
         let second = addrs[1];
 
-        let c1 = connect_message(first);
-        let c2 = connect_message(second);
+        let t1 = test_events(cfg, first);
+        let t2 = test_events(cfg, second);
+        let c1 = t1.connect_message();
+        let c2 = t2.connect_message();
 
-        let mut t1 = test_events(cfg, first).spawn();
-        let mut t2 = test_events(cfg, second).spawn();
+        let mut t1 = t1.spawn();
+        let mut t2 = t2.spawn();
 
         t1.connect_with(second);
         t2.connect_with(first);
@@ -19,6 +19,7 @@ use tokio_io::codec::{Decoder, Encoder};
 use std::io;
 
 use messages::{MessageBuffer, RawMessage, HEADER_LENGTH};
+use super::compression;
 use super::error::other_error;
 use events::noise::wrapper::NoiseWrapper;
 use events::noise::wrapper::NOISE_HEADER_LENGTH;
@@ -29,13 +30,20 @@ pub struct MessagesCodec {
     max_message_len: u32,
     /// Noise session to encrypt/decrypt messages.
     session: NoiseWrapper,
+    /// Compression threshold, if compression is negotiated for the connection.
+    compression: Option<u32>,
 }
 
 impl MessagesCodec {
-    pub fn new(max_message_len: u32, session: NoiseWrapper) -> MessagesCodec {
+    pub fn new(
+        max_message_len: u32,
+        session: NoiseWrapper,
+        compression: Option<u32>,
+    ) -> MessagesCodec {
         MessagesCodec {
             max_message_len,
             session,
+            compression,
         }
     }
 }
@@ -59,6 +67,19 @@ impl Decoder for MessagesCodec {
 
         let mut buf = self.session.decrypt_msg(len, buf)?;
 
+        if self.compression.is_some() {
+            let data = compression::decompress(&buf, self.max_message_len as usize)?;
+            buf = BytesMut::from(data);
+        }
+
+        if buf.len() < HEADER_LENGTH {
+            return Err(other_error(format!(
+                "Received message is too short: {}, expected header size {}",
+                buf.len(),
+                HEADER_LENGTH
+            )));
+        }
+
         if buf[0] != 0 {
             return Err(other_error("Message first byte must be set to 0"));
         }
@@ -96,7 +117,16 @@ impl Encoder for MessagesCodec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: Self::Item, buf: &mut BytesMut) -> io::Result<()> {
-        self.session.encrypt_msg(msg.as_ref(), buf)?;
+        // Messages are compressed before encryption, since encrypted data is incompressible.
+        match self.compression {
+            Some(threshold) => {
+                let data = compression::compress(msg.as_ref(), threshold)?;
+                self.session.encrypt_msg(&data, buf)?;
+            }
+            None => {
+                self.session.encrypt_msg(msg.as_ref(), buf)?;
+            }
+        }
         Ok(())
     }
 }
@@ -105,6 +135,7 @@ impl Encoder for MessagesCodec {
 mod test {
     use super::MessagesCodec;
 
+    use byteorder::{ByteOrder, LittleEndian};
     use messages::{MessageBuffer, RawMessage};
     use bytes::BytesMut;
     use tokio_io::codec::{Decoder, Encoder};
@@ -148,6 +179,61 @@ mod test {
         assert!(responder.decode(&mut bytes).is_err());
     }
 
+    #[test]
+    fn decode_compressed_message() {
+        let (mut responder, mut initiator) = create_encrypted_codecs();
+        responder.compression = Some(100);
+        initiator.compression = Some(100);
+
+        let mut data = vec![0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[7u8; 1000]);
+        LittleEndian::write_u32(&mut data[6..10], 1010);
+        let raw = RawMessage::new(MessageBuffer::from_vec(data.clone()));
+
+        let mut bytes: BytesMut = BytesMut::new();
+        initiator.encode(raw.clone(), &mut bytes).unwrap();
+        assert!(bytes.len() < data.len());
+        assert_eq!(responder.decode(&mut bytes).unwrap(), Some(raw));
+
+        // Messages shorter than the threshold are sent uncompressed.
+        let data = vec![0u8, 0, 0, 0, 0, 0, 10, 0, 0, 0];
+        let raw = RawMessage::new(MessageBuffer::from_vec(data));
+        let mut bytes: BytesMut = BytesMut::new();
+        initiator.encode(raw.clone(), &mut bytes).unwrap();
+        assert_eq!(responder.decode(&mut bytes).unwrap(), Some(raw));
+    }
+
+    #[test]
+    fn decode_compressed_message_too_short() {
+        let (mut responder, mut initiator) = create_encrypted_codecs();
+        responder.compression = Some(100);
+        initiator.compression = Some(100);
+
+        let data = vec![0u8, 0, 0, 0];
+        let raw = RawMessage::new(MessageBuffer::from_vec(data));
+
+        let mut bytes: BytesMut = BytesMut::new();
+        initiator.encode(raw, &mut bytes).unwrap();
+        assert!(responder.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn decode_compressed_message_too_long() {
+        let (mut responder, mut initiator) = create_encrypted_codecs();
+        responder.compression = Some(100);
+        responder.max_message_len = 500;
+        initiator.compression = Some(100);
+
+        let mut data = vec![0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[7u8; 1000]);
+        LittleEndian::write_u32(&mut data[6..10], 1010);
+        let raw = RawMessage::new(MessageBuffer::from_vec(data));
+
+        let mut bytes: BytesMut = BytesMut::new();
+        initiator.encode(raw, &mut bytes).unwrap();
+        assert!(responder.decode(&mut bytes).is_err());
+    }
+
     fn create_encrypted_codecs() -> (MessagesCodec, MessagesCodec) {
         let (public_key, secret_key) = gen_keypair_from_seed(&Seed::new([0; 32]));
 
@@ -155,6 +241,8 @@ mod test {
             public_key,
             secret_key,
             max_message_len: 1024,
+            whitelist: Default::default(),
+            compression: Default::default(),
         };
 
         let mut initiator = NoiseWrapper::initiator(&params).session;
@@ -187,11 +275,13 @@ mod test {
         let responder_codec = MessagesCodec {
             max_message_len: 10000,
             session: initiator,
+            compression: None,
         };
 
         let initiator_codec = MessagesCodec {
             max_message_len: 10000,
             session: responder,
+            compression: None,
         };
 
         (responder_codec, initiator_codec)
@@ -0,0 +1,155 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compression of the messages sent over the wire.
+//!
+//! Support of compression is announced in the payload of the Noise handshake messages,
+//! so that it is used only if both sides of the connection support it. Once negotiated,
+//! each message is prefixed with a byte denoting whether the rest of it is compressed.
+
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use std::io::{self, Read, Write};
+
+use super::error::other_error;
+
+/// Handshake payload flag denoting support of compression.
+pub const COMPRESSION_FLAG: u8 = 0b0000_0001;
+
+const UNCOMPRESSED: u8 = 0;
+const DEFLATE: u8 = 1;
+
+/// Compression of the messages sent to the peers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CompressionConfig {
+    /// Whether compression is offered to the peers.
+    pub enabled: bool,
+    /// Messages shorter than this number of bytes are sent uncompressed.
+    pub threshold: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: true,
+            threshold: 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Returns the payload of the handshake message announcing the supported features.
+    pub fn handshake_payload(&self) -> [u8; 1] {
+        if self.enabled {
+            [COMPRESSION_FLAG]
+        } else {
+            [0]
+        }
+    }
+
+    /// Returns the compression threshold for the connection if both sides support
+    /// compression.
+    pub fn negotiate(&self, remote_payload: &[u8]) -> Option<u32> {
+        let remote_flags = remote_payload.first().cloned().unwrap_or(0);
+        if self.enabled && remote_flags & COMPRESSION_FLAG != 0 {
+            Some(self.threshold)
+        } else {
+            None
+        }
+    }
+}
+
+/// Prefixes the message with the compression byte, compressing the message if it is
+/// not shorter than the threshold.
+pub fn compress(msg: &[u8], threshold: u32) -> io::Result<Vec<u8>> {
+    if msg.len() >= threshold as usize {
+        let mut encoder = DeflateEncoder::new(vec![DEFLATE], Compression::fast());
+        encoder.write_all(msg)?;
+        let compressed = encoder.finish()?;
+        // Incompressible messages are sent as is.
+        if compressed.len() <= msg.len() {
+            return Ok(compressed);
+        }
+    }
+
+    let mut data = Vec::with_capacity(msg.len() + 1);
+    data.push(UNCOMPRESSED);
+    data.extend_from_slice(msg);
+    Ok(data)
+}
+
+/// Strips the compression byte from the message, decompressing the message if needed.
+/// Decompressed messages longer than `max_len` bytes are rejected.
+pub fn decompress(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    match data.first() {
+        Some(&UNCOMPRESSED) => Ok(data[1..].to_vec()),
+        Some(&DEFLATE) => {
+            let mut msg = Vec::new();
+            DeflateDecoder::new(&data[1..])
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut msg)?;
+            if msg.len() > max_len {
+                return Err(other_error(format!(
+                    "Decompressed message is too long, maximum allowed length is {} bytes",
+                    max_len
+                )));
+            }
+            Ok(msg)
+        }
+        Some(byte) => Err(other_error(format!("Unknown compression: {}", byte))),
+        None => Err(other_error("Empty message")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, CompressionConfig, COMPRESSION_FLAG};
+
+    #[test]
+    fn test_compression_roundtrip() {
+        let msg = (0..100)
+            .flat_map(|_| b"{\"key\": \"value\"}".iter().cloned())
+            .collect::<Vec<u8>>();
+        let compressed = compress(&msg, 1024).unwrap();
+        assert!(compressed.len() < msg.len());
+        assert_eq!(decompress(&compressed, msg.len()).unwrap(), msg);
+        assert!(decompress(&compressed, msg.len() - 1).is_err());
+
+        let short = b"short message".to_vec();
+        let data = compress(&short, 1024).unwrap();
+        assert_eq!(data.len(), short.len() + 1);
+        assert_eq!(decompress(&data, 1024).unwrap(), short);
+
+        assert!(decompress(&[], 1024).is_err());
+        assert!(decompress(&[2, 0], 1024).is_err());
+    }
+
+    #[test]
+    fn test_compression_negotiation() {
+        let enabled = CompressionConfig::default();
+        let disabled = CompressionConfig {
+            enabled: false,
+            ..enabled
+        };
+
+        assert_eq!(enabled.negotiate(&[COMPRESSION_FLAG]), Some(enabled.threshold));
+        assert_eq!(enabled.negotiate(&disabled.handshake_payload()), None);
+        assert_eq!(disabled.negotiate(&enabled.handshake_payload()), None);
+        // Peers without compression send zero byte in the handshake payload.
+        assert_eq!(enabled.negotiate(&[0]), None);
+        assert_eq!(enabled.negotiate(&[]), None);
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Priority lanes of the outgoing connections.
+//!
+//! Messages sent to a peer are queued in separate lanes depending on their kind. Once the
+//! connection is ready to accept a message, it is taken from the lane with the highest
+//! priority, so that consensus messages are not delayed by large blocks or transactions.
+//!
+//! Messages are queued without waiting for the connection. If a lane is full, the action
+//! is determined by `Lane::overflow`, so that a slow peer does not stall the other peers.
+
+use futures::{Async, Poll, Stream};
+use futures::sync::mpsc;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use messages::{RawMessage, BLOCK_RESPONSE_MESSAGE_ID, COMPACT_BLOCK_RESPONSE_MESSAGE_ID,
+               CONSENSUS, TRANSACTIONS_ANNOUNCE_MESSAGE_ID, TRANSACTIONS_RESPONSE_MESSAGE_ID,
+               TRANSACTION_BATCH_MESSAGE_ID, TRANSACTION_ENVELOPE_MESSAGE_ID};
+
+/// Lane of an outgoing connection. Lanes are listed in the order of decreasing priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// Consensus messages, status messages and requests.
+    Consensus,
+    /// Blocks and transactions sent in response to requests.
+    Sync,
+    /// Transactions, their batches and announces.
+    Transactions,
+}
+
+impl Lane {
+    /// All lanes in the order of decreasing priority.
+    pub const ALL: [Lane; 3] = [Lane::Consensus, Lane::Sync, Lane::Transactions];
+
+    /// Returns the lane for the message.
+    pub fn of(message: &RawMessage) -> Lane {
+        if message.service_id() != CONSENSUS {
+            return Lane::Transactions;
+        }
+        match message.message_type() {
+            BLOCK_RESPONSE_MESSAGE_ID
+            | COMPACT_BLOCK_RESPONSE_MESSAGE_ID
+            | TRANSACTIONS_RESPONSE_MESSAGE_ID => Lane::Sync,
+            TRANSACTION_BATCH_MESSAGE_ID
+            | TRANSACTION_ENVELOPE_MESSAGE_ID
+            | TRANSACTIONS_ANNOUNCE_MESSAGE_ID => Lane::Transactions,
+            _ => Lane::Consensus,
+        }
+    }
+
+    /// Returns the action taken if the lane has no room for a message.
+    ///
+    /// Consensus messages must not be lost silently, so the peer which does not read them
+    /// is disconnected and has to reconnect. Blocks and transactions are dropped, since
+    /// the peer requests them again once its request times out.
+    pub fn overflow(self) -> Overflow {
+        match self {
+            Lane::Consensus => Overflow::Disconnect,
+            Lane::Sync | Lane::Transactions => Overflow::DropMessage,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Action taken if the lane has no room for a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The message is dropped.
+    DropMessage,
+    /// The message is dropped and the connection with the peer is closed.
+    Disconnect,
+}
+
+/// Error returned if the message cannot be queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The lane of the message is full.
+    Full(Lane),
+    /// The connection is closed.
+    Disconnected,
+}
+
+/// Numbers of the messages waiting to be sent in the lanes of a connection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, ApiSchema)]
+pub struct QueueDepths {
+    /// Number of messages in the `Consensus` lane.
+    pub consensus: usize,
+    /// Number of messages in the `Sync` lane.
+    pub sync: usize,
+    /// Number of messages in the `Transactions` lane.
+    pub transactions: usize,
+}
+
+#[derive(Debug, Default)]
+struct LaneCounters([AtomicUsize; 3]);
+
+impl LaneCounters {
+    fn increment(&self, lane: Lane) {
+        self.0[lane.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn decrement(&self, lane: Lane) {
+        self.0[lane.index()].fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn depths(&self) -> QueueDepths {
+        QueueDepths {
+            consensus: self.0[Lane::Consensus.index()].load(Ordering::Relaxed),
+            sync: self.0[Lane::Sync.index()].load(Ordering::Relaxed),
+            transactions: self.0[Lane::Transactions.index()].load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Queue depths of the outgoing connections shared between the network part and the API.
+#[derive(Debug, Clone, Default)]
+pub struct SharedQueueDepths {
+    inner: Arc<RwLock<HashMap<SocketAddr, Arc<LaneCounters>>>>,
+}
+
+impl SharedQueueDepths {
+    /// Creates an empty instance.
+    pub fn new() -> Self {
+        SharedQueueDepths::default()
+    }
+
+    /// Returns queue depths of the outgoing connections.
+    pub fn depths(&self) -> HashMap<SocketAddr, QueueDepths> {
+        self.inner
+            .read()
+            .expect("Expected read lock")
+            .iter()
+            .map(|(address, counters)| (*address, counters.depths()))
+            .collect()
+    }
+
+    fn register(&self, peer: SocketAddr) -> Arc<LaneCounters> {
+        let counters = Arc::new(LaneCounters::default());
+        self.inner
+            .write()
+            .expect("Expected write lock")
+            .insert(peer, Arc::clone(&counters));
+        counters
+    }
+
+    fn unregister(&self, peer: &SocketAddr, counters: &Arc<LaneCounters>) {
+        let mut inner = self.inner.write().expect("Expected write lock");
+        // The peer could have been reconnected in the meantime.
+        if inner
+            .get(peer)
+            .map_or(false, |current| Arc::ptr_eq(current, counters))
+        {
+            inner.remove(peer);
+        }
+    }
+}
+
+/// Sending half of the lanes of a connection.
+#[derive(Debug, Clone)]
+pub struct LanesSender {
+    senders: Vec<mpsc::Sender<RawMessage>>,
+    counters: Arc<LaneCounters>,
+}
+
+impl LanesSender {
+    /// Queues the message in its lane without waiting for the lane to have room
+    /// for the message.
+    pub fn try_send(&mut self, message: RawMessage) -> Result<(), SendError> {
+        let lane = Lane::of(&message);
+        // The counter is raised in advance, since the receiver may take the message
+        // before `try_send` returns.
+        let counters = &self.counters;
+        counters.increment(lane);
+        self.senders[lane.index()].try_send(message).map_err(|e| {
+            counters.decrement(lane);
+            if e.is_full() {
+                SendError::Full(lane)
+            } else {
+                SendError::Disconnected
+            }
+        })
+    }
+}
+
+/// Receiving half of the lanes of a connection. The stream yields messages from the lane
+/// with the highest priority first.
+#[derive(Debug)]
+pub struct LanesReceiver {
+    receivers: Vec<mpsc::Receiver<RawMessage>>,
+    counters: Arc<LaneCounters>,
+    depths: SharedQueueDepths,
+    peer: SocketAddr,
+}
+
+impl Stream for LanesReceiver {
+    type Item = RawMessage;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut finished = 0;
+        for (receiver, lane) in self.receivers.iter_mut().zip(Lane::ALL.iter()) {
+            match receiver.poll()? {
+                Async::Ready(Some(message)) => {
+                    self.counters.decrement(*lane);
+                    return Ok(Async::Ready(Some(message)));
+                }
+                Async::Ready(None) => finished += 1,
+                Async::NotReady => {}
+            }
+        }
+
+        if finished == self.receivers.len() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl Drop for LanesReceiver {
+    fn drop(&mut self) {
+        self.depths.unregister(&self.peer, &self.counters);
+    }
+}
+
+/// Creates lanes for the connection with the peer. Each lane can hold up to `capacity`
+/// messages (plus one message per sender, so the senders should not be cloned
+/// for each message).
+pub fn lanes(
+    peer: SocketAddr,
+    capacity: usize,
+    depths: &SharedQueueDepths,
+) -> (LanesSender, LanesReceiver) {
+    let counters = depths.register(peer);
+    let (senders, receivers) = Lane::ALL
+        .iter()
+        .map(|_| mpsc::channel(capacity))
+        .unzip();
+    let sender = LanesSender {
+        senders,
+        counters: Arc::clone(&counters),
+    };
+    let receiver = LanesReceiver {
+        receivers,
+        counters,
+        depths: depths.clone(),
+        peer,
+    };
+    (sender, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use std::net::SocketAddr;
+
+    use crypto::{gen_keypair, hash};
+    use helpers::Height;
+    use messages::{Message, RawMessage, Status, TransactionsAnnounce, TransactionsResponse};
+    use super::{lanes, Lane, Overflow, QueueDepths, SendError, SharedQueueDepths};
+
+    fn messages() -> (RawMessage, RawMessage, RawMessage) {
+        let (public_key, secret_key) = gen_keypair();
+        let status = Status::new(&public_key, Height(1), &hash(&[]), &secret_key);
+        let response = TransactionsResponse::new(&public_key, &public_key, vec![], &secret_key);
+        let announce = TransactionsAnnounce::new(&public_key, &[hash(&[1])], &secret_key);
+        (
+            status.raw().clone(),
+            response.raw().clone(),
+            announce.raw().clone(),
+        )
+    }
+
+    #[test]
+    fn test_message_lanes() {
+        let (status, response, announce) = messages();
+        assert_eq!(Lane::of(&status), Lane::Consensus);
+        assert_eq!(Lane::of(&response), Lane::Sync);
+        assert_eq!(Lane::of(&announce), Lane::Transactions);
+        assert_eq!(Lane::Consensus.overflow(), Overflow::Disconnect);
+        assert_eq!(Lane::Sync.overflow(), Overflow::DropMessage);
+        assert_eq!(Lane::Transactions.overflow(), Overflow::DropMessage);
+    }
+
+    #[test]
+    fn test_lanes_priority() {
+        let peer = SocketAddr::from(([127, 0, 0, 1], 8000));
+        let depths = SharedQueueDepths::new();
+        let (mut sender, receiver) = lanes(peer, 10, &depths);
+        let (status, response, announce) = messages();
+
+        sender.try_send(announce.clone()).unwrap();
+        sender.try_send(response.clone()).unwrap();
+        sender.try_send(status.clone()).unwrap();
+        assert_eq!(
+            depths.depths()[&peer],
+            QueueDepths {
+                consensus: 1,
+                sync: 1,
+                transactions: 1,
+            }
+        );
+
+        drop(sender);
+        let received = receiver.wait().map(Result::unwrap).collect::<Vec<_>>();
+        assert!(received == vec![status, response, announce]);
+        assert!(depths.depths().is_empty());
+    }
+
+    #[test]
+    fn test_lanes_failed_send() {
+        let peer = SocketAddr::from(([127, 0, 0, 1], 8000));
+        let depths = SharedQueueDepths::new();
+        let (mut sender, receiver) = lanes(peer, 10, &depths);
+        let (status, _, _) = messages();
+
+        drop(receiver);
+        assert_eq!(sender.try_send(status), Err(SendError::Disconnected));
+        assert_eq!(sender.counters.depths(), QueueDepths::default());
+    }
+
+    #[test]
+    fn test_lanes_full() {
+        let peer = SocketAddr::from(([127, 0, 0, 1], 8000));
+        let depths = SharedQueueDepths::new();
+        let (mut sender, _receiver) = lanes(peer, 1, &depths);
+        let (status, response, _) = messages();
+
+        // The lane holds `capacity` messages plus one message of the sender.
+        sender.try_send(status.clone()).unwrap();
+        sender.try_send(status.clone()).unwrap();
+        assert_eq!(
+            sender.try_send(status),
+            Err(SendError::Full(Lane::Consensus))
+        );
+        // Other lanes are not affected.
+        sender.try_send(response).unwrap();
+        assert_eq!(
+            depths.depths()[&peer],
+            QueueDepths {
+                consensus: 2,
+                sync: 1,
+                transactions: 0,
+            }
+        );
+    }
+}
@@ -16,10 +16,13 @@ pub use self::network::{NetworkConfiguration, NetworkEvent, NetworkPart, Network
 pub use self::internal::InternalPart;
 
 pub mod codec;
+pub mod compression;
 pub mod error;
 pub mod network;
 pub mod internal;
+pub mod lanes;
 pub mod noise;
+pub mod rate_limit;
 
 use futures::{Async, Future, Poll, Stream};
 use futures::sink::Wait;
@@ -138,6 +141,7 @@ impl Into<Event> for InternalEvent {
 
 /// Receives timeout, network and api events and invokes `handle_event` method of handler.
 /// If one of these streams closes, the aggregator stream completes immediately.
+/// The stream also completes right after yielding `InternalEvent::Shutdown`.
 #[derive(Debug)]
 pub struct EventsAggregator<S1, S2, S3>
 where
@@ -181,10 +185,15 @@ where
             Ok(Async::Ready(None))
         } else {
             match self.internal.poll()? {
-                Async::Ready(None) | Async::Ready(Some(InternalEvent::Shutdown)) => {
+                Async::Ready(None) => {
                     self.done = true;
                     return Ok(Async::Ready(None));
                 }
+                // The handler receives the shutdown event as the last one.
+                Async::Ready(Some(InternalEvent::Shutdown)) => {
+                    self.done = true;
+                    return Ok(Async::Ready(Some(Event::Internal(InternalEvent::Shutdown))));
+                }
                 Async::Ready(Some(item)) => {
                     return Ok(Async::Ready(Some(Event::Internal(item))));
                 }
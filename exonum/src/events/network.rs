@@ -15,37 +15,52 @@
 use futures::{future, unsync, Future, IntoFuture, Poll, Sink, Stream};
 use futures::{future::Either, sync::mpsc};
 use tokio_core::net::{TcpListener, TcpStream};
-use tokio_core::reactor::Handle;
+use tokio_core::reactor::{Handle, Interval, Timeout};
 use tokio_retry::{Retry, strategy::{jitter, FixedInterval}};
 
 use std::io;
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use messages::{Any, Connect, Message, RawMessage};
+use crypto::{x25519, PublicKey};
+use encoding;
+use messages::{Any, Connect, Message, ProtocolInfo, RawMessage, PROTOCOL_MAJOR_VERSION};
 use helpers::Milliseconds;
+use node::{ReconnectConfig, SharedWhitelist};
 use super::to_box;
 use super::error::{into_other, log_error, other_error, result_ok};
 
-use events::noise::NoiseHandshake;
+use events::compression::CompressionConfig;
+use events::lanes::{lanes, Lane, LanesSender, Overflow, SendError, SharedQueueDepths};
+use events::noise::{NoiseHandshake, RemoteStaticKey};
 use events::noise::HandshakeParams;
+use events::rate_limit::{PeerBans, PeerLimiter, PeerRateLimit};
 
 const OUTGOING_CHANNEL_SIZE: usize = 10;
+/// Maximal time to wait for the queued messages to be sent on shutdown.
+const SHUTDOWN_DRAIN_TIMEOUT: Milliseconds = 1000;
+const SHUTDOWN_DRAIN_INTERVAL: Milliseconds = 10;
 
 #[derive(Debug)]
 pub enum NetworkEvent {
     MessageReceived(SocketAddr, RawMessage),
-    PeerConnected(SocketAddr, Connect),
+    /// Peer has connected to the node. The protocol versions and features of the peer
+    /// are announced in the handshake.
+    PeerConnected(SocketAddr, Connect, ProtocolInfo),
     PeerDisconnected(SocketAddr),
     UnableConnectToPeer(SocketAddr),
+    /// Peer has exceeded the rate limits too many times and is banned for the given duration.
+    PeerBanned(SocketAddr, PublicKey, Milliseconds),
 }
 
 #[derive(Debug, Clone)]
 pub enum NetworkRequest {
-    SendMessage(SocketAddr, RawMessage),
+    /// Sends the message to the given address. If the connection is not established yet and
+    /// the consensus key of the peer is known, the key is checked during the handshake.
+    SendMessage(SocketAddr, RawMessage, Option<PublicKey>),
     DisconnectWithPeer(SocketAddr),
     Shutdown,
 }
@@ -59,6 +74,12 @@ pub struct NetworkConfiguration {
     pub tcp_keep_alive: Option<u64>,
     pub tcp_connect_retry_timeout: Milliseconds,
     pub tcp_connect_max_retries: u64,
+    #[serde(default)]
+    pub peer_rate_limit: PeerRateLimit,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
 }
 
 impl Default for NetworkConfiguration {
@@ -70,6 +91,9 @@ impl Default for NetworkConfiguration {
             tcp_nodelay: true,
             tcp_connect_retry_timeout: 15_000,
             tcp_connect_max_retries: 10,
+            peer_rate_limit: PeerRateLimit::default(),
+            reconnect: ReconnectConfig::default(),
+            compression: CompressionConfig::default(),
         }
     }
 }
@@ -82,45 +106,100 @@ pub struct NetworkPart {
     pub max_message_len: u32,
     pub network_requests: (mpsc::Sender<NetworkRequest>, mpsc::Receiver<NetworkRequest>),
     pub network_tx: mpsc::Sender<NetworkEvent>,
+    /// Queue depths of the outgoing connections, shown by the network info API.
+    pub queue_depths: SharedQueueDepths,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 struct ConnectionsPool {
-    inner: Rc<RefCell<HashMap<SocketAddr, mpsc::Sender<RawMessage>>>>,
+    inner: Rc<RefCell<HashMap<SocketAddr, LanesSender>>>,
+    queue_depths: SharedQueueDepths,
 }
 
 impl ConnectionsPool {
-    fn new() -> ConnectionsPool {
-        ConnectionsPool::default()
+    fn new(queue_depths: SharedQueueDepths) -> ConnectionsPool {
+        ConnectionsPool {
+            inner: Rc::default(),
+            queue_depths,
+        }
     }
 
-    fn insert(&self, peer: SocketAddr, sender: &mpsc::Sender<RawMessage>) {
-        self.inner.borrow_mut().insert(peer, sender.clone());
+    fn insert(&self, peer: SocketAddr, sender: LanesSender) {
+        self.inner.borrow_mut().insert(peer, sender);
     }
 
-    fn remove(&self, peer: &SocketAddr) -> Result<mpsc::Sender<RawMessage>, &'static str> {
+    fn remove(&self, peer: &SocketAddr) -> Result<LanesSender, &'static str> {
         self.inner
             .borrow_mut()
             .remove(peer)
             .ok_or("there is no sender in the connection pool")
     }
 
-    fn get(&self, peer: SocketAddr) -> Option<mpsc::Sender<RawMessage>> {
-        self.inner.borrow_mut().get(&peer).cloned()
+    fn contains(&self, peer: &SocketAddr) -> bool {
+        self.inner.borrow().contains_key(peer)
     }
 
     fn len(&self) -> usize {
         self.inner.borrow_mut().len()
     }
 
+    /// Drops the senders of all connections, so that the connections are closed once
+    /// the queued messages are written to the sockets.
+    fn close_all(&self) {
+        self.inner.borrow_mut().clear();
+    }
+
+    /// Queues the message to the peer without waiting for the connection. If the lane
+    /// of the message is full, the message is dropped, and the peer is disconnected
+    /// if required by the lane.
+    fn send_message(
+        &self,
+        peer: SocketAddr,
+        message: RawMessage,
+        network_tx: &mpsc::Sender<NetworkEvent>,
+    ) -> Box<Future<Item = (), Error = io::Error>> {
+        let result = self.inner
+            .borrow_mut()
+            .get_mut(&peer)
+            .map(|sender| sender.try_send(message));
+        match result {
+            Some(Err(SendError::Full(lane))) => self.handle_overflow(peer, lane, network_tx),
+            Some(Err(SendError::Disconnected)) => {
+                trace!("Dropped message to peer={}, connection is closed", peer);
+                to_box(future::ok(()))
+            }
+            Some(Ok(())) | None => to_box(future::ok(())),
+        }
+    }
+
+    fn handle_overflow(
+        &self,
+        peer: SocketAddr,
+        lane: Lane,
+        network_tx: &mpsc::Sender<NetworkEvent>,
+    ) -> Box<Future<Item = (), Error = io::Error>> {
+        match lane.overflow() {
+            Overflow::DropMessage => {
+                trace!("Dropped message to peer={}, {:?} lane is full", peer, lane);
+                to_box(future::ok(()))
+            }
+            Overflow::Disconnect => {
+                warn!("Disconnecting peer={}, {:?} lane is full", peer, lane);
+                self.disconnect_with_peer(peer, network_tx.clone())
+            }
+        }
+    }
+
+    /// Starts connecting to the peer. Returns `false` if the connections limit is reached.
     fn connect_to_peer(
         self,
         network_config: NetworkConfiguration,
         peer: SocketAddr,
+        peer_key: Option<PublicKey>,
         network_tx: mpsc::Sender<NetworkEvent>,
         handle: &Handle,
         handshake_params: &HandshakeParams,
-    ) -> Option<mpsc::Sender<RawMessage>> {
+    ) -> bool {
         let limit = network_config.max_outgoing_connections;
         if self.len() >= limit {
             warn!(
@@ -128,11 +207,11 @@ impl ConnectionsPool {
                  connections limit reached.",
                 peer
             );
-            return None;
+            return false;
         }
-        // Register outgoing channel.
-        let (conn_tx, conn_rx) = mpsc::channel(OUTGOING_CHANNEL_SIZE);
-        self.insert(peer, &conn_tx);
+        // Register outgoing lanes.
+        let (conn_tx, conn_rx) = lanes(peer, OUTGOING_CHANNEL_SIZE, &self.queue_depths);
+        self.insert(peer, conn_tx);
         // Enable retry feature for outgoing connection.
         let timeout = network_config.tcp_connect_retry_timeout;
         let max_tries = network_config.tcp_connect_max_retries as usize;
@@ -154,9 +233,17 @@ impl ConnectionsPool {
                 Ok(sock)
             })
             .and_then(move |sock| {
-                NoiseHandshake::send(&handshake_params, sock).and_then(|framed|{
-                    Ok(framed)
-                })
+                let whitelist = handshake_params.whitelist.clone();
+                NoiseHandshake::send(&handshake_params, sock, peer_key).and_then(
+                    move |(framed, remote_key, _protocol)| {
+                        // Reject peers that are not in whitelist before sending anything to them.
+                        if whitelist.allow_static_key(&remote_key) {
+                            Ok(framed)
+                        } else {
+                            Err(other_error(&format!("Peer {} is not in whitelist", peer)))
+                        }
+                    },
+                )
             })
             // Connect socket with the outgoing channel
             .and_then(move |stream| {
@@ -186,7 +273,7 @@ impl ConnectionsPool {
             })
             .map_err(log_error);
         handle.spawn(connect_handle);
-        Some(conn_tx)
+        true
     }
 
     fn disconnect_with_peer(
@@ -225,7 +312,9 @@ impl NetworkPart {
             self.network_requests.1,
             cancel_sender,
             handshake_params,
+            self.queue_depths,
         );
+        let peer_bans = PeerBans::new(network_config.peer_rate_limit);
         // TODO Don't use unwrap here!
         let server = Listener::bind(
             network_config,
@@ -233,6 +322,7 @@ impl NetworkPart {
             handle.clone(),
             &self.network_tx,
             handshake_params,
+            &peer_bans,
         ).unwrap();
 
         let cancel_handler = cancel_handler.or_else(|e| {
@@ -254,6 +344,7 @@ struct RequestHandler(
 );
 
 impl RequestHandler {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     fn new(
         connect_message: Connect,
         network_config: NetworkConfiguration,
@@ -262,72 +353,87 @@ impl RequestHandler {
         receiver: mpsc::Receiver<NetworkRequest>,
         cancel_sender: unsync::oneshot::Sender<()>,
         handshake_params: &HandshakeParams,
+        queue_depths: SharedQueueDepths,
     ) -> RequestHandler {
         let mut cancel_sender = Some(cancel_sender);
         let handshake_params = handshake_params.clone();
-        let outgoing_connections = ConnectionsPool::new();
+        let outgoing_connections = ConnectionsPool::new(queue_depths.clone());
         let requests_handler = receiver
             .map_err(|_| other_error("no network requests"))
             .for_each(move |request| {
                 match request {
-                    NetworkRequest::SendMessage(peer, msg) => {
-                        let conn_tx = outgoing_connections
-                            .get(peer)
-                            .map(|conn_tx| conn_fut(Ok(conn_tx).into_future()))
-                            .or_else(|| {
-                                outgoing_connections
+                    NetworkRequest::SendMessage(peer, msg, peer_key) => {
+                        if !outgoing_connections.contains(&peer) {
+                            let connected = outgoing_connections.clone().connect_to_peer(
+                                network_config,
+                                peer,
+                                peer_key,
+                                network_tx.clone(),
+                                &handle,
+                                &handshake_params,
+                            );
+                            if !connected {
+                                let event = NetworkEvent::UnableConnectToPeer(peer);
+                                let fut = network_tx
                                     .clone()
-                                    .connect_to_peer(
-                                        network_config,
-                                        peer,
-                                        network_tx.clone(),
-                                        &handle,
-                                        &handshake_params
-                                    )
-                                    .map(|conn_tx|
-                                        // if we create new connect, we should send connect message
-                                        if &msg != connect_message.raw() {
-                                            conn_fut(conn_tx.send(connect_message.raw().clone())
-                                                .map_err(|_| {
-                                                    other_error("can't send message to a connection")
-                                                }))
-                                        } else {
-                                            conn_fut(Ok(conn_tx).into_future())
-                                        })
-                            });
-                        if let Some(conn_tx) = conn_tx {
-                            let fut = conn_tx.and_then(|conn_tx| {
-                                conn_tx
-                                    .send(msg)
-                                    .map_err(|_| other_error("can't send message to a connection"))
-                            });
-                            to_box(fut)
-                        } else {
-                            let event = NetworkEvent::UnableConnectToPeer(peer);
-                            let fut = network_tx
-                                .clone()
-                                .send(event)
-                                .map_err(|_| other_error("can't send network event"))
-                                .into_future();
-                            to_box(fut)
+                                    .send(event)
+                                    .map(drop)
+                                    .map_err(|_| other_error("can't send network event"));
+                                return to_box(fut);
+                            }
+                            // if we create new connect, we should send connect message
+                            if &msg != connect_message.raw() {
+                                let connect = connect_message.raw().clone();
+                                let fut = outgoing_connections
+                                    .send_message(peer, connect, &network_tx)
+                                    .join(outgoing_connections.send_message(peer, msg, &network_tx))
+                                    .map(drop);
+                                return to_box(fut);
+                            }
                         }
+                        outgoing_connections.send_message(peer, msg, &network_tx)
                     }
                     NetworkRequest::DisconnectWithPeer(peer) => {
                         outgoing_connections.disconnect_with_peer(peer, network_tx.clone())
                     }
-                    // Immediately stop the event loop.
-                    NetworkRequest::Shutdown => to_box(
-                        cancel_sender
-                            .take()
-                            .ok_or_else(|| other_error("shutdown twice"))
-                            .into_future(),
-                    ),
+                    // Stop the event loop once the queued messages are sent.
+                    NetworkRequest::Shutdown => {
+                        outgoing_connections.close_all();
+                        let cancel_sender = cancel_sender.take();
+                        let fut = drain_connections(queue_depths.clone(), &handle).then(
+                            move |_| cancel_sender.ok_or_else(|| other_error("shutdown twice")),
+                        );
+                        to_box(fut)
+                    }
                 }
             });
         RequestHandler(to_box(requests_handler))
     }
 }
 
+/// Waits until the outgoing connections are closed, but no longer than
+/// `SHUTDOWN_DRAIN_TIMEOUT`.
+fn drain_connections(
+    queue_depths: SharedQueueDepths,
+    handle: &Handle,
+) -> Box<Future<Item = (), Error = io::Error>> {
+    let interval = Duration::from_millis(SHUTDOWN_DRAIN_INTERVAL);
+    let timeout = Duration::from_millis(SHUTDOWN_DRAIN_TIMEOUT);
+    let fut = Interval::new(interval, handle)
+        .into_future()
+        .and_then(|interval| {
+            interval
+                .skip_while(move |_| Ok(!queue_depths.depths().is_empty()))
+                .into_future()
+                .map(drop)
+                .map_err(|(e, _)| e)
+        })
+        .select(Timeout::new(timeout, handle).into_future().flatten())
+        .map(drop)
+        .map_err(|(e, _)| e);
+    to_box(fut)
+}
+
 impl Future for RequestHandler {
     type Item = ();
     type Error = io::Error;
@@ -346,6 +452,7 @@ impl Listener {
         handle: Handle,
         network_tx: &mpsc::Sender<NetworkEvent>,
         handshake_params: &HandshakeParams,
+        peer_bans: &PeerBans,
     ) -> Result<Listener, io::Error> {
         // Incoming connections limiter
         let incoming_connections_limit = network_config.max_incoming_connections;
@@ -355,6 +462,7 @@ impl Listener {
         let listener = TcpListener::bind(&listen_address, &handle)?;
         let network_tx = network_tx.clone();
         let handshake_params = handshake_params.clone();
+        let peer_bans = peer_bans.clone();
         let server = listener.incoming().for_each(move |(sock, addr)| {
             let holder = Rc::downgrade(&incoming_connections_counter);
             // Check incoming connections count
@@ -370,24 +478,40 @@ impl Listener {
             trace!("Accepted incoming connection with peer={}", addr);
             let network_tx = network_tx.clone();
 
-            let stream = NoiseHandshake::listen(&handshake_params, sock).flatten_stream();
+            let whitelist = handshake_params.whitelist.clone();
+            let peer_bans = peer_bans.clone();
+            let connect_bans = peer_bans.clone();
 
-            let connection_handler = stream
-                .into_future()
-                .and_then(Ok)
-                .map_err(|e| e.0)
-                .and_then(move |(raw, stream)| match raw.map(Any::from_raw) {
-                    Some(Ok(Any::Connect(msg))) => Ok((msg, stream)),
+            let connection_handler = NoiseHandshake::listen(&handshake_params, sock)
+                .and_then(|(stream, remote_key, protocol)| {
+                    stream
+                        .into_future()
+                        .map_err(|e| e.0)
+                        .map(move |(raw, stream)| (raw, stream, remote_key, protocol))
+                })
+                .and_then(move |(raw, stream, remote_key, protocol)| match raw.map(Any::from_raw) {
+                    Some(Ok(Any::Connect(msg))) => {
+                        check_connect(&msg, &remote_key, &whitelist, &connect_bans)
+                            .map(|_| (msg, protocol, stream))
+                    }
                     Some(Ok(other)) => Err(other_error(&format!(
                         "First message is not Connect, got={:?}",
                         other
                     ))),
+                    Some(Err(encoding::Error::UnsupportedProtocolVersion { version })) => {
+                        Err(other_error(&format!(
+                            "Peer {} uses message format version {}, while the node supports \
+                             only version {}",
+                            addr, version, PROTOCOL_MAJOR_VERSION
+                        )))
+                    }
                     Some(Err(e)) => Err(into_other(e)),
                     None => Err(other_error("Incoming socket closed")),
                 })
-                .and_then(move |(connect, stream)| {
+                .and_then(move |(connect, protocol, stream)| {
                     trace!("Received handshake message={:?}", connect);
-                    let event = NetworkEvent::PeerConnected(addr, connect);
+                    let peer = *connect.pub_key();
+                    let event = NetworkEvent::PeerConnected(addr, connect, protocol);
                     let stream = network_tx
                         .clone()
                         .send(event)
@@ -395,9 +519,26 @@ impl Listener {
                         .and_then(move |_| Ok(stream))
                         .flatten_stream();
 
+                    let mut limiter = PeerLimiter::new(peer_bans.limits(), Instant::now());
                     stream.for_each(move |raw| {
-                        let event = NetworkEvent::MessageReceived(addr, raw);
-                        network_tx.clone().send(event).map_err(into_other).map(drop)
+                        let now = Instant::now();
+                        let event = if limiter.allow(raw.len(), now) {
+                            NetworkEvent::MessageReceived(addr, raw)
+                        } else if peer_bans.penalize(&peer, now) {
+                            warn!("Banned peer={:?} with address={}", peer, addr);
+                            let duration = peer_bans.limits().ban_duration;
+                            let event = NetworkEvent::PeerBanned(addr, peer, duration);
+                            let fut = network_tx
+                                .clone()
+                                .send(event)
+                                .map_err(into_other)
+                                .and_then(|_| Err::<(), _>(other_error("Peer is banned")));
+                            return to_box(fut);
+                        } else {
+                            trace!("Dropped message from peer={}, rate limit exceeded", addr);
+                            return to_box(future::ok(()));
+                        };
+                        to_box(network_tx.clone().send(event).map_err(into_other).map(drop))
                     })
                 })
                 .map(|_| {
@@ -422,9 +563,31 @@ impl Future for Listener {
     }
 }
 
-fn conn_fut<F>(fut: F) -> Box<Future<Item = mpsc::Sender<RawMessage>, Error = io::Error>>
-where
-    F: Future<Item = mpsc::Sender<RawMessage>, Error = io::Error> + 'static,
-{
-    Box::new(fut)
+/// Checks that the `Connect` message is signed by the owner of the static Noise key
+/// and that the peer is allowed to connect and is not banned.
+fn check_connect(
+    connect: &Connect,
+    remote_key: &RemoteStaticKey,
+    whitelist: &SharedWhitelist,
+    peer_bans: &PeerBans,
+) -> Result<(), io::Error> {
+    if x25519::into_x25519_public_key(connect.pub_key()) != *remote_key {
+        return Err(other_error(&format!(
+            "Connect message public key doesn't match the Noise static key, connect={:?}",
+            connect
+        )));
+    }
+    if !whitelist.allow(connect.pub_key()) {
+        return Err(other_error(&format!(
+            "Peer {:?} is not in whitelist",
+            connect.pub_key()
+        )));
+    }
+    if peer_bans.is_banned(connect.pub_key(), Instant::now()) {
+        return Err(other_error(&format!(
+            "Peer {:?} is banned",
+            connect.pub_key()
+        )));
+    }
+    Ok(())
 }
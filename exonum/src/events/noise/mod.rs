@@ -20,19 +20,42 @@ use tokio_io::{AsyncRead, codec::Framed, io::{read_exact, write_all}};
 use std::io;
 
 use crypto::{PublicKey, SecretKey};
+use crypto::x25519;
 use events::codec::MessagesCodec;
-use events::noise::wrapper::{NoiseWrapper, HANDSHAKE_HEADER_LENGTH};
+use events::compression::CompressionConfig;
+use events::noise::wrapper::{NoiseError, NoiseWrapper, HANDSHAKE_HEADER_LENGTH};
+use messages::{ProtocolInfo, PROTOCOL_VERSIONS};
+use node::SharedWhitelist;
 
 pub mod wrapper;
 
-type HandshakeResult = Box<Future<Item = Framed<TcpStream, MessagesCodec>, Error = io::Error>>;
+/// Static Curve25519 public key of the remote peer.
+pub type RemoteStaticKey = [u8; x25519::KEY_LENGTH];
+
+/// Offset of the protocol features in the handshake payload. The payload starts with
+/// the compression flags, see `CompressionConfig::handshake_payload`.
+const FEATURES_OFFSET: usize = 1;
+/// Offset of the protocol versions in the handshake payload.
+const VERSIONS_OFFSET: usize = FEATURES_OFFSET + 8;
+
+type HandshakeResult = Box<
+    Future<
+        Item = (Framed<TcpStream, MessagesCodec>, RemoteStaticKey, ProtocolInfo),
+        Error = io::Error,
+    >,
+>;
 
 #[derive(Debug, Clone)]
 /// Params needed to establish secured connection using Noise Protocol.
+///
+/// The static Noise key of the node is derived from its consensus key, so the remote side
+/// can check that the transport identity matches the `Connect` message of the node.
 pub struct HandshakeParams {
     pub public_key: PublicKey,
     pub secret_key: SecretKey,
     pub max_message_len: u32,
+    pub whitelist: SharedWhitelist,
+    pub compression: CompressionConfig,
 }
 
 #[derive(Debug)]
@@ -43,50 +66,128 @@ impl NoiseHandshake {
         listen_handshake(stream, params)
     }
 
-    pub fn send(params: &HandshakeParams, stream: TcpStream) -> HandshakeResult {
-        send_handshake(stream, params)
+    /// Initiates the handshake. If `expected_key` is set, the handshake fails unless
+    /// the static key of the remote peer is derived from this consensus key.
+    pub fn send(
+        params: &HandshakeParams,
+        stream: TcpStream,
+        expected_key: Option<PublicKey>,
+    ) -> HandshakeResult {
+        send_handshake(stream, params, expected_key)
     }
 }
 
 fn listen_handshake(stream: TcpStream, params: &HandshakeParams) -> HandshakeResult {
     let max_message_len = params.max_message_len;
+    let compression = params.compression;
+    let payload = handshake_payload(&compression);
     let mut noise = NoiseWrapper::responder(params);
     let framed = read(stream).and_then(move |(stream, msg)| {
         let _buf = noise.read_handshake_msg(&msg);
-        write_handshake_msg(&mut noise)
+        write_handshake_msg(&mut noise, &payload)
             .and_then(|(len, buf)| write(stream, &buf, len))
             .and_then(|(stream, _msg)| read(stream))
             .and_then(move |(stream, msg)| {
-                let _buf = noise.read_handshake_msg(&msg);
+                // The initiator announces its features in the last handshake message,
+                // which is authenticated by its static key.
+                let (len, buf) = noise.read_handshake_msg(&msg)?;
+                let compression = compression.negotiate(&buf[..len]);
+                let protocol = remote_protocol(&buf[..len])?;
+                let remote_key = remote_static_key(&noise)?;
                 let noise = noise.into_transport_mode()?;
-                let framed = stream.framed(MessagesCodec::new(max_message_len, noise));
-                Ok(framed)
+                let codec = MessagesCodec::new(max_message_len, noise, compression);
+                let framed = stream.framed(codec);
+                Ok((framed, remote_key, protocol))
             })
     });
 
     Box::new(framed)
 }
 
-fn send_handshake(stream: TcpStream, params: &HandshakeParams) -> HandshakeResult {
+fn send_handshake(
+    stream: TcpStream,
+    params: &HandshakeParams,
+    expected_key: Option<PublicKey>,
+) -> HandshakeResult {
     let max_message_len = params.max_message_len;
+    let compression = params.compression;
+    let payload = handshake_payload(&compression);
     let mut noise = NoiseWrapper::initiator(params);
-    let framed = write_handshake_msg(&mut noise)
+    let framed = write_handshake_msg(&mut noise, &payload)
         .and_then(|(len, buf)| write(stream, &buf, len))
         .and_then(|(stream, _msg)| read(stream))
         .and_then(move |(stream, msg)| {
-            let _buf = noise.read_handshake_msg(&msg);
-            write_handshake_msg(&mut noise)
-                .and_then(|(len, buf)| write(stream, &buf, len))
-                .and_then(move |(stream, _msg)| {
-                    let noise = noise.into_transport_mode()?;
-                    let framed = stream.framed(MessagesCodec::new(max_message_len, noise));
-                    Ok(framed)
+            let remote = noise
+                .read_handshake_msg(&msg)
+                .and_then(|(len, buf)| {
+                    let remote_key = remote_static_key(&noise)?;
+                    // Check the identity of the peer before authenticating ourselves to it.
+                    if let Some(ref expected_key) = expected_key {
+                        if x25519::into_x25519_public_key(expected_key) != remote_key {
+                            return Err(NoiseError::new(format!(
+                                "Remote static key does not match the consensus key {:?}",
+                                expected_key
+                            )));
+                        }
+                    }
+                    let protocol = remote_protocol(&buf[..len])?;
+                    Ok((remote_key, compression.negotiate(&buf[..len]), protocol))
                 })
+                .map_err(io::Error::from);
+            done(remote).and_then(move |(remote_key, compression, protocol)| {
+                write_handshake_msg(&mut noise, &payload)
+                    .and_then(|(len, buf)| write(stream, &buf, len))
+                    .and_then(move |(stream, _msg)| {
+                        let noise = noise.into_transport_mode()?;
+                        let codec = MessagesCodec::new(max_message_len, noise, compression);
+                        let framed = stream.framed(codec);
+                        Ok((framed, remote_key, protocol))
+                    })
+            })
         });
 
     Box::new(framed)
 }
 
+/// Returns the payload of the handshake messages: the compression flags followed by
+/// the protocol features and versions of the node.
+fn handshake_payload(compression: &CompressionConfig) -> Vec<u8> {
+    let protocol = ProtocolInfo::current();
+    let mut payload = vec![0; VERSIONS_OFFSET];
+    payload[..FEATURES_OFFSET].copy_from_slice(&compression.handshake_payload());
+    LittleEndian::write_u64(&mut payload[FEATURES_OFFSET..], protocol.features);
+    payload.extend_from_slice(&protocol.versions);
+    payload
+}
+
+/// Reads the protocol features and versions of the remote peer from the handshake payload
+/// and checks that the peer has a common protocol version with the node. The peers which
+/// don't announce their versions are assumed to support only the first one.
+fn remote_protocol(payload: &[u8]) -> Result<ProtocolInfo, NoiseError> {
+    let protocol = if payload.len() > VERSIONS_OFFSET {
+        ProtocolInfo {
+            versions: payload[VERSIONS_OFFSET..].to_vec(),
+            features: LittleEndian::read_u64(&payload[FEATURES_OFFSET..VERSIONS_OFFSET]),
+        }
+    } else {
+        ProtocolInfo::default()
+    };
+    if protocol.common_version(PROTOCOL_VERSIONS).is_none() {
+        return Err(NoiseError::new(format!(
+            "Peer has no common protocol version with the node, peer versions: {:?}, \
+             supported versions: {:?}",
+            protocol.versions, PROTOCOL_VERSIONS
+        )));
+    }
+    Ok(protocol)
+}
+
+fn remote_static_key(noise: &NoiseWrapper) -> Result<RemoteStaticKey, NoiseError> {
+    noise
+        .remote_static_key()
+        .ok_or_else(|| NoiseError::new("Remote static key is not available."))
+}
+
 fn read(sock: TcpStream) -> Box<Future<Item = (TcpStream, Vec<u8>), Error = io::Error>> {
     let buf = vec![0u8; HANDSHAKE_HEADER_LENGTH];
     Box::new(
@@ -108,7 +209,36 @@ fn write(
 
 fn write_handshake_msg(
     noise: &mut NoiseWrapper,
+    payload: &[u8],
 ) -> Box<Future<Item = (usize, Vec<u8>), Error = io::Error>> {
-    let res = noise.write_handshake_msg();
+    let res = noise.write_handshake_msg(payload);
     Box::new(done(res.map_err(|e| e.into())))
 }
+
+#[cfg(test)]
+mod tests {
+    use events::compression::{CompressionConfig, COMPRESSION_FLAG};
+    use messages::{ProtocolInfo, PROTOCOL_FEATURES};
+    use super::{handshake_payload, remote_protocol, VERSIONS_OFFSET};
+
+    #[test]
+    fn test_protocol_payload() {
+        let payload = handshake_payload(&CompressionConfig::default());
+        assert_eq!(payload[0], COMPRESSION_FLAG);
+        assert_eq!(remote_protocol(&payload).unwrap(), ProtocolInfo::current());
+
+        // Peers which announce only the compression support use the first version.
+        let protocol = remote_protocol(&[COMPRESSION_FLAG]).unwrap();
+        assert_eq!(protocol, ProtocolInfo::default());
+        assert!(!protocol.supports(PROTOCOL_FEATURES));
+        assert_eq!(remote_protocol(&[]).unwrap(), ProtocolInfo::default());
+    }
+
+    #[test]
+    fn test_protocol_payload_incompatible_versions() {
+        let mut payload = handshake_payload(&CompressionConfig::default());
+        payload.truncate(VERSIONS_OFFSET);
+        payload.push(200);
+        assert!(remote_protocol(&payload).is_err());
+    }
+}
@@ -20,7 +20,8 @@ use std::fmt;
 use std::fmt::{Error, Formatter};
 use std::io;
 
-use events::noise::HandshakeParams;
+use crypto::x25519;
+use events::noise::{HandshakeParams, RemoteStaticKey};
 
 pub const NOISE_MAX_MESSAGE_LENGTH: usize = 65_535;
 pub const TAG_LENGTH: usize = 16;
@@ -28,7 +29,8 @@ pub const NOISE_HEADER_LENGTH: usize = 4;
 pub const HANDSHAKE_HEADER_LENGTH: usize = 2;
 
 // We choose XX pattern since it provides mutual authentication and
-// transmission of static public keys.
+// transmission of static public keys. Static keys are derived from the consensus keys
+// of the nodes.
 // See: https://noiseprotocol.org/noise.html#interactive-patterns
 static PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
 
@@ -39,8 +41,8 @@ pub struct NoiseWrapper {
 
 impl NoiseWrapper {
     pub fn responder(params: &HandshakeParams) -> Self {
-        let builder: NoiseBuilder = Self::noise_builder(params);
-        let private_key = builder.generate_private_key().unwrap();
+        let builder: NoiseBuilder = Self::noise_builder();
+        let private_key = x25519::into_x25519_secret_key(&params.secret_key);
         let session = builder
             .local_private_key(&private_key)
             .build_responder()
@@ -50,8 +52,8 @@ impl NoiseWrapper {
     }
 
     pub fn initiator(params: &HandshakeParams) -> Self {
-        let builder: NoiseBuilder = Self::noise_builder(params);
-        let private_key = builder.generate_private_key().unwrap();
+        let builder: NoiseBuilder = Self::noise_builder();
+        let private_key = x25519::into_x25519_secret_key(&params.secret_key);
         let session = builder
             .local_private_key(&private_key)
             .build_initiator()
@@ -64,9 +66,23 @@ impl NoiseWrapper {
         self.read(input, NOISE_MAX_MESSAGE_LENGTH)
     }
 
-    pub fn write_handshake_msg(&mut self) -> Result<(usize, Vec<u8>), NoiseError> {
-        // Payload in handshake messages can be empty.
-        self.write(&[0u8])
+    /// Writes a handshake message with the given payload. The payload announces
+    /// the compression support and the protocol versions and features of the node.
+    pub fn write_handshake_msg(&mut self, payload: &[u8]) -> Result<(usize, Vec<u8>), NoiseError> {
+        self.write(payload)
+    }
+
+    /// Returns the static public key of the remote peer, if it has been received
+    /// during the handshake.
+    pub fn remote_static_key(&self) -> Option<RemoteStaticKey> {
+        self.session.get_remote_static().and_then(|key| {
+            if key.len() != x25519::KEY_LENGTH {
+                return None;
+            }
+            let mut remote_key = [0; x25519::KEY_LENGTH];
+            remote_key.copy_from_slice(key);
+            Some(remote_key)
+        })
     }
 
     pub fn into_transport_mode(self) -> Result<Self, NoiseError> {
@@ -149,9 +165,8 @@ impl NoiseWrapper {
         Ok((len, buf))
     }
 
-    fn noise_builder(params: &HandshakeParams) -> NoiseBuilder {
-        let public_key = params.public_key.as_ref();
-        NoiseBuilder::new(PARAMS.parse().unwrap()).remote_public_key(public_key)
+    fn noise_builder() -> NoiseBuilder {
+        NoiseBuilder::new(PARAMS.parse().unwrap())
     }
 }
 
@@ -0,0 +1,256 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-peer rate limiting of the incoming traffic.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crypto::PublicKey;
+use helpers::Milliseconds;
+
+/// Limits of the incoming traffic for a single peer.
+///
+/// Messages which exceed the limits are dropped, and each dropped message increases
+/// the score of the peer. The peer is temporarily banned once its score reaches `ban_score`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PeerRateLimit {
+    /// Average number of messages per second a peer is allowed to send.
+    pub messages_per_second: u32,
+    /// Maximum number of messages a peer is allowed to send at once.
+    pub messages_burst: u32,
+    /// Average number of bytes per second a peer is allowed to send.
+    pub bytes_per_second: u32,
+    /// Maximum number of bytes a peer is allowed to send at once. Should not be less
+    /// than `max_message_len` from the `ConsensusConfig`.
+    pub bytes_burst: u32,
+    /// Number of dropped messages after which the peer is banned.
+    pub ban_score: u32,
+    /// Duration of the ban in milliseconds.
+    pub ban_duration: Milliseconds,
+}
+
+impl Default for PeerRateLimit {
+    fn default() -> Self {
+        PeerRateLimit {
+            messages_per_second: 1_000,
+            messages_burst: 5_000,
+            bytes_per_second: 10 * 1024 * 1024,
+            bytes_burst: 20 * 1024 * 1024,
+            ban_score: 100,
+            ban_duration: 60_000,
+        }
+    }
+}
+
+/// Token bucket which is refilled with the constant rate up to its capacity.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, capacity: u32, now: Instant) -> Self {
+        TokenBucket {
+            capacity: f64::from(capacity),
+            rate: f64::from(rate),
+            tokens: f64::from(capacity),
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now > self.last_refill {
+            let elapsed = now - self.last_refill;
+            let elapsed =
+                elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+            self.last_refill = now;
+        }
+    }
+
+    fn has(&self, amount: f64) -> bool {
+        self.tokens >= amount
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.tokens -= amount;
+    }
+}
+
+/// Rate limiter of a single connection.
+#[derive(Debug)]
+pub struct PeerLimiter {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+}
+
+impl PeerLimiter {
+    /// Creates a limiter with full buckets.
+    pub fn new(limits: &PeerRateLimit, now: Instant) -> Self {
+        PeerLimiter {
+            messages: TokenBucket::new(limits.messages_per_second, limits.messages_burst, now),
+            bytes: TokenBucket::new(limits.bytes_per_second, limits.bytes_burst, now),
+        }
+    }
+
+    /// Returns `true` if a message of the given length fits into the limits.
+    pub fn allow(&mut self, len: usize, now: Instant) -> bool {
+        self.messages.refill(now);
+        self.bytes.refill(now);
+        let len = len as f64;
+        if self.messages.has(1.0) && self.bytes.has(len) {
+            self.messages.take(1.0);
+            self.bytes.take(len);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PeerBansInner {
+    scores: HashMap<PublicKey, u32>,
+    bans: HashMap<PublicKey, Instant>,
+}
+
+/// Scores of misbehaving peers and their temporary bans, shared between the connections
+/// of the network part.
+#[derive(Debug, Clone)]
+pub struct PeerBans {
+    limits: PeerRateLimit,
+    inner: Rc<RefCell<PeerBansInner>>,
+}
+
+impl PeerBans {
+    /// Creates an empty registry.
+    pub fn new(limits: PeerRateLimit) -> Self {
+        PeerBans {
+            limits,
+            inner: Rc::default(),
+        }
+    }
+
+    /// Returns limits of the incoming traffic.
+    pub fn limits(&self) -> &PeerRateLimit {
+        &self.limits
+    }
+
+    /// Returns `true` if the peer is banned at the given moment.
+    pub fn is_banned(&self, peer: &PublicKey, now: Instant) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        match inner.bans.get(peer).cloned() {
+            Some(until) if until > now => true,
+            Some(_) => {
+                inner.bans.remove(peer);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Increases the score of the peer. Returns `true` if the peer is banned as a result.
+    pub fn penalize(&self, peer: &PublicKey, now: Instant) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let banned = {
+            let score = inner.scores.entry(*peer).or_insert(0);
+            *score += 1;
+            *score >= self.limits.ban_score
+        };
+        if banned {
+            inner.scores.remove(peer);
+            let until = now + Duration::from_millis(self.limits.ban_duration);
+            inner.bans.insert(*peer, until);
+        }
+        banned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crypto::gen_keypair;
+    use super::{PeerBans, PeerLimiter, PeerRateLimit};
+
+    fn limits() -> PeerRateLimit {
+        PeerRateLimit {
+            messages_per_second: 10,
+            messages_burst: 20,
+            bytes_per_second: 1_000,
+            bytes_burst: 2_000,
+            ban_score: 3,
+            ban_duration: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_limiter_messages() {
+        let now = Instant::now();
+        let mut limiter = PeerLimiter::new(&limits(), now);
+        for _ in 0..20 {
+            assert!(limiter.allow(1, now));
+        }
+        assert!(!limiter.allow(1, now));
+
+        let now = now + Duration::from_millis(100);
+        assert!(limiter.allow(1, now));
+        assert!(!limiter.allow(1, now));
+
+        let now = now + Duration::from_secs(10);
+        for _ in 0..20 {
+            assert!(limiter.allow(1, now));
+        }
+        assert!(!limiter.allow(1, now));
+    }
+
+    #[test]
+    fn test_limiter_bytes() {
+        let now = Instant::now();
+        let mut limiter = PeerLimiter::new(&limits(), now);
+        assert!(limiter.allow(1_500, now));
+        assert!(!limiter.allow(1_000, now));
+        assert!(limiter.allow(500, now));
+
+        let now = now + Duration::from_millis(500);
+        assert!(!limiter.allow(1_000, now));
+        assert!(limiter.allow(500, now));
+    }
+
+    #[test]
+    fn test_bans() {
+        let (peer, _) = gen_keypair();
+        let (other, _) = gen_keypair();
+        let bans = PeerBans::new(limits());
+        let now = Instant::now();
+
+        assert!(!bans.penalize(&peer, now));
+        assert!(!bans.penalize(&peer, now));
+        assert!(!bans.penalize(&other, now));
+        assert!(!bans.is_banned(&peer, now));
+        assert!(bans.penalize(&peer, now));
+        assert!(bans.is_banned(&peer, now));
+        assert!(!bans.is_banned(&other, now));
+
+        let now = now + Duration::from_millis(1_000);
+        assert!(!bans.is_banned(&peer, now));
+        assert!(!bans.penalize(&peer, now));
+    }
+}
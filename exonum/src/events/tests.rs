@@ -21,12 +21,13 @@ use std::net::SocketAddr;
 use std::thread;
 use std::time::{self, Duration};
 
-use crypto::{gen_keypair, gen_keypair_from_seed, PublicKey, Seed, Signature};
+use crypto::{gen_keypair, PublicKey, SecretKey, Signature};
 use messages::{Connect, Message, MessageWriter, RawMessage};
 use events::{NetworkEvent, NetworkRequest};
 use events::network::{NetworkConfiguration, NetworkPart};
+use events::rate_limit::PeerRateLimit;
 use events::error::log_error;
-use node::{EventsPoolCapacity, NodeChannel};
+use node::{EventsPoolCapacity, NodeChannel, SharedWhitelist, Whitelist};
 use blockchain::ConsensusConfig;
 use helpers::user_agent;
 use events::noise::HandshakeParams;
@@ -34,14 +35,14 @@ use events::noise::HandshakeParams;
 #[derive(Debug)]
 pub struct TestHandler {
     handle: Option<thread::JoinHandle<()>>,
-    listen_address: SocketAddr,
+    connect: Connect,
     network_events_rx: Wait<TimeoutStream<mpsc::Receiver<NetworkEvent>>>,
     network_requests_tx: mpsc::Sender<NetworkRequest>,
 }
 
 impl TestHandler {
     pub fn new(
-        listen_address: SocketAddr,
+        connect: Connect,
         network_requests_tx: mpsc::Sender<NetworkRequest>,
         network_events_rx: mpsc::Receiver<NetworkEvent>,
     ) -> TestHandler {
@@ -49,7 +50,7 @@ impl TestHandler {
         let receiver = timer.timeout_stream(network_events_rx, Duration::from_secs(30));
         TestHandler {
             handle: None,
-            listen_address,
+            connect,
             network_requests_tx,
             network_events_rx: receiver.wait(),
         }
@@ -61,10 +62,18 @@ impl TestHandler {
     }
 
     pub fn connect_with(&self, addr: SocketAddr) {
-        let connect = connect_message(self.listen_address);
         self.network_requests_tx
             .clone()
-            .send(NetworkRequest::SendMessage(addr, connect.raw().clone()))
+            .send(NetworkRequest::SendMessage(addr, self.connect.raw().clone(), None))
+            .wait()
+            .unwrap();
+    }
+
+    pub fn connect_with_key(&self, addr: SocketAddr, public_key: PublicKey) {
+        let raw = self.connect.raw().clone();
+        self.network_requests_tx
+            .clone()
+            .send(NetworkRequest::SendMessage(addr, raw, Some(public_key)))
             .wait()
             .unwrap();
     }
@@ -80,14 +89,14 @@ impl TestHandler {
     pub fn send_to(&self, addr: SocketAddr, raw: RawMessage) {
         self.network_requests_tx
             .clone()
-            .send(NetworkRequest::SendMessage(addr, raw))
+            .send(NetworkRequest::SendMessage(addr, raw, None))
             .wait()
             .unwrap();
     }
 
     pub fn wait_for_connect(&mut self) -> Connect {
         match self.wait_for_event() {
-            Ok(NetworkEvent::PeerConnected(_addr, connect)) => connect,
+            Ok(NetworkEvent::PeerConnected(_addr, connect, _)) => connect,
             Ok(other) => panic!("Unexpected connect received, {:?}", other),
             Err(e) => panic!("An error during wait for connect occurred, {:?}", e),
         }
@@ -132,27 +141,41 @@ pub struct TestEvents {
     pub listen_address: SocketAddr,
     pub network_config: NetworkConfiguration,
     pub events_config: EventsPoolCapacity,
+    /// Public key in the `Connect` message of the node.
+    pub public_key: PublicKey,
+    /// Secret key the static Noise key of the node is derived from.
+    pub secret_key: SecretKey,
+    pub whitelist: Whitelist,
 }
 
 impl TestEvents {
     pub fn with_addr(listen_address: SocketAddr) -> TestEvents {
+        let (public_key, secret_key) = gen_keypair();
         TestEvents {
             listen_address,
             network_config: NetworkConfiguration::default(),
             events_config: EventsPoolCapacity::default(),
+            public_key,
+            secret_key,
+            whitelist: Whitelist::default(),
         }
     }
 
+    pub fn connect_message(&self) -> Connect {
+        connect_message(self.listen_address, &self.public_key)
+    }
+
     pub fn spawn(self) -> TestHandler {
+        let handshake_params = HandshakeParams {
+            public_key: self.public_key,
+            secret_key: self.secret_key.clone(),
+            max_message_len: ConsensusConfig::DEFAULT_MAX_MESSAGE_LEN,
+            whitelist: SharedWhitelist::new(self.whitelist.clone()),
+            compression: Default::default(),
+        };
         let (mut handler_part, network_part) = self.into_reactor();
         let handle = thread::spawn(move || {
             let mut core = Core::new().unwrap();
-            let (public_key, secret_key) = gen_keypair_from_seed(&Seed::new([0; 32]));
-            let handshake_params = HandshakeParams {
-                public_key,
-                secret_key,
-                max_message_len: network_part.max_message_len,
-            };
             let fut = network_part.run(&core.handle(), &handshake_params);
             core.run(fut).map_err(log_error).unwrap();
         });
@@ -166,24 +189,26 @@ impl TestEvents {
         let (network_tx, network_rx) = channel.network_events;
         let network_requests_tx = channel.network_requests.0.clone();
 
+        let connect = self.connect_message();
         let network_part = NetworkPart {
-            our_connect_message: connect_message(self.listen_address),
+            our_connect_message: connect.clone(),
             listen_address: self.listen_address,
             network_config,
             max_message_len: ConsensusConfig::DEFAULT_MAX_MESSAGE_LEN,
             network_requests: channel.network_requests,
             network_tx: network_tx.clone(),
+            queue_depths: Default::default(),
         };
 
-        let handler_part = TestHandler::new(self.listen_address, network_requests_tx, network_rx);
+        let handler_part = TestHandler::new(connect, network_requests_tx, network_rx);
         (handler_part, network_part)
     }
 }
 
-pub fn connect_message(addr: SocketAddr) -> Connect {
+pub fn connect_message(addr: SocketAddr, public_key: &PublicKey) -> Connect {
     let time = time::UNIX_EPOCH;
     Connect::new_with_signature(
-        &PublicKey::zero(),
+        public_key,
         addr,
         time.into(),
         &user_agent::get(),
@@ -204,8 +229,8 @@ fn test_network_handshake() {
     let e1 = TestEvents::with_addr(first);
     let e2 = TestEvents::with_addr(second);
 
-    let c1 = connect_message(first);
-    let c2 = connect_message(second);
+    let c1 = e1.connect_message();
+    let c2 = e2.connect_message();
 
     let mut e1 = e1.spawn();
     let mut e2 = e2.spawn();
@@ -304,9 +329,10 @@ fn test_network_reconnect() {
     let second = "127.0.0.1:19101".parse().unwrap();
 
     let msg = raw_message(11, 1000);
-    let c1 = connect_message(first);
+    let t1 = TestEvents::with_addr(first);
+    let c1 = t1.connect_message();
 
-    let mut t1 = TestEvents::with_addr(first).spawn();
+    let mut t1 = t1.spawn();
 
     // First connect attempt.
     let mut t2 = TestEvents::with_addr(second).spawn();
@@ -347,11 +373,9 @@ fn test_network_multiple_connect() {
 
     let mut node = TestEvents::with_addr(main).spawn();
 
-    let connect_messages: Vec<_> = nodes.iter().cloned().map(connect_message).collect();
-    let connectors: Vec<_> = nodes
-        .iter()
-        .map(|addr| TestEvents::with_addr(*addr).spawn())
-        .collect();
+    let events: Vec<_> = nodes.iter().cloned().map(TestEvents::with_addr).collect();
+    let connect_messages: Vec<_> = events.iter().map(TestEvents::connect_message).collect();
+    let connectors: Vec<_> = events.into_iter().map(TestEvents::spawn).collect();
 
     connectors[0].connect_with(main);
     assert_eq!(node.wait_for_connect(), connect_messages[0]);
@@ -367,11 +391,136 @@ fn test_send_first_not_connect() {
     let other = "127.0.0.1:19501".parse().unwrap();
 
     let mut node = TestEvents::with_addr(main).spawn();
-    let other_node = TestEvents::with_addr(other).spawn();
+    let other_node = TestEvents::with_addr(other);
+    let other_connect = other_node.connect_message();
+    let other_node = other_node.spawn();
 
     let message = raw_message(11, 1000);
     other_node.send_to(main, message.clone()); // should connect before send message
 
-    assert_eq!(node.wait_for_connect(), connect_message(other));
+    assert_eq!(node.wait_for_connect(), other_connect);
     assert_eq!(node.wait_for_message(), message);
 }
+
+#[test]
+fn test_connect_key_mismatch() {
+    let first = "127.0.0.1:19700".parse().unwrap();
+    let second = "127.0.0.1:19701".parse().unwrap();
+    let third = "127.0.0.1:19702".parse().unwrap();
+
+    // The `Connect` message is not signed by the owner of the static Noise key.
+    let mut e1 = TestEvents::with_addr(first);
+    e1.public_key = gen_keypair().0;
+    let e3 = TestEvents::with_addr(third);
+    let c3 = e3.connect_message();
+
+    let mut e1 = e1.spawn();
+    let mut e2 = TestEvents::with_addr(second).spawn();
+    let e3 = e3.spawn();
+
+    e1.connect_with(second);
+    assert_eq!(e1.wait_for_disconnect(), second);
+
+    e3.connect_with(second);
+    assert_eq!(e2.wait_for_connect(), c3);
+}
+
+#[test]
+fn test_outgoing_static_key_mismatch() {
+    let first = "127.0.0.1:19703".parse().unwrap();
+    let second = "127.0.0.1:19704".parse().unwrap();
+
+    let e1 = TestEvents::with_addr(first);
+    let e2 = TestEvents::with_addr(second);
+    let c1 = e1.connect_message();
+    let second_key = e2.public_key;
+
+    let mut e1 = e1.spawn();
+    let mut e2 = e2.spawn();
+
+    // Another node answers at the address of the expected peer.
+    e1.connect_with_key(second, gen_keypair().0);
+    assert_eq!(e1.wait_for_disconnect(), second);
+
+    e1.connect_with_key(second, second_key);
+    assert_eq!(e2.wait_for_connect(), c1);
+}
+
+#[test]
+fn test_whitelist_before_connect() {
+    let first = "127.0.0.1:19710".parse().unwrap();
+    let second = "127.0.0.1:19711".parse().unwrap();
+    let third = "127.0.0.1:19712".parse().unwrap();
+
+    let e1 = TestEvents::with_addr(first);
+    let mut e2 = TestEvents::with_addr(second);
+    let e3 = TestEvents::with_addr(third);
+    let c3 = e3.connect_message();
+
+    e2.whitelist.set_enabled(true);
+    e2.whitelist.add(e3.public_key);
+
+    let mut e1 = e1.spawn();
+    let mut e2 = e2.spawn();
+    let e3 = e3.spawn();
+
+    // Incoming connection from the peer which is not in whitelist.
+    e1.connect_with(second);
+    assert_eq!(e1.wait_for_disconnect(), second);
+
+    e3.connect_with(second);
+    assert_eq!(e2.wait_for_connect(), c3);
+
+    // Outgoing connection to the peer which is not in whitelist.
+    e2.connect_with(first);
+    assert_eq!(e2.wait_for_disconnect(), first);
+}
+
+#[test]
+fn test_peer_rate_limit_ban() {
+    let first = "127.0.0.1:19720".parse().unwrap();
+    let second = "127.0.0.1:19721".parse().unwrap();
+    let third = "127.0.0.1:19722".parse().unwrap();
+
+    let e1 = TestEvents::with_addr(first);
+    let mut e2 = TestEvents::with_addr(second);
+    let e3 = TestEvents::with_addr(third);
+    let spammer = e1.public_key;
+    let c3 = e3.connect_message();
+
+    e2.network_config.peer_rate_limit = PeerRateLimit {
+        messages_per_second: 1,
+        messages_burst: 5,
+        ban_score: 3,
+        ..PeerRateLimit::default()
+    };
+
+    let e1 = e1.spawn();
+    let mut e2 = e2.spawn();
+    let e3 = e3.spawn();
+
+    e1.connect_with(second);
+    assert_eq!(e2.wait_for_connect(), e1.connect);
+    for i in 0..20 {
+        e1.send_to(second, raw_message(i, 100));
+    }
+
+    let mut received = 0;
+    loop {
+        match e2.wait_for_event() {
+            Ok(NetworkEvent::MessageReceived(..)) => received += 1,
+            Ok(NetworkEvent::PeerBanned(_, key, duration)) => {
+                assert_eq!(key, spammer);
+                assert_eq!(duration, PeerRateLimit::default().ban_duration);
+                break;
+            }
+            other => panic!("Unexpected event received, {:?}", other),
+        }
+    }
+    assert!(received >= 5 && received < 20);
+
+    // Connections of the banned peer are rejected.
+    e1.connect_with(second);
+    e3.connect_with(second);
+    assert_eq!(e2.wait_for_connect(), c3);
+}
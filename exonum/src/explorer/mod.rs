@@ -30,7 +30,8 @@ use blockchain::{Block, Blockchain, Schema, Transaction, TransactionError, Trans
                  TransactionResult, TxLocation};
 use encoding;
 use helpers::Height;
-use messages::{Precommit, RawMessage};
+use messages::{Message, Precommit, RawMessage, TransactionBatch, CONSENSUS,
+               TRANSACTION_BATCH_MESSAGE_ID};
 use storage::{ListProof, Snapshot};
 
 /// Transaction parsing result.
@@ -343,6 +344,7 @@ impl<'a, T> IntoIterator for &'a BlockWithTransactions<T> {
 /// | `location` | [`TxLocation`] | Location of the transaction in the block |
 /// | `location_proof` | [`ListProof`]`<`[`Hash`]`>` | Proof of transaction inclusion into a block |
 /// | `status` | (custom; see below) | Execution status |
+/// | `batch` | (custom; see below) | Statuses of transactions in a batch |
 ///
 /// ## `status` field
 ///
@@ -369,6 +371,33 @@ impl<'a, T> IntoIterator for &'a BlockWithTransactions<T> {
 /// { type: 'panic', description?: string }
 /// ```
 ///
+/// Transactions of a failed batch that have not caused the failure themselves have
+/// the following status:
+///
+/// ```javascript
+/// { type: 'batch-rollback', description?: string }
+/// ```
+///
+/// Transaction envelopes which have been committed too late or with a wrong nonce have
+/// the following statuses correspondingly:
+///
+/// ```javascript
+/// { type: 'expired', description?: string }
+/// { type: 'invalid-nonce', description?: string }
+/// ```
+///
+/// ## `batch` field
+///
+/// The `batch` field is present only for `TransactionBatch` messages. It lists the hashes
+/// of the inner transactions in the order of their execution together with their statuses:
+///
+/// ```javascript
+/// { hash: string, status: object }[]
+/// ```
+///
+/// Transactions wrapped into a batch or an envelope share its `location` and
+/// `location_proof`.
+///
 /// [`Transaction`]: ../blockchain/trait.Transaction.html
 /// [`TxLocation`]: ../blockchain/struct.TxLocation.html
 /// [`ListProof`]: ../storage/enum.ListProof.html
@@ -432,28 +461,61 @@ impl<'a, T> IntoIterator for &'a BlockWithTransactions<T> {
 /// assert_eq!(parsed.content().name(), "Alice");
 /// # } // main
 /// ```
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
 #[serde(bound(serialize = "T: SerializeContent"))]
+#[api_schema(bound = "")]
 pub struct CommittedTransaction<T = Box<Transaction>> {
     #[serde(serialize_with = "SerializeContent::serialize_content")]
+    #[api_schema(with = "::serde_json::Value")]
     content: T,
     location: TxLocation,
+    #[api_schema(with = "::serde_json::Value")]
     location_proof: ListProof<Hash>,
     #[serde(with = "TxStatus")]
+    #[api_schema(with = "TxStatus<'static>")]
+    status: TransactionResult,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    batch: Vec<BatchedTransaction>,
+}
+
+/// Status of a transaction executed as a part of a batch.
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+pub struct BatchedTransaction {
+    hash: Hash,
+    #[serde(with = "TxStatus")]
+    #[api_schema(with = "TxStatus<'static>")]
     status: TransactionResult,
 }
 
+impl BatchedTransaction {
+    /// Returns the hash of the transaction.
+    pub fn hash(&self) -> &Hash {
+        &self.hash
+    }
+
+    /// Returns the status of the transaction execution.
+    pub fn status(&self) -> Result<(), &TransactionError> {
+        self.status.as_ref().map(|_| ())
+    }
+}
+
 /// Transaction execution status. Simplified version of `TransactionResult`.
 #[serde(tag = "type", rename_all = "kebab-case")]
-#[derive(Debug, Serialize, Deserialize)]
-enum TxStatus<'a> {
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
+pub(crate) enum TxStatus<'a> {
     Success,
     Panic { description: &'a str },
+    BatchRollback { description: &'a str },
+    Expired { description: &'a str },
+    InvalidNonce { description: &'a str },
     Error { code: u8, description: &'a str },
 }
 
 impl<'a> TxStatus<'a> {
-    fn serialize<S>(result: &TransactionResult, serializer: S) -> Result<S::Ok, S::Error>
+    pub(crate) fn serialize<S>(
+        result: &TransactionResult,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
@@ -480,6 +542,9 @@ impl<'a> From<&'a TransactionResult> for TxStatus<'a> {
                 let description = e.description().unwrap_or_default();
                 match e.error_type() {
                     Panic => TxStatus::Panic { description },
+                    BatchRollback => TxStatus::BatchRollback { description },
+                    Expired => TxStatus::Expired { description },
+                    InvalidNonce => TxStatus::InvalidNonce { description },
                     Code(code) => TxStatus::Error { code, description },
                 }
             }
@@ -500,6 +565,15 @@ impl<'a> From<TxStatus<'a>> for TransactionResult {
         match status {
             TxStatus::Success => Ok(()),
             TxStatus::Panic { description } => Err(TransactionError::panic(to_option(description))),
+            TxStatus::BatchRollback { description } => {
+                Err(TransactionError::batch_rollback(to_option(description)))
+            }
+            TxStatus::Expired { description } => {
+                Err(TransactionError::expired(to_option(description)))
+            }
+            TxStatus::InvalidNonce { description } => {
+                Err(TransactionError::invalid_nonce(to_option(description)))
+            }
             TxStatus::Error { code, description } => {
                 Err(TransactionError::code(code, to_option(description)))
             }
@@ -527,6 +601,11 @@ impl<T> CommittedTransaction<T> {
     pub fn status(&self) -> Result<(), &TransactionError> {
         self.status.as_ref().map(|_| ())
     }
+
+    /// Returns the statuses of the inner transactions if the transaction is a batch.
+    pub fn batch(&self) -> &[BatchedTransaction] {
+        &self.batch
+    }
 }
 
 /// Information about the transaction.
@@ -603,13 +682,15 @@ impl<T> CommittedTransaction<T> {
 /// assert_eq!(parsed.content().name(), "Alice");
 /// # } // main
 /// ```
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ApiSchema)]
 #[serde(tag = "type", rename_all = "kebab-case", bound(serialize = "T: SerializeContent"))]
+#[api_schema(bound = "")]
 pub enum TransactionInfo<T = Box<Transaction>> {
     /// Transaction is in the memory pool, but not yet committed to the blockchain.
     InPool {
         /// Transaction contents.
         #[serde(serialize_with = "SerializeContent::serialize_content")]
+        #[api_schema(with = "::serde_json::Value")]
         content: T,
     },
 
@@ -785,15 +866,35 @@ impl<'a> BlockchainExplorer<'a> {
         // Unwrap is OK here, because we already know that transaction is committed.
         let status = schema.transaction_results().get(tx_hash).unwrap();
 
+        let raw_tx = schema.transactions().get(tx_hash).unwrap();
+        let batch = if raw_tx.service_id() == CONSENSUS
+            && raw_tx.message_type() == TRANSACTION_BATCH_MESSAGE_ID
+        {
+            TransactionBatch::from_raw(raw_tx.clone())
+                .expect("Committed batch cannot be parsed")
+                .transactions()
+                .iter()
+                .map(|tx| tx.hash())
+                // A batch rejected as a whole does not touch its inner transactions.
+                .filter(|hash| {
+                    schema.transactions_locations().get(hash).as_ref() == Some(&location)
+                })
+                .map(|hash| BatchedTransaction {
+                    hash,
+                    status: schema.transaction_results().get(&hash).unwrap(),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
         CommittedTransaction {
-            content: maybe_content.unwrap_or_else(|| {
-                let raw_tx = schema.transactions().get(tx_hash).unwrap();
-                (self.transaction_parser)(raw_tx).unwrap()
-            }),
+            content: maybe_content.unwrap_or_else(|| (self.transaction_parser)(raw_tx).unwrap()),
 
             location,
             location_proof,
             status,
+            batch,
         }
     }
 
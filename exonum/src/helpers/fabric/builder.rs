@@ -23,8 +23,8 @@ use node::Node;
 use super::internal::{CollectedCommand, Feedback};
 use super::clap_backend::ClapBackend;
 use super::ServiceFactory;
-use super::details::{Finalize, GenerateCommonConfig, GenerateNodeConfig, GenerateTestnet, Run,
-                     RunDev};
+use super::details::{Finalize, GenerateCommonConfig, GenerateKey, GenerateNodeConfig,
+                     GenerateTestnet, Run, RunDev};
 use super::maintenance::Maintenance;
 use super::keys;
 use super::CommandName;
@@ -105,7 +105,7 @@ impl NodeBuilder {
         panic::set_hook(old_hook);
 
         if let Some(node) = feedback {
-            node.run().expect("Node return error")
+            node.run().expect("Node return error");
         }
     }
 
@@ -126,6 +126,10 @@ impl NodeBuilder {
             CollectedCommand::new(Box::new(GenerateCommonConfig)),
         );
         commands.insert(Finalize::name(), CollectedCommand::new(Box::new(Finalize)));
+        commands.insert(
+            GenerateKey::name(),
+            CollectedCommand::new(Box::new(GenerateKey)),
+        );
         commands.insert(
             Maintenance::name(),
             CollectedCommand::new(Box::new(Maintenance)),
@@ -19,14 +19,14 @@
 
 use toml;
 
-use std::fs;
+use std::{env, fs};
 use std::path::{Path, PathBuf};
 use std::net::{IpAddr, SocketAddr};
 use std::collections::{BTreeMap, HashMap};
 
 use blockchain::{GenesisConfig, config::ValidatorKeys};
 use helpers::{generate_testnet_config, config::ConfigFile};
-use node::{AllowOrigin, NodeApiConfig, NodeConfig};
+use node::{AllowOrigin, EncryptedKey, NodeApiConfig, NodeConfig, KEYSTORE_PASSPHRASE_ENV};
 use storage::{Database, DbOptions, RocksDB};
 use crypto;
 use super::internal::{CollectedCommand, Command, Feedback};
@@ -421,6 +421,7 @@ impl Command for GenerateNodeConfig {
         let validator_keys = ValidatorKeys {
             consensus_key: consensus_public_key,
             service_key: service_public_key,
+            weight: ValidatorKeys::DEFAULT_WEIGHT,
         };
         let node_pub_config = NodePublicConfig {
             addr: addr.0,
@@ -625,6 +626,8 @@ impl Command for Finalize {
                 network: Default::default(),
                 whitelist: Default::default(),
                 peers,
+                peer_list: None,
+                keystore: None,
                 consensus_public_key: secret_config.consensus_public_key,
                 consensus_secret_key: secret_config.consensus_secret_key,
                 service_public_key: secret_config.service_public_key,
@@ -740,3 +743,50 @@ impl Command for GenerateTestnet {
         Feedback::None
     }
 }
+
+/// Command for the encrypted key file generation.
+pub struct GenerateKey;
+
+impl GenerateKey {
+    /// Returns the name of the `GenerateKey` command.
+    pub fn name() -> CommandName {
+        "generate-key"
+    }
+}
+
+impl Command for GenerateKey {
+    fn args(&self) -> Vec<Argument> {
+        vec![
+            Argument::new_positional("KEY_PATH", true, "Path where save encrypted key."),
+        ]
+    }
+
+    fn name(&self) -> CommandName {
+        Self::name()
+    }
+
+    fn about(&self) -> &str {
+        "Generate a signing key encrypted with the passphrase from the \
+         EXONUM_KEYSTORE_PASSPHRASE environment variable."
+    }
+
+    fn execute(
+        &self,
+        _commands: &HashMap<CommandName, CollectedCommand>,
+        context: Context,
+        _exts: &Fn(Context) -> Context,
+    ) -> Feedback {
+        let key_path = context
+            .arg::<String>("KEY_PATH")
+            .expect("KEY_PATH not found");
+        let passphrase = env::var(KEYSTORE_PASSPHRASE_ENV)
+            .unwrap_or_else(|_| panic!("{} is not set", KEYSTORE_PASSPHRASE_ENV));
+
+        let (public_key, secret_key) = crypto::gen_keypair();
+        EncryptedKey::encrypt(&public_key, &secret_key, passphrase.as_bytes())
+            .save(&key_path)
+            .expect("Could not write key file.");
+        println!("{}", public_key.to_hex());
+        Feedback::None
+    }
+}
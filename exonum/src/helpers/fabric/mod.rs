@@ -15,7 +15,8 @@
 //! Command line commands utilities.
 
 pub use self::builder::NodeBuilder;
-pub use self::details::{Finalize, GenerateCommonConfig, GenerateNodeConfig, GenerateTestnet, Run};
+pub use self::details::{Finalize, GenerateCommonConfig, GenerateKey, GenerateNodeConfig,
+                        GenerateTestnet, Run};
 pub use self::maintenance::Maintenance;
 pub use self::shared::{AbstractConfig, CommonConfigTemplate, NodePrivateConfig, NodePublicConfig};
 pub use self::context_key::ContextKey;
@@ -13,18 +13,48 @@
 // limitations under the License.
 
 //! Utilities for collecting metrics.
+//!
+//! Each node collects its metrics into its own [`Registry`], which is available through
+//! `Blockchain::metrics`. Metrics are registered once and updated through the returned
+//! handles with atomic operations, so updates do not take locks or allocate. The private
+//! API of the node exports the registry in [the Prometheus text format][prometheus] at
+//! the `/metrics` path.
+//!
+//! The [`metric!`] macro only writes values to the trace log with the `metrics-log` feature.
+//!
+//! [`Registry`]: struct.Registry.html
+//! [`metric!`]: ../../macro.metric.html
+//! [prometheus]: https://prometheus.io/docs/instrumenting/exposition_formats/
 
-use chrono::offset::Utc;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Prefix of the metric names in the exported registry.
+const NAMESPACE: &str = "exonum";
+
+/// Suffix of the exported counter names.
+const COUNTER_SUFFIX: &str = "_total";
+
+/// Upper bounds of the histogram buckets, in seconds.
+const HISTOGRAM_BUCKETS: &[f64] = &[
+    0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0
+];
+
+/// Histogram sums are accumulated in millionths of the observed unit.
+const HISTOGRAM_SUM_SCALE: f64 = 1_000_000.0;
 
 /// Adds given metric with given value.
 ///
 /// Metric name should be in the following format: `module_name.metric_name`, where `module_name`
 /// is a high level name. For example `storage` or `node` (not `storage_proof_list_index`).
 ///
-/// Value is a string and can be formatted similar to the `println!`. See `std::fmt` and example
-/// for details.
+/// Metrics output direction is determined by the corresponding `metrics-...` feature. Use
+/// [`Registry`] to export metrics through the node API.
 ///
-/// Metrics output direction is determined by the corresponding `metrics-...` feature. If
+/// [`Registry`]: helpers/metrics/struct.Registry.html
 ///
 /// # Examples
 ///
@@ -47,10 +77,326 @@ macro_rules! metric {
 #[doc(hidden)]
 #[allow(unused_variables)]
 pub fn add_metric(metric_name: &str, value: i64) {
-    let time = format!("{:?}", Utc::now());
-
     #[cfg(feature = "metrics-log")]
     {
-        trace!("{} {} {}", metric_name, value, time);
+        trace!(
+            "{} {} {:?}",
+            metric_name,
+            value,
+            ::chrono::offset::Utc::now()
+        );
+    }
+}
+
+/// Converts the duration to seconds observed by histograms.
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+/// Counter that can only be incremented.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicUsize>);
+
+impl Counter {
+    /// Increments the counter by one.
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of the counter.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Gauge that is set to arbitrary values.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicIsize>);
+
+impl Gauge {
+    /// Sets the value of the gauge.
+    pub fn set(&self, value: i64) {
+        self.0.store(value as isize, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of the gauge.
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed) as i64
+    }
+}
+
+/// Histogram of non-negative values, usually durations in seconds.
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramValues>);
+
+#[derive(Debug)]
+struct HistogramValues {
+    // Cumulative counts of the observations in `HISTOGRAM_BUCKETS`.
+    buckets: Vec<AtomicUsize>,
+    sum: AtomicUsize,
+    count: AtomicUsize,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram(Arc::new(HistogramValues {
+            buckets: HISTOGRAM_BUCKETS
+                .iter()
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+            sum: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+        }))
+    }
+}
+
+impl Histogram {
+    /// Adds the observed value to the histogram.
+    pub fn observe(&self, value: f64) {
+        let values = &self.0;
+        for (bucket, bound) in values.buckets.iter().zip(HISTOGRAM_BUCKETS) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let scaled = (value.max(0.0) * HISTOGRAM_SUM_SCALE).round() as usize;
+        values.sum.fetch_add(scaled, Ordering::Relaxed);
+        values.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of observed values.
+    pub fn count(&self) -> usize {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the sum of observed values.
+    pub fn sum(&self) -> f64 {
+        self.0.sum.load(Ordering::Relaxed) as f64 / HISTOGRAM_SUM_SCALE
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Metric {
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+}
+
+/// Metrics of a node exported in the Prometheus text format.
+///
+/// Registering a metric with the same name and labels again returns the handle to the same
+/// values. Metric names should follow the format of the [`metric!`] macro; they are prefixed
+/// with `exonum_` on export, and dots in them are replaced with underscores.
+///
+/// [`metric!`]: ../../macro.metric.html
+///
+/// # Examples
+///
+/// ```
+/// use exonum::helpers::metrics::Registry;
+///
+/// let registry = Registry::new();
+/// let requests = registry.counter("mod_name.requests", &[("method", "GET")]);
+/// requests.inc();
+/// registry.gauge("mod_name.metric_name", &[]).set(10);
+///
+/// let text = registry.prometheus_text();
+/// assert!(text.contains("exonum_mod_name_requests_total{method=\"GET\"} 1"));
+/// assert!(text.contains("exonum_mod_name_metric_name 10"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    families: Arc<Mutex<BTreeMap<String, BTreeMap<Labels, Metric>>>>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Registers the counter with the given name and labels.
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> Counter {
+        match self.register(name, labels, Metric::Counter(Counter::default())) {
+            Metric::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Registers the gauge with the given name and labels.
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> Gauge {
+        match self.register(name, labels, Metric::Gauge(Gauge::default())) {
+            Metric::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Registers the histogram with the given name and labels.
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)]) -> Histogram {
+        match self.register(name, labels, Metric::Histogram(Histogram::default())) {
+            Metric::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the registered metrics in the Prometheus text format.
+    pub fn prometheus_text(&self) -> String {
+        let families = self.families.lock().expect("Expected metrics lock");
+        let mut text = String::new();
+        for (name, family) in families.iter() {
+            let type_name = match family.values().next() {
+                Some(metric) => metric.type_name(),
+                None => continue,
+            };
+            let mut name = format!("{}_{}", NAMESPACE, name.replace('.', "_"));
+            if type_name == "counter" && !name.ends_with(COUNTER_SUFFIX) {
+                name.push_str(COUNTER_SUFFIX);
+            }
+
+            writeln!(text, "# TYPE {} {}", name, type_name).unwrap();
+            for (labels, metric) in family {
+                match *metric {
+                    Metric::Counter(ref counter) => {
+                        let labels = format_labels(labels, None);
+                        writeln!(text, "{}{} {}", name, labels, counter.get()).unwrap();
+                    }
+                    Metric::Gauge(ref gauge) => {
+                        let labels = format_labels(labels, None);
+                        writeln!(text, "{}{} {}", name, labels, gauge.get()).unwrap();
+                    }
+                    Metric::Histogram(ref histogram) => {
+                        let buckets = &histogram.0.buckets;
+                        for (bound, count) in HISTOGRAM_BUCKETS.iter().zip(buckets) {
+                            let le = bound.to_string();
+                            let labels = format_labels(labels, Some(&le));
+                            let count = count.load(Ordering::Relaxed);
+                            writeln!(text, "{}_bucket{} {}", name, labels, count).unwrap();
+                        }
+                        let count = histogram.count();
+                        let labels_inf = format_labels(labels, Some("+Inf"));
+                        writeln!(text, "{}_bucket{} {}", name, labels_inf, count).unwrap();
+                        let labels = format_labels(labels, None);
+                        writeln!(text, "{}_sum{} {}", name, labels, histogram.sum()).unwrap();
+                        writeln!(text, "{}_count{} {}", name, labels, count).unwrap();
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    // Returns the registered metric or registers the new one. If the metric name has been
+    // registered with another type, the new metric is returned without registration.
+    fn register(&self, name: &str, labels: &[(&str, &str)], metric: Metric) -> Metric {
+        let labels = labels
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        let mut families = self.families.lock().expect("Expected metrics lock");
+        let family = families
+            .entry(name.to_owned())
+            .or_insert_with(BTreeMap::new);
+        let registered_type = family.values().next().map(Metric::type_name);
+        if let Some(registered_type) = registered_type {
+            if registered_type != metric.type_name() {
+                error!(
+                    "Metric {} has been registered as {}, not {}",
+                    name,
+                    registered_type,
+                    metric.type_name()
+                );
+                return metric;
+            }
+        }
+        family.entry(labels).or_insert(metric).clone()
+    }
+}
+
+fn format_labels(labels: &[(String, String)], le: Option<&str>) -> String {
+    let mut pairs = labels
+        .iter()
+        .map(|&(ref name, ref value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect::<Vec<_>>();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{seconds, Registry};
+
+    #[test]
+    fn test_counters_and_gauges() {
+        let registry = Registry::new();
+        let status = registry.counter("test.messages", &[("type", "Status")]);
+        status.inc();
+        registry.counter("test.messages", &[("type", "Status")]).inc();
+        registry.counter("test.messages", &[("type", "Propose")]).inc();
+        let height = registry.gauge("test.height", &[]);
+        height.set(5);
+        height.set(6);
+        registry.gauge("test.quoted", &[("name", "a \"b\"")]).set(1);
+        assert_eq!(status.get(), 2);
+
+        let text = registry.prometheus_text();
+        assert!(text.contains("# TYPE exonum_test_messages_total counter\n"));
+        assert!(text.contains("exonum_test_messages_total{type=\"Status\"} 2\n"));
+        assert!(text.contains("exonum_test_messages_total{type=\"Propose\"} 1\n"));
+        assert!(text.contains("# TYPE exonum_test_height gauge\nexonum_test_height 6\n"));
+        assert!(text.contains("exonum_test_quoted{name=\"a \\\"b\\\"\"} 1\n"));
+
+        // Registries of different nodes do not share values.
+        let other = Registry::new();
+        other.counter("test.messages", &[("type", "Status")]).inc();
+        assert_eq!(status.get(), 2);
+        assert!(!other.prometheus_text().contains("test_height"));
+    }
+
+    #[test]
+    fn test_histograms() {
+        let registry = Registry::new();
+        let latency = registry.histogram("test.latency", &[("id", "1")]);
+        latency.observe(seconds(Duration::from_millis(250)));
+        latency.observe(2.0);
+        // Metrics of another type are not registered.
+        registry.gauge("test.latency", &[]).set(1);
+
+        let text = registry.prometheus_text();
+        assert!(text.contains("# TYPE exonum_test_latency histogram\n"));
+        assert!(text.contains("exonum_test_latency_bucket{id=\"1\",le=\"0.1\"} 0\n"));
+        assert!(text.contains("exonum_test_latency_bucket{id=\"1\",le=\"0.5\"} 1\n"));
+        assert!(text.contains("exonum_test_latency_bucket{id=\"1\",le=\"5\"} 2\n"));
+        assert!(text.contains("exonum_test_latency_bucket{id=\"1\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("exonum_test_latency_sum{id=\"1\"} 2.25\n"));
+        assert!(text.contains("exonum_test_latency_count{id=\"1\"} 2\n"));
+        assert!(!text.contains("exonum_test_latency 1"));
     }
 }
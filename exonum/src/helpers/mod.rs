@@ -58,6 +58,7 @@ pub fn generate_testnet_config(count: u8, start_port: u16) -> Vec<NodeConfig> {
         ValidatorKeys {
             consensus_key: (x.0).0,
             service_key: (x.1).0,
+            weight: ValidatorKeys::DEFAULT_WEIGHT,
         }
     }));
     let peers = (0..validators.len())
@@ -77,6 +78,8 @@ pub fn generate_testnet_config(count: u8, start_port: u16) -> Vec<NodeConfig> {
             external_address: Some(peers[idx]),
             network: Default::default(),
             peers: peers.clone(),
+            peer_list: None,
+            keystore: None,
             consensus_public_key: validator.0,
             consensus_secret_key: validator.1,
             service_public_key: service.0,
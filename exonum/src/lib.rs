@@ -34,19 +34,28 @@ extern crate colored;
 extern crate cookie;
 extern crate env_logger;
 #[macro_use]
+extern crate exonum_derive;
+#[macro_use]
 extern crate exonum_profiler;
 extern crate exonum_rocksdb as rocksdb;
 extern crate exonum_sodiumoxide as sodiumoxide;
 #[macro_use]
 extern crate failure;
+extern crate flate2;
 extern crate futures;
+extern crate futures_cpupool;
 extern crate hex;
+extern crate http;
 extern crate hyper;
 extern crate iron;
+extern crate iron_hyper;
 extern crate iron_cors;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
 extern crate mount;
+extern crate num_cpus;
 extern crate params;
 extern crate rand;
 extern crate router;
@@ -56,21 +65,22 @@ extern crate serde;
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate serde_urlencoded;
 extern crate snow;
 extern crate term;
 extern crate tokio_core;
 extern crate tokio_io;
 extern crate tokio_retry;
-#[cfg(any(test, feature = "long_benchmarks"))]
+extern crate tokio_signal;
 extern crate tokio_timer;
 extern crate toml;
 extern crate uuid;
 extern crate vec_map;
+extern crate ws;
 
 // Test dependencies.
 #[cfg(test)]
-#[macro_use]
-extern crate lazy_static;
+extern crate iron_test;
 #[cfg(test)]
 extern crate tempdir;
 #[cfg(all(test, feature = "long_benchmarks"))]
@@ -93,3 +103,8 @@ pub mod api;
 mod events;
 #[cfg(test)]
 mod sandbox;
+
+// Lets the code generated by `#[derive(ApiSchema)]` refer to the crate as `::exonum`.
+mod exonum {
+    pub use api;
+}
@@ -61,6 +61,8 @@ pub enum Any {
     Status(Status),
     /// `Block` message.
     Block(BlockResponse),
+    /// `Block` message with the transaction hashes only.
+    CompactBlock(CompactBlockResponse),
     /// Consensus message.
     Consensus(ConsensusMessage),
     /// Request for the some data.
@@ -69,6 +71,8 @@ pub enum Any {
     Transaction(RawTransaction),
     /// A batch of the transactions.
     TransactionsBatch(TransactionsResponse),
+    /// Announcement of the transaction hashes.
+    TransactionsAnnounce(TransactionsAnnounce),
 }
 
 /// Consensus message.
@@ -222,9 +226,15 @@ impl Any {
                 CONNECT_MESSAGE_ID => Any::Connect(Connect::from_raw(raw)?),
                 STATUS_MESSAGE_ID => Any::Status(Status::from_raw(raw)?),
                 BLOCK_RESPONSE_MESSAGE_ID => Any::Block(BlockResponse::from_raw(raw)?),
+                COMPACT_BLOCK_RESPONSE_MESSAGE_ID => {
+                    Any::CompactBlock(CompactBlockResponse::from_raw(raw)?)
+                }
                 TRANSACTIONS_RESPONSE_MESSAGE_ID => {
                     Any::TransactionsBatch(TransactionsResponse::from_raw(raw)?)
                 }
+                TRANSACTIONS_ANNOUNCE_MESSAGE_ID => {
+                    Any::TransactionsAnnounce(TransactionsAnnounce::from_raw(raw)?)
+                }
 
                 PROPOSE_MESSAGE_ID => {
                     Any::Consensus(ConsensusMessage::Propose(Propose::from_raw(raw)?))
@@ -252,6 +262,16 @@ impl Any {
                     Any::Request(RequestMessage::Block(BlockRequest::from_raw(raw)?))
                 }
 
+                TRANSACTION_BATCH_MESSAGE_ID => {
+                    // Batches are processed in the same way as service transactions.
+                    TransactionBatch::from_raw(raw.clone())?;
+                    Any::Transaction(raw)
+                }
+                TRANSACTION_ENVELOPE_MESSAGE_ID => {
+                    TransactionEnvelope::from_raw(raw.clone())?;
+                    Any::Transaction(raw)
+                }
+
                 message_type => {
                     return Err(Error::IncorrectMessageType { message_type });
                 }
@@ -262,3 +282,48 @@ impl Any {
         Ok(msg)
     }
 }
+
+/// Names of the message types returned by `message_name`.
+pub(crate) const MESSAGE_NAMES: &[&str] = &[
+    "connect",
+    "status",
+    "propose",
+    "prevote",
+    "precommit",
+    "block_response",
+    "compact_block_response",
+    "transactions_response",
+    "transactions_announce",
+    "propose_request",
+    "transactions_request",
+    "prevotes_request",
+    "peers_request",
+    "block_request",
+    "transaction",
+    "unknown",
+];
+
+/// Returns the name of the message type used as a metric label.
+pub(crate) fn message_name(raw: &RawMessage) -> &'static str {
+    if raw.service_id() != CONSENSUS {
+        return "transaction";
+    }
+    match raw.message_type() {
+        CONNECT_MESSAGE_ID => "connect",
+        STATUS_MESSAGE_ID => "status",
+        PROPOSE_MESSAGE_ID => "propose",
+        PREVOTE_MESSAGE_ID => "prevote",
+        PRECOMMIT_MESSAGE_ID => "precommit",
+        BLOCK_RESPONSE_MESSAGE_ID => "block_response",
+        COMPACT_BLOCK_RESPONSE_MESSAGE_ID => "compact_block_response",
+        TRANSACTIONS_RESPONSE_MESSAGE_ID => "transactions_response",
+        TRANSACTIONS_ANNOUNCE_MESSAGE_ID => "transactions_announce",
+        PROPOSE_REQUEST_MESSAGE_ID => "propose_request",
+        TRANSACTIONS_REQUEST_MESSAGE_ID => "transactions_request",
+        PREVOTES_REQUEST_MESSAGE_ID => "prevotes_request",
+        PEERS_REQUEST_MESSAGE_ID => "peers_request",
+        BLOCK_REQUEST_MESSAGE_ID => "block_request",
+        TRANSACTION_BATCH_MESSAGE_ID | TRANSACTION_ENVELOPE_MESSAGE_ID => "transaction",
+        _ => "unknown",
+    }
+}
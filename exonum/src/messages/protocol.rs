@@ -64,6 +64,78 @@ pub const PREVOTES_REQUEST_MESSAGE_ID: u16 = PrevotesRequest::MESSAGE_ID;
 pub const PEERS_REQUEST_MESSAGE_ID: u16 = PeersRequest::MESSAGE_ID;
 /// `BlockRequest` message id.
 pub const BLOCK_REQUEST_MESSAGE_ID: u16 = BlockRequest::MESSAGE_ID;
+/// `TransactionBatch` message id.
+pub const TRANSACTION_BATCH_MESSAGE_ID: u16 = TransactionBatch::MESSAGE_ID;
+/// `TransactionEnvelope` message id.
+pub const TRANSACTION_ENVELOPE_MESSAGE_ID: u16 = TransactionEnvelope::MESSAGE_ID;
+/// `CompactBlockResponse` message id.
+pub const COMPACT_BLOCK_RESPONSE_MESSAGE_ID: u16 = CompactBlockResponse::MESSAGE_ID;
+/// `TransactionsAnnounce` message id.
+pub const TRANSACTIONS_ANNOUNCE_MESSAGE_ID: u16 = TransactionsAnnounce::MESSAGE_ID;
+
+/// Versions of the consensus protocol supported by the node, in ascending order.
+///
+/// Unlike `PROTOCOL_MAJOR_VERSION`, which defines the format of the messages, these versions
+/// define the behaviour of the nodes. The versions are announced in the Noise handshake,
+/// and peers use the highest common one.
+pub const PROTOCOL_VERSIONS: &[u8] = &[0];
+
+/// `CompactBlockResponse` messages are accepted by the node.
+pub const FEATURE_COMPACT_BLOCKS: u64 = 1;
+/// `TransactionsAnnounce` messages are accepted by the node.
+pub const FEATURE_TRANSACTIONS_ANNOUNCE: u64 = 1 << 1;
+/// Optional protocol features supported by the node, announced in the Noise handshake.
+pub const PROTOCOL_FEATURES: u64 = FEATURE_COMPACT_BLOCKS | FEATURE_TRANSACTIONS_ANNOUNCE;
+
+/// Protocol versions and optional features supported by a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    /// Supported versions of the consensus protocol, see `PROTOCOL_VERSIONS`.
+    pub versions: Vec<u8>,
+    /// Bit set of the supported optional features, see `PROTOCOL_FEATURES`.
+    pub features: u64,
+}
+
+impl ProtocolInfo {
+    /// Returns the versions and features supported by this node.
+    pub fn current() -> Self {
+        ProtocolInfo {
+            versions: PROTOCOL_VERSIONS.to_vec(),
+            features: PROTOCOL_FEATURES,
+        }
+    }
+
+    /// Returns the highest of the given protocol versions which is also supported
+    /// by the node, or `None` if the versions are incompatible.
+    pub fn common_version(&self, versions: &[u8]) -> Option<u8> {
+        self.versions
+            .iter()
+            .filter(|version| versions.contains(version))
+            .max()
+            .cloned()
+    }
+
+    /// Returns `true` if the node supports the given protocol version.
+    pub fn supports_version(&self, version: u8) -> bool {
+        self.versions.contains(&version)
+    }
+
+    /// Returns `true` if the node supports the given features.
+    pub fn supports(&self, features: u64) -> bool {
+        self.features & features == features
+    }
+}
+
+impl Default for ProtocolInfo {
+    /// Returns the versions and features of the nodes which don't announce them, that is,
+    /// the first protocol version without optional features.
+    fn default() -> Self {
+        ProtocolInfo {
+            versions: vec![PROTOCOL_VERSIONS[0]],
+            features: 0,
+        }
+    }
+}
 
 messages! {
     const SERVICE_ID = CONSENSUS;
@@ -360,4 +432,107 @@ messages! {
         /// The height to which the message is related.
         height: Height,
     }
+
+    /// Signed bundle of transactions committed atomically.
+    ///
+    /// ### Validation
+    /// The batch is considered valid if it is not empty, every transaction inside
+    /// it is signed by the author of the batch, passes its own `verify` check and
+    /// occurs in the batch only once. Batches cannot be nested.
+    ///
+    /// ### Processing
+    /// The batch is handled as an ordinary transaction. During the block execution
+    /// the inner transactions are executed one by one; if any of them fails, the
+    /// changes made by the whole batch are rolled back.
+    ///
+    /// ### Generation
+    /// The message is created by a client which needs several transactions to be
+    /// committed together.
+    struct TransactionBatch {
+        /// The author of the batch and of all the inner transactions.
+        from: &PublicKey,
+        /// The inner transactions.
+        transactions: Vec<RawMessage>,
+    }
+
+    /// Transaction with an expiration height and a replay protection nonce.
+    ///
+    /// ### Validation
+    /// The envelope is considered valid if the wrapped transaction is signed by
+    /// the author of the envelope and passes its own `verify` check. Envelopes
+    /// cannot wrap batches or other envelopes. The wrapped transaction is carried
+    /// unchanged, so its hash is the same as outside of the envelope.
+    ///
+    /// ### Processing
+    /// The envelope is handled as an ordinary transaction. It is evicted from
+    /// the pool once the blockchain height exceeds `valid_until`. During the block
+    /// execution the wrapped transaction is executed only if the block height does
+    /// not exceed `valid_until` and `nonce` is the next nonce of the author, starting
+    /// from zero. The envelope fails if the wrapped transaction is already committed.
+    ///
+    /// ### Generation
+    /// The message is created by a client which needs its transaction to be
+    /// protected from delays and replays.
+    struct TransactionEnvelope {
+        /// The author of the envelope and of the wrapped transaction.
+        from: &PublicKey,
+        /// The last height at which the transaction can be committed.
+        valid_until: Height,
+        /// Sequence number of the envelope among the envelopes of the same author.
+        nonce: u64,
+        /// The wrapped transaction.
+        transaction: RawMessage,
+    }
+
+    /// Information about a block with the hashes of its transactions instead of
+    /// their bodies.
+    ///
+    /// ### Validation
+    /// The message is ignored if
+    ///     * its `to` field corresponds to a different node
+    ///     * the `block` or `precommits` fields cannot be parsed or verified
+    ///     * the `transactions` field contains an already committed transaction
+    ///
+    /// ### Processing
+    /// Transactions are taken from the pool; the missing ones are requested with
+    /// `TransactionsRequest`. Once all the transactions are known, the block is added
+    /// to the blockchain.
+    ///
+    /// ### Generation
+    /// The message is sent as response to `BlockRequest` instead of `BlockResponse`
+    /// if the requested block is one of the latest `compact_block_depth` blocks.
+    struct CompactBlockResponse {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+        /// Block header.
+        block: blockchain::Block,
+        /// List of pre-commits.
+        precommits: Vec<Precommit>,
+        /// List of the transaction hashes.
+        transactions: &[Hash],
+    }
+
+    /// Announcement of the transactions known to the node.
+    ///
+    /// ### Validation
+    /// The message is ignored if
+    ///     * its signature is incorrect
+    ///     * the sender is not connected to the node
+    ///
+    /// ### Processing
+    /// Transactions which are neither in the pool nor in the blockchain and have not
+    /// been announced to the node before are requested from the sender with
+    /// `TransactionsRequest`.
+    ///
+    /// ### Generation
+    /// The message is broadcast when the node receives a new transaction from
+    /// its clients.
+    struct TransactionsAnnounce {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// List of the transaction hashes.
+        txs: &[Hash],
+    }
 }
@@ -94,7 +94,7 @@ macro_rules! __ex_message {
                 // Check identifiers
                 if raw.version() != $crate::messages::PROTOCOL_MAJOR_VERSION {
                     return Err($crate::encoding::Error::UnsupportedProtocolVersion {
-                        version: $crate::messages::PROTOCOL_MAJOR_VERSION
+                        version: raw.version()
                     });
                 }
                 if raw.message_type() != <Self as $crate::messages::ServiceMessage>::MESSAGE_ID {
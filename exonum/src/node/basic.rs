@@ -16,10 +16,15 @@ use rand::{self, Rng};
 
 use std::net::SocketAddr;
 use std::error::Error;
+use std::time::Duration;
 
-use messages::{Any, Connect, Message, PeersRequest, RawMessage, Status};
-use helpers::Height;
-use super::{NodeHandler, RequestData};
+use messages::{Any, Connect, Message, PeersRequest, ProtocolInfo, RawMessage, Status,
+               PROTOCOL_VERSIONS};
+use blockchain::Schema;
+use crypto::PublicKey;
+use events::InternalRequest;
+use helpers::{Height, Milliseconds};
+use super::{NodeHandler, RequestData, ShutdownReason};
 
 impl NodeHandler {
     /// Redirects message to the corresponding `handle_...` function.
@@ -30,14 +35,17 @@ impl NodeHandler {
         //         return;
         //     }
 
+        self.metrics.message_received(&raw);
         match Any::from_raw(raw) {
             Ok(Any::Connect(msg)) => self.handle_connect(msg),
             Ok(Any::Status(msg)) => self.handle_status(&msg),
             Ok(Any::Consensus(msg)) => self.handle_consensus(msg),
             Ok(Any::Request(msg)) => self.handle_request(msg),
             Ok(Any::Block(msg)) => self.handle_block(&msg),
+            Ok(Any::CompactBlock(msg)) => self.handle_compact_block(&msg),
             Ok(Any::Transaction(msg)) => self.handle_tx(msg),
             Ok(Any::TransactionsBatch(msg)) => self.handle_txs_batch(&msg),
+            Ok(Any::TransactionsAnnounce(msg)) => self.handle_txs_announce(&msg),
             Err(err) => {
                 error!("Invalid message received: {:?}", err.description());
             }
@@ -45,39 +53,97 @@ impl NodeHandler {
     }
 
     /// Handles the `Connected` event. Node's `Connect` message is sent as response
-    /// if received `Connect` message is correct.
-    pub fn handle_connected(&mut self, addr: SocketAddr, connect: Connect) {
+    /// if received `Connect` message is correct and the peer supports the protocol version
+    /// required by the actual configuration.
+    pub fn handle_connected(&mut self, addr: SocketAddr, connect: Connect, protocol: ProtocolInfo) {
         info!("Received Connect message from peer: {}", addr);
+        if !self.is_compatible_peer(&connect, &protocol) {
+            return;
+        }
+        self.peers_liveness.connected(connect.addr());
+        let public_key = *connect.pub_key();
         self.handle_connect(connect);
+        if self.state.peers().contains_key(&public_key) {
+            self.state.set_peer_protocol(public_key, protocol);
+        }
     }
 
-    /// Handles the `Disconnected` event. Node will try to connect to that address again if it was
-    /// in the validators list.
+    /// Handles the `Disconnected` event. Node will try to connect to that address again
+    /// after a delay.
     pub fn handle_disconnected(&mut self, addr: SocketAddr) {
         info!("Disconnected from: {}", addr);
         self.remove_peer_with_addr(addr);
     }
 
     /// Handles the `UnableConnectToPeer` event. Node will try to connect to that address again
-    /// if it was in the validators list.
+    /// after a delay.
     pub fn handle_unable_to_connect(&mut self, addr: SocketAddr) {
         info!("Could not connect to: {}", addr);
         self.remove_peer_with_addr(addr);
     }
 
-    /// Removes peer from the state and from the cache. Node will try to connect to that address
-    /// again if it was in the validators list.
-    fn remove_peer_with_addr(&mut self, addr: SocketAddr) {
-        let need_reconnect = self.state.remove_peer_with_addr(&addr);
-        if need_reconnect {
+    /// Handles `NodeTimeout::Reconnect`. Node connects to the address unless it is
+    /// already connected.
+    pub fn handle_reconnect_timeout(&mut self, addr: SocketAddr) {
+        let is_connected = self.state
+            .peers()
+            .values()
+            .any(|connect| connect.addr() == addr);
+        if !is_connected {
+            info!("Trying to reconnect with peer {}", addr);
             self.connect(&addr);
         }
-        self.blockchain.remove_peer_with_addr(&addr);
+    }
+
+    /// Handles the `PeerBanned` event. The peer is removed from the state, and its
+    /// connections are rejected by the network part until the ban expires.
+    pub fn handle_peer_banned(
+        &mut self,
+        addr: SocketAddr,
+        peer: PublicKey,
+        duration: Milliseconds,
+    ) {
+        warn!("Peer {:?} from {} is banned for {} ms", peer, addr, duration);
+        let until = self.system_state.current_time() + Duration::from_millis(duration);
+        self.api_state.add_banned_peer(peer, addr, until);
+        let address = self.state.peers().get(&peer).map(Connect::addr);
+        if let Some(address) = address {
+            self.state.remove_peer_with_addr(&address);
+            self.metrics.peers.set(self.state.peers().len() as i64);
+        }
+    }
+
+    /// Removes peer from the state and schedules reconnection with a backoff. The peer is
+    /// removed from the cache once it fails too many times in a row, unless it is a validator
+    /// or it is listed in the node configuration.
+    fn remove_peer_with_addr(&mut self, addr: SocketAddr) {
+        let is_validator = self.state.remove_peer_with_addr(&addr) || self.is_validator_addr(addr);
+        self.metrics.peers.set(self.state.peers().len() as i64);
+        let delay = self.peers_liveness.failed(addr);
+        if is_validator || self.peer_discovery.contains(&addr)
+            || !self.peers_liveness.is_dead(&addr)
+        {
+            self.add_reconnect_timeout(addr, delay);
+        } else {
+            info!("Removing peer {} from the cache", addr);
+            self.peers_liveness.forget(&addr);
+            self.blockchain.remove_peer_with_addr(&addr);
+        }
+    }
+
+    /// Checks whether the address belongs to a validator according to the peers cache.
+    fn is_validator_addr(&self, addr: SocketAddr) -> bool {
+        let validators = self.state.validators();
+        self.blockchain.get_saved_peers().values().any(|connect| {
+            connect.addr() == addr
+                && validators
+                    .iter()
+                    .any(|keys| keys.consensus_key == *connect.pub_key())
+        })
     }
 
     /// Handles the `Connect` message and connects to a peer as result.
     pub fn handle_connect(&mut self, message: Connect) {
-        // TODO add spam protection (ECR-170)
         let address = message.addr();
         if address == self.state.our_connect_message().addr() {
             trace!("Received Connect with same address as our external_address.");
@@ -123,6 +189,7 @@ impl NodeHandler {
             }
         }
         self.state.add_peer(public_key, message.clone());
+        self.metrics.peers.set(self.state.peers().len() as i64);
         info!(
             "Received Connect message from {}, {}",
             address, need_connect,
@@ -135,6 +202,86 @@ impl NodeHandler {
         }
     }
 
+    /// Checks that the peer supports the protocol version required by the actual
+    /// configuration. Peers without a common protocol version are rejected during
+    /// the handshake.
+    fn is_compatible_peer(&self, message: &Connect, protocol: &ProtocolInfo) -> bool {
+        if let Some(version) = protocol.common_version(PROTOCOL_VERSIONS) {
+            trace!("Using protocol version {} with peer {}", version, message.addr());
+        }
+        if let Some(required) = self.protocol_version() {
+            if !protocol.supports_version(required) {
+                error!(
+                    "Peer {} doesn't support protocol version {} required by the configuration, \
+                     peer versions: {:?}",
+                    message.addr(),
+                    required,
+                    protocol.versions
+                );
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Checks that the node supports the protocol version required by the actual
+    /// configuration. Otherwise, the node refuses to work and shuts down with
+    /// `ShutdownReason::UnsupportedProtocolVersion`.
+    pub fn check_protocol_version(&mut self) -> bool {
+        match self.protocol_version() {
+            Some(required) if !PROTOCOL_VERSIONS.contains(&required) => {
+                error!(
+                    "Protocol version {} required by the configuration is not supported \
+                     by the node, supported versions: {:?}. The node should be upgraded.",
+                    required, PROTOCOL_VERSIONS
+                );
+                let reason = ShutdownReason::UnsupportedProtocolVersion(required);
+                if self.api_state.request_shutdown(reason) {
+                    self.execute_later(InternalRequest::Shutdown);
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Disconnects the peers which don't support the protocol version required by the actual
+    /// configuration. The peers with unknown versions are disconnected as well; they are
+    /// checked again once they reconnect. Called when the required version changes.
+    ///
+    /// If the node itself doesn't support the version, it shuts down, see
+    /// `check_protocol_version`.
+    pub fn disconnect_incompatible_peers(&mut self) {
+        let required = match self.protocol_version() {
+            Some(version) => version,
+            None => return,
+        };
+        if !self.check_protocol_version() {
+            return;
+        }
+        info!("Switching to protocol version {}", required);
+
+        let incompatible = self.state
+            .peers()
+            .iter()
+            .filter(|&(public_key, _)| {
+                !self.state
+                    .peer_protocol(public_key)
+                    .map_or(false, |protocol| protocol.supports_version(required))
+            })
+            .map(|(_, connect)| connect.addr())
+            .collect::<Vec<_>>();
+        for address in incompatible {
+            warn!(
+                "Disconnecting from peer {}, which doesn't support protocol version {}",
+                address, required
+            );
+            self.state.remove_peer_with_addr(&address);
+            self.disconnect(address);
+        }
+        self.metrics.peers.set(self.state.peers().len() as i64);
+    }
+
     /// Handles the `Status` message. Node sends `BlockRequest` as response if height in the
     /// message is higher than node's height.
     pub fn handle_status(&mut self, msg: &Status) {
@@ -228,6 +375,9 @@ impl NodeHandler {
     /// Node update internal `ApiState`.
     pub fn handle_update_api_state_timeout(&mut self) {
         self.api_state.update_node_state(&self.state);
+        let snapshot = self.blockchain.snapshot();
+        let pool = Schema::new(&snapshot).transactions_pool();
+        self.api_state.retain_pool_arrivals(|hash| pool.contains(hash));
         self.add_update_api_state_timeout();
     }
 
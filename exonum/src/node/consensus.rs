@@ -14,15 +14,17 @@
 
 use std::collections::HashSet;
 use std::error::Error;
+use std::time::Duration;
 
 use crypto::{CryptoHash, Hash, PublicKey};
-use blockchain::{Schema, Transaction};
-use messages::{BlockRequest, BlockResponse, ConsensusMessage, Message, Precommit, Prevote,
-               PrevotesRequest, Propose, ProposeRequest, RawTransaction, TransactionsRequest,
-               TransactionsResponse};
-use helpers::{Height, Round, ValidatorId};
-use storage::Patch;
-use node::{NodeHandler, RequestData};
+use blockchain::{Blockchain, Envelope, Schema, Transaction};
+use messages::{BlockRequest, BlockResponse, CompactBlockResponse, ConsensusMessage, Message,
+               Precommit, Prevote, PrevotesRequest, Propose, ProposeRequest, RawTransaction,
+               TransactionsRequest, TransactionsResponse};
+use blockchain::Block;
+use helpers::{metrics, Height, Round, ValidatorId};
+use storage::{Patch, Snapshot};
+use node::{NodeHandler, NodeTimeout, RemoveTransactionError, RequestData};
 use events::InternalRequest;
 
 // TODO reduce view invocations (ECR-171)
@@ -195,37 +197,125 @@ impl NodeHandler {
     // TODO write helper function which returns Result (ECR-123)
     #[cfg_attr(feature = "flame_profile", flame)]
     pub fn handle_block(&mut self, msg: &BlockResponse) {
+        if !self.verify_block_message(msg, msg.from(), msg.to()) {
+            return;
+        }
+
+        trace!("Handle block");
+
+        let block = msg.block();
+        let block_hash = block.hash();
+
+        if !self.is_next_block(msg, &block, &msg.precommits()) {
+            return;
+        }
+
+        if self.state.block(&block_hash).is_none() {
+            // Verify transactions
+            let tx_hashes = if let Some(res) = self.validate_block_transactions(msg) {
+                self.blockchain
+                    .merge(res.1)
+                    .expect("Unable to save transaction to persistent pool.");
+                res.0
+            } else {
+                return;
+            };
+
+            self.add_received_block(msg, &block, tx_hashes);
+        }
+        self.commit(block_hash, msg.precommits().iter(), None);
+        self.request_next_block();
+    }
+
+    /// Handles the `CompactBlockResponse` message. For details see the message documentation.
+    #[cfg_attr(feature = "flame_profile", flame)]
+    pub fn handle_compact_block(&mut self, msg: &CompactBlockResponse) {
+        if !self.verify_block_message(msg, msg.from(), msg.to()) {
+            return;
+        }
+
+        trace!("Handle compact block");
+
+        let block = msg.block();
+        let block_hash = block.hash();
+
+        if !self.is_next_block(msg, &block, &msg.precommits()) {
+            return;
+        }
+
+        if self.state.block(&block_hash).is_none() {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            let has_unknown_txs = match self.state.add_compact_block(
+                msg,
+                &schema.transactions(),
+                &schema.transactions_pool(),
+            ) {
+                Ok(block_state) => block_state.has_unknown_txs(),
+                Err(err) => {
+                    error!("{}, block={:?}", err, msg);
+                    return;
+                }
+            };
+
+            if has_unknown_txs {
+                trace!("REQUEST BLOCK TRANSACTIONS");
+                self.request(RequestData::BlockTransactions(block_hash), *msg.from());
+            } else {
+                self.handle_full_compact_block(&block_hash);
+            }
+            return;
+        }
+        self.commit(block_hash, msg.precommits().iter(), None);
+        self.request_next_block();
+    }
+
+    /// Executes and commits the compact block. This function is called when all transactions
+    /// of the block are known.
+    pub fn handle_full_compact_block(&mut self, block_hash: &Hash) {
+        let msg = match self.state.take_compact_block(block_hash) {
+            Some(block_state) => block_state.message().clone(),
+            None => return,
+        };
+        let block = msg.block();
+
+        self.add_received_block(&msg, &block, msg.transactions().to_vec());
+        self.commit(*block_hash, msg.precommits().iter(), None);
+        self.request_next_block();
+    }
+
+    // Checks that the block message is sent to us by a whitelisted peer and is signed by it.
+    fn verify_block_message<M: Message>(&self, msg: &M, from: &PublicKey, to: &PublicKey) -> bool {
         // Request are sent to us
-        if msg.to() != self.state.consensus_public_key() {
+        if to != self.state.consensus_public_key() {
             error!(
                 "Received block intended for another peer, to={}, from={}",
-                msg.to().to_hex(),
-                msg.from().to_hex()
+                to.to_hex(),
+                from.to_hex()
             );
-            return;
+            return false;
         }
 
-        if !self.state.whitelist().allow(msg.from()) {
+        if !self.state.whitelist().allow(from) {
             error!(
                 "Received request message from peer = {} which not in whitelist.",
-                msg.from().to_hex()
+                from.to_hex()
             );
-            return;
+            return false;
         }
 
-        if !msg.verify_signature(msg.from()) {
+        if !msg.verify_signature(from) {
             error!("Received block with incorrect signature, msg={:?}", msg);
-            return;
+            return false;
         }
+        true
+    }
 
-        trace!("Handle block");
-
-        let block = msg.block();
-        let block_hash = block.hash();
-
+    // Checks that the block follows the last committed block and is confirmed by pre-commits.
+    fn is_next_block<M: Message>(&self, msg: &M, block: &Block, precommits: &[Precommit]) -> bool {
         // TODO add block with greater height to queue (ECR-171)
         if self.state.height() != block.height() {
-            return;
+            return false;
         }
 
         // Check block content
@@ -237,46 +327,46 @@ impl NodeHandler {
                 *block.prev_hash(),
                 self.last_block_hash()
             );
-            return;
+            return false;
         }
 
-        if let Err(err) = self.verify_precommits(&msg.precommits(), &block_hash, block.height()) {
+        if let Err(err) = self.verify_precommits(precommits, &block.hash(), block.height()) {
             error!("{}, block={:?}", err, msg);
-            return;
+            return false;
         }
+        true
+    }
 
-        if self.state.block(&block_hash).is_none() {
-            // Verify transactions
-            let tx_hashes = if let Some(res) = self.validate_block_transactions(msg) {
-                self.blockchain
-                    .merge(res.1)
-                    .expect("Unable to save transaction to persistent pool.");
-                res.0
-            } else {
-                return;
-            };
-
-            let (block_hash, patch) =
-                self.create_block(block.proposer_id(), block.height(), tx_hashes.as_slice());
-            // Verify block_hash
-            if block_hash != block.hash() {
-                panic!(
-                    "Block_hash incorrect in the received block={:?}. Either a node's \
-                     implementation is incorrect or validators majority works incorrectly",
-                    msg
-                );
-            }
-
-            // Commit block
-            self.state
-                .add_block(block_hash, patch, tx_hashes, block.proposer_id());
+    // Executes the received block and adds it to the blocks of the current height.
+    fn add_received_block<M: Message>(&mut self, msg: &M, block: &Block, tx_hashes: Vec<Hash>) {
+        let (block_hash, patch) =
+            self.create_block(block.proposer_id(), block.height(), tx_hashes.as_slice());
+        // Verify block_hash
+        if block_hash != block.hash() {
+            panic!(
+                "Block_hash incorrect in the received block={:?}. Either a node's \
+                 implementation is incorrect or validators majority works incorrectly",
+                msg
+            );
         }
-        self.commit(block_hash, msg.precommits().iter(), None);
-        self.request_next_block();
+
+        // Commit block
+        self.state
+            .add_block(block_hash, patch, tx_hashes, block.proposer_id());
     }
 
     /// Executes and commits block. This function is called when node has full propose information.
     pub fn handle_full_propose(&mut self, hash: Hash, propose_round: Round) {
+        // Check that no transaction is included both directly and within a batch or an envelope
+        let tx_hashes = self.state.propose(&hash).unwrap().message().transactions().to_vec();
+        if let Some(tx_hash) = self.blockchain.find_wrapped_duplicate(&tx_hashes) {
+            error!(
+                "Received propose with transaction {:?} included twice, propose={:?}",
+                tx_hash, hash
+            );
+            return;
+        }
+
         // Send prevote
         if self.state.locked_round() == Round::zero() {
             if self.state.is_validator() && !self.state.have_prevote(propose_round) {
@@ -485,21 +575,35 @@ impl NodeHandler {
                 .commit(block_state.patch(), block_hash, precommits)
                 .unwrap();
             // Update node state
+            let protocol_version = self.protocol_version();
             self.state
                 .update_config(Schema::new(&self.blockchain.snapshot()).actual_configuration());
+            if self.protocol_version() != protocol_version {
+                self.disconnect_incompatible_peers();
+            }
             // Update state to new height
             let block_hash = self.blockchain.last_hash();
-            self.state
-                .new_height(&block_hash, self.system_state.current_time());
+            let now = self.system_state.current_time();
+            if let Ok(elapsed) = now.duration_since(self.state.height_start_time()) {
+                self.metrics
+                    .block_commit_seconds
+                    .observe(metrics::seconds(elapsed));
+            }
+            self.state.new_height(&block_hash, now);
+            self.update_inactive_validators();
             (block_state.txs().len(), block_state.proposer_id())
         };
+        self.evict_expired_transactions();
         let snapshot = self.blockchain.snapshot();
         let schema = Schema::new(&snapshot);
         let pool_len = schema.transactions_pool_len();
 
+        let height = self.state.height();
         metric!("node.mempool", pool_len);
+        self.metrics.mempool.set(pool_len as i64);
+        self.metrics.height.set(height.0 as i64);
+        self.metrics.round.set(i64::from(self.state.round().0));
 
-        let height = self.state.height();
         info!(
             "COMMIT ====== height={}, proposer={}, round={}, committed={}, pool={}, hash={}",
             height,
@@ -529,6 +633,21 @@ impl NodeHandler {
         }
     }
 
+    /// Removes transaction envelopes which cannot be committed at the current height
+    /// from the pool.
+    fn evict_expired_transactions(&mut self) {
+        let height = self.state.height();
+        let mut fork = self.blockchain.fork();
+        let evicted = Schema::new(&mut fork).evict_expired_transactions(height);
+
+        if evicted > 0 {
+            info!("Evicted {} expired transactions from the pool", evicted);
+            self.blockchain
+                .merge(fork.into_patch())
+                .expect("Unable to evict expired transactions from the pool.");
+        }
+    }
+
     /// Checks if the transaction is new and adds it to the pool.
     fn handle_tx_inner(&mut self, msg: RawTransaction) -> Result<(), String> {
         let hash = msg.hash();
@@ -541,6 +660,13 @@ impl NodeHandler {
             }
         });
 
+        if let Some(valid_until) = Envelope::valid_until(&msg) {
+            if valid_until < self.state.height() {
+                let err = format!("Received expired transaction, hash {:?}", hash);
+                return Err(err);
+            }
+        }
+
         let mut fork = self.blockchain.fork();
         {
             let mut schema = Schema::new(&mut fork);
@@ -549,6 +675,8 @@ impl NodeHandler {
         self.blockchain
             .merge(fork.into_patch())
             .expect("Unable to save transaction to persistent pool.");
+        self.api_state
+            .add_pool_arrival(hash, self.system_state.current_time());
 
         let full_proposes = self.state.check_incomplete_proposes(hash);
         // Go to handle full propose if we get last transaction
@@ -556,6 +684,25 @@ impl NodeHandler {
             self.remove_request(&RequestData::Transactions(hash));
             self.handle_full_propose(hash, round);
         }
+
+        // Go to handle full compact block if we get its last transaction
+        if let Some(block_hash) = self.state.check_incomplete_compact_block(hash) {
+            self.remove_request(&RequestData::BlockTransactions(block_hash));
+            self.handle_full_compact_block(&block_hash);
+        }
+
+        // Schedule the propose postponed because of the empty pool. The propose is sent
+        // after `min_propose_timeout`, so that transactions arriving in the meantime
+        // are included in the same block.
+        if let Some(round) = self.state.take_postponed_propose() {
+            if round == self.state.round() {
+                let time = self.system_state.current_time()
+                    + Duration::from_millis(self.min_propose_timeout());
+                trace!("Schedule postponed propose at {:?}", time);
+                let height = self.state.height();
+                self.add_timeout(NodeTimeout::Propose(height, round), time);
+            }
+        }
         Ok(())
     }
 
@@ -563,6 +710,12 @@ impl NodeHandler {
     /// added to the transactions pool.
     #[cfg_attr(feature = "flame_profile", flame)]
     pub fn handle_tx(&mut self, msg: RawTransaction) {
+        let hash = msg.hash();
+        self.state.mark_tx_seen(hash);
+        // The transaction fetched after an announcement is relayed further.
+        let request = RequestData::AnnouncedTransaction(hash);
+        let is_announced = !self.remove_request(&request).is_empty();
+
         let tx = match self.blockchain.tx_from_raw(msg.clone()) {
             Ok(tx) => tx,
             Err(e) => {
@@ -578,9 +731,11 @@ impl NodeHandler {
             }
         });
 
-        // We don't care about result, because situation when transaction received twice
+        // We don't care about error, because situation when transaction received twice
         // is normal for internal messages (transaction may be received from 2+ nodes).
-        let _ = self.handle_tx_inner(msg);
+        if self.handle_tx_inner(msg.clone()).is_ok() && is_announced {
+            self.announce_tx(&msg);
+        }
     }
 
     /// Handles raw transactions.
@@ -613,17 +768,43 @@ impl NodeHandler {
         }
     }
 
-    /// Handles external boxed transaction. Additionally transaction will be broadcast to the
+    /// Handles external boxed transaction. Additionally transaction will be announced to the
     /// Node's peers.
     #[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
     pub fn handle_incoming_tx(&mut self, msg: Box<Transaction>) {
         trace!("Handle incoming transaction");
+        self.state.mark_tx_seen(msg.hash());
         match self.handle_tx_inner(msg.raw().clone()) {
-            Ok(_) => self.broadcast(msg.raw()),
+            Ok(_) => self.announce_tx(msg.raw()),
             Err(e) => error!("{}", e),
         }
     }
 
+    /// Handles the request to remove the transaction from the pool. Transactions included
+    /// in the known proposes are not removed, since they are required to execute the block.
+    pub fn handle_remove_tx(&mut self, hash: &Hash) -> Result<(), RemoveTransactionError> {
+        if self.state.is_tx_proposed(hash) {
+            warn!(
+                "Transaction {:?} is included in a propose and cannot be removed from the pool",
+                hash
+            );
+            return Err(RemoveTransactionError::Proposed);
+        }
+
+        let mut fork = self.blockchain.fork();
+        // `reject_transaction` also removes committed transactions, so the fork is discarded
+        // if the transaction is not in the pool.
+        if Schema::new(&mut fork).reject_transaction(hash).is_err() {
+            warn!("Transaction {:?} is not in the pool", hash);
+            return Err(RemoveTransactionError::NotInPool);
+        }
+        self.blockchain
+            .merge(fork.into_patch())
+            .expect("Unable to remove transaction from the pool.");
+        info!("Removed transaction {:?} from the pool", hash);
+        Ok(())
+    }
+
     /// Handle new round, after jump.
     pub fn handle_new_round(&mut self, height: Height, round: Round) {
         trace!("Handle new round");
@@ -637,6 +818,7 @@ impl NodeHandler {
 
         info!("Jump to a new round = {}", round);
         self.state.jump_round(round);
+        self.metrics.round.set(i64::from(round.0));
         self.add_round_timeout();
         self.process_new_round();
     }
@@ -675,6 +857,7 @@ impl NodeHandler {
 
         // Update state to new round
         self.state.new_round();
+        self.metrics.round.set(i64::from(self.state.round().0));
 
         // Add timeout for this round
         self.add_round_timeout();
@@ -701,15 +884,29 @@ impl NodeHandler {
             }
             let snapshot = self.blockchain.snapshot();
             let schema = Schema::new(&snapshot);
-            let pool = schema.transactions_pool();
             let pool_len = schema.transactions_pool_len();
 
+            if pool_len == 0 {
+                if let Some(interval) = self.max_empty_block_interval() {
+                    let heartbeat_time =
+                        self.state.height_start_time() + Duration::from_millis(interval);
+                    if self.system_state.current_time() < heartbeat_time {
+                        // The propose is sent either when a transaction arrives
+                        // or as a heartbeat block.
+                        trace!("Postpone empty propose until {:?}", heartbeat_time);
+                        self.state.set_postponed_propose(round);
+                        self.add_timeout(NodeTimeout::Propose(height, round), heartbeat_time);
+                        return;
+                    }
+                }
+            }
+
             info!("LEADER: pool = {}", pool_len);
 
             let round = self.state.round();
             let max_count = ::std::cmp::min(self.txs_block_limit() as usize, pool_len);
 
-            let txs: Vec<Hash> = pool.iter().take(max_count).collect();
+            let txs = self.propose_transactions(&schema, max_count);
             let propose = Propose::new(
                 validator_id,
                 self.state.height(),
@@ -736,10 +933,45 @@ impl NodeHandler {
         }
     }
 
+    // Takes up to `max_count` transactions from the pool, skipping the transactions
+    // included into the propose within a batch or an envelope and vice versa.
+    fn propose_transactions<T>(&self, schema: &Schema<T>, max_count: usize) -> Vec<Hash>
+    where
+        T: AsRef<Snapshot>,
+    {
+        let transactions = schema.transactions();
+        let mut selected = HashSet::new();
+        let mut wrapped = HashSet::new();
+        let mut txs = Vec::new();
+        for hash in schema.transactions_pool().iter() {
+            if txs.len() == max_count {
+                break;
+            }
+            let inner = transactions
+                .get(&hash)
+                .map_or_else(Vec::new, |raw| Blockchain::wrapped_tx_hashes(&raw));
+            if wrapped.contains(&hash) || inner.iter().any(|inner| selected.contains(inner)) {
+                continue;
+            }
+            wrapped.extend(inner);
+            selected.insert(hash);
+            txs.push(hash);
+        }
+        txs
+    }
+
     /// Handles request timeout by sending the corresponding request message to a peer.
     pub fn handle_request_timeout(&mut self, data: &RequestData, peer: Option<PublicKey>) {
         trace!("HANDLE REQUEST TIMEOUT");
         // FIXME: check height?
+        if let RequestData::BlockTransactions(ref block_hash) = *data {
+            // The compact block could be already committed or replaced.
+            if self.state.compact_block(block_hash).is_none() {
+                trace!("Drop request for the unknown compact block {:?}", block_hash);
+                self.remove_request(data);
+                return;
+            }
+        }
         if let Some(peer) = self.state.retry(data, peer) {
             self.add_request_timeout(data.clone(), Some(peer));
 
@@ -778,6 +1010,29 @@ impl NodeHandler {
                     self.state.consensus_secret_key(),
                 ).raw()
                     .clone(),
+                RequestData::BlockTransactions(ref block_hash) => {
+                    let txs: Vec<_> = self.state
+                        .compact_block(block_hash)
+                        .expect("Compact block is checked above")
+                        .unknown_txs()
+                        .iter()
+                        .cloned()
+                        .collect();
+                    TransactionsRequest::new(
+                        self.state.consensus_public_key(),
+                        &peer,
+                        &txs,
+                        self.state.consensus_secret_key(),
+                    ).raw()
+                        .clone()
+                }
+                RequestData::AnnouncedTransaction(ref tx_hash) => TransactionsRequest::new(
+                    self.state.consensus_public_key(),
+                    &peer,
+                    &[*tx_hash],
+                    self.state.consensus_secret_key(),
+                ).raw()
+                    .clone(),
                 RequestData::Block(height) => BlockRequest::new(
                     self.state.consensus_public_key(),
                     &peer,
@@ -927,26 +1182,32 @@ impl NodeHandler {
         self.broadcast(precommit.raw());
     }
 
-    /// Checks that pre-commits count is correct and calls `verify_precommit` for each of them.
+    /// Checks that pre-commits voting power is correct and calls `verify_precommit` for each
+    /// of them.
     fn verify_precommits(
         &self,
         precommits: &[Precommit],
         block_hash: &Hash,
         block_height: Height,
     ) -> Result<(), String> {
-        if precommits.len() < self.state.majority_count() {
-            return Err("Received block without consensus".to_string());
-        } else if precommits.len() > self.state.validators().len() {
+        if precommits.len() > self.state.validators().len() {
             return Err("Wrong precommits count in block".to_string());
         }
 
         let mut validators = HashSet::with_capacity(precommits.len());
-        let round = precommits[0].round();
+        let mut weight = 0;
         for precommit in precommits {
             if !validators.insert(precommit.validator()) {
                 return Err("Several precommits from one validator in block".to_string());
             }
+            weight += self.state.validator_weight(precommit.validator());
+        }
+        if weight < self.state.majority_count() {
+            return Err("Received block without consensus".to_string());
+        }
 
+        let round = precommits[0].round();
+        for precommit in precommits {
             self.verify_precommit(block_hash, block_height, round, precommit)?;
         }
 
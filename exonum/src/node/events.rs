@@ -14,7 +14,7 @@
 
 use events::{Event, EventHandler, InternalEvent, InternalRequest, NetworkEvent};
 use events::error::LogError;
-use super::{ExternalMessage, NodeHandler, NodeTimeout};
+use super::{ExternalMessage, NodeHandler, NodeTimeout, ShutdownReason};
 
 impl EventHandler for NodeHandler {
     fn handle_event(&mut self, event: Event) {
@@ -34,7 +34,7 @@ impl NodeHandler {
         match event {
             InternalEvent::Timeout(timeout) => self.handle_timeout(timeout),
             InternalEvent::JumpToRound(height, round) => self.handle_new_round(height, round),
-            InternalEvent::Shutdown => panic!("Shutdown should be processed in the event loop"),
+            InternalEvent::Shutdown => self.shutdown(),
         }
     }
 
@@ -47,16 +47,28 @@ impl NodeHandler {
             return;
         }
         match event {
-            NetworkEvent::PeerConnected(peer, connect) => self.handle_connected(peer, connect),
+            NetworkEvent::PeerConnected(peer, connect, protocol) => {
+                self.handle_connected(peer, connect, protocol)
+            }
             NetworkEvent::PeerDisconnected(peer) => self.handle_disconnected(peer),
             NetworkEvent::UnableConnectToPeer(peer) => self.handle_unable_to_connect(peer),
             NetworkEvent::MessageReceived(_, raw) => self.handle_message(raw),
+            NetworkEvent::PeerBanned(peer, key, duration) => {
+                self.handle_peer_banned(peer, key, duration)
+            }
         }
     }
 
     fn handle_api_event(&mut self, event: ExternalMessage) {
         match event {
             ExternalMessage::Transaction(tx) => {
+                if self.api_state.shutdown_reason().is_some() {
+                    info!(
+                        "Ignoring a transaction {:?} because the node is shutting down",
+                        tx
+                    );
+                    return;
+                }
                 if !self.is_enabled {
                     info!(
                         "Ignoring a transaction {:?} because the node is disabled",
@@ -90,7 +102,15 @@ impl NodeHandler {
                     }
                 }
             }
-            ExternalMessage::Shutdown => self.execute_later(InternalRequest::Shutdown),
+            ExternalMessage::Shutdown => {
+                // The reason could have been set by the signal handler.
+                self.api_state.request_shutdown(ShutdownReason::Requested);
+                self.execute_later(InternalRequest::Shutdown);
+            }
+            ExternalMessage::RemoveTransaction(hash, result) => {
+                // The requester may have gone away, which is fine.
+                let _ = result.send(self.handle_remove_tx(&hash));
+            }
         }
     }
 
@@ -107,6 +127,7 @@ impl NodeHandler {
             NodeTimeout::Request(data, peer) => self.handle_request_timeout(&data, peer),
             NodeTimeout::Status(height) => self.handle_status_timeout(height),
             NodeTimeout::PeerExchange => self.handle_peer_exchange_timeout(),
+            NodeTimeout::Reconnect(address) => self.handle_reconnect_timeout(address),
             NodeTimeout::UpdateApiState => self.handle_update_api_state_timeout(),
             NodeTimeout::Propose(height, round) => self.handle_propose_timeout(height, round),
         }
@@ -0,0 +1,169 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Announce-then-fetch gossip of transactions.
+
+use std::collections::{HashSet, VecDeque};
+
+use blockchain::Schema;
+use crypto::Hash;
+use messages::{Message, RawTransaction, TransactionsAnnounce, TransactionsRequest,
+               FEATURE_TRANSACTIONS_ANNOUNCE};
+use super::{NodeHandler, RequestData};
+
+/// Bounded set of the transaction hashes which the node has already seen. Once the capacity
+/// is reached, the oldest hashes are forgotten.
+#[derive(Debug)]
+pub struct SeenTransactions {
+    capacity: usize,
+    order: VecDeque<Hash>,
+    hashes: HashSet<Hash>,
+}
+
+impl SeenTransactions {
+    /// Creates an empty set with the given capacity.
+    pub fn new(capacity: usize) -> Self {
+        SeenTransactions {
+            capacity,
+            order: VecDeque::new(),
+            hashes: HashSet::new(),
+        }
+    }
+
+    /// Returns `true` if the transaction has been seen.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Marks the transaction as seen. Returns `false` if it has already been seen.
+    pub fn insert(&mut self, hash: Hash) -> bool {
+        if self.hashes.contains(&hash) {
+            return false;
+        }
+        if self.capacity > 0 {
+            if self.order.len() == self.capacity {
+                let oldest = self.order.pop_front().unwrap();
+                self.hashes.remove(&oldest);
+            }
+            self.order.push_back(hash);
+            self.hashes.insert(hash);
+        }
+        true
+    }
+}
+
+impl NodeHandler {
+    /// Handles `TransactionsAnnounce` message. For details see the message documentation.
+    pub fn handle_txs_announce(&mut self, msg: &TransactionsAnnounce) {
+        trace!("Handle transactions announce");
+        if !self.state.peers().contains_key(msg.from()) {
+            warn!(
+                "Received announce from the unknown peer, from={}",
+                msg.from().to_hex()
+            );
+            return;
+        }
+        if !msg.verify_signature(msg.from()) {
+            error!("Received announce with incorrect signature, msg={:?}", msg);
+            return;
+        }
+
+        let unknown_txs = {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            let transactions = schema.transactions();
+            msg.txs()
+                .iter()
+                .filter(|hash| !self.state.is_tx_seen(hash) && !transactions.contains(hash))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        // The transactions which are already being fetched from another peer are requested
+        // from this one only if the first request times out.
+        let mut new_txs = Vec::new();
+        for hash in unknown_txs {
+            let data = RequestData::AnnouncedTransaction(hash);
+            if self.state.request(data.clone(), *msg.from()) {
+                self.add_request_timeout(data, Some(*msg.from()));
+                new_txs.push(hash);
+            }
+        }
+        if new_txs.is_empty() {
+            return;
+        }
+
+        let request = TransactionsRequest::new(
+            self.state.consensus_public_key(),
+            msg.from(),
+            &new_txs,
+            self.state.consensus_secret_key(),
+        );
+        self.send_to_peer(*msg.from(), request.raw());
+    }
+
+    /// Sends `TransactionsAnnounce` with the given transaction to the peers supporting
+    /// announces, and the transaction itself to the other peers.
+    pub fn announce_tx(&mut self, tx: &RawTransaction) {
+        let (announce_peers, other_peers): (Vec<_>, Vec<_>) =
+            self.state.peers().keys().cloned().partition(|peer| {
+                self.state
+                    .peer_protocol(peer)
+                    .map_or(false, |protocol| protocol.supports(FEATURE_TRANSACTIONS_ANNOUNCE))
+            });
+
+        let announce = TransactionsAnnounce::new(
+            self.state.consensus_public_key(),
+            &[tx.hash()],
+            self.state.consensus_secret_key(),
+        );
+        for peer in announce_peers {
+            self.send_to_peer(peer, announce.raw());
+        }
+        for peer in other_peers {
+            self.send_to_peer(peer, tx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::hash;
+    use super::SeenTransactions;
+
+    #[test]
+    fn test_seen_transactions() {
+        let hashes = (0_u8..4).map(|i| hash(&[i])).collect::<Vec<_>>();
+        let mut seen = SeenTransactions::new(3);
+
+        assert!(seen.insert(hashes[0]));
+        assert!(seen.insert(hashes[1]));
+        assert!(!seen.insert(hashes[0]));
+        assert!(seen.insert(hashes[2]));
+        assert!(!seen.insert(hashes[1]));
+
+        // The oldest hash is forgotten.
+        assert!(seen.insert(hashes[3]));
+        assert!(!seen.insert(hashes[2]));
+        assert!(seen.insert(hashes[0]));
+        assert!(seen.insert(hashes[1]));
+    }
+
+    #[test]
+    fn test_seen_transactions_zero_capacity() {
+        let mut seen = SeenTransactions::new(0);
+        assert!(seen.insert(hash(&[0])));
+        assert!(seen.insert(hash(&[0])));
+    }
+}
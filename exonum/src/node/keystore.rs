@@ -0,0 +1,224 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signing keys stored in encrypted files and unlocked at the node startup.
+//!
+//! Secret keys are encrypted with a key derived from a passphrase. Services sign data
+//! with the unlocked keys through `ApiContext` by the key identifier, so that the secret
+//! keys never leave the node.
+
+use failure;
+use sodiumoxide::crypto::pwhash;
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::utils;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crypto::{self, PublicKey, SecretKey, Signature};
+use encoding::serialize::{encode_hex, FromHex};
+use helpers::config::ConfigFile;
+
+/// Name of the environment variable with the passphrase of the keystore.
+pub const KEYSTORE_PASSPHRASE_ENV: &str = "EXONUM_KEYSTORE_PASSPHRASE";
+
+/// Paths to the encrypted key files by their identifiers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct KeystoreConfig {
+    /// Paths to the key files.
+    pub keys: BTreeMap<String, String>,
+}
+
+/// Secret key encrypted with a passphrase.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncryptedKey {
+    /// Public key corresponding to the encrypted secret key.
+    pub public_key: PublicKey,
+    salt: String,
+    nonce: String,
+    secret_key: String,
+}
+
+impl EncryptedKey {
+    /// Encrypts the secret key with the passphrase.
+    pub fn encrypt(public_key: &PublicKey, secret_key: &SecretKey, passphrase: &[u8]) -> Self {
+        let salt = pwhash::gen_salt();
+        let nonce = secretbox::gen_nonce();
+        let key = derive_key(passphrase, &salt).expect("Unable to derive encryption key");
+        let ciphertext = secretbox::seal(&secret_key[..], &nonce, &key);
+        EncryptedKey {
+            public_key: *public_key,
+            salt: encode_hex(&salt.0[..]),
+            nonce: encode_hex(&nonce.0[..]),
+            secret_key: encode_hex(&ciphertext),
+        }
+    }
+
+    /// Decrypts the secret key with the passphrase.
+    pub fn decrypt(&self, passphrase: &[u8]) -> Result<SecretKey, failure::Error> {
+        let salt = Vec::<u8>::from_hex(&self.salt)?;
+        let salt = pwhash::Salt::from_slice(&salt).ok_or_else(|| format_err!("Invalid salt"))?;
+        let nonce = Vec::<u8>::from_hex(&self.nonce)?;
+        let nonce =
+            secretbox::Nonce::from_slice(&nonce).ok_or_else(|| format_err!("Invalid nonce"))?;
+        let ciphertext = Vec::<u8>::from_hex(&self.secret_key)?;
+
+        let key = derive_key(passphrase, &salt)?;
+        let mut plaintext = secretbox::open(&ciphertext, &nonce, &key)
+            .map_err(|_| format_err!("Invalid passphrase"))?;
+        let secret_key = SecretKey::from_slice(&plaintext);
+        // `SecretKey` is zeroed on drop, but the decrypted copy has to be zeroed manually.
+        utils::memzero(&mut plaintext);
+        let secret_key = secret_key.ok_or_else(|| format_err!("Invalid secret key length"))?;
+        ensure!(
+            crypto::verify(
+                &crypto::sign(&[], &secret_key),
+                &[],
+                &self.public_key
+            ),
+            "Secret key does not match the public key"
+        );
+        Ok(secret_key)
+    }
+
+    /// Loads the encrypted key from the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error> {
+        ConfigFile::load(path)
+    }
+
+    /// Saves the encrypted key to the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), failure::Error> {
+        ConfigFile::save(self, path)
+    }
+}
+
+fn derive_key(passphrase: &[u8], salt: &pwhash::Salt) -> Result<secretbox::Key, failure::Error> {
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+    {
+        let secretbox::Key(ref mut bytes) = key;
+        pwhash::derive_key(
+            bytes,
+            passphrase,
+            salt,
+            pwhash::OPSLIMIT_INTERACTIVE,
+            pwhash::MEMLIMIT_INTERACTIVE,
+        ).map_err(|_| format_err!("Unable to derive encryption key"))?;
+    }
+    Ok(key)
+}
+
+/// Unlocked signing keys of the node.
+#[derive(Clone, Default)]
+pub struct Keystore {
+    keys: Arc<HashMap<String, (PublicKey, SecretKey)>>,
+}
+
+impl Keystore {
+    /// Creates an empty keystore.
+    pub fn new() -> Self {
+        Keystore::default()
+    }
+
+    /// Decrypts the key files listed in the configuration with the passphrase.
+    pub fn unlock(config: &KeystoreConfig, passphrase: &[u8]) -> Result<Self, failure::Error> {
+        let mut keystore = Keystore::new();
+        for (id, path) in &config.keys {
+            let encrypted = EncryptedKey::load(path)?;
+            let secret_key = encrypted
+                .decrypt(passphrase)
+                .map_err(|e| format_err!("Unable to unlock key {}: {}", id, e))?;
+            keystore.add_key(id, encrypted.public_key, secret_key);
+        }
+        Ok(keystore)
+    }
+
+    /// Adds the key with the given identifier, replacing the previous key if any.
+    pub fn add_key(&mut self, id: &str, public_key: PublicKey, secret_key: SecretKey) {
+        Arc::make_mut(&mut self.keys).insert(id.to_owned(), (public_key, secret_key));
+    }
+
+    /// Returns identifiers of the keys.
+    pub fn key_ids(&self) -> Vec<&str> {
+        self.keys.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the public key with the given identifier.
+    pub fn public_key(&self, id: &str) -> Option<&PublicKey> {
+        self.keys.get(id).map(|&(ref public_key, _)| public_key)
+    }
+
+    /// Signs the data with the key with the given identifier.
+    pub fn sign(&self, id: &str, data: &[u8]) -> Option<Signature> {
+        self.keys
+            .get(id)
+            .map(|&(_, ref secret_key)| crypto::sign(data, secret_key))
+    }
+}
+
+impl fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keystore")
+            .field("keys", &self.key_ids())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use std::collections::BTreeMap;
+
+    use crypto::{gen_keypair, verify};
+    use super::{EncryptedKey, Keystore, KeystoreConfig};
+
+    #[test]
+    fn test_encrypted_key() {
+        let (public_key, secret_key) = gen_keypair();
+        let encrypted = EncryptedKey::encrypt(&public_key, &secret_key, b"passphrase");
+        assert_eq!(encrypted.decrypt(b"passphrase").unwrap(), secret_key);
+        assert!(encrypted.decrypt(b"wrong passphrase").is_err());
+
+        let (other_public_key, _) = gen_keypair();
+        let forged = EncryptedKey {
+            public_key: other_public_key,
+            ..encrypted
+        };
+        assert!(forged.decrypt(b"passphrase").is_err());
+    }
+
+    #[test]
+    fn test_keystore_unlock() {
+        let dir = TempDir::new("exonum_keystore").unwrap();
+        let path = dir.path().join("key.toml");
+        let (public_key, secret_key) = gen_keypair();
+        EncryptedKey::encrypt(&public_key, &secret_key, b"passphrase")
+            .save(&path)
+            .unwrap();
+
+        let mut keys = BTreeMap::new();
+        keys.insert("oracle".to_owned(), path.to_str().unwrap().to_owned());
+        let config = KeystoreConfig { keys };
+        assert!(Keystore::unlock(&config, b"wrong passphrase").is_err());
+
+        let keystore = Keystore::unlock(&config, b"passphrase").unwrap();
+        assert_eq!(keystore.key_ids(), vec!["oracle"]);
+        assert_eq!(keystore.public_key("oracle"), Some(&public_key));
+        let signature = keystore.sign("oracle", b"data").unwrap();
+        assert!(verify(&signature, b"data", &public_key));
+        assert!(keystore.sign("unknown", b"data").is_none());
+    }
+}
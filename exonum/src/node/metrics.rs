@@ -0,0 +1,62 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Metrics of the node exported with the blockchain metrics registry.
+
+use std::collections::HashMap;
+
+use helpers::metrics::{Counter, Gauge, Histogram, Registry};
+use messages::{self, RawMessage, MESSAGE_NAMES};
+
+/// Handles of the node metrics registered once at the node startup.
+#[derive(Debug, Clone)]
+pub(crate) struct NodeMetrics {
+    pub height: Gauge,
+    pub round: Gauge,
+    pub mempool: Gauge,
+    pub peers: Gauge,
+    pub block_commit_seconds: Histogram,
+    messages_received: HashMap<&'static str, Counter>,
+    messages_sent: HashMap<&'static str, Counter>,
+}
+
+impl NodeMetrics {
+    pub fn new(registry: &Registry) -> Self {
+        let counters = |name: &str| {
+            MESSAGE_NAMES
+                .iter()
+                .map(|&message| (message, registry.counter(name, &[("type", message)])))
+                .collect()
+        };
+
+        NodeMetrics {
+            height: registry.gauge("node.height", &[]),
+            round: registry.gauge("node.round", &[]),
+            mempool: registry.gauge("node.mempool", &[]),
+            peers: registry.gauge("node.peers", &[]),
+            block_commit_seconds: registry.histogram("node.block_commit_seconds", &[]),
+            messages_received: counters("node.messages_received"),
+            messages_sent: counters("node.messages_sent"),
+        }
+    }
+
+    pub fn message_received(&self, message: &RawMessage) {
+        self.messages_received[messages::message_name(message)].inc();
+    }
+
+    pub fn message_sent(&self, message: &RawMessage) {
+        self.messages_sent[messages::message_name(message)].inc();
+    }
+}
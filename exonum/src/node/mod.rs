@@ -18,19 +18,21 @@
 // spell-checker:ignore cors
 
 pub use self::state::{RequestData, State, ValidatorState};
-pub use self::whitelist::Whitelist;
+pub use self::peers::{PeerList, PeerListEntry, PeerListSignature, ReconnectConfig};
+pub use self::whitelist::{SharedWhitelist, Whitelist};
+pub use self::keystore::{EncryptedKey, Keystore, KeystoreConfig, KEYSTORE_PASSPHRASE_ENV};
 
 pub mod state; // TODO: temporary solution to get access to WAIT constants (ECR-167)
 
 use failure;
 use toml::Value;
-use router::Router;
-use mount::Mount;
-use iron::{Chain, Iron, Listening};
 use iron_cors::CorsMiddleware;
 use serde::{de, ser};
-use futures::{Future, Sink, sync::mpsc};
-use tokio_core::reactor::Core;
+use futures::{Future, Sink, Stream, sync::{mpsc, oneshot}};
+use tokio_core::reactor::{Core, Handle};
+use tokio_signal;
+#[cfg(unix)]
+use tokio_signal::unix::{Signal, SIGTERM};
 
 use std::{fmt, io};
 use std::str::FromStr;
@@ -41,21 +43,30 @@ use std::time::{Duration, SystemTime};
 use std::collections::{BTreeMap, HashSet};
 
 use crypto::{self, CryptoHash, Hash, PublicKey, SecretKey};
-use blockchain::{Blockchain, GenesisConfig, Schema, Service, SharedNodeState, Transaction};
-use api::{private, public, Api};
+use blockchain::{Blockchain, GenesisConfig, LeaderElection, Schema, Service, SharedNodeState,
+                 Transaction};
+use api::{private, public, ApiAuthConfig, ApiScope, AsyncHandler, AsyncMount, AuthHandler,
+          CorsHandler, HttpServer, OpenApi, ShutdownHandler, WebSocketConfig, WebSocketServer};
 use messages::{Connect, Message, RawMessage};
 use events::{HandlerPart, InternalEvent, InternalPart, InternalRequest, NetworkConfiguration,
              NetworkEvent, NetworkPart, NetworkRequest, SyncSender, TimeoutRequest,
              noise::HandshakeParams};
 use events::error::{into_other, log_error, other_error, LogError};
 use helpers::{user_agent, Height, Milliseconds, Round, ValidatorId};
+use helpers::config::ConfigFile;
 use storage::{Database, DbOptions};
+use self::metrics::NodeMetrics;
+use self::peers::PeersLiveness;
 
 mod events;
 mod basic;
 mod consensus;
+mod gossip;
+mod peers;
 mod requests;
 mod whitelist;
+mod keystore;
+mod metrics;
 
 /// External messages.
 #[derive(Debug)]
@@ -66,8 +77,38 @@ pub enum ExternalMessage {
     Transaction(Box<Transaction>),
     /// Enable or disable the node.
     Enable(bool),
-    /// Shutdown the node.
+    /// Shutdown the node. Transactions received after this message are ignored; the node
+    /// handles the events queued before it, flushes its state and closes the connections.
     Shutdown,
+    /// Remove the transaction from the pool. Transactions included in the known proposes
+    /// are kept in the pool. The result of the removal is sent to the given channel.
+    RemoveTransaction(Hash, oneshot::Sender<Result<(), RemoveTransactionError>>),
+}
+
+/// The reason why the transaction has not been removed from the pool,
+/// see `ExternalMessage::RemoveTransaction`.
+#[derive(Fail, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoveTransactionError {
+    /// The transaction is not in the pool.
+    #[fail(display = "Transaction is not in the pool")]
+    NotInPool,
+    /// The transaction is included in a known propose.
+    #[fail(display = "Transaction is included in a propose")]
+    Proposed,
+}
+
+/// The reason of the node shutdown returned by `Node::run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShutdownReason {
+    /// Shutdown has been requested with `ExternalMessage::Shutdown`.
+    Requested,
+    /// The node has received a termination signal (`SIGTERM` or `Ctrl+C`).
+    Signal,
+    /// The event loop has stopped without a shutdown request.
+    Interrupted,
+    /// The actual configuration requires a protocol version which is not supported
+    /// by the node, so the node should be upgraded.
+    UnsupportedProtocolVersion(u8),
 }
 
 /// Node timeout types.
@@ -85,6 +126,8 @@ pub enum NodeTimeout {
     UpdateApiState,
     /// Exchange peers timeout.
     PeerExchange,
+    /// Reconnect to the peer with the given address.
+    Reconnect(SocketAddr),
 }
 
 /// A helper trait that provides the node with information about the state of the system such
@@ -115,8 +158,14 @@ pub struct NodeHandler {
     /// Known peer addresses.
     // TODO: move this into peer exchange service
     pub peer_discovery: Vec<SocketAddr>,
+    /// Addresses from the signed peer list.
+    peer_list: Vec<SocketAddr>,
+    /// Liveness scores of the peer addresses.
+    peers_liveness: PeersLiveness,
     /// Does this node participate in the consensus?
     is_enabled: bool,
+    /// Metrics of the node.
+    metrics: NodeMetrics,
 }
 
 /// Service configuration.
@@ -147,6 +196,8 @@ pub struct NodeApiConfig {
     /// Timeout to update api state.
     pub state_update_timeout: usize,
     /// Enable api endpoints for the `blockchain_explorer` on public api address.
+    /// Transactions can be submitted with `POST api/explorer/v1/transactions`
+    /// regardless of this option.
     pub enable_blockchain_explorer: bool,
     /// Listen address for public api endpoints.
     pub public_api_address: Option<SocketAddr>,
@@ -162,6 +213,12 @@ pub struct NodeApiConfig {
     ///
     /// [cors]: https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
     pub private_allow_origin: Option<AllowOrigin>,
+    /// Authentication of the requests to the private API. If not set, private API
+    /// handlers are accessible to anyone who can connect to the private API address.
+    pub private_api_auth: Option<ApiAuthConfig>,
+    /// WebSocket API pushing notifications about committed blocks, transactions
+    /// and service events. If not set, the WebSocket API is disabled.
+    pub websocket: Option<WebSocketConfig>,
 }
 
 impl Default for NodeApiConfig {
@@ -173,6 +230,8 @@ impl Default for NodeApiConfig {
             private_api_address: None,
             public_allow_origin: None,
             private_allow_origin: None,
+            private_api_auth: None,
+            websocket: None,
         }
     }
 }
@@ -302,15 +361,26 @@ impl Default for EventsPoolCapacity {
 pub struct MemoryPoolConfig {
     /// Maximum number of uncommitted transactions.
     pub tx_pool_capacity: usize,
+    /// Maximum number of the received transaction hashes remembered by the node
+    /// to avoid fetching the same transactions again.
+    #[serde(default = "MemoryPoolConfig::default_tx_seen_cache_capacity")]
+    pub tx_seen_cache_capacity: usize,
     /// Sets the maximum number of messages that can be buffered on the event loop's
     /// notification channel before a send will fail.
     pub events_pool_capacity: EventsPoolCapacity,
 }
 
+impl MemoryPoolConfig {
+    fn default_tx_seen_cache_capacity() -> usize {
+        100_000
+    }
+}
+
 impl Default for MemoryPoolConfig {
     fn default() -> MemoryPoolConfig {
         MemoryPoolConfig {
             tx_pool_capacity: 100_000,
+            tx_seen_cache_capacity: Self::default_tx_seen_cache_capacity(),
             events_pool_capacity: EventsPoolCapacity::default(),
         }
     }
@@ -330,6 +400,13 @@ pub struct NodeConfig {
     /// Peer addresses.
     #[serde(default)]
     pub peers: Vec<SocketAddr>,
+    /// Path to the list of peers signed by the validators.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_list: Option<String>,
+    /// Encrypted signing keys available to the service APIs. The keys are unlocked with
+    /// the passphrase from the `EXONUM_KEYSTORE_PASSPHRASE` environment variable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keystore: Option<KeystoreConfig>,
     /// Consensus public key.
     pub consensus_public_key: PublicKey,
     /// Consensus secret key.
@@ -363,6 +440,8 @@ pub struct Configuration {
     pub network: NetworkConfiguration,
     /// Known peer addresses.
     pub peer_discovery: Vec<SocketAddr>,
+    /// Addresses from the signed peer list.
+    pub peer_list: Vec<SocketAddr>,
     /// Memory pool configuration.
     pub mempool: MemoryPoolConfig,
 }
@@ -422,6 +501,7 @@ impl NodeHandler {
             config.service.service_public_key,
             config.service.service_secret_key,
             config.mempool.tx_pool_capacity,
+            config.mempool.tx_seen_cache_capacity,
             whitelist,
             stored,
             connect,
@@ -431,15 +511,24 @@ impl NodeHandler {
             system_state.current_time(),
         );
 
-        NodeHandler {
+        let metrics = NodeMetrics::new(blockchain.metrics());
+        let mut handler = NodeHandler {
             blockchain,
             api_state,
             system_state,
             state,
             channel: sender,
             peer_discovery: config.peer_discovery,
+            peer_list: config.peer_list,
+            peers_liveness: PeersLiveness::new(
+                config.network.reconnect,
+                config.network.max_outgoing_connections,
+            ),
             is_enabled: true,
-        }
+            metrics,
+        };
+        handler.update_inactive_validators();
+        handler
     }
 
     /// Return internal `SharedNodeState`
@@ -482,21 +571,69 @@ impl NodeHandler {
         self.state().consensus_config().propose_timeout_threshold
     }
 
+    /// Returns value of the `max_empty_block_interval` field from the current `ConsensusConfig`.
+    pub fn max_empty_block_interval(&self) -> Option<Milliseconds> {
+        self.state().consensus_config().max_empty_block_interval
+    }
+
+    /// Returns value of the `compact_block_depth` field from the current `ConsensusConfig`.
+    pub fn compact_block_depth(&self) -> Option<u64> {
+        self.state().consensus_config().compact_block_depth
+    }
+
+    /// Returns value of the `protocol_version` field from the current `ConsensusConfig`.
+    pub fn protocol_version(&self) -> Option<u8> {
+        self.state().consensus_config().protocol_version
+    }
+
     /// Returns `State` of the node.
     pub fn state(&self) -> &State {
         &self.state
     }
 
+    /// Updates validators skipped by the leader election at the current height.
+    pub fn update_inactive_validators(&mut self) {
+        let inactive = match self.state.consensus_config().leader_election {
+            LeaderElection::SkipMissed { window } => {
+                let snapshot = self.blockchain.snapshot();
+                Schema::new(&snapshot).inactive_validators(self.state.height(), window)
+            }
+            _ => HashSet::new(),
+        };
+        self.state.set_inactive_validators(inactive);
+    }
+
     /// Performs node initialization, so it starts consensus process from the first round.
     pub fn initialize(&mut self) {
+        if !self.check_protocol_version() {
+            return;
+        }
+
         let listen_address = self.system_state.listen_address();
         info!("Start listening address={}", listen_address);
 
-        let peers: HashSet<_> = {
-            let it = self.state.peers().values().map(Connect::addr);
-            let it = it.chain(self.peer_discovery.iter().cloned());
-            let it = it.filter(|&address| address != listen_address);
-            it.collect()
+        // Validators and peers from the configuration are always dialed, other known peers
+        // are chosen according to their liveness scores.
+        let peers = {
+            let validators = self.state
+                .validators()
+                .iter()
+                .map(|keys| keys.consensus_key)
+                .collect::<HashSet<_>>();
+            let (validators, others): (Vec<_>, Vec<_>) = self.state
+                .peers()
+                .values()
+                .partition(|connect| validators.contains(connect.pub_key()));
+
+            let required = validators.into_iter().map(Connect::addr);
+            let required = required.chain(self.peer_discovery.iter().cloned());
+            let optional = others.into_iter().map(Connect::addr);
+            let optional = optional.chain(self.peer_list.iter().cloned());
+            self.peers_liveness
+                .select(
+                    required.filter(|&address| address != listen_address),
+                    optional.filter(|&address| address != listen_address),
+                )
         };
 
         for address in &peers {
@@ -540,7 +677,8 @@ impl NodeHandler {
         if let Some(conn) = self.state.peers().get(&public_key) {
             let address = conn.addr();
             trace!("Send to address: {}", address);
-            let request = NetworkRequest::SendMessage(address, message.clone());
+            self.metrics.message_sent(message);
+            let request = NetworkRequest::SendMessage(address, message.clone(), Some(public_key));
             self.channel.network_requests.send(request).log_error();
         } else {
             warn!("Hasn't connection with peer {:?}", public_key);
@@ -550,26 +688,77 @@ impl NodeHandler {
     /// Sends `RawMessage` to the specified address.
     pub fn send_to_addr(&mut self, address: &SocketAddr, message: &RawMessage) {
         trace!("Send to address: {}", address);
-        let request = NetworkRequest::SendMessage(*address, message.clone());
+        self.metrics.message_sent(message);
+        let peer_key = self.peer_key_by_addr(address);
+        let request = NetworkRequest::SendMessage(*address, message.clone(), peer_key);
         self.channel.network_requests.send(request).log_error();
     }
 
     /// Broadcasts given message to all peers.
     pub fn broadcast(&mut self, message: &RawMessage) {
-        for conn in self.state.peers().values() {
+        for (public_key, conn) in self.state.peers() {
             let address = conn.addr();
             trace!("Send to address: {}", address);
-            let request = NetworkRequest::SendMessage(address, message.clone());
+            self.metrics.message_sent(message);
+            let request = NetworkRequest::SendMessage(address, message.clone(), Some(*public_key));
             self.channel.network_requests.send(request).log_error();
         }
     }
 
+    /// Returns the consensus key of the peer with the given address, if it is known
+    /// from the connected or previously saved peers.
+    #[cfg_attr(feature = "cargo-clippy", allow(let_and_return))]
+    fn peer_key_by_addr(&self, address: &SocketAddr) -> Option<PublicKey> {
+        let connected = self.state
+            .peers()
+            .iter()
+            .find(|&(_, connect)| connect.addr() == *address)
+            .map(|(public_key, _)| *public_key);
+        connected.or_else(|| {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            let peers_cache = schema.peers_cache();
+            let key = peers_cache
+                .iter()
+                .find(|&(_, ref connect)| connect.addr() == *address)
+                .map(|(public_key, _)| public_key);
+            key
+        })
+    }
+
     /// Performs connection to the specified network address.
     pub fn connect(&mut self, address: &SocketAddr) {
         let connect = self.state.our_connect_message().clone();
         self.send_to_addr(address, connect.raw());
     }
 
+    /// Closes the connection with the specified network address.
+    pub fn disconnect(&mut self, address: SocketAddr) {
+        let request = NetworkRequest::DisconnectWithPeer(address);
+        self.channel.network_requests.send(request).log_error();
+    }
+
+    /// Saves the peers cache to the disk and asks the network part to close
+    /// the connections once the queued messages are sent.
+    pub fn shutdown(&mut self) {
+        info!("Shutting down the node");
+        let mut fork = self.blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            let mut peers_cache = schema.peers_cache_mut();
+            for (public_key, connect) in self.state.peers() {
+                peers_cache.put(public_key, connect.clone());
+            }
+        }
+        if let Err(e) = self.blockchain.merge_sync(fork.into_patch()) {
+            error!("Unable to flush the node state: {}", e);
+        }
+        self.channel
+            .network_requests
+            .send(NetworkRequest::Shutdown)
+            .log_error();
+    }
+
     /// Add timeout request.
     pub fn add_timeout(&mut self, timeout: NodeTimeout, time: SystemTime) {
         let request = TimeoutRequest(time, timeout);
@@ -645,6 +834,13 @@ impl NodeHandler {
         self.add_timeout(NodeTimeout::PeerExchange, time);
     }
 
+    /// Adds `NodeTimeout::Reconnect` timeout to the channel.
+    pub fn add_reconnect_timeout(&mut self, address: SocketAddr, delay: Milliseconds) {
+        trace!("ADD RECONNECT TIMEOUT: address={}, delay={}", address, delay);
+        let time = self.system_state.current_time() + Duration::from_millis(delay);
+        self.add_timeout(NodeTimeout::Reconnect(address), time);
+    }
+
     /// Adds `NodeTimeout::UpdateApiState` timeout to the channel.
     pub fn add_update_api_state_timeout(&mut self) {
         let time = self.system_state.current_time()
@@ -793,11 +989,27 @@ impl NodeChannel {
 
 impl Node {
     /// Creates node for the given services and node configuration.
+    ///
+    /// # Panics
+    ///
+    /// If the configured keystore cannot be unlocked, see `Node::try_new`.
     pub fn new<D: Into<Arc<Database>>>(
         db: D,
         services: Vec<Box<Service>>,
         node_cfg: NodeConfig,
     ) -> Self {
+        Node::try_new(db, services, node_cfg).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates node for the given services and node configuration.
+    ///
+    /// Returns an error if the configured keystore cannot be unlocked, for example, if
+    /// the `EXONUM_KEYSTORE_PASSPHRASE` environment variable is not set.
+    pub fn try_new<D: Into<Arc<Database>>>(
+        db: D,
+        services: Vec<Box<Service>>,
+        node_cfg: NodeConfig,
+    ) -> Result<Self, failure::Error> {
         crypto::init();
 
         if cfg!(feature = "flame_profile") {
@@ -819,6 +1031,30 @@ impl Node {
         );
         blockchain.initialize(node_cfg.genesis.clone()).unwrap();
 
+        if let Some(ref keystore) = node_cfg.keystore {
+            let passphrase = ::std::env::var(KEYSTORE_PASSPHRASE_ENV).map_err(|_| {
+                format_err!(
+                    "The keystore is configured, but {} is not set",
+                    KEYSTORE_PASSPHRASE_ENV
+                )
+            })?;
+            let keystore = Keystore::unlock(keystore, passphrase.as_bytes())
+                .map_err(|e| format_err!("Unable to unlock the keystore: {}", e))?;
+            blockchain.set_keystore(keystore);
+        }
+
+        let peer_list = node_cfg.peer_list.as_ref().map_or_else(Vec::new, |path| {
+            let stored = Schema::new(&blockchain.snapshot()).actual_configuration();
+            let peer_list = ConfigFile::load::<_, PeerList>(path).and_then(|list| {
+                list.verify(&stored)?;
+                Ok(list.addresses())
+            });
+            peer_list.unwrap_or_else(|e| {
+                error!("Unable to import peer list from {}: {}", path, e);
+                Vec::new()
+            })
+        });
+
         let config = Configuration {
             listener: ListenerConfig {
                 consensus_public_key: node_cfg.consensus_public_key,
@@ -833,6 +1069,7 @@ impl Node {
             mempool: node_cfg.mempool,
             network: node_cfg.network,
             peer_discovery: node_cfg.peers,
+            peer_list,
         };
 
         let external_address = if let Some(v) = node_cfg.external_address {
@@ -852,20 +1089,35 @@ impl Node {
             config,
             api_state,
         );
-        Node {
+        Ok(Node {
             api_options: node_cfg.api,
             handler,
             channel,
             network_config,
             max_message_len: node_cfg.genesis.consensus.max_message_len,
-        }
+        })
     }
 
     /// Launches only consensus messages handler.
     /// This may be used if you want to customize api with the `ApiContext`.
-    pub fn run_handler(mut self, handshake_params: &HandshakeParams) -> io::Result<()> {
+    ///
+    /// The handler stops on `ExternalMessage::Shutdown` or a termination signal
+    /// and returns the reason of the shutdown.
+    pub fn run_handler(self, handshake_params: &HandshakeParams) -> io::Result<ShutdownReason> {
+        self.run_with_api(handshake_params, Vec::new())
+    }
+
+    /// Launches the consensus messages handler, serving the API servers on the event loop
+    /// of the network thread. The servers are stopped together with the network.
+    fn run_with_api(
+        mut self,
+        handshake_params: &HandshakeParams,
+        api_servers: Vec<HttpServer>,
+    ) -> io::Result<ShutdownReason> {
         self.handler.initialize();
 
+        let api_state = self.handler.api_state.clone();
+        let api_sender = self.channel();
         let (handler_part, network_part, timeouts_part) = self.into_reactor();
         let handshake_params = handshake_params.clone();
 
@@ -874,6 +1126,10 @@ impl Node {
             let handle = core.handle();
             core.handle()
                 .spawn(timeouts_part.run(handle).map_err(log_error));
+            for server in api_servers {
+                let server = server.run(&core.handle())?;
+                core.handle().spawn(server.map_err(log_error));
+            }
             let network_handler = network_part.run(&core.handle(), &handshake_params);
             core.run(network_handler).map(drop).map_err(|e| {
                 other_error(&format!("An error in the `Network` thread occurred: {}", e))
@@ -881,20 +1137,38 @@ impl Node {
         });
 
         let mut core = Core::new()?;
+        let signals = {
+            let api_state = api_state.clone();
+            termination_signals(&core.handle())
+                .into_future()
+                .map_err(|(e, _)| e)
+                .and_then(move |_| {
+                    info!("Received a termination signal");
+                    api_state.request_shutdown(ShutdownReason::Signal);
+                    api_sender.send_external_message(ExternalMessage::Shutdown)
+                })
+                .map_err(log_error)
+        };
+        core.handle().spawn(signals);
         core.run(handler_part.run())
             .map_err(|_| other_error("An error in the `Handler` thread occurred"))?;
-        network_thread.join().unwrap()
+        network_thread.join().unwrap()?;
+        Ok(api_state
+            .shutdown_reason()
+            .unwrap_or(ShutdownReason::Interrupted))
     }
 
-    /// A generic implementation that launches `Node` and optionally creates threads
-    /// for public and private api handlers.
+    /// A generic implementation that launches `Node` and optionally serves the public
+    /// and private api on the event loop of the network thread.
     /// Explorer api prefix is `/api/explorer`
     /// Public api prefix is `/api/services/{service_name}`
     /// Private api prefix is `/api/services/{service_name}`
-    pub fn run(self) -> io::Result<()> {
+    ///
+    /// Returns the reason of the shutdown once the node has stopped.
+    pub fn run(self) -> io::Result<ShutdownReason> {
         let api_state = self.handler.api_state.clone();
         let blockchain = self.handler.blockchain.clone();
-        let mut api_handlers: Vec<Listening> = Vec::new();
+        let mut api_servers = Vec::new();
 
         // Start private api.
         if let Some(listen_address) = self.api_options.private_api_address {
@@ -905,8 +1179,7 @@ impl Node {
                 api_sender,
                 &self.api_options,
             );
-            let listener = Iron::new(handler).http(listen_address).unwrap();
-            api_handlers.push(listener);
+            api_servers.push(HttpServer::bind(&listen_address, handler)?);
 
             info!("Private exonum api started on {}", listen_address);
         };
@@ -914,25 +1187,36 @@ impl Node {
         // Start public api.
         if let Some(listen_address) = self.api_options.public_api_address {
             let handler = create_public_api_handler(blockchain, api_state, &self.api_options);
-            let listener = Iron::new(handler).http(listen_address).unwrap();
-            api_handlers.push(listener);
+            api_servers.push(HttpServer::bind(&listen_address, handler)?);
 
             info!("Public exonum api started on {}", listen_address);
         };
 
+        // Start websocket api.
+        let websocket_server = match self.api_options.websocket {
+            Some(ref config) => {
+                let server = WebSocketServer::start(config, self.handler.blockchain.clone())?;
+                info!("WebSocket exonum api started on {}", config.address);
+                Some(server)
+            }
+            None => None,
+        };
+
         let handshake_params = HandshakeParams {
             public_key: *self.handler().state().consensus_public_key(),
             secret_key: self.handler().state().consensus_secret_key().clone(),
             max_message_len: self.max_message_len,
+            whitelist: self.handler().state().whitelist().clone(),
+            compression: self.network_config.compression,
         };
-        self.run_handler(&handshake_params)?;
+        let reason = self.run_with_api(&handshake_params, api_servers)?;
 
-        // Stop all api handlers.
-        for mut handler in api_handlers {
-            handler.close().unwrap();
+        if let Some(server) = websocket_server {
+            server.shutdown();
         }
 
-        Ok(())
+        info!("The node has stopped, reason: {:?}", reason);
+        Ok(reason)
     }
 
     fn into_reactor(self) -> (HandlerPart<NodeHandler>, NetworkPart, InternalPart) {
@@ -946,6 +1230,7 @@ impl Node {
             network_tx,
             network_config: self.network_config,
             max_message_len: self.max_message_len,
+            queue_depths: self.handler.api_state.shared_queue_depths().clone(),
         };
 
         let (internal_tx, internal_rx) = self.channel.internal_events;
@@ -984,33 +1269,62 @@ impl Node {
     }
 }
 
+/// Returns the stream of the signals requesting the node to terminate.
+#[cfg(unix)]
+fn termination_signals(handle: &Handle) -> Box<Stream<Item = (), Error = io::Error>> {
+    let ctrl_c = tokio_signal::ctrl_c(handle).flatten_stream();
+    let sigterm = Signal::new(SIGTERM, handle)
+        .flatten_stream()
+        .map(drop);
+    Box::new(ctrl_c.select(sigterm))
+}
+
+/// Returns the stream of the signals requesting the node to terminate.
+#[cfg(not(unix))]
+fn termination_signals(handle: &Handle) -> Box<Stream<Item = (), Error = io::Error>> {
+    Box::new(tokio_signal::ctrl_c(handle).flatten_stream())
+}
+
 /// Public for testing
 #[doc(hidden)]
 pub fn create_public_api_handler(
     blockchain: Blockchain,
     shared_api_state: SharedNodeState,
     config: &NodeApiConfig,
-) -> Chain {
-    let mut mount = Mount::new();
-    mount.mount("api/services", blockchain.mount_public_api());
-
+) -> Box<AsyncHandler> {
+    let mut mount = AsyncMount::new();
+    let mut openapi = OpenApi::new();
+    // Services and the explorer accept transactions, which are rejected during the shutdown.
+    mount.mount(
+        "api/services",
+        ShutdownHandler::new(shared_api_state.clone(), blockchain.public_api()),
+    );
+
+    let explorer_api = public::ExplorerApi::new(blockchain.clone());
+    let mut explorer_scope = ApiScope::new();
     if config.enable_blockchain_explorer {
-        let mut router = Router::new();
-        let explorer_api = public::ExplorerApi::new(blockchain.clone());
-        explorer_api.wire(&mut router);
-        mount.mount("api/explorer", router);
+        explorer_api.wire(&mut explorer_scope);
+    } else {
+        explorer_api.wire_submission(&mut explorer_scope);
     }
-
-    let mut router = Router::new();
-    let system_api = public::SystemApi::new(blockchain, shared_api_state);
-    system_api.wire(&mut router);
-    mount.mount("api/system", router);
-
-    let mut chain = Chain::new(mount);
-    if let Some(ref allow_origin) = config.public_allow_origin {
-        chain.link_around(CorsMiddleware::from(allow_origin.clone()));
+    openapi.add_scope("api/explorer", &explorer_scope);
+    mount.mount(
+        "api/explorer",
+        ShutdownHandler::new(shared_api_state.clone(), explorer_scope),
+    );
+
+    let mut system_scope = ApiScope::new();
+    public::SystemApi::new(blockchain.clone(), shared_api_state).wire(&mut system_scope);
+
+    openapi.add_scope("api/system", &system_scope);
+    blockchain.describe_public_api("api/services", &mut openapi);
+    mount.mount("api/system", system_scope);
+    mount.mount("api/openapi.json", openapi);
+
+    match config.public_allow_origin {
+        Some(ref allow_origin) => Box::new(CorsHandler::new(allow_origin.clone(), mount)),
+        None => Box::new(mount),
     }
-    chain
 }
 
 /// Public for testing
@@ -1020,21 +1334,31 @@ pub fn create_private_api_handler(
     shared_api_state: SharedNodeState,
     api_sender: ApiSender,
     config: &NodeApiConfig,
-) -> Chain {
-    let mut mount = Mount::new();
-    mount.mount("api/services", blockchain.mount_private_api());
-
-    let mut router = Router::new();
+) -> Box<AsyncHandler> {
+    let mut mount = AsyncMount::new();
+    mount.mount(
+        "api/services",
+        ShutdownHandler::new(shared_api_state.clone(), blockchain.private_api()),
+    );
+    mount.mount(
+        "metrics",
+        private::MetricsApi::new(blockchain.metrics().clone()),
+    );
+
+    let mut system_scope = ApiScope::new();
     let node_info = private::NodeInfo::new(blockchain.service_map().iter().map(|(_, s)| s));
     let system_api = private::SystemApi::new(node_info, blockchain, shared_api_state, api_sender);
-    system_api.wire(&mut router);
-    mount.mount("api/system", router);
-
-    let mut chain = Chain::new(mount);
-    if let Some(ref allow_origin) = config.private_allow_origin {
-        chain.link_around(CorsMiddleware::from(allow_origin.clone()));
+    system_api.wire(&mut system_scope);
+    mount.mount("api/system", system_scope);
+
+    let handler: Box<AsyncHandler> = match config.private_api_auth {
+        Some(ref auth) => Box::new(AuthHandler::new(auth, mount)),
+        None => Box::new(mount),
+    };
+    match config.private_allow_origin {
+        Some(ref allow_origin) => Box::new(CorsHandler::new(allow_origin.clone(), handler)),
+        None => handler,
     }
-    chain
 }
 
 #[cfg(test)]
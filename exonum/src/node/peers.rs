@@ -0,0 +1,351 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Liveness of the known peers and signed peer lists.
+
+use failure;
+use serde_json;
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+use blockchain::StoredConfiguration;
+use crypto::{self, Hash, PublicKey, SecretKey, Signature};
+use helpers::Milliseconds;
+use node::State;
+
+const MAX_SCORE: i32 = 10;
+const MIN_SCORE: i32 = -10;
+
+/// Parameters of reconnection to the known peers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnection attempt in milliseconds. The delay is doubled
+    /// after each consecutive failure.
+    pub min_delay: Milliseconds,
+    /// Maximum delay between reconnection attempts in milliseconds.
+    pub max_delay: Milliseconds,
+    /// Number of consecutive failures after which a peer is removed from the peers cache.
+    /// Validators and peers from the node configuration are never removed.
+    pub max_failures: u32,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            min_delay: 1_000,
+            max_delay: 60_000,
+            max_failures: 10,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Liveness {
+    score: i32,
+    failures: u32,
+}
+
+/// Liveness scores of the peer addresses. The score of an address is increased with each
+/// successful connection and decreased with each failure.
+#[derive(Debug)]
+pub struct PeersLiveness {
+    config: ReconnectConfig,
+    max_connections: usize,
+    peers: HashMap<SocketAddr, Liveness>,
+}
+
+impl PeersLiveness {
+    /// Creates empty scores. At most `max_connections` addresses are chosen for dialing.
+    pub fn new(config: ReconnectConfig, max_connections: usize) -> Self {
+        PeersLiveness {
+            config,
+            max_connections,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Returns the score of the address.
+    pub fn score(&self, address: &SocketAddr) -> i32 {
+        self.peers.get(address).map_or(0, |liveness| liveness.score)
+    }
+
+    /// Records a successful connection to the address.
+    pub fn connected(&mut self, address: SocketAddr) {
+        let liveness = self.peers.entry(address).or_insert_with(Liveness::default);
+        liveness.score = cmp::min(liveness.score + 1, MAX_SCORE);
+        liveness.failures = 0;
+    }
+
+    /// Records a failed or closed connection to the address. Returns the delay before
+    /// the next connection attempt.
+    pub fn failed(&mut self, address: SocketAddr) -> Milliseconds {
+        let liveness = self.peers.entry(address).or_insert_with(Liveness::default);
+        liveness.score = cmp::max(liveness.score - 1, MIN_SCORE);
+        liveness.failures = liveness.failures.saturating_add(1);
+
+        let delay = 1_u64
+            .checked_shl(liveness.failures - 1)
+            .and_then(|factor| self.config.min_delay.checked_mul(factor))
+            .unwrap_or(self.config.max_delay);
+        cmp::min(delay, self.config.max_delay)
+    }
+
+    /// Returns `true` if the address has failed too many times in a row.
+    pub fn is_dead(&self, address: &SocketAddr) -> bool {
+        self.peers
+            .get(address)
+            .map_or(false, |liveness| liveness.failures >= self.config.max_failures)
+    }
+
+    /// Forgets the score of the address.
+    pub fn forget(&mut self, address: &SocketAddr) {
+        self.peers.remove(address);
+    }
+
+    /// Chooses addresses to dial. All the `required` addresses are chosen; the rest of
+    /// the `max_connections` limit is filled with the `optional` addresses having
+    /// the best scores.
+    pub fn select<I, J>(&self, required: I, optional: J) -> Vec<SocketAddr>
+    where
+        I: IntoIterator<Item = SocketAddr>,
+        J: IntoIterator<Item = SocketAddr>,
+    {
+        let mut selected = Vec::new();
+        let mut seen = HashSet::new();
+        for address in required {
+            if seen.insert(address) {
+                selected.push(address);
+            }
+        }
+
+        let mut optional = optional
+            .into_iter()
+            .filter(|address| seen.insert(*address))
+            .collect::<Vec<_>>();
+        // The sort is stable, so addresses with equal scores keep their order.
+        optional.sort_by(|a, b| self.score(b).cmp(&self.score(a)));
+
+        let free = self.max_connections.saturating_sub(selected.len());
+        selected.extend(optional.into_iter().take(free));
+        selected
+    }
+}
+
+/// Address of a peer in the signed peer list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PeerListEntry {
+    /// Consensus public key of the peer.
+    pub public_key: PublicKey,
+    /// Network address of the peer.
+    pub address: SocketAddr,
+}
+
+/// Signature of a validator for the peer list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PeerListSignature {
+    /// Consensus public key of the validator.
+    pub validator: PublicKey,
+    /// Signature of the peer list hash.
+    pub signature: Signature,
+}
+
+/// List of the peers signed by the validators.
+///
+/// The list is loaded from a TOML file set by the `peer_list` field of the `NodeConfig`,
+/// so that the node can bootstrap from the vetted addresses without resolving any names.
+/// The list is accepted only if it is signed by the +2/3 majority of the validators.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PeerList {
+    /// Peers from the list.
+    pub peers: Vec<PeerListEntry>,
+    /// Signatures of the validators.
+    #[serde(default)]
+    pub signatures: Vec<PeerListSignature>,
+}
+
+impl PeerList {
+    /// Creates an unsigned list with the given peers.
+    pub fn new(peers: Vec<PeerListEntry>) -> Self {
+        PeerList {
+            peers,
+            signatures: Vec::new(),
+        }
+    }
+
+    /// Returns the hash of the peers, which is signed by the validators.
+    pub fn hash(&self) -> Hash {
+        let bytes = serde_json::to_vec(&self.peers).expect("Unable to serialize peer list");
+        crypto::hash(&bytes)
+    }
+
+    /// Adds a signature of the validator, replacing its previous signature if any.
+    pub fn sign(&mut self, public_key: &PublicKey, secret_key: &SecretKey) {
+        let signature = crypto::sign(self.hash().as_ref(), secret_key);
+        self.signatures.retain(|s| s.validator != *public_key);
+        self.signatures.push(PeerListSignature {
+            validator: *public_key,
+            signature,
+        });
+    }
+
+    /// Checks that the list is signed by the +2/3 majority of the validators from
+    /// the given configuration.
+    pub fn verify(&self, config: &StoredConfiguration) -> Result<(), failure::Error> {
+        let hash = self.hash();
+        let mut signed = HashSet::new();
+        for signature in &self.signatures {
+            let is_validator = config
+                .validator_keys
+                .iter()
+                .any(|keys| keys.consensus_key == signature.validator);
+            if !is_validator {
+                bail!("Peer list is signed by {:?}, which is not a validator", signature.validator);
+            }
+            if !crypto::verify(&signature.signature, hash.as_ref(), &signature.validator) {
+                bail!("Invalid signature of the peer list by {:?}", signature.validator);
+            }
+            signed.insert(signature.validator);
+        }
+
+        let signed_weight: u64 = config
+            .validator_keys
+            .iter()
+            .filter(|keys| signed.contains(&keys.consensus_key))
+            .map(|keys| keys.weight)
+            .sum();
+        if signed_weight < State::byzantine_majority_weight(config.total_weight()) {
+            bail!("Peer list is not signed by the majority of the validators");
+        }
+        Ok(())
+    }
+
+    /// Returns addresses of the peers.
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        self.peers.iter().map(|entry| entry.address).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+
+    use blockchain::{ConsensusConfig, StoredConfiguration, ValidatorKeys};
+    use crypto::{gen_keypair, Hash, PublicKey, SecretKey};
+    use helpers::Height;
+    use super::{PeerList, PeerListEntry, PeersLiveness, ReconnectConfig};
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let config = ReconnectConfig {
+            min_delay: 100,
+            max_delay: 1_000,
+            max_failures: 3,
+        };
+        let mut liveness = PeersLiveness::new(config, 10);
+        let peer = address(1);
+
+        assert_eq!(liveness.failed(peer), 100);
+        assert_eq!(liveness.failed(peer), 200);
+        assert!(!liveness.is_dead(&peer));
+        assert_eq!(liveness.failed(peer), 400);
+        assert!(liveness.is_dead(&peer));
+        assert_eq!(liveness.failed(peer), 800);
+        assert_eq!(liveness.failed(peer), 1_000);
+        for _ in 0..100 {
+            liveness.failed(peer);
+        }
+        assert_eq!(liveness.failed(peer), 1_000);
+
+        liveness.connected(peer);
+        assert!(!liveness.is_dead(&peer));
+        assert_eq!(liveness.failed(peer), 100);
+    }
+
+    #[test]
+    fn test_select_peers() {
+        let mut liveness = PeersLiveness::new(ReconnectConfig::default(), 3);
+        liveness.connected(address(3));
+        liveness.connected(address(3));
+        liveness.connected(address(4));
+        liveness.failed(address(5));
+
+        let selected = liveness.select(
+            vec![address(1)],
+            vec![address(5), address(1), address(4), address(6), address(3)],
+        );
+        assert_eq!(selected, vec![address(1), address(3), address(4)]);
+
+        let selected = liveness.select(vec![address(1), address(2), address(5)], vec![address(3)]);
+        assert_eq!(selected, vec![address(1), address(2), address(5)]);
+    }
+
+    fn config(validators: &[(PublicKey, SecretKey)]) -> StoredConfiguration {
+        StoredConfiguration {
+            previous_cfg_hash: Hash::zero(),
+            actual_from: Height(0),
+            validator_keys: validators
+                .iter()
+                .map(|&(consensus_key, _)| ValidatorKeys {
+                    consensus_key,
+                    service_key: consensus_key,
+                    weight: ValidatorKeys::DEFAULT_WEIGHT,
+                })
+                .collect(),
+            consensus: ConsensusConfig::default(),
+            majority_count: None,
+            services: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_peer_list_signatures() {
+        let validators = (0..4).map(|_| gen_keypair()).collect::<Vec<_>>();
+        let config = config(&validators);
+        let mut list = PeerList::new(vec![
+            PeerListEntry {
+                public_key: gen_keypair().0,
+                address: address(1),
+            },
+        ]);
+
+        assert!(list.verify(&config).is_err());
+        for &(ref public_key, ref secret_key) in &validators[..2] {
+            list.sign(public_key, secret_key);
+        }
+        list.sign(&validators[1].0, &validators[1].1);
+        assert_eq!(list.signatures.len(), 2);
+        assert!(list.verify(&config).is_err());
+
+        list.sign(&validators[2].0, &validators[2].1);
+        assert!(list.verify(&config).is_ok());
+        assert_eq!(list.addresses(), vec![address(1)]);
+
+        // Changed list invalidates the signatures.
+        let mut changed = list.clone();
+        changed.peers[0].address = address(2);
+        assert!(changed.verify(&config).is_err());
+
+        // Signatures of non-validators are rejected.
+        let (public_key, secret_key) = gen_keypair();
+        list.sign(&public_key, &secret_key);
+        assert!(list.verify(&config).is_err());
+    }
+}
@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use messages::{BlockRequest, BlockResponse, Message, PrevotesRequest, ProposeRequest,
-               RequestMessage, TransactionsRequest, TransactionsResponse, HEADER_LENGTH};
+use messages::{BlockRequest, BlockResponse, CompactBlockResponse, Message, PrevotesRequest,
+               ProposeRequest, RequestMessage, TransactionsRequest, TransactionsResponse,
+               FEATURE_COMPACT_BLOCKS, HEADER_LENGTH};
 use blockchain::Schema;
 use crypto::{PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
 use super::NodeHandler;
@@ -158,6 +159,26 @@ impl NodeHandler {
         let precommits = schema.precommits(&block_hash);
         let transactions = schema.block_transactions(height);
 
+        // Send the latest blocks in the compact form to the peers supporting it, because
+        // the peer is likely to have their transactions in the pool.
+        let is_compact = self.compact_block_depth()
+            .map_or(false, |depth| self.state.height().0 - height.0 <= depth)
+            && self.state
+                .peer_protocol(msg.from())
+                .map_or(false, |protocol| protocol.supports(FEATURE_COMPACT_BLOCKS));
+        if is_compact {
+            let block_msg = CompactBlockResponse::new(
+                self.state.consensus_public_key(),
+                msg.from(),
+                block,
+                precommits.iter().collect(),
+                &transactions.iter().collect::<Vec<_>>(),
+                self.state.consensus_secret_key(),
+            );
+            self.send_to_peer(*msg.from(), block_msg.raw());
+            return;
+        }
+
         let block_msg = BlockResponse::new(
             self.state.consensus_public_key(),
             msg.from(),
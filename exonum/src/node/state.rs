@@ -22,12 +22,14 @@ use std::collections::{BTreeMap, HashMap, HashSet, hash_map::Entry};
 use std::net::SocketAddr;
 use std::time::{Duration, SystemTime};
 
-use messages::{Connect, ConsensusMessage, Message, Precommit, Prevote, Propose, RawMessage};
+use messages::{CompactBlockResponse, Connect, ConsensusMessage, Message, Precommit, Prevote,
+               Propose, ProtocolInfo, RawMessage};
 use crypto::{CryptoHash, Hash, PublicKey, SecretKey};
 use storage::{KeySetIndex, MapIndex, Patch, Snapshot};
 use blockchain::{ConsensusConfig, StoredConfiguration, ValidatorKeys};
 use helpers::{Height, Milliseconds, Round, ValidatorId};
-use node::whitelist::Whitelist;
+use node::gossip::SeenTransactions;
+use node::whitelist::{SharedWhitelist, Whitelist};
 
 // TODO: move request timeouts into node configuration (ECR-171)
 
@@ -52,10 +54,11 @@ pub struct State {
     service_secret_key: SecretKey,
 
     config: StoredConfiguration,
-    whitelist: Whitelist,
+    whitelist: SharedWhitelist,
     tx_pool_capacity: usize,
 
     peers: HashMap<PublicKey, Connect>,
+    peer_protocols: HashMap<PublicKey, ProtocolInfo>,
     connections: HashMap<SocketAddr, PublicKey>,
     height_start_time: SystemTime,
     height: Height,
@@ -68,12 +71,14 @@ pub struct State {
     // Messages.
     proposes: HashMap<Hash, ProposeState>,
     blocks: HashMap<Hash, BlockState>,
+    compact_block: Option<CompactBlockState>,
     prevotes: HashMap<(Round, Hash), Votes<Prevote>>,
     precommits: HashMap<(Round, Hash), Votes<Precommit>>,
 
     queued: Vec<ConsensusMessage>,
 
     unknown_txs: HashMap<Hash, Vec<Hash>>,
+    seen_txs: SeenTransactions,
     unknown_proposes_with_precommits: HashMap<Hash, Vec<(Round, Hash)>>,
 
     // Our requests state.
@@ -86,6 +91,11 @@ pub struct State {
 
     // Current value of the propose timeout.
     propose_timeout: Milliseconds,
+    // Round of the propose postponed because of the empty transactions pool.
+    postponed_propose: Option<Round>,
+
+    // Validators skipped by the leader election at the current height.
+    inactive_validators: HashSet<PublicKey>,
 }
 
 /// State of a validator-node.
@@ -108,6 +118,10 @@ pub enum RequestData {
     Prevotes(Round, Hash),
     /// Represents `BlockRequest` message.
     Block(Height),
+    /// Represents `TransactionsRequest` message for the transactions of a compact block.
+    BlockTransactions(Hash),
+    /// Represents `TransactionsRequest` message for a transaction announced by peers.
+    AnnouncedTransaction(Hash),
 }
 
 #[derive(Debug)]
@@ -129,6 +143,14 @@ pub struct ProposeState {
     is_saved: bool,
 }
 
+/// `CompactBlockState` represents the state of a block received in the compact form and is used
+/// for tracking of unknown transactions.
+#[derive(Debug)]
+pub struct CompactBlockState {
+    message: CompactBlockResponse,
+    unknown_txs: HashSet<Hash>,
+}
+
 /// State of a block.
 #[derive(Clone, Debug)]
 pub struct BlockState {
@@ -163,6 +185,7 @@ pub struct Votes<T: VoteMessage> {
     messages: Vec<T>,
     validators: BitVec,
     count: usize,
+    weight: u64,
 }
 
 impl ValidatorState {
@@ -207,14 +230,17 @@ where
             messages: Vec::new(),
             validators: BitVec::from_elem(validators_len, false),
             count: 0,
+            weight: 0,
         }
     }
 
-    /// Inserts a new message if it hasn't been inserted yet.
-    pub fn insert(&mut self, message: &T) {
+    /// Inserts a new message with the voting power of its author if it hasn't been
+    /// inserted yet.
+    pub fn insert(&mut self, message: &T, weight: u64) {
         let voter: usize = message.validator().into();
         if !self.validators[voter] {
             self.count += 1;
+            self.weight += weight;
             self.validators.set(voter, true);
             self.messages.push(message.clone());
         }
@@ -230,6 +256,11 @@ where
         self.count
     }
 
+    /// Returns total voting power of contained messages.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
     /// Returns messages.
     pub fn messages(&self) -> &Vec<T> {
         &self.messages
@@ -245,6 +276,8 @@ impl RequestData {
             RequestData::Transactions(..) => TRANSACTIONS_REQUEST_TIMEOUT,
             RequestData::Prevotes(..) => PREVOTES_REQUEST_TIMEOUT,
             RequestData::Block(..) => BLOCK_REQUEST_TIMEOUT,
+            RequestData::BlockTransactions(..) => TRANSACTIONS_REQUEST_TIMEOUT,
+            RequestData::AnnouncedTransaction(..) => TRANSACTIONS_REQUEST_TIMEOUT,
         };
         Duration::from_millis(ms)
     }
@@ -276,6 +309,28 @@ impl RequestState {
     }
 }
 
+impl CompactBlockState {
+    /// Returns hash of the block.
+    pub fn hash(&self) -> Hash {
+        self.message.block().hash()
+    }
+
+    /// Returns the compact block message.
+    pub fn message(&self) -> &CompactBlockResponse {
+        &self.message
+    }
+
+    /// Returns unknown transactions of the block.
+    pub fn unknown_txs(&self) -> &HashSet<Hash> {
+        &self.unknown_txs
+    }
+
+    /// Returns `true` if there are unknown transactions in the block.
+    pub fn has_unknown_txs(&self) -> bool {
+        !self.unknown_txs.is_empty()
+    }
+}
+
 impl ProposeState {
     /// Returns hash of the propose.
     pub fn hash(&self) -> Hash {
@@ -360,6 +415,7 @@ impl State {
         service_public_key: PublicKey,
         service_secret_key: SecretKey,
         tx_pool_capacity: usize,
+        tx_seen_cache_capacity: usize,
         whitelist: Whitelist,
         stored: StoredConfiguration,
         connect: Connect,
@@ -375,8 +431,9 @@ impl State {
             service_public_key,
             service_secret_key,
             tx_pool_capacity,
-            whitelist,
+            whitelist: SharedWhitelist::new(whitelist),
             peers,
+            peer_protocols: HashMap::new(),
             connections: HashMap::new(),
             height: last_height,
             height_start_time,
@@ -386,6 +443,7 @@ impl State {
             last_hash,
 
             proposes: HashMap::new(),
+            compact_block: None,
             blocks: HashMap::new(),
             prevotes: HashMap::new(),
             precommits: HashMap::new(),
@@ -393,6 +451,7 @@ impl State {
             queued: Vec::new(),
 
             unknown_txs: HashMap::new(),
+            seen_txs: SeenTransactions::new(tx_seen_cache_capacity),
             unknown_proposes_with_precommits: HashMap::new(),
 
             nodes_max_height: BTreeMap::new(),
@@ -403,6 +462,8 @@ impl State {
             requests: HashMap::new(),
 
             propose_timeout: stored.consensus.max_propose_timeout,
+            postponed_propose: None,
+            inactive_validators: HashSet::new(),
             config: stored,
         }
     }
@@ -444,7 +505,7 @@ impl State {
     }
 
     /// Returns node's whitelist.
-    pub fn whitelist(&self) -> &Whitelist {
+    pub fn whitelist(&self) -> &SharedWhitelist {
         &self.whitelist
     }
 
@@ -507,6 +568,16 @@ impl State {
         self.propose_timeout
     }
 
+    /// Marks the propose of the given round as postponed until a transaction arrives.
+    pub fn set_postponed_propose(&mut self, round: Round) {
+        self.postponed_propose = Some(round);
+    }
+
+    /// Returns the round of the postponed propose and resets it.
+    pub fn take_postponed_propose(&mut self) -> Option<Round> {
+        self.postponed_propose.take()
+    }
+
     /// Adds the public key, address, and `Connect` message of a validator.
     pub fn add_peer(&mut self, pubkey: PublicKey, msg: Connect) -> bool {
         self.connections.insert(msg.addr(), pubkey);
@@ -517,6 +588,7 @@ impl State {
     pub fn remove_peer_with_addr(&mut self, addr: &SocketAddr) -> bool {
         if let Some(pubkey) = self.connections.remove(addr) {
             self.peers.remove(&pubkey);
+            self.peer_protocols.remove(&pubkey);
             return self.config
                 .validator_keys
                 .iter()
@@ -530,6 +602,17 @@ impl State {
         &self.peers
     }
 
+    /// Saves the protocol versions and features announced by the connected peer.
+    pub fn set_peer_protocol(&mut self, pubkey: PublicKey, protocol: ProtocolInfo) {
+        self.peer_protocols.insert(pubkey, protocol);
+    }
+
+    /// Returns the protocol versions and features of the peer, if the peer has connected
+    /// to the node.
+    pub fn peer_protocol(&self, pubkey: &PublicKey) -> Option<&ProtocolInfo> {
+        self.peer_protocols.get(pubkey)
+    }
+
     /// Returns public key of a validator identified by id.
     pub fn consensus_public_key_of(&self, id: ValidatorId) -> Option<PublicKey> {
         let id: usize = id.into();
@@ -557,10 +640,26 @@ impl State {
     }
 
     /// Returns the leader id for the specified round and current height.
+    ///
+    /// The leader is chosen according to the `leader_election` strategy from the current
+    /// `ConsensusConfig`.
     pub fn leader(&self, round: Round) -> ValidatorId {
-        let height: u64 = self.height().into();
-        let round: u64 = round.into();
-        ValidatorId(((height + round) % (self.validators().len() as u64)) as u16)
+        self.consensus_config().leader_election.leader(
+            self.validators(),
+            &self.inactive_validators,
+            self.height(),
+            round,
+        )
+    }
+
+    /// Returns consensus keys of validators skipped by the leader election at the current height.
+    pub fn inactive_validators(&self) -> &HashSet<PublicKey> {
+        &self.inactive_validators
+    }
+
+    /// Sets consensus keys of validators skipped by the leader election at the current height.
+    pub fn set_inactive_validators(&mut self, inactive_validators: HashSet<PublicKey>) {
+        self.inactive_validators = inactive_validators;
     }
 
     /// Updates known round for a validator and returns
@@ -625,9 +724,9 @@ impl State {
             .collect()
     }
 
-    /// Returns sufficient number of votes for current validators number.
-    pub fn majority_count(&self) -> usize {
-        State::byzantine_majority_count(self.validators().len())
+    /// Returns sufficient voting power for current validators weights.
+    pub fn majority_count(&self) -> u64 {
+        State::byzantine_majority_weight(self.total_weight())
     }
 
     /// Returns sufficient number of votes for the given validators number.
@@ -635,6 +734,24 @@ impl State {
         total * 2 / 3 + 1
     }
 
+    /// Returns sufficient voting power for the given total weight of validators.
+    pub fn byzantine_majority_weight(total: u64) -> u64 {
+        // Same as `total * 2 / 3 + 1`, but without overflow.
+        total / 3 * 2 + total % 3 * 2 / 3 + 1
+    }
+
+    /// Returns total voting power of current validators.
+    pub fn total_weight(&self) -> u64 {
+        self.validators().iter().map(|keys| keys.weight).sum()
+    }
+
+    /// Returns voting power of the validator with the given id.
+    pub fn validator_weight(&self, id: ValidatorId) -> u64 {
+        self.validators()
+            .get(id.0 as usize)
+            .map_or(0, |keys| keys.weight)
+    }
+
     /// Returns current height.
     pub fn height(&self) -> Height {
         self.height
@@ -687,6 +804,14 @@ impl State {
         self.proposes.get(hash)
     }
 
+    /// Returns `true` if the transaction is included in a known propose or block.
+    pub fn is_tx_proposed(&self, tx_hash: &Hash) -> bool {
+        self.proposes
+            .values()
+            .any(|state| state.propose.transactions().contains(tx_hash))
+            || self.blocks.values().any(|block| block.txs.contains(tx_hash))
+    }
+
     /// Returns a block with the specified hash.
     pub fn block(&self, hash: &Hash) -> Option<&BlockState> {
         self.blocks.get(hash)
@@ -713,6 +838,8 @@ impl State {
         self.last_hash = *block_hash;
         // TODO: destruct/construct structure HeightState instead of call clear (ECR-171)
         self.blocks.clear();
+        self.compact_block = None;
+        self.postponed_propose = None;
         self.proposes.clear();
         self.prevotes.clear();
         self.precommits.clear();
@@ -735,6 +862,17 @@ impl State {
         self.queued.push(msg);
     }
 
+    /// Marks the transaction as received by the node. Returns `false` if it has already
+    /// been seen.
+    pub fn mark_tx_seen(&mut self, tx_hash: Hash) -> bool {
+        self.seen_txs.insert(tx_hash)
+    }
+
+    /// Returns `true` if the transaction has already been received by the node.
+    pub fn is_tx_seen(&self, tx_hash: &Hash) -> bool {
+        self.seen_txs.contains(tx_hash)
+    }
+
     /// Checks whether some proposes are waiting for this transaction.
     /// Returns a list of proposes that don't contain unknown transactions.
     ///
@@ -754,6 +892,27 @@ impl State {
         full_proposes
     }
 
+    /// Removes the transaction from the unknown transactions of the compact block.
+    /// Returns the block hash if all transactions of the block are known now.
+    pub fn check_incomplete_compact_block(&mut self, tx_hash: Hash) -> Option<Hash> {
+        let block_state = self.compact_block.as_mut()?;
+        if block_state.unknown_txs.remove(&tx_hash) && block_state.unknown_txs.is_empty() {
+            Some(block_state.hash())
+        } else {
+            None
+        }
+    }
+
+    /// Returns pre-votes collected at the current height, grouped by round and propose hash.
+    pub fn collected_prevotes(&self) -> &HashMap<(Round, Hash), Votes<Prevote>> {
+        &self.prevotes
+    }
+
+    /// Returns pre-commits collected at the current height, grouped by round and block hash.
+    pub fn collected_precommits(&self) -> &HashMap<(Round, Hash), Votes<Precommit>> {
+        &self.precommits
+    }
+
     /// Returns pre-votes for the specified round and propose hash.
     pub fn prevotes(&self, round: Round, propose_hash: Hash) -> &[Prevote] {
         self.prevotes
@@ -845,6 +1004,52 @@ impl State {
         }
     }
 
+    /// Adds block received in the compact form. The previous compact block is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block contains an already committed transaction.
+    pub fn add_compact_block(
+        &mut self,
+        msg: &CompactBlockResponse,
+        transactions: &MapIndex<&&Snapshot, Hash, RawMessage>,
+        transaction_pool: &KeySetIndex<&&Snapshot, Hash>,
+    ) -> Result<&CompactBlockState, failure::Error> {
+        let mut unknown_txs = HashSet::new();
+        for hash in msg.transactions() {
+            if transactions.contains(hash) {
+                if !transaction_pool.contains(hash) {
+                    bail!("Received compact block with already committed transaction")
+                }
+            } else {
+                unknown_txs.insert(*hash);
+            }
+        }
+
+        self.compact_block = Some(CompactBlockState {
+            message: msg.clone(),
+            unknown_txs,
+        });
+        Ok(self.compact_block.as_ref().unwrap())
+    }
+
+    /// Returns the compact block with the given hash.
+    pub fn compact_block(&self, block_hash: &Hash) -> Option<&CompactBlockState> {
+        match self.compact_block {
+            Some(ref block_state) if block_state.hash() == *block_hash => Some(block_state),
+            _ => None,
+        }
+    }
+
+    /// Removes the compact block with the given hash from the state and returns it.
+    pub fn take_compact_block(&mut self, block_hash: &Hash) -> Option<CompactBlockState> {
+        if self.compact_block(block_hash).is_some() {
+            self.compact_block.take()
+        } else {
+            None
+        }
+    }
+
     /// Adds block to the list of blocks for the current height. Returns `BlockState` if it is a
     /// new block.
     pub fn add_block(
@@ -891,17 +1096,18 @@ impl State {
 
         let key = (msg.round(), *msg.propose_hash());
         let validators_len = self.validators().len();
+        let weight = self.validator_weight(msg.validator());
         let votes = self.prevotes
             .entry(key)
             .or_insert_with(|| Votes::new(validators_len));
-        votes.insert(msg);
-        votes.count() >= majority_count
+        votes.insert(msg, weight);
+        votes.weight() >= majority_count
     }
 
     /// Returns `true` if there are +2/3 pre-votes for the specified round and hash.
     pub fn has_majority_prevotes(&self, round: Round, propose_hash: Hash) -> bool {
         match self.prevotes.get(&(round, propose_hash)) {
-            Some(votes) => votes.count() >= self.majority_count(),
+            Some(votes) => votes.weight() >= self.majority_count(),
             None => false,
         }
     }
@@ -950,11 +1156,12 @@ impl State {
 
         let key = (msg.round(), *msg.block_hash());
         let validators_len = self.validators().len();
+        let weight = self.validator_weight(msg.validator());
         let votes = self.precommits
             .entry(key)
             .or_insert_with(|| Votes::new(validators_len));
-        votes.insert(msg);
-        votes.count() >= majority_count
+        votes.insert(msg, weight);
+        votes.weight() >= majority_count
     }
 
     /// Adds unknown (for this node) propose.
@@ -980,7 +1187,7 @@ impl State {
     /// Returns true if the node has +2/3 pre-commits for the specified round and block hash.
     pub fn has_majority_precommits(&self, round: Round, block_hash: Hash) -> bool {
         match self.precommits.get(&(round, block_hash)) {
-            Some(votes) => votes.count() >= self.majority_count(),
+            Some(votes) => votes.weight() >= self.majority_count(),
             None => false,
         }
     }
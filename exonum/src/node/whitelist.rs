@@ -13,8 +13,10 @@
 // limitations under the License.
 
 use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
 
 use crypto::PublicKey;
+use crypto::x25519;
 
 // TODO: don't reload whitelisted_peers if path the same (ECR-172)
 
@@ -63,12 +65,65 @@ impl Whitelist {
     pub fn is_enabled(&self) -> bool {
         self.whitelist_enabled
     }
+
+    /// Enables or disables the whitelist.
+    #[cfg(test)]
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.whitelist_enabled = enabled;
+    }
+}
+
+/// `Whitelist` shared between the node handler and the network part.
+///
+/// The network part uses the whitelist to reject peers before any consensus message
+/// is accepted from them, while the node handler updates the list of validators.
+#[derive(Debug, Clone, Default)]
+pub struct SharedWhitelist(Arc<RwLock<Whitelist>>);
+
+impl SharedWhitelist {
+    /// Creates a shared whitelist from the given one.
+    pub fn new(whitelist: Whitelist) -> Self {
+        SharedWhitelist(Arc::new(RwLock::new(whitelist)))
+    }
+
+    /// Returns `true` if a peer with the given public key can connect.
+    pub fn allow(&self, peer: &PublicKey) -> bool {
+        self.0.read().unwrap().allow(peer)
+    }
+
+    /// Returns `true` if a peer with the given static Noise key can connect.
+    pub fn allow_static_key(&self, key: &[u8; x25519::KEY_LENGTH]) -> bool {
+        let whitelist = self.0.read().unwrap();
+        !whitelist.is_enabled()
+            || whitelist
+                .collect_allowed()
+                .into_iter()
+                .any(|peer| x25519::into_x25519_public_key(peer) == *key)
+    }
+
+    /// Resets list of validators with the given public keys.
+    pub fn set_validators<I>(&self, list: I)
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        self.0.write().unwrap().set_validators(list);
+    }
+
+    /// Returns `true` if whitelist is enabled, otherwise everyone can connect.
+    pub fn is_enabled(&self) -> bool {
+        self.0.read().unwrap().is_enabled()
+    }
+
+    /// Returns a copy of the underlying whitelist.
+    pub fn get(&self) -> Whitelist {
+        self.0.read().unwrap().clone()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Whitelist;
-    use crypto::PublicKey;
+    use super::{SharedWhitelist, Whitelist};
+    use crypto::{gen_keypair, x25519, PublicKey};
     use rand::{Rand, SeedableRng, XorShiftRng};
 
     static VALIDATORS: [[u32; 4]; 2] = [[123, 45, 67, 89], [223, 45, 67, 98]];
@@ -154,4 +209,26 @@ mod test {
         check_in_whitelist(&whitelist, &validators0, &[], &[0, 1]);
         check_in_whitelist(&whitelist, &validators1, &[0, 1], &[]);
     }
+
+    #[test]
+    fn test_shared_whitelist() {
+        let (allowed, _) = gen_keypair();
+        let (validator, _) = gen_keypair();
+        let (stranger, _) = gen_keypair();
+
+        let mut whitelist = Whitelist::default();
+        whitelist.whitelist_enabled = true;
+        whitelist.add(allowed);
+        let shared = SharedWhitelist::new(whitelist);
+        assert!(shared.is_enabled());
+        assert!(shared.allow(&allowed));
+        assert!(!shared.allow(&validator));
+
+        shared.clone().set_validators(vec![validator]);
+        assert!(shared.allow(&validator));
+        assert!(shared.allow_static_key(&x25519::into_x25519_public_key(&allowed)));
+        assert!(shared.allow_static_key(&x25519::into_x25519_public_key(&validator)));
+        assert!(!shared.allow_static_key(&x25519::into_x25519_public_key(&stranger)));
+        assert!(SharedWhitelist::default().allow_static_key(&[0; x25519::KEY_LENGTH]));
+    }
 }
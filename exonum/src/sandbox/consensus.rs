@@ -14,17 +14,20 @@
 
 // spell-checker:ignore precommiters, uncommented, uncomment, commited
 
+use futures::{Future, sync::oneshot};
 use rand::{thread_rng, Rng};
 use bit_vec::BitVec;
 
 use std::time::Duration;
 use std::collections::BTreeMap;
 
-use messages::{BlockRequest, BlockResponse, Connect, Message, PeersRequest, Precommit, Prevote,
-               PrevotesRequest, Propose, ProposeRequest, RawMessage, Status, TransactionsRequest,
-               TransactionsResponse, CONSENSUS};
+use messages::{BlockRequest, BlockResponse, CompactBlockResponse, Connect, Message, PeersRequest,
+               Precommit, Prevote, PrevotesRequest, Propose, ProposeRequest, RawMessage, Status,
+               ProtocolInfo, TransactionBatch, TransactionEnvelope, TransactionsAnnounce,
+               TransactionsRequest, TransactionsResponse, CONSENSUS, FEATURE_COMPACT_BLOCKS,
+               PROTOCOL_VERSIONS};
 use crypto::{gen_keypair, gen_keypair_from_seed, CryptoHash, Hash, Seed};
-use blockchain::{Blockchain, Schema};
+use blockchain::{Blockchain, LeaderElection, Schema, StoredConfiguration};
 use node;
 use node::state::{BLOCK_REQUEST_TIMEOUT, PREVOTES_REQUEST_TIMEOUT, PROPOSE_REQUEST_TIMEOUT,
                   TRANSACTIONS_REQUEST_TIMEOUT};
@@ -221,6 +224,152 @@ fn test_disable_and_enable() {
     sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
 }
 
+#[test]
+fn test_shutdown() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    try_add_one_height(&sandbox, &sandbox_state).unwrap();
+
+    let message = node::ExternalMessage::Shutdown;
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(message)
+        .unwrap();
+    sandbox.process_events();
+    assert_eq!(
+        sandbox.node_handler_mut().api_state().shutdown_reason(),
+        Some(node::ShutdownReason::Requested)
+    );
+
+    // Transactions received after the shutdown request are ignored.
+    let message = node::ExternalMessage::Transaction(Box::new(gen_timestamping_tx()));
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(message)
+        .unwrap();
+    sandbox.process_events();
+    assert!(sandbox.transactions_hashes().is_empty());
+
+    // Known peers are saved to the peers cache.
+    let saved_peers = sandbox.blockchain_ref().get_saved_peers();
+    assert_eq!(saved_peers, sandbox.node_state().peers().clone());
+}
+
+#[test]
+fn test_remove_transaction_from_pool() {
+    let sandbox = timestamping_sandbox();
+
+    let mut tx_gen = TimestampingTxGenerator::new(64);
+    let tx1 = tx_gen.next().unwrap();
+    let tx2 = tx_gen.next().unwrap();
+    sandbox.recv(&tx1);
+    sandbox.recv(&tx2);
+    assert!(
+        sandbox
+            .node_handler_mut()
+            .api_state()
+            .pool_arrival_time(&tx2.hash())
+            .is_some()
+    );
+
+    // Transactions included in a known propose are kept in the pool.
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hashes(&[tx1.hash()])
+        .build();
+    sandbox.recv(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+
+    let tx3 = tx_gen.next().unwrap();
+    let results = [tx1.hash(), tx2.hash(), tx3.hash()]
+        .iter()
+        .map(|hash| {
+            let (result_tx, result_rx) = oneshot::channel();
+            let message = node::ExternalMessage::RemoveTransaction(*hash, result_tx);
+            sandbox
+                .node_handler_mut()
+                .channel
+                .api_requests
+                .send(message)
+                .unwrap();
+            result_rx
+        })
+        .collect::<Vec<_>>();
+    sandbox.process_events();
+    assert_eq!(sandbox.transactions_hashes(), vec![tx1.hash()]);
+
+    let results = results
+        .into_iter()
+        .map(|result| result.wait().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        vec![
+            Err(node::RemoveTransactionError::Proposed),
+            Ok(()),
+            Err(node::RemoveTransactionError::NotInPool),
+        ]
+    );
+}
+
+#[test]
+fn test_consensus_status() {
+    let sandbox = timestamping_sandbox();
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    sandbox.recv(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+    sandbox.recv(&Prevote::new(
+        VALIDATOR_1,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_1),
+    ));
+
+    {
+        let handler = sandbox.node_handler_mut();
+        handler.api_state.update_node_state(&handler.state);
+    }
+    let status = sandbox
+        .node_handler_mut()
+        .api_state()
+        .consensus_status()
+        .unwrap();
+
+    assert_eq!(status.height, HEIGHT_ONE);
+    assert_eq!(status.round, ROUND_ONE);
+    assert_eq!(status.validator_id, Some(VALIDATOR_0));
+    assert_eq!(status.leader, sandbox.leader(ROUND_ONE));
+    assert_eq!(status.is_leader, sandbox.is_leader());
+    assert_eq!(status.locked_propose, None);
+    assert_eq!(status.prevotes.len(), 1);
+    assert_eq!(status.prevotes[0].round, ROUND_ONE);
+    assert_eq!(status.prevotes[0].hash, propose.hash());
+    assert_eq!(status.prevotes[0].validators, vec![VALIDATOR_0, VALIDATOR_1]);
+    assert!(status.precommits.is_empty());
+    assert_eq!(status.validators.len(), sandbox.n_validators());
+    assert_eq!(status.validators[0].height, HEIGHT_ONE);
+    assert_eq!(status.validators[1].height, Height::zero());
+
+    // Consensus is stalled when no blocks are committed for several rounds.
+    let last_commit_time = status.last_commit_time;
+    assert_eq!(last_commit_time, sandbox.node_state().height_start_time());
+    let stall_timeout = Duration::from_millis(status.stall_timeout);
+    let status = status.at(last_commit_time + stall_timeout / 2);
+    assert!(!status.stalled);
+    assert_eq!(status.since_last_commit, status.stall_timeout / 2);
+    let status = status.at(last_commit_time + stall_timeout);
+    assert!(status.stalled);
+}
+
 #[test]
 fn test_query_state_hash() {
     let sandbox = timestamping_sandbox();
@@ -3163,260 +3312,1133 @@ fn handle_block_response_tx_in_pool() {
     ));
 }
 
-// - ignore existed transaction (in both blockchain and pool)
-/// - idea of test is to receive propose with unknown tx
-/// - receive that tx
-/// - broadcast prevote
-#[test]
-fn broadcast_prevote_with_tx_positive() {
-    let sandbox = timestamping_sandbox();
-    let sandbox_state = SandboxState::new();
-
-    add_one_height(&sandbox, &sandbox_state);
-    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
-
-    // option: with transaction
-    let tx = gen_timestamping_tx();
-    sandbox.recv(&tx);
+/// Creates a compact block for `HEIGHT_ONE` with the given transaction, signed by
+/// the `VALIDATOR_3`.
+fn compact_block_with_tx(sandbox: &TimestampingSandbox, tx: &TimestampTx) -> CompactBlockResponse {
+    let propose = ProposeBuilder::new(sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
 
-    let propose = ProposeBuilder::new(&sandbox)
+    let block = BlockBuilder::new(sandbox)
         .with_duration_since_sandbox_time(sandbox.propose_timeout())
-        .with_tx_hashes(&[tx.hash()]) //ordinary propose, but with this received tx
+        .with_tx_hash(&tx.hash())
+        .with_state_hash(&sandbox.compute_state_hash(&[tx.raw().clone()]))
         .build();
-    sandbox.recv(&propose);
 
-    // check scenario:
-    // HANDLE FULL PROPOSE
-    // - send prevote when lock=0 for known propose
-    sandbox.assert_lock(LOCK_ZERO, None);
-    sandbox.broadcast(&Prevote::new(
-        VALIDATOR_0,
-        HEIGHT_TWO,
-        ROUND_ONE,
-        &propose.hash(),
-        LOCK_ZERO,
-        sandbox.s(VALIDATOR_0),
-    ));
+    let precommits = [VALIDATOR_1, VALIDATOR_2, VALIDATOR_3]
+        .iter()
+        .map(|&validator| {
+            Precommit::new(
+                validator,
+                HEIGHT_ONE,
+                ROUND_ONE,
+                &propose.hash(),
+                &block.hash(),
+                sandbox.time().into(),
+                sandbox.s(validator),
+            )
+        })
+        .collect();
+
+    CompactBlockResponse::new(
+        &sandbox.p(VALIDATOR_3),
+        &sandbox.p(VALIDATOR_0),
+        block,
+        precommits,
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_3),
+    )
 }
 
-// - ignore existed transaction (in both pool)
-/// - idea of test is to receive propose with unknown tx
-/// - receive that tx
-/// - broadcast prevote
+/// - request block from the peer with the higher height
+/// - receive compact block with the transaction from the pool
+/// - commit the block without requesting transactions
 #[test]
-fn handle_tx_ignore_existing_tx_in_blockchain() {
+fn handle_compact_block_tx_in_pool() {
     let sandbox = timestamping_sandbox();
-    let sandbox_state = SandboxState::new();
-
-    // option: with transaction
     let tx = gen_timestamping_tx();
+    let compact_block = compact_block_with_tx(&sandbox, &tx);
 
-    add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx.raw().clone()]);
-    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
-
-    // add rounds & become leader
-    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
-    assert!(sandbox.is_leader());
+    sandbox.recv(&Status::new(
+        &sandbox.p(VALIDATOR_3),
+        HEIGHT_TWO,
+        &compact_block.block().hash(),
+        sandbox.s(VALIDATOR_3),
+    ));
 
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.a(VALIDATOR_3),
+        &BlockRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            HEIGHT_ONE,
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
     sandbox.recv(&tx);
+    sandbox.recv(&compact_block);
 
-    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
-    let propose = ProposeBuilder::new(&sandbox)
-        //.with_tx_hashes(&[tx.hash()]) //ordinary propose, but with this received tx
-        // !! note that here no tx are expected whereas old tx is received earlier
-        .with_tx_hashes(&[])
-        .build();
-    sandbox.broadcast(&propose);
-    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
-    sandbox.add_time(Duration::from_millis(0));
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.broadcast(&Status::new(
+        &sandbox.p(VALIDATOR_0),
+        HEIGHT_TWO,
+        &compact_block.block().hash(),
+        sandbox.s(VALIDATOR_0),
+    ));
 }
 
-/// HANDLE ROUND TIMEOUT:
-/// - Ignore if height and round are not the same
-/// scenario:
-///  - make commit at first round
-///  - and verify that at moment when first `round_timeout` is triggered, round remains the same
+/// - receive compact block with the unknown transaction
+/// - request the transaction from the peer which sent the block
+/// - commit the block as soon as the transaction is received
 #[test]
-fn handle_round_timeout_ignore_if_height_and_round_are_not_the_same() {
+fn handle_compact_block_unknown_tx() {
     let sandbox = timestamping_sandbox();
-
-    // option: with transaction
     let tx = gen_timestamping_tx();
+    let compact_block = compact_block_with_tx(&sandbox, &tx);
 
-    let propose = ProposeBuilder::new(&sandbox)
-        .with_duration_since_sandbox_time(sandbox.propose_timeout())
-        .with_tx_hashes(&[tx.hash()]) //ordinary propose, but with this unreceived tx
-        .build();
-
-    // this block with transactions should be in real
-    let block = BlockBuilder::new(&sandbox)
-        .with_duration_since_sandbox_time(sandbox.propose_timeout())
-        .with_tx_hash(&tx.hash())
-        .with_state_hash(&sandbox.compute_state_hash(&[tx.raw().clone()]))
-        .build();
-
-    let precommit_1 = Precommit::new(
-        VALIDATOR_1,
-        HEIGHT_ONE,
-        ROUND_ONE,
-        &propose.hash(),
-        &block.hash(),
-        sandbox.time().into(),
-        sandbox.s(VALIDATOR_1),
-    );
-    let precommit_2 = Precommit::new(
-        VALIDATOR_2,
-        HEIGHT_ONE,
-        ROUND_ONE,
-        &propose.hash(),
-        &block.hash(),
-        sandbox.time().into(),
-        sandbox.s(VALIDATOR_2),
-    );
-    let precommit_3 = Precommit::new(
-        VALIDATOR_3,
-        HEIGHT_ONE,
-        ROUND_ONE,
-        &propose.hash(),
-        &block.hash(),
-        sandbox.time().into(),
+    sandbox.recv(&Status::new(
+        &sandbox.p(VALIDATOR_3),
+        HEIGHT_TWO,
+        &compact_block.block().hash(),
         sandbox.s(VALIDATOR_3),
-    );
+    ));
 
-    sandbox.recv(&precommit_1);
-    sandbox.add_time(Duration::from_millis(PROPOSE_REQUEST_TIMEOUT));
-    sandbox.send(
-        sandbox.a(VALIDATOR_1),
-        &make_request_propose_from_precommit(&sandbox, &precommit_1),
-    );
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
     sandbox.send(
-        sandbox.a(VALIDATOR_1),
-        &make_request_prevote_from_precommit(&sandbox, &precommit_1),
+        sandbox.a(VALIDATOR_3),
+        &BlockRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            HEIGHT_ONE,
+            sandbox.s(VALIDATOR_0),
+        ),
     );
+    sandbox.recv(&compact_block);
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
 
-    sandbox.recv(&precommit_2);
-    // second addition is required in order to make sandbox time >= propose time because
-    // this condition is checked at node/mod.rs->actual_round()
-    sandbox.add_time(Duration::from_millis(PROPOSE_REQUEST_TIMEOUT));
-    sandbox.send(
-        sandbox.a(VALIDATOR_2),
-        &make_request_propose_from_precommit(&sandbox, &precommit_2),
-    );
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
     sandbox.send(
-        sandbox.a(VALIDATOR_2),
-        &make_request_prevote_from_precommit(&sandbox, &precommit_2),
+        sandbox.a(VALIDATOR_3),
+        &TransactionsRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            &[tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ),
     );
-    sandbox.recv(&propose);
     sandbox.recv(&tx);
-    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
 
-    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
-    // Here consensus.rs->handle_majority_precommits()->//Commit is achieved
-    sandbox.recv(&precommit_3);
     sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
-    sandbox.check_broadcast_status(HEIGHT_TWO, &block.hash());
-    sandbox.add_time(Duration::from_millis(0));
-
-    sandbox.add_time(Duration::from_millis(
-        sandbox.round_timeout() - 2 * PROPOSE_REQUEST_TIMEOUT,
+    sandbox.broadcast(&Status::new(
+        &sandbox.p(VALIDATOR_0),
+        HEIGHT_TWO,
+        &compact_block.block().hash(),
+        sandbox.s(VALIDATOR_0),
     ));
-    // This assert would fail if check for same height is absent in
-    // node/consensus.rs->handle_round_timeout()
-    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+}
+
+/// - receive compact block with the unknown transaction
+/// - receive another compact block for the same height, which replaces the first one
+/// - drop the transactions request for the replaced block on timeout
+/// - commit the second block as soon as its transaction is received
+#[test]
+fn handle_compact_block_replaced() {
+    let sandbox = timestamping_sandbox();
+    let first_tx = gen_timestamping_tx();
+    let second_tx = gen_timestamping_tx();
+    let first_block = compact_block_with_tx(&sandbox, &first_tx);
+    let second_block = compact_block_with_tx(&sandbox, &second_tx);
+
+    sandbox.recv(&Status::new(
+        &sandbox.p(VALIDATOR_3),
+        HEIGHT_TWO,
+        &first_block.block().hash(),
+        sandbox.s(VALIDATOR_3),
+    ));
+
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.a(VALIDATOR_3),
+        &BlockRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            HEIGHT_ONE,
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+    sandbox.recv(&first_block);
+    sandbox.recv(&second_block);
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+
+    // Only the request for the second block is sent.
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.a(VALIDATOR_3),
+        &TransactionsRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            &[second_tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+    sandbox.recv(&second_tx);
+
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.broadcast(&Status::new(
+        &sandbox.p(VALIDATOR_0),
+        HEIGHT_TWO,
+        &second_block.block().hash(),
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
+/// - fetch unknown transaction from the peer which announced it
+/// - do not request the transaction again while it is being fetched
+/// - relay the fetched transaction to the peers
+#[test]
+fn handle_txs_announce_unknown_tx() {
+    let sandbox = timestamping_sandbox();
+    let tx = gen_timestamping_tx();
+
+    sandbox.recv(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_1),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &TransactionsRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            &[tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+
+    sandbox.recv(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_2),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_2),
+    ));
+    sandbox.recv(&TransactionsResponse::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        vec![tx.raw().clone()],
+        sandbox.s(VALIDATOR_1),
+    ));
+    assert_eq!(sandbox.transactions_hashes(), vec![tx.hash()]);
+    sandbox.broadcast(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_0),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_0),
+    ));
+
+    // The request timeout for the received transaction is ignored.
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+}
+
+/// - relay the fetched transaction itself to the peers which do not support announces
+#[test]
+fn handle_txs_announce_relay_to_peer_without_announces() {
+    let sandbox = timestamping_sandbox();
+    let tx = gen_timestamping_tx();
+    let protocol = ProtocolInfo {
+        versions: PROTOCOL_VERSIONS.to_vec(),
+        features: FEATURE_COMPACT_BLOCKS,
+    };
+    sandbox
+        .node_handler_mut()
+        .state
+        .set_peer_protocol(sandbox.p(VALIDATOR_2), protocol);
+
+    sandbox.recv(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_1),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &TransactionsRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            &[tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+    sandbox.recv(&TransactionsResponse::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        vec![tx.raw().clone()],
+        sandbox.s(VALIDATOR_1),
+    ));
+
+    let announce = TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_0),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_0),
+    );
+    sandbox.broadcast_to_addrs(&announce, &[sandbox.a(VALIDATOR_1), sandbox.a(VALIDATOR_3)]);
+    sandbox.send(sandbox.a(VALIDATOR_2), &tx);
+
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+}
+
+/// - fetch unknown transaction from the peer which announced it
+/// - request the transaction from another announcing peer if the response doesn't arrive
+/// - ignore announcements of the received transaction
+#[test]
+fn handle_txs_announce_retry() {
+    let sandbox = timestamping_sandbox();
+    let tx = gen_timestamping_tx();
+
+    for &validator in &[VALIDATOR_1, VALIDATOR_2] {
+        sandbox.recv(&TransactionsAnnounce::new(
+            &sandbox.p(validator),
+            &[tx.hash()],
+            sandbox.s(validator),
+        ));
+    }
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &TransactionsRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            &[tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.a(VALIDATOR_2),
+        &TransactionsRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_2),
+            &[tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+    sandbox.recv(&TransactionsResponse::new(
+        &sandbox.p(VALIDATOR_2),
+        &sandbox.p(VALIDATOR_0),
+        vec![tx.raw().clone()],
+        sandbox.s(VALIDATOR_2),
+    ));
+    sandbox.broadcast(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_0),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_0),
+    ));
+
+    sandbox.recv(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_3),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_3),
+    ));
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+}
+
+/// - ignore announcements of the transactions from the pool
+/// - ignore announcements from unknown peers
+#[test]
+fn handle_txs_announce_known_tx() {
+    let sandbox = timestamping_sandbox();
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+
+    sandbox.recv(&TransactionsAnnounce::new(
+        &sandbox.p(VALIDATOR_1),
+        &[tx.hash()],
+        sandbox.s(VALIDATOR_1),
+    ));
+
+    let (public_key, secret_key) = gen_keypair();
+    sandbox.recv(&TransactionsAnnounce::new(
+        &public_key,
+        &[gen_timestamping_tx().hash()],
+        &secret_key,
+    ));
+}
+
+// - ignore existed transaction (in both blockchain and pool)
+/// - idea of test is to receive propose with unknown tx
+/// - receive that tx
+/// - broadcast prevote
+#[test]
+fn broadcast_prevote_with_tx_positive() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    add_one_height(&sandbox, &sandbox_state);
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+
+    // option: with transaction
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hashes(&[tx.hash()]) //ordinary propose, but with this received tx
+        .build();
+    sandbox.recv(&propose);
+
+    // check scenario:
+    // HANDLE FULL PROPOSE
+    // - send prevote when lock=0 for known propose
+    sandbox.assert_lock(LOCK_ZERO, None);
+    sandbox.broadcast(&Prevote::new(
+        VALIDATOR_0,
+        HEIGHT_TWO,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
+// - ignore existed transaction (in both pool)
+/// - idea of test is to receive propose with unknown tx
+/// - receive that tx
+/// - broadcast prevote
+#[test]
+fn handle_tx_ignore_existing_tx_in_blockchain() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    // option: with transaction
+    let tx = gen_timestamping_tx();
+
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx.raw().clone()]);
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+
+    // add rounds & become leader
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    assert!(sandbox.is_leader());
+
+    sandbox.recv(&tx);
+
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    let propose = ProposeBuilder::new(&sandbox)
+        //.with_tx_hashes(&[tx.hash()]) //ordinary propose, but with this received tx
+        // !! note that here no tx are expected whereas old tx is received earlier
+        .with_tx_hashes(&[])
+        .build();
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+    sandbox.add_time(Duration::from_millis(0));
 }
 
 /// HANDLE ROUND TIMEOUT:
-// - add new round timeout
+/// - Ignore if height and round are not the same
+/// scenario:
+///  - make commit at first round
+///  - and verify that at moment when first `round_timeout` is triggered, round remains the same
+#[test]
+fn handle_round_timeout_ignore_if_height_and_round_are_not_the_same() {
+    let sandbox = timestamping_sandbox();
+
+    // option: with transaction
+    let tx = gen_timestamping_tx();
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hashes(&[tx.hash()]) //ordinary propose, but with this unreceived tx
+        .build();
+
+    // this block with transactions should be in real
+    let block = BlockBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hash(&tx.hash())
+        .with_state_hash(&sandbox.compute_state_hash(&[tx.raw().clone()]))
+        .build();
+
+    let precommit_1 = Precommit::new(
+        VALIDATOR_1,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_1),
+    );
+    let precommit_2 = Precommit::new(
+        VALIDATOR_2,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_2),
+    );
+    let precommit_3 = Precommit::new(
+        VALIDATOR_3,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_3),
+    );
+
+    sandbox.recv(&precommit_1);
+    sandbox.add_time(Duration::from_millis(PROPOSE_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &make_request_propose_from_precommit(&sandbox, &precommit_1),
+    );
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &make_request_prevote_from_precommit(&sandbox, &precommit_1),
+    );
+
+    sandbox.recv(&precommit_2);
+    // second addition is required in order to make sandbox time >= propose time because
+    // this condition is checked at node/mod.rs->actual_round()
+    sandbox.add_time(Duration::from_millis(PROPOSE_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.a(VALIDATOR_2),
+        &make_request_propose_from_precommit(&sandbox, &precommit_2),
+    );
+    sandbox.send(
+        sandbox.a(VALIDATOR_2),
+        &make_request_prevote_from_precommit(&sandbox, &precommit_2),
+    );
+    sandbox.recv(&propose);
+    sandbox.recv(&tx);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    // Here consensus.rs->handle_majority_precommits()->//Commit is achieved
+    sandbox.recv(&precommit_3);
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.check_broadcast_status(HEIGHT_TWO, &block.hash());
+    sandbox.add_time(Duration::from_millis(0));
+
+    sandbox.add_time(Duration::from_millis(
+        sandbox.round_timeout() - 2 * PROPOSE_REQUEST_TIMEOUT,
+    ));
+    // This assert would fail if check for same height is absent in
+    // node/consensus.rs->handle_round_timeout()
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+}
+
+/// HANDLE ROUND TIMEOUT:
+// - add new round timeout
+#[test]
+fn handle_round_timeout_increment_round_add_new_round_timeout() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout() - 1));
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    sandbox.add_time(Duration::from_millis(1));
+    sandbox.assert_state(HEIGHT_ONE, ROUND_TWO);
+
+    // next round timeout is added
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout() - 1));
+    sandbox.assert_state(HEIGHT_ONE, ROUND_TWO);
+    sandbox.add_time(Duration::from_millis(1));
+    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
+    sandbox.add_time(Duration::from_millis(0));
+}
+
+/// idea of the scenario is to become leader
+/// then:
+///  - propose timeout is added
+///   - when propose timeout is triggered - propose is send
+#[test]
+fn test_send_propose_and_prevote_when_we_are_leader() {
+    let sandbox = timestamping_sandbox();
+
+    // round happens
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    sandbox.add_time(Duration::from_millis(
+        sandbox.round_timeout() + sandbox.propose_timeout(),
+    ));
+
+    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
+
+    // ok, we are leader
+    let propose = ProposeBuilder::new(&sandbox).build();
+
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+    sandbox.add_time(Duration::from_millis(0));
+}
+
+/// HANDLE ROUND TIMEOUT:
+/// - send prevote if locked to propose
+/// idea:
+///  - lock to propose
+///  - trigger `round_timeout`
+///  - observe broadcasted prevote
+#[test]
+fn handle_round_timeout_send_prevote_if_locked_to_propose() {
+    // fn test_get_lock_and_send_precommit() {
+    let sandbox = timestamping_sandbox();
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+
+    let block = BlockBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+
+    sandbox.recv(&propose);
+    sandbox.broadcast(&Prevote::new(
+        VALIDATOR_0,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_0),
+    ));
+
+    sandbox.recv(&Prevote::new(
+        VALIDATOR_1,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.assert_lock(LOCK_ZERO, None); //do not lock if <2/3 prevotes
+
+    sandbox.recv(&Prevote::new(
+        VALIDATOR_2,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_2),
+    ));
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash())); //only if round > locked round
+
+    sandbox.broadcast(&Precommit::new(
+        VALIDATOR_0,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    ));
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
+    sandbox.add_time(Duration::from_millis(0));
+
+    // trigger round_timeout
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    //    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+    sandbox.broadcast(&Prevote::new(
+        VALIDATOR_0,
+        HEIGHT_ONE,
+        ROUND_TWO,
+        &propose.hash(),
+        LOCK_ONE,
+        sandbox.s(VALIDATOR_0),
+    ));
+    sandbox.add_time(Duration::from_millis(0));
+}
+
+/// HANDLE ROUND TIMEOUT:
+///  - handle queued messages
+/// idea:
+///  - lock to propose
+///  - trigger `round_timeout`
+///  - observe broadcasted prevote
+#[test]
+#[should_panic(expected = "Send unexpected message Request(ProposeRequest")]
+fn test_handle_round_timeout_queue_prevote_message_from_next_round() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox.recv(&Prevote::new(
+        VALIDATOR_2,
+        HEIGHT_ONE,
+        ROUND_TWO,
+        &empty_hash(),
+        Round::zero(),
+        sandbox.s(VALIDATOR_2),
+    ));
+
+    // trigger round_timeout
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    // trigger request_propose_timeout
+    sandbox.add_time(Duration::from_millis(PROPOSE_REQUEST_TIMEOUT));
+    // observe requestPropose request
+    sandbox.add_time(Duration::from_millis(0));
+}
+
+/// - exclude validator from consensus
+/// - idea of test is to exclude sandbox validator from consensus
+/// - node continues as `full node`
+#[test]
+fn test_exclude_validator_from_consensus() {
+    use storage::StorageValue;
+
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    add_one_height(&sandbox, &sandbox_state);
+
+    let tx_cfg = {
+        let mut consensus_cfg = sandbox.cfg();
+        consensus_cfg.validator_keys.swap_remove(0);
+        consensus_cfg.actual_from = sandbox.current_height().next().next();
+        consensus_cfg.previous_cfg_hash = sandbox.cfg().hash();
+
+        TxConfig::new(
+            &sandbox.p(VALIDATOR_0),
+            &consensus_cfg.clone().into_bytes(),
+            consensus_cfg.actual_from,
+            sandbox.s(VALIDATOR_0),
+        )
+    };
+
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx_cfg.raw().clone()]);
+    add_one_height(&sandbox, &sandbox_state);
+    // node loses validator status
+    add_one_height_with_transactions_from_other_validator(&sandbox, &sandbox_state, &[]);
+}
+
+/// - Check `following_configuration` method in schema
+/// - idea of the test is check configurations method from schema
+#[test]
+fn test_schema_config_changes() {
+    use storage::StorageValue;
+
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    add_one_height(&sandbox, &sandbox_state);
+
+    let (tx_cfg, following_cfg) = {
+        let mut consensus_cfg = sandbox.cfg();
+        consensus_cfg.consensus.txs_block_limit = 2000;
+        consensus_cfg.actual_from = sandbox.current_height().next().next();
+        consensus_cfg.previous_cfg_hash = sandbox.cfg().hash();
+
+        let tx = TxConfig::new(
+            &sandbox.p(VALIDATOR_0),
+            &consensus_cfg.clone().into_bytes(),
+            consensus_cfg.actual_from,
+            sandbox.s(VALIDATOR_0),
+        );
+        (tx, consensus_cfg)
+    };
+    let prev_cfg = sandbox.cfg();
+
+    // Check configuration from genesis block
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot()).actual_configuration(),
+        prev_cfg
+    );
+    // Try to get configuration from non exists height
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot()).configuration_by_height(HEIGHT_FOUR),
+        prev_cfg
+    );
+    // Commit a new configuration
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx_cfg.raw().clone()]);
+    // Check that following configuration is visible
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot()).following_configuration(),
+        Some(following_cfg.clone())
+    );
+    // Make following configuration actual
+    add_one_height(&sandbox, &sandbox_state);
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[]);
+    // Check that following configuration becomes actual
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot()).actual_configuration(),
+        following_cfg
+    );
+    // Check previous configuration
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot())
+            .previous_configuration()
+            .unwrap(),
+        prev_cfg
+    );
+
+    // Finally check configuration for some heights
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot()).configuration_by_height(HEIGHT_ZERO),
+        prev_cfg
+    );
+    assert_eq!(
+        Schema::new(&sandbox.blockchain_ref().snapshot())
+            .configuration_by_height(sandbox.current_height()),
+        following_cfg
+    );
+}
+
+/// Commits a configuration modified by the given closure and waits until it becomes actual.
+fn commit_configuration<F>(sandbox: &TimestampingSandbox, sandbox_state: &SandboxState, update: F)
+where
+    F: FnOnce(&mut StoredConfiguration),
+{
+    use storage::StorageValue;
+
+    let tx_cfg = {
+        let mut consensus_cfg = sandbox.cfg();
+        update(&mut consensus_cfg);
+        consensus_cfg.actual_from = sandbox.current_height().next().next();
+        consensus_cfg.previous_cfg_hash = sandbox.cfg().hash();
+
+        TxConfig::new(
+            &sandbox.p(VALIDATOR_0),
+            &consensus_cfg.clone().into_bytes(),
+            consensus_cfg.actual_from,
+            sandbox.s(VALIDATOR_0),
+        )
+    };
+
+    add_one_height_with_transactions(sandbox, sandbox_state, &[tx_cfg.raw().clone()]);
+    add_one_height(sandbox, sandbox_state);
+}
+
+/// - round-robin leader election is used by default
+#[test]
+fn test_round_robin_leader_election() {
+    let sandbox = timestamping_sandbox();
+
+    assert_eq!(
+        sandbox.cfg().consensus.leader_election,
+        LeaderElection::RoundRobin
+    );
+    assert_eq!(sandbox.leader(ROUND_ONE), VALIDATOR_2);
+    assert_eq!(sandbox.leader(ROUND_TWO), VALIDATOR_3);
+    assert_eq!(sandbox.leader(ROUND_THREE), VALIDATOR_0);
+    assert_eq!(sandbox.leader(ROUND_FOUR), VALIDATOR_1);
+}
+
+/// - validators which missed their rounds are skipped by the leader election
+/// - propose from the skipped validator is ignored
+#[test]
+fn test_skip_missed_leader_election() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.consensus.leader_election = LeaderElection::SkipMissed { window: 1 };
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    assert!(sandbox.node_state().inactive_validators().is_empty());
+
+    // Sandbox validator is the leader of the first round, nobody misses a proposal.
+    add_one_height(&sandbox, &sandbox_state);
+    assert!(sandbox.node_state().inactive_validators().is_empty());
+
+    // Validators 1, 2 and 3 miss their rounds before the sandbox validator proposes a block.
+    add_one_height(&sandbox, &sandbox_state);
+    sandbox.assert_state(Height(5), ROUND_ONE);
+    assert_eq!(sandbox.node_state().inactive_validators().len(), 3);
+    for round in ROUND_ONE.iter_to(Round(10)) {
+        assert_eq!(sandbox.leader(round), VALIDATOR_0);
+    }
+
+    // Validator 2 would be the round-robin leader, so its propose is ignored.
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_validator(VALIDATOR_2)
+        .build();
+    sandbox.recv(&propose);
+    assert!(sandbox.node_state().propose(&propose.hash()).is_none());
+}
+
+/// - leaders are chosen according to the weights of validators
+/// - propose from the leader of the round is accepted
+#[test]
+fn test_weighted_leader_election() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.consensus.leader_election = LeaderElection::Weighted;
+        for (weight, keys) in cfg.validator_keys.iter_mut().enumerate() {
+            keys.weight = weight as u64 + 1;
+        }
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    let leaders = ROUND_ONE
+        .iter_to(Round(11))
+        .map(|round| sandbox.leader(round))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        leaders,
+        vec![
+            VALIDATOR_2,
+            VALIDATOR_2,
+            VALIDATOR_3,
+            VALIDATOR_3,
+            VALIDATOR_3,
+            VALIDATOR_2,
+            VALIDATOR_3,
+            VALIDATOR_3,
+            VALIDATOR_1,
+            VALIDATOR_3,
+        ]
+    );
+    assert!(!sandbox.is_leader());
+
+    // Sandbox validator does not propose even after the propose timeout.
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    sandbox.recv(&propose);
+    sandbox.broadcast(&Prevote::new(
+        VALIDATOR_0,
+        HEIGHT_THREE,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
+/// - blocks within `compact_block_depth` from the current height are sent in the compact form
+/// - older blocks are sent together with their transactions
+#[test]
+fn test_compact_block_depth() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.consensus.compact_block_depth = Some(2);
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+
+    let snapshot = sandbox.blockchain_ref().snapshot();
+    let schema = Schema::new(&snapshot);
+
+    sandbox.recv(&BlockRequest::new(
+        &sandbox.p(VALIDATOR_3),
+        &sandbox.p(VALIDATOR_0),
+        HEIGHT_ONE,
+        sandbox.s(VALIDATOR_3),
+    ));
+    let proof = sandbox.block_and_precommits(HEIGHT_ONE).unwrap();
+    let tx_hashes = schema.block_transactions(HEIGHT_ONE).iter().collect::<Vec<_>>();
+    sandbox.send(
+        sandbox.a(VALIDATOR_3),
+        &CompactBlockResponse::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            proof.block,
+            proof.precommits,
+            &tx_hashes,
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+
+    sandbox.recv(&BlockRequest::new(
+        &sandbox.p(VALIDATOR_3),
+        &sandbox.p(VALIDATOR_0),
+        HEIGHT_ZERO,
+        sandbox.s(VALIDATOR_3),
+    ));
+    let proof = sandbox.block_and_precommits(HEIGHT_ZERO).unwrap();
+    sandbox.send(
+        sandbox.a(VALIDATOR_3),
+        &BlockResponse::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_3),
+            proof.block,
+            proof.precommits,
+            vec![],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+}
+
+/// - +2/3 majority is computed from the voting power of validators
+/// - node doesn't lock on a propose prevoted by the most of validators with little weight
+/// - node locks when prevotes from the heavy validator arrive
+#[test]
+fn test_weighted_validators_majority() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    assert_eq!(sandbox.node_state().majority_count(), 3);
+
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.validator_keys[3].weight = 3;
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    assert_eq!(sandbox.node_state().total_weight(), 6);
+    assert_eq!(sandbox.node_state().majority_count(), 5);
+    assert!(sandbox.is_leader());
+
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    let propose = ProposeBuilder::new(&sandbox).with_tx_hashes(&[]).build();
+    let block = BlockBuilder::new(&sandbox).build();
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+
+    // Three of four validators have only half of the voting power.
+    for &validator in &[VALIDATOR_1, VALIDATOR_2] {
+        sandbox.recv(&Prevote::new(
+            validator,
+            HEIGHT_THREE,
+            ROUND_ONE,
+            &propose.hash(),
+            LOCK_ZERO,
+            sandbox.s(validator),
+        ));
+    }
+    sandbox.assert_lock(LOCK_ZERO, None);
+
+    sandbox.recv(&Prevote::new(
+        VALIDATOR_3,
+        HEIGHT_THREE,
+        ROUND_ONE,
+        &propose.hash(),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_3),
+    ));
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
+    sandbox.broadcast(&Precommit::new(
+        VALIDATOR_0,
+        HEIGHT_THREE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
+/// - leader doesn't propose an empty block on the propose timeout
+/// - leader proposes `min_propose_timeout` after a transaction arrives
+/// - transactions received in the meantime are included in the same propose
+#[test]
+fn test_empty_block_postponed_until_transaction() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.consensus.max_empty_block_interval = Some(5000);
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    assert!(sandbox.is_leader());
+
+    // The pool is empty, so there is no propose.
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    sandbox.add_time(Duration::from_millis(300));
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+    sandbox.add_time(Duration::from_millis(100));
+    let other_tx = gen_timestamping_tx();
+    sandbox.recv(&other_tx);
+    sandbox.add_time(Duration::from_millis(
+        sandbox.cfg().consensus.min_propose_timeout - 100,
+    ));
+
+    let mut tx_hashes = vec![tx.hash(), other_tx.hash()];
+    tx_hashes.sort();
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_tx_hashes(&tx_hashes)
+        .build();
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+}
+
+/// - status messages are broadcast on the status timeout while the empty block is postponed
 #[test]
-fn handle_round_timeout_increment_round_add_new_round_timeout() {
+fn test_empty_block_postponed_status_timeout() {
     let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
 
-    sandbox.add_time(Duration::from_millis(sandbox.round_timeout() - 1));
-    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
-    sandbox.add_time(Duration::from_millis(1));
-    sandbox.assert_state(HEIGHT_ONE, ROUND_TWO);
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.consensus.max_empty_block_interval = Some(5000);
+        cfg.consensus.status_timeout = 500;
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    assert!(sandbox.is_leader());
 
-    // next round timeout is added
-    sandbox.add_time(Duration::from_millis(sandbox.round_timeout() - 1));
-    sandbox.assert_state(HEIGHT_ONE, ROUND_TWO);
-    sandbox.add_time(Duration::from_millis(1));
-    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
-    sandbox.add_time(Duration::from_millis(0));
+    // The pool is empty, so there is no propose.
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    sandbox.add_time(Duration::from_millis(500 - sandbox.propose_timeout()));
+    sandbox.check_broadcast_status(HEIGHT_THREE, &sandbox.last_hash());
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
 }
 
-/// idea of the scenario is to become leader
-/// then:
-///  - propose timeout is added
-///   - when propose timeout is triggered - propose is send
+/// - leader proposes an empty heartbeat block when `max_empty_block_interval` elapses
 #[test]
-fn test_send_propose_and_prevote_when_we_are_leader() {
+fn test_empty_block_heartbeat() {
     let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
 
-    // round happens
-    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
-    sandbox.add_time(Duration::from_millis(
-        sandbox.round_timeout() + sandbox.propose_timeout(),
-    ));
-
-    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
+    commit_configuration(&sandbox, &sandbox_state, |cfg| {
+        cfg.consensus.max_empty_block_interval = Some(700);
+    });
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    assert!(sandbox.is_leader());
 
-    // ok, we are leader
-    let propose = ProposeBuilder::new(&sandbox).build();
+    // The pool is empty, so there is no propose.
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    sandbox.add_time(Duration::from_millis(700 - sandbox.propose_timeout()));
 
+    let propose = ProposeBuilder::new(&sandbox).with_tx_hashes(&[]).build();
     sandbox.broadcast(&propose);
     sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
-    sandbox.add_time(Duration::from_millis(0));
 }
 
-/// HANDLE ROUND TIMEOUT:
-/// - send prevote if locked to propose
-/// idea:
-///  - lock to propose
-///  - trigger `round_timeout`
-///  - observe broadcasted prevote
+fn gen_envelope(valid_until: Height) -> TransactionEnvelope {
+    let (public_key, secret_key) = gen_keypair();
+    let tx = TimestampingTxGenerator::with_keypair(64, (public_key, secret_key.clone()))
+        .next()
+        .unwrap();
+    TransactionEnvelope::new(&public_key, valid_until, 0, tx.raw().clone(), &secret_key)
+}
+
+/// - expired transaction envelope is not added into the pool
 #[test]
-fn handle_round_timeout_send_prevote_if_locked_to_propose() {
-    // fn test_get_lock_and_send_precommit() {
+fn test_expired_envelope_rejected() {
     let sandbox = timestamping_sandbox();
 
+    sandbox.recv(&gen_envelope(HEIGHT_ZERO));
+    assert!(sandbox.transactions_hashes().is_empty());
+
+    let envelope = gen_envelope(HEIGHT_ONE);
+    sandbox.recv(&envelope);
+    assert_eq!(sandbox.transactions_hashes(), vec![envelope.hash()]);
+}
+
+/// - transaction envelope is evicted from the pool when its height has passed
+#[test]
+fn test_expired_envelope_evicted_after_commit() {
+    let sandbox = timestamping_sandbox();
+
+    let envelope = gen_envelope(HEIGHT_ONE);
+    sandbox.recv(&envelope);
+
+    let tx = gen_timestamping_tx();
     let propose = ProposeBuilder::new(&sandbox)
         .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hashes(&[tx.hash()])
         .build();
-
     let block = BlockBuilder::new(&sandbox)
         .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hash(&tx.hash())
+        .with_state_hash(&sandbox.compute_state_hash(&[tx.raw().clone()]))
         .build();
 
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    sandbox.recv(&tx);
     sandbox.recv(&propose);
-    sandbox.broadcast(&Prevote::new(
-        VALIDATOR_0,
-        HEIGHT_ONE,
-        ROUND_ONE,
-        &propose.hash(),
-        LOCK_ZERO,
-        sandbox.s(VALIDATOR_0),
-    ));
-
-    sandbox.recv(&Prevote::new(
-        VALIDATOR_1,
-        HEIGHT_ONE,
-        ROUND_ONE,
-        &propose.hash(),
-        LOCK_ZERO,
-        sandbox.s(VALIDATOR_1),
-    ));
-    sandbox.assert_lock(LOCK_ZERO, None); //do not lock if <2/3 prevotes
-
-    sandbox.recv(&Prevote::new(
-        VALIDATOR_2,
-        HEIGHT_ONE,
-        ROUND_ONE,
-        &propose.hash(),
-        LOCK_ZERO,
-        sandbox.s(VALIDATOR_2),
-    ));
-    sandbox.assert_lock(LOCK_ONE, Some(propose.hash())); //only if round > locked round
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
 
+    for &validator in &[VALIDATOR_1, VALIDATOR_2] {
+        sandbox.recv(&Prevote::new(
+            validator,
+            HEIGHT_ONE,
+            ROUND_ONE,
+            &propose.hash(),
+            LOCK_ZERO,
+            sandbox.s(validator),
+        ));
+    }
     sandbox.broadcast(&Precommit::new(
         VALIDATOR_0,
         HEIGHT_ONE,
@@ -3426,153 +4448,77 @@ fn handle_round_timeout_send_prevote_if_locked_to_propose() {
         sandbox.time().into(),
         sandbox.s(VALIDATOR_0),
     ));
-    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
-    sandbox.add_time(Duration::from_millis(0));
+    assert_eq!(sandbox.transactions_hashes().len(), 2);
 
-    // trigger round_timeout
-    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
-    //    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
-    sandbox.broadcast(&Prevote::new(
-        VALIDATOR_0,
-        HEIGHT_ONE,
-        ROUND_TWO,
-        &propose.hash(),
-        LOCK_ONE,
-        sandbox.s(VALIDATOR_0),
-    ));
-    sandbox.add_time(Duration::from_millis(0));
+    for &validator in &[VALIDATOR_1, VALIDATOR_2] {
+        sandbox.recv(&Precommit::new(
+            validator,
+            HEIGHT_ONE,
+            ROUND_ONE,
+            &propose.hash(),
+            &block.hash(),
+            sandbox.time().into(),
+            sandbox.s(validator),
+        ));
+    }
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.check_broadcast_status(HEIGHT_TWO, &block.hash());
+    assert!(sandbox.transactions_hashes().is_empty());
 }
 
-/// HANDLE ROUND TIMEOUT:
-///  - handle queued messages
-/// idea:
-///  - lock to propose
-///  - trigger `round_timeout`
-///  - observe broadcasted prevote
-#[test]
-#[should_panic(expected = "Send unexpected message Request(ProposeRequest")]
-fn test_handle_round_timeout_queue_prevote_message_from_next_round() {
-    let sandbox = timestamping_sandbox();
-
-    sandbox.recv(&Prevote::new(
-        VALIDATOR_2,
-        HEIGHT_ONE,
-        ROUND_TWO,
-        &empty_hash(),
-        Round::zero(),
-        sandbox.s(VALIDATOR_2),
-    ));
-
-    // trigger round_timeout
-    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
-    // trigger request_propose_timeout
-    sandbox.add_time(Duration::from_millis(PROPOSE_REQUEST_TIMEOUT));
-    // observe requestPropose request
-    sandbox.add_time(Duration::from_millis(0));
+fn gen_batch_with_tx() -> (TransactionBatch, TimestampTx) {
+    let (public_key, secret_key) = gen_keypair();
+    let tx = TimestampingTxGenerator::with_keypair(64, (public_key, secret_key.clone()))
+        .next()
+        .unwrap();
+    let batch = TransactionBatch::new(&public_key, vec![tx.raw().clone()], &secret_key);
+    (batch, tx)
 }
 
-/// - exclude validator from consensus
-/// - idea of test is to exclude sandbox validator from consensus
-/// - node continues as `full node`
+/// - propose including a transaction both directly and within a batch is not prevoted
 #[test]
-fn test_exclude_validator_from_consensus() {
-    use storage::StorageValue;
-
+fn test_propose_with_batched_tx_duplicate_rejected() {
     let sandbox = timestamping_sandbox();
-    let sandbox_state = SandboxState::new();
-
-    add_one_height(&sandbox, &sandbox_state);
 
-    let tx_cfg = {
-        let mut consensus_cfg = sandbox.cfg();
-        consensus_cfg.validator_keys.swap_remove(0);
-        consensus_cfg.actual_from = sandbox.current_height().next().next();
-        consensus_cfg.previous_cfg_hash = sandbox.cfg().hash();
+    let (batch, tx) = gen_batch_with_tx();
+    sandbox.recv(&batch);
+    sandbox.recv(&tx);
+    assert_eq!(sandbox.transactions_hashes().len(), 2);
 
-        TxConfig::new(
-            &sandbox.p(VALIDATOR_0),
-            &consensus_cfg.clone().into_bytes(),
-            consensus_cfg.actual_from,
-            sandbox.s(VALIDATOR_0),
-        )
-    };
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hashes(&[batch.hash(), tx.hash()])
+        .build();
 
-    add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx_cfg.raw().clone()]);
-    add_one_height(&sandbox, &sandbox_state);
-    // node loses validator status
-    add_one_height_with_transactions_from_other_validator(&sandbox, &sandbox_state, &[]);
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+    sandbox.recv(&propose);
+    // The sandbox panics on drop if the prevote is broadcast.
 }
 
-/// - Check `following_configuration` method in schema
-/// - idea of the test is check configurations method from schema
+/// - leader does not propose a transaction both directly and within a batch
 #[test]
-fn test_schema_config_changes() {
-    use storage::StorageValue;
-
+fn test_leader_skips_batched_tx_duplicate() {
     let sandbox = timestamping_sandbox();
-    let sandbox_state = SandboxState::new();
-
-    add_one_height(&sandbox, &sandbox_state);
-
-    let (tx_cfg, following_cfg) = {
-        let mut consensus_cfg = sandbox.cfg();
-        consensus_cfg.consensus.txs_block_limit = 2000;
-        consensus_cfg.actual_from = sandbox.current_height().next().next();
-        consensus_cfg.previous_cfg_hash = sandbox.cfg().hash();
 
-        let tx = TxConfig::new(
-            &sandbox.p(VALIDATOR_0),
-            &consensus_cfg.clone().into_bytes(),
-            consensus_cfg.actual_from,
-            sandbox.s(VALIDATOR_0),
-        );
-        (tx, consensus_cfg)
-    };
-    let prev_cfg = sandbox.cfg();
+    let (batch, tx) = gen_batch_with_tx();
+    sandbox.recv(&batch);
+    sandbox.recv(&tx);
 
-    // Check configuration from genesis block
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot()).actual_configuration(),
-        prev_cfg
-    );
-    // Try to get configuration from non exists height
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot()).configuration_by_height(HEIGHT_FOUR),
-        prev_cfg
-    );
-    // Commit a new configuration
-    add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx_cfg.raw().clone()]);
-    // Check that following configuration is visible
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot()).following_configuration(),
-        Some(following_cfg.clone())
-    );
-    // Make following configuration actual
-    add_one_height(&sandbox, &sandbox_state);
-    add_one_height_with_transactions(&sandbox, &sandbox_state, &[]);
-    // Check that following configuration becomes actual
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot()).actual_configuration(),
-        following_cfg
-    );
-    // Check previous configuration
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot())
-            .previous_configuration()
-            .unwrap(),
-        prev_cfg
-    );
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    sandbox.add_time(Duration::from_millis(
+        sandbox.round_timeout() + sandbox.propose_timeout(),
+    ));
+    assert!(sandbox.is_leader());
+    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
 
-    // Finally check configuration for some heights
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot()).configuration_by_height(HEIGHT_ZERO),
-        prev_cfg
-    );
-    assert_eq!(
-        Schema::new(&sandbox.blockchain_ref().snapshot())
-            .configuration_by_height(sandbox.current_height()),
-        following_cfg
-    );
+    // The pool is ordered by hashes, and the first of the conflicting transactions is proposed.
+    let first = ::std::cmp::min(batch.hash(), tx.hash());
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .with_tx_hashes(&[first])
+        .build();
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
 }
 
 // - lock to propose when get +2/3 prevote
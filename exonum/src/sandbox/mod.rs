@@ -19,3 +19,4 @@ mod timestamping;
 mod consensus;
 mod old;
 mod requests;
+mod simulator;
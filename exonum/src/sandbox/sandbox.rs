@@ -27,10 +27,11 @@ use std::iter::FromIterator;
 
 use node::{ApiSender, Configuration, ExternalMessage, ListenerConfig, NodeHandler, NodeSender,
            ServiceConfig, State, SystemStateProvider};
-use blockchain::{Block, BlockProof, Blockchain, ConsensusConfig, GenesisConfig, Schema, Service,
-                 SharedNodeState, StoredConfiguration, Transaction, ValidatorKeys};
+use blockchain::{Block, BlockProof, Blockchain, ConsensusConfig, GenesisConfig, LeaderElection,
+                 Schema, Service, SharedNodeState, StoredConfiguration, Transaction,
+                 ValidatorKeys};
 use storage::{MapProof, MemoryDB};
-use messages::{Any, Connect, Message, RawMessage, RawTransaction, Status};
+use messages::{Any, Connect, Message, ProtocolInfo, RawMessage, RawTransaction, Status};
 use crypto::{gen_keypair, gen_keypair_from_seed, Hash, PublicKey, SecretKey, Seed};
 use helpers::{user_agent, Height, Milliseconds, Round, ValidatorId};
 use events::{Event, EventHandler, InternalEvent, InternalRequest, NetworkEvent, NetworkRequest,
@@ -42,7 +43,7 @@ use super::sandbox_tests_helper::VALIDATOR_0;
 
 pub type SharedTime = Arc<Mutex<SystemTime>>;
 
-const INITIAL_TIME_IN_SECS: u64 = 1_486_720_340;
+pub const INITIAL_TIME_IN_SECS: u64 = 1_486_720_340;
 
 #[derive(Debug)]
 pub struct SandboxSystemStateProvider {
@@ -50,6 +51,15 @@ pub struct SandboxSystemStateProvider {
     shared_time: SharedTime,
 }
 
+impl SandboxSystemStateProvider {
+    pub fn new(listen_address: SocketAddr, shared_time: SharedTime) -> Self {
+        SandboxSystemStateProvider {
+            listen_address,
+            shared_time,
+        }
+    }
+}
+
 impl SystemStateProvider for SandboxSystemStateProvider {
     fn current_time(&self) -> SystemTime {
         *self.shared_time.lock().unwrap()
@@ -89,7 +99,7 @@ impl SandboxInner {
         let network_getter = futures::lazy(|| -> Result<(), ()> {
             while let Async::Ready(Some(network)) = self.network_requests_rx.poll()? {
                 match network {
-                    NetworkRequest::SendMessage(peer, msg) => self.sent.push_back((peer, msg)),
+                    NetworkRequest::SendMessage(peer, msg, _) => self.sent.push_back((peer, msg)),
                     NetworkRequest::DisconnectWithPeer(_) | NetworkRequest::Shutdown => {}
                 }
             }
@@ -105,7 +115,8 @@ impl SandboxInner {
                     InternalRequest::Timeout(t) => self.timers.push(t),
                     InternalRequest::JumpToRound(height, round) => self.handler
                         .handle_event(InternalEvent::JumpToRound(height, round).into()),
-                    InternalRequest::Shutdown => unimplemented!(),
+                    InternalRequest::Shutdown => self.handler
+                        .handle_event(InternalEvent::Shutdown.into()),
                 }
             }
             Ok(())
@@ -149,13 +160,14 @@ impl Sandbox {
 
         for validator in start_index..end_index {
             let validator = ValidatorId(validator as u16);
-            self.recv(&Connect::new(
+            let peer_connect = Connect::new(
                 &self.p(validator),
                 self.a(validator),
                 self.time().into(),
                 &user_agent::get(),
                 self.s(validator),
-            ));
+            );
+            self.connect_peer(&peer_connect, ProtocolInfo::current());
             self.send(self.a(validator), &connect);
         }
 
@@ -238,6 +250,14 @@ impl Sandbox {
         self.inner.borrow_mut().handle_event(event);
     }
 
+    /// Simulates an incoming connection from the peer, which announces the given protocol
+    /// versions and features in the handshake.
+    pub fn connect_peer(&self, connect: &Connect, protocol: ProtocolInfo) {
+        self.check_unexpected_message();
+        let event = NetworkEvent::PeerConnected(connect.addr(), connect.clone(), protocol);
+        self.inner.borrow_mut().handle_event(event);
+    }
+
     pub fn process_events(&self) {
         self.inner.borrow_mut().process_events();
     }
@@ -591,6 +611,7 @@ impl Sandbox {
             },
             network: NetworkConfiguration::default(),
             peer_discovery: Vec::new(),
+            peer_list: Vec::new(),
             mempool: Default::default(),
         };
 
@@ -647,7 +668,7 @@ impl Drop for Sandbox {
     }
 }
 
-fn gen_primitive_socket_addr(idx: u8) -> SocketAddr {
+pub fn gen_primitive_socket_addr(idx: u8) -> SocketAddr {
     let addr = Ipv4Addr::new(idx, idx, idx, idx);
     SocketAddr::new(IpAddr::V4(addr), u16::from(idx))
 }
@@ -697,6 +718,10 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
         min_propose_timeout: 200,
         max_propose_timeout: 200,
         propose_timeout_threshold: 0,
+        leader_election: LeaderElection::RoundRobin,
+        max_empty_block_interval: None,
+        compact_block_depth: None,
+        protocol_version: None,
     };
     let genesis = GenesisConfig::new_with_consensus(
         consensus,
@@ -706,6 +731,7 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
             .map(|x| ValidatorKeys {
                 consensus_key: (x.0).0,
                 service_key: (x.1).0,
+                weight: ValidatorKeys::DEFAULT_WEIGHT,
             }),
     );
     blockchain.initialize(genesis).unwrap();
@@ -723,6 +749,7 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
         },
         network: NetworkConfiguration::default(),
         peer_discovery: Vec::new(),
+        peer_list: Vec::new(),
         mempool: Default::default(),
     };
 
@@ -788,7 +815,8 @@ pub fn timestamping_sandbox() -> Sandbox {
 mod tests {
     use super::*;
     use blockchain::{ExecutionResult, ServiceContext, TransactionSet};
-    use messages::RawTransaction;
+    use messages::{RawTransaction, TransactionsAnnounce, PROTOCOL_FEATURES, PROTOCOL_VERSIONS};
+    use node::{ReconnectConfig, ShutdownReason};
     use encoding;
     use crypto::{gen_keypair_from_seed, Seed};
     use storage::{Fork, Snapshot};
@@ -981,6 +1009,83 @@ mod tests {
         panic!("Oops! We don't catch unexpected message");
     }
 
+    #[test]
+    fn test_sandbox_reconnect_after_disconnect() {
+        let sandbox = timestamping_sandbox();
+        let connect = sandbox.node_state().our_connect_message().clone();
+        let delay = ReconnectConfig::default().min_delay;
+
+        let event = NetworkEvent::PeerDisconnected(sandbox.a(VALIDATOR_1));
+        sandbox.inner.borrow_mut().handle_event(event);
+        assert!(!sandbox.node_state().peers().contains_key(&sandbox.p(VALIDATOR_1)));
+
+        sandbox.add_time(Duration::from_millis(delay - 1));
+        sandbox.add_time(Duration::from_millis(1));
+        sandbox.send(sandbox.a(VALIDATOR_1), &connect);
+    }
+
+    #[test]
+    fn test_sandbox_required_protocol_version() {
+        let s = timestamping_sandbox();
+        let required = PROTOCOL_VERSIONS[0];
+
+        // The versions of the peer connected without the handshake are unknown.
+        let (public, secret) = gen_keypair();
+        s.recv(&Connect::new(
+            &public,
+            s.a(VALIDATOR_2),
+            s.time().into(),
+            &user_agent::get(),
+            &secret,
+        ));
+        let connect = s.node_state().our_connect_message().clone();
+        s.send(s.a(VALIDATOR_2), &connect);
+
+        let mut cfg = s.cfg();
+        cfg.consensus.protocol_version = Some(required);
+        s.node_handler_mut().state.update_config(cfg);
+        s.node_handler_mut().disconnect_incompatible_peers();
+        assert!(!s.node_state().peers().contains_key(&public));
+        assert!(s.node_state().peers().contains_key(&s.p(VALIDATOR_1)));
+
+        // Peers which don't support the required version are ignored.
+        let (public, secret) = gen_keypair();
+        let peer_connect = Connect::new(
+            &public,
+            s.a(VALIDATOR_3),
+            s.time().into(),
+            &user_agent::get(),
+            &secret,
+        );
+        let protocol = ProtocolInfo {
+            versions: vec![required + 1],
+            features: PROTOCOL_FEATURES,
+        };
+        s.connect_peer(&peer_connect, protocol);
+        assert!(!s.node_state().peers().contains_key(&public));
+
+        s.connect_peer(&peer_connect, ProtocolInfo::current());
+        s.send(s.a(VALIDATOR_3), &connect);
+        assert!(s.node_state().peers().contains_key(&public));
+    }
+
+    #[test]
+    fn test_sandbox_unsupported_protocol_version() {
+        let s = timestamping_sandbox();
+        let required = PROTOCOL_VERSIONS.iter().max().unwrap() + 1;
+        let mut cfg = s.cfg();
+        cfg.consensus.protocol_version = Some(required);
+        s.node_handler_mut().state.update_config(cfg);
+        s.node_handler_mut().disconnect_incompatible_peers();
+
+        // The node refuses to work, but keeps the peers, since it should be upgraded itself.
+        assert_eq!(
+            s.node_handler_mut().api_state().shutdown_reason(),
+            Some(ShutdownReason::UnsupportedProtocolVersion(required))
+        );
+        assert!(s.node_state().peers().contains_key(&s.p(VALIDATOR_1)));
+    }
+
     #[test]
     fn test_sandbox_service_handle_commit() {
         let sandbox = sandbox_with_services(vec![
@@ -990,6 +1095,10 @@ mod tests {
         let state = SandboxState::new();
         add_one_height(&sandbox, &state);
         let tx = TxAfterCommit::new_with_height(Height(1));
-        sandbox.broadcast(&tx);
+        sandbox.broadcast(&TransactionsAnnounce::new(
+            &sandbox.p(VALIDATOR_0),
+            &[tx.hash()],
+            sandbox.s(VALIDATOR_0),
+        ));
     }
 }
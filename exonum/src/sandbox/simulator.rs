@@ -0,0 +1,577 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-process simulation of a network of validators.
+//!
+//! Every node runs its own `NodeHandler` on top of a separate database, while messages
+//! between nodes are delivered by the simulator according to the configured links. All nodes
+//! share the same virtual clock, which is advanced straight to the next pending event.
+//! Latency of a link is chosen randomly for every message, so messages may be reordered.
+
+use futures::{self, Async, Future, Sink, Stream, sync::mpsc};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::net::SocketAddr;
+use std::ops::AddAssign;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use blockchain::{Blockchain, ConsensusConfig, GenesisConfig, LeaderElection, Schema, Service,
+                 SharedNodeState, Transaction, ValidatorKeys};
+use crypto::{gen_keypair_from_seed, Hash, Seed};
+use events::{Event, EventHandler, InternalEvent, InternalRequest, NetworkEvent, NetworkRequest,
+             TimeoutRequest};
+use events::network::NetworkConfiguration;
+use helpers::{Height, Milliseconds};
+use messages::RawMessage;
+use node::{ApiSender, Configuration, ExternalMessage, ListenerConfig, NodeHandler, NodeSender,
+           ServiceConfig};
+use storage::MemoryDB;
+use super::sandbox::{gen_primitive_socket_addr, SandboxSystemStateProvider, SharedTime,
+                     INITIAL_TIME_IN_SECS};
+use super::timestamping::TimestampingService;
+
+const CHANNEL_CAPACITY: usize = 10_000;
+
+/// Properties of a directed link between two nodes.
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    /// Minimal latency of the link.
+    pub min_latency: Milliseconds,
+    /// Maximal latency of the link.
+    pub max_latency: Milliseconds,
+    /// Probability of a message to be lost.
+    pub loss: f64,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Link {
+            min_latency: 10,
+            max_latency: 50,
+            loss: 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Delivery {
+    time: SystemTime,
+    seq: u64,
+    to: usize,
+    from: SocketAddr,
+    message: RawMessage,
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delivery {}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed, so that `BinaryHeap` yields the earliest delivery first.
+impl Ord for Delivery {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .cmp(&self.time)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Debug)]
+struct SimulatedNode {
+    handler: NodeHandler,
+    timers: BinaryHeap<TimeoutRequest>,
+    network_requests_rx: mpsc::Receiver<NetworkRequest>,
+    internal_requests_rx: mpsc::Receiver<InternalRequest>,
+    api_requests_rx: mpsc::Receiver<ExternalMessage>,
+    checked_height: Height,
+}
+
+impl SimulatedNode {
+    fn next_timer(&self) -> Option<SystemTime> {
+        self.timers.peek().map(|&TimeoutRequest(time, _)| time)
+    }
+}
+
+/// Network of validators running in a single thread with a virtual clock.
+#[derive(Debug)]
+pub struct Simulator {
+    nodes: Vec<SimulatedNode>,
+    addresses: Vec<SocketAddr>,
+    time: SharedTime,
+    deliveries: BinaryHeap<Delivery>,
+    seq: u64,
+    rng: XorShiftRng,
+    default_link: Link,
+    links: HashMap<(usize, usize), Link>,
+    groups: Option<Vec<usize>>,
+    committed: HashMap<Height, Hash>,
+}
+
+impl Simulator {
+    /// Creates a network of `validators` nodes running the timestamping service.
+    /// Random decisions of the simulator are determined by the `seed`.
+    pub fn new(validators: usize, seed: [u32; 4]) -> Self {
+        Self::with_services(validators, seed, || {
+            vec![Box::new(TimestampingService::new()) as Box<Service>]
+        })
+    }
+
+    /// Creates a network of `validators` nodes with services produced by `services`.
+    pub fn with_services<F>(validators: usize, seed: [u32; 4], services: F) -> Self
+    where
+        F: Fn() -> Vec<Box<Service>>,
+    {
+        assert!(validators > 0 && validators < 100);
+        let consensus_keys = (0..validators)
+            .map(|i| gen_keypair_from_seed(&Seed::new([i as u8; 32])))
+            .collect::<Vec<_>>();
+        let service_keys = (0..validators)
+            .map(|i| gen_keypair_from_seed(&Seed::new([100 + i as u8; 32])))
+            .collect::<Vec<_>>();
+        let addresses = (0..validators)
+            .map(|i| gen_primitive_socket_addr(i as u8 + 1))
+            .collect::<Vec<_>>();
+
+        let consensus = ConsensusConfig {
+            round_timeout: 1000,
+            status_timeout: 1000,
+            peers_timeout: 10_000,
+            txs_block_limit: 1000,
+            max_message_len: 1024 * 1024,
+            min_propose_timeout: 200,
+            max_propose_timeout: 200,
+            propose_timeout_threshold: 0,
+            leader_election: LeaderElection::RoundRobin,
+            max_empty_block_interval: None,
+            compact_block_depth: None,
+            protocol_version: None,
+        };
+        let genesis = GenesisConfig::new_with_consensus(
+            consensus,
+            consensus_keys
+                .iter()
+                .zip(service_keys.iter())
+                .map(|x| ValidatorKeys {
+                    consensus_key: (x.0).0,
+                    service_key: (x.1).0,
+                    weight: ValidatorKeys::DEFAULT_WEIGHT,
+                }),
+        );
+
+        let time = SharedTime::new(Mutex::new(
+            UNIX_EPOCH + Duration::new(INITIAL_TIME_IN_SECS, 0),
+        ));
+
+        let nodes = (0..validators)
+            .map(|i| {
+                let api_channel = mpsc::channel(CHANNEL_CAPACITY);
+                let network_channel = mpsc::channel(CHANNEL_CAPACITY);
+                let internal_channel = mpsc::channel(CHANNEL_CAPACITY);
+
+                let mut blockchain = Blockchain::new(
+                    MemoryDB::new(),
+                    services(),
+                    service_keys[i].0,
+                    service_keys[i].1.clone(),
+                    ApiSender::new(api_channel.0.clone()),
+                );
+                blockchain.initialize(genesis.clone()).unwrap();
+
+                let config = Configuration {
+                    listener: ListenerConfig {
+                        address: addresses[i],
+                        consensus_public_key: consensus_keys[i].0,
+                        consensus_secret_key: consensus_keys[i].1.clone(),
+                        whitelist: Default::default(),
+                    },
+                    service: ServiceConfig {
+                        service_public_key: service_keys[i].0,
+                        service_secret_key: service_keys[i].1.clone(),
+                    },
+                    network: NetworkConfiguration::default(),
+                    peer_discovery: addresses.clone(),
+                    peer_list: Vec::new(),
+                    mempool: Default::default(),
+                };
+                let system_state =
+                    SandboxSystemStateProvider::new(addresses[i], Arc::clone(&time));
+                let node_sender = NodeSender {
+                    network_requests: network_channel.0.clone().wait(),
+                    internal_requests: internal_channel.0.clone().wait(),
+                    api_requests: api_channel.0.clone().wait(),
+                };
+
+                let handler = NodeHandler::new(
+                    blockchain,
+                    addresses[i],
+                    node_sender,
+                    Box::new(system_state),
+                    config,
+                    SharedNodeState::new(5000),
+                );
+                SimulatedNode {
+                    handler,
+                    timers: BinaryHeap::new(),
+                    network_requests_rx: network_channel.1,
+                    internal_requests_rx: internal_channel.1,
+                    api_requests_rx: api_channel.1,
+                    checked_height: Height(1),
+                }
+            })
+            .collect();
+
+        let mut simulator = Simulator {
+            nodes,
+            addresses,
+            time,
+            deliveries: BinaryHeap::new(),
+            seq: 0,
+            rng: XorShiftRng::from_seed(seed),
+            default_link: Link::default(),
+            links: HashMap::new(),
+            groups: None,
+            committed: HashMap::new(),
+        };
+        for node in 0..validators {
+            simulator.nodes[node].handler.initialize();
+            simulator.process_requests(node);
+        }
+        simulator
+    }
+
+    /// Returns the current virtual time.
+    pub fn time(&self) -> SystemTime {
+        *self.time.lock().unwrap()
+    }
+
+    /// Sets properties of all links that have not been configured explicitly.
+    pub fn set_default_link(&mut self, link: Link) {
+        self.default_link = link;
+    }
+
+    /// Sets properties of the link from the node `from` to the node `to`.
+    pub fn set_link(&mut self, from: usize, to: usize, link: Link) {
+        self.links.insert((from, to), link);
+    }
+
+    /// Splits the network into the given groups of nodes. Messages between nodes
+    /// from different groups are dropped; nodes not listed in any group are isolated.
+    /// Connections are not closed, so the nodes keep sending messages to each other
+    /// and resume communication once the partition is healed.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut assignment = (0..self.nodes.len())
+            .map(|node| groups.len() + node)
+            .collect::<Vec<_>>();
+        for (group, nodes) in groups.iter().enumerate() {
+            for &node in nodes.iter() {
+                assignment[node] = group;
+            }
+        }
+        self.groups = Some(assignment);
+    }
+
+    /// Removes the partition of the network.
+    pub fn heal(&mut self) {
+        self.groups = None;
+    }
+
+    /// Returns the height of the node, i.e., the height of the next block.
+    pub fn height(&self, node: usize) -> Height {
+        self.nodes[node].handler.state.height()
+    }
+
+    /// Returns the hash of the block committed at the height.
+    pub fn block_hash(&self, height: Height) -> Option<Hash> {
+        self.committed.get(&height).cloned()
+    }
+
+    /// Submits the transaction to the node.
+    pub fn add_transaction(&mut self, node: usize, tx: Box<Transaction>) {
+        self.handle_event(node, ExternalMessage::Transaction(tx).into());
+    }
+
+    /// Processes events during the given period of the virtual time.
+    pub fn run_for(&mut self, duration: Duration) {
+        let deadline = self.time() + duration;
+        self.run_until(deadline, |_| false);
+    }
+
+    /// Processes events until all of the `nodes` reach the `height`. Returns `false`
+    /// if they fail to do so within the `timeout` of the virtual time.
+    pub fn run_until_height(&mut self, nodes: &[usize], height: Height, timeout: Duration) -> bool {
+        let deadline = self.time() + timeout;
+        self.run_until(deadline, |simulator| {
+            nodes.iter().all(|&node| simulator.height(node) >= height)
+        })
+    }
+
+    fn run_until<F>(&mut self, deadline: SystemTime, stop: F) -> bool
+    where
+        F: Fn(&Self) -> bool,
+    {
+        loop {
+            if stop(self) {
+                return true;
+            }
+
+            let next_delivery = self.deliveries.peek().map(|delivery| delivery.time);
+            let next_timer = self.nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(node, state)| state.next_timer().map(|time| (time, node)))
+                .min();
+
+            let time = match (next_delivery, next_timer) {
+                (Some(delivery), Some((timer, _))) => ::std::cmp::min(delivery, timer),
+                (Some(delivery), None) => delivery,
+                (None, Some((timer, _))) => timer,
+                (None, None) => break,
+            };
+            if time > deadline {
+                break;
+            }
+            self.advance_time(time);
+
+            if next_delivery == Some(time) {
+                let delivery = self.deliveries.pop().unwrap();
+                let event = NetworkEvent::MessageReceived(delivery.from, delivery.message);
+                self.handle_event(delivery.to, event.into());
+            } else {
+                let node = next_timer.unwrap().1;
+                let TimeoutRequest(_, timeout) = self.nodes[node].timers.pop().unwrap();
+                self.handle_event(node, timeout.into());
+            }
+        }
+
+        self.advance_time(deadline);
+        stop(self)
+    }
+
+    fn advance_time(&mut self, time: SystemTime) {
+        let mut now = self.time.lock().unwrap();
+        if time > *now {
+            let elapsed = time.duration_since(*now).unwrap();
+            now.add_assign(elapsed);
+        }
+    }
+
+    fn handle_event(&mut self, node: usize, event: Event) {
+        self.nodes[node].handler.handle_event(event);
+        self.process_requests(node);
+        self.check_safety(node);
+    }
+
+    fn process_requests(&mut self, node: usize) {
+        loop {
+            let internal = drain(&mut self.nodes[node].internal_requests_rx);
+            let api = drain(&mut self.nodes[node].api_requests_rx);
+            let network = drain(&mut self.nodes[node].network_requests_rx);
+            if internal.is_empty() && api.is_empty() && network.is_empty() {
+                break;
+            }
+
+            for request in internal {
+                match request {
+                    InternalRequest::Timeout(timeout) => self.nodes[node].timers.push(timeout),
+                    InternalRequest::JumpToRound(height, round) => self.nodes[node]
+                        .handler
+                        .handle_event(InternalEvent::JumpToRound(height, round).into()),
+                    InternalRequest::Shutdown => {}
+                }
+            }
+            for message in api {
+                self.nodes[node].handler.handle_event(message.into());
+            }
+            for request in network {
+                match request {
+                    NetworkRequest::SendMessage(address, message, _) => {
+                        self.send(node, address, message)
+                    }
+                    NetworkRequest::DisconnectWithPeer(_) | NetworkRequest::Shutdown => {}
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, address: SocketAddr, message: RawMessage) {
+        let to = match self.addresses.iter().position(|&a| a == address) {
+            Some(to) => to,
+            None => return,
+        };
+        if let Some(ref groups) = self.groups {
+            if groups[from] != groups[to] {
+                return;
+            }
+        }
+
+        let link = self.links
+            .get(&(from, to))
+            .cloned()
+            .unwrap_or(self.default_link);
+        if link.loss > 0.0 && self.rng.gen::<f64>() < link.loss {
+            return;
+        }
+        let latency = self.rng
+            .gen_range(link.min_latency, link.max_latency + 1);
+
+        self.seq += 1;
+        self.deliveries.push(Delivery {
+            time: self.time() + Duration::from_millis(latency),
+            seq: self.seq,
+            to,
+            from: self.addresses[from],
+            message,
+        });
+    }
+
+    /// Checks that the blocks committed by the node agree with the blocks committed
+    /// by other nodes at the same heights.
+    fn check_safety(&mut self, node: usize) {
+        let height = self.height(node);
+        if self.nodes[node].checked_height >= height {
+            return;
+        }
+
+        let snapshot = self.nodes[node].handler.blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        let mut current = self.nodes[node].checked_height;
+        while current < height {
+            let hash = schema
+                .block_hash_by_height(current)
+                .expect("Committed block is absent");
+            let expected = *self.committed.entry(current).or_insert(hash);
+            assert_eq!(
+                expected, hash,
+                "Node {} committed a conflicting block at height {}",
+                node, current
+            );
+            current = current.next();
+        }
+        self.nodes[node].checked_height = height;
+    }
+}
+
+fn drain<T>(receiver: &mut mpsc::Receiver<T>) -> Vec<T> {
+    let mut items = Vec::new();
+    futures::lazy(|| -> Result<(), ()> {
+        while let Async::Ready(Some(item)) = receiver.poll()? {
+            items.push(item);
+        }
+        Ok(())
+    }).wait()
+        .unwrap();
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use blockchain::Schema;
+    use helpers::Height;
+    use messages::Message;
+    use sandbox::timestamping::TimestampingTxGenerator;
+    use super::{Link, Simulator};
+
+    const SEED: [u32; 4] = [1, 2, 3, 4];
+
+    fn timeout() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    #[test]
+    fn test_simulator_liveness() {
+        let mut simulator = Simulator::new(4, SEED);
+        assert!(simulator.run_until_height(&[0, 1, 2, 3], Height(5), timeout()));
+        assert!(simulator.block_hash(Height(4)).is_some());
+    }
+
+    #[test]
+    fn test_simulator_lossy_links() {
+        let mut simulator = Simulator::new(4, SEED);
+        simulator.set_default_link(Link {
+            min_latency: 5,
+            max_latency: 400,
+            loss: 0.1,
+        });
+        simulator.set_link(
+            0,
+            1,
+            Link {
+                min_latency: 500,
+                max_latency: 900,
+                loss: 0.5,
+            },
+        );
+        assert!(simulator.run_until_height(&[0, 1, 2, 3], Height(5), timeout()));
+    }
+
+    #[test]
+    fn test_simulator_partition_without_majority() {
+        let mut simulator = Simulator::new(4, SEED);
+        assert!(simulator.run_until_height(&[0, 1, 2, 3], Height(2), timeout()));
+
+        simulator.partition(&[&[0, 1], &[2, 3]]);
+        simulator.run_for(Duration::from_secs(5));
+        let height = (0..4).map(|node| simulator.height(node)).max().unwrap();
+        simulator.run_for(timeout());
+        assert!((0..4).all(|node| simulator.height(node) <= height));
+
+        simulator.heal();
+        assert!(simulator.run_until_height(&[0, 1, 2, 3], height.next(), timeout()));
+    }
+
+    #[test]
+    fn test_simulator_partition_with_majority() {
+        let mut simulator = Simulator::new(4, SEED);
+        assert!(simulator.run_until_height(&[0, 1, 2, 3], Height(2), timeout()));
+
+        simulator.partition(&[&[0, 1, 2]]);
+        simulator.run_for(Duration::from_secs(1));
+        let height = simulator.height(3);
+        assert!(simulator.run_until_height(&[0, 1, 2], height.next().next(), timeout()));
+        assert_eq!(simulator.height(3), height);
+
+        simulator.heal();
+        let height = simulator.height(0);
+        assert!(simulator.run_until_height(&[3], height, timeout()));
+    }
+
+    #[test]
+    fn test_simulator_transactions() {
+        let mut simulator = Simulator::new(4, SEED);
+        let tx = TimestampingTxGenerator::new(64).next().unwrap();
+        let tx_hash = tx.hash();
+        simulator.add_transaction(0, Box::new(tx));
+        assert!(simulator.run_until_height(&[0, 1, 2, 3], Height(3), timeout()));
+        for node in &simulator.nodes {
+            let snapshot = node.handler.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            assert!(schema.transactions_locations().contains(&tx_hash));
+        }
+    }
+}
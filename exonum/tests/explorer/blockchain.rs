@@ -115,6 +115,7 @@ pub fn create_blockchain() -> Blockchain {
     let keys = ValidatorKeys {
         consensus_key,
         service_key: service_keys.0,
+        weight: ValidatorKeys::DEFAULT_WEIGHT,
     };
     blockchain
         .initialize(GenesisConfig::new(vec![keys].into_iter()))
@@ -17,6 +17,7 @@ extern crate exonum;
 extern crate futures;
 extern crate serde_json;
 extern crate tokio_timer;
+extern crate ws;
 
 use futures::Future;
 use futures::sync::oneshot;
@@ -27,10 +28,11 @@ use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
 
+use exonum::api::{ClientMessage, Subscription, WebSocketConfig};
 use exonum::blockchain::{Service, ServiceContext, Transaction};
 use exonum::encoding::Error as EncodingError;
 use exonum::messages::RawTransaction;
-use exonum::node::{ApiSender, ExternalMessage, Node};
+use exonum::node::{ApiSender, ExternalMessage, Node, ShutdownReason};
 use exonum::storage::{Database, Fork, MemoryDB, Snapshot};
 use exonum::helpers;
 use exonum::crypto::Hash;
@@ -137,7 +139,7 @@ fn test_node_restart_regression() {
         let node = Node::new(db, vec![service], node_cfg);
         let api_tx = node.channel();
         let node_thread = thread::spawn(move || {
-            node.run().unwrap();
+            assert_eq!(node.run().unwrap(), ShutdownReason::Requested);
         });
         // Wait for shutdown
         api_tx
@@ -156,3 +158,92 @@ fn test_node_restart_regression() {
     start_node(node_cfg, db, Arc::clone(&init_times));
     assert_eq!(*init_times.lock().unwrap(), 1);
 }
+
+#[test]
+fn test_websocket_block_notifications() {
+    let mut node_cfg = helpers::generate_testnet_config(1, 16_400)[0].clone();
+    let ws_address = "127.0.0.1:16401".parse().unwrap();
+    node_cfg.api.websocket = Some(WebSocketConfig::new(ws_address));
+    let node = Node::new(MemoryDB::new(), vec![], node_cfg);
+    let api_tx = node.channel();
+    let node_thread = thread::spawn(move || {
+        node.run().unwrap();
+    });
+
+    let notification = Arc::new(Mutex::new(None));
+    // The server may be not started yet, so the connection is retried.
+    for _ in 0..50 {
+        ws::connect(format!("ws://{}", ws_address), |out| {
+            out.send(r#"{ "action": "subscribe", "subscription": { "type": "blocks" } }"#)
+                .unwrap();
+            let notification = Arc::clone(&notification);
+            move |message: ws::Message| {
+                *notification.lock().unwrap() = Some(message.into_text()?);
+                out.close(ws::CloseCode::Normal)
+            }
+        }).unwrap();
+        if notification.lock().unwrap().is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let notification = notification.lock().unwrap().take().unwrap();
+    let notification: Value = serde_json::from_str(&notification).unwrap();
+    assert_eq!(notification["type"], "block_committed");
+    assert!(notification["block_hash"].is_string());
+
+    api_tx
+        .send_external_message(ExternalMessage::Shutdown)
+        .unwrap();
+    node_thread.join().unwrap();
+}
+
+#[test]
+fn test_websocket_transaction_subscriptions_limit() {
+    let mut node_cfg = helpers::generate_testnet_config(1, 16_402)[0].clone();
+    let ws_address = "127.0.0.1:16403".parse().unwrap();
+    let mut ws_config = WebSocketConfig::new(ws_address);
+    ws_config.max_transaction_subscriptions = 1;
+    node_cfg.api.websocket = Some(ws_config);
+    let node = Node::new(MemoryDB::new(), vec![], node_cfg);
+    let api_tx = node.channel();
+    let node_thread = thread::spawn(move || {
+        node.run().unwrap();
+    });
+
+    let subscribe = |tx_hash: Hash| {
+        let message = ClientMessage::Subscribe(Subscription::Transaction { tx_hash });
+        serde_json::to_string(&message).unwrap()
+    };
+    let notification = Arc::new(Mutex::new(None));
+    // The server may be not started yet, so the connection is retried.
+    for _ in 0..50 {
+        ws::connect(format!("ws://{}", ws_address), |out| {
+            out.send(subscribe(Hash::new([1; 32]))).unwrap();
+            out.send(subscribe(Hash::new([2; 32]))).unwrap();
+            let notification = Arc::clone(&notification);
+            move |message: ws::Message| {
+                *notification.lock().unwrap() = Some(message.into_text()?);
+                out.close(ws::CloseCode::Normal)
+            }
+        }).unwrap();
+        if notification.lock().unwrap().is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let notification = notification.lock().unwrap().take().unwrap();
+    let notification: Value = serde_json::from_str(&notification).unwrap();
+    assert_eq!(notification["type"], "error");
+    assert_eq!(
+        notification["description"],
+        "Too many transaction subscriptions, at most 1 are allowed"
+    );
+
+    api_tx
+        .send_external_message(ExternalMessage::Shutdown)
+        .unwrap();
+    node_thread.join().unwrap();
+}
@@ -88,9 +88,9 @@ pub(crate) enum Error {
     #[fail(display = "Invalid majority count: {}, it should be >= {} and <= {}", proposed, min,
            max)]
     InvalidMajorityCount {
-        min: usize,
-        max: usize,
-        proposed: usize,
+        min: u64,
+        max: u64,
+        proposed: u64,
     },
 
     #[fail(display = "Does not reference known config with hash {:?}", _0)]
@@ -259,7 +259,7 @@ fn test_discard_proposes_with_too_big_majority_count() {
     let cfg_change_height = Height(5);
     let new_cfg = {
         let mut cfg = testkit.configuration_change_proposal();
-        let excessive_majority_count = (&testkit.network().validators().len() + 100) as u16;
+        let excessive_majority_count = (&testkit.network().validators().len() + 100) as u64;
         cfg.set_service_config("dummy", "First cfg");
         cfg.set_majority_count(Some(excessive_majority_count));
         cfg.set_actual_from(cfg_change_height);
@@ -278,7 +278,7 @@ fn test_discard_proposes_with_too_small_majority_count() {
     let cfg_change_height = Height(5);
     let new_cfg = {
         let mut cfg = testkit.configuration_change_proposal();
-        let insufficient_majority_count = (&testkit.network().validators().len() / 2) as u16;
+        let insufficient_majority_count = (&testkit.network().validators().len() / 2) as u64;
         cfg.set_service_config("dummy", "First cfg");
         cfg.set_majority_count(Some(insufficient_majority_count));
         cfg.set_actual_from(cfg_change_height);
@@ -290,6 +290,57 @@ fn test_discard_proposes_with_too_small_majority_count() {
     assert!(testkit.find_propose(new_cfg.hash()).is_none());
 }
 
+#[test]
+fn test_apply_with_weighted_validators() {
+    let mut testkit: TestKit = TestKit::configuration_default();
+
+    // Applying the first configuration with custom validator weights.
+    let cfg_change_height = Height(5);
+    let new_cfg = {
+        let mut cfg = testkit.configuration_change_proposal();
+        cfg.set_service_config("dummy", "First cfg");
+        cfg.set_validator_weights(vec![4, 2, 1, 1]);
+        cfg.set_actual_from(cfg_change_height);
+        cfg.stored_configuration().clone()
+    };
+    testkit.apply_configuration(ValidatorId(0), new_cfg);
+
+    // Trying to apply the second configuration.
+    // Total weight is 8, so the voting power of at least 6 is required.
+    let cfg_change_height = Height(10);
+    let new_cfg = {
+        let mut cfg = testkit.configuration_change_proposal();
+        cfg.set_service_config("dummy", "Second cfg");
+        cfg.set_actual_from(cfg_change_height);
+        cfg.stored_configuration().clone()
+    };
+
+    let validators = testkit.network().validators().to_vec();
+    let tx_propose = new_tx_config_propose(&validators[1], new_cfg.clone());
+    testkit.create_block_with_transactions(txvec![tx_propose]);
+
+    // Three of four validators have voted, but their voting power is 4.
+    let tx_votes = validators[1..4]
+        .iter()
+        .map(|validator| new_tx_config_vote(validator, new_cfg.hash()))
+        .map(to_boxed)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(tx_votes);
+    assert_eq!(
+        Schema::new(&testkit.snapshot()).following_configuration(),
+        None
+    );
+
+    // The vote of the heaviest validator is enough to commit the configuration.
+    let tx_vote = new_tx_config_vote(&validators[0], new_cfg.hash());
+    testkit.create_block_with_transactions(txvec![tx_vote]);
+    testkit.create_blocks_until(cfg_change_height);
+    assert_eq!(
+        Schema::new(&testkit.snapshot()).actual_configuration(),
+        new_cfg
+    );
+}
+
 #[test]
 fn test_discard_propose_for_same_cfg() {
     let mut testkit: TestKit = TestKit::configuration_default();
@@ -20,8 +20,10 @@ use exonum::messages::Message;
 use exonum::node::State;
 use exonum::storage::{Fork, Snapshot};
 
+use std::collections::HashSet;
+
 use errors::Error as ServiceError;
-use schema::{MaybeVote, ProposeData, Schema};
+use schema::{MaybeVote, ProposeData, Schema, VotingDecision};
 
 transactions! {
     /// Configuration Service transactions.
@@ -110,19 +112,34 @@ fn validator_index(snapshot: &Snapshot, key: &PublicKey) -> Option<usize> {
     keys.iter().position(|k| k.service_key == *key)
 }
 
-/// Checks if there is enough votes for a particular configuration hash.
+/// Checks if there is enough voting power for a particular configuration hash.
+///
+/// The voting power is the total weight of the validators of the actual configuration
+/// which have voted for the configuration.
 fn enough_votes_to_commit(snapshot: &Snapshot, cfg_hash: &Hash) -> bool {
     let actual_config = CoreSchema::new(snapshot).actual_configuration();
 
     let schema = Schema::new(snapshot);
-    let votes = schema.votes_by_config_hash(cfg_hash);
-    let votes_count = votes.iter().filter(|vote| vote.is_consent()).count();
+    let voters = schema
+        .votes_by_config_hash(cfg_hash)
+        .iter()
+        .filter_map(|vote| match *vote {
+            Some(VotingDecision::Yea(ref vote)) => Some(*vote.from()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let votes_weight: u64 = actual_config
+        .validator_keys
+        .iter()
+        .filter(|keys| voters.contains(&keys.service_key))
+        .map(|keys| keys.weight)
+        .sum();
     let majority_count = match actual_config.majority_count {
-        Some(majority_count) => majority_count as usize,
-        _ => State::byzantine_majority_count(actual_config.validator_keys.len()),
+        Some(majority_count) => majority_count,
+        _ => State::byzantine_majority_weight(actual_config.total_weight()),
     };
 
-    votes_count >= majority_count
+    votes_weight >= majority_count
 }
 
 impl Propose {
@@ -175,15 +192,14 @@ impl Propose {
         }
 
         if let Some(proposed_majority_count) = candidate.majority_count {
-            let proposed_majority_count = proposed_majority_count as usize;
-            let validators_num = candidate.validator_keys.len();
-            let min_votes_count = State::byzantine_majority_count(validators_num);
+            let total_weight = candidate.total_weight();
+            let min_votes_weight = State::byzantine_majority_weight(total_weight);
 
-            if proposed_majority_count < min_votes_count || proposed_majority_count > validators_num
+            if proposed_majority_count < min_votes_weight || proposed_majority_count > total_weight
             {
                 return Err(InvalidMajorityCount {
-                    min: min_votes_count,
-                    max: validators_num,
+                    min: min_votes_weight,
+                    max: total_weight,
                     proposed: proposed_majority_count,
                 });
             }
@@ -45,7 +45,8 @@ pub mod transactions;
 pub mod time_provider;
 
 use exonum::api::Api;
-use exonum::blockchain::{ApiContext, Service, ServiceContext, Transaction, TransactionSet};
+use exonum::blockchain::{ApiContext, Schema, Service, ServiceContext, Transaction,
+                         TransactionSet};
 use exonum::crypto::Hash;
 use exonum::encoding;
 use exonum::encoding::serialize::json::reexport::Value;
@@ -91,6 +92,19 @@ impl TimeService {
             time: time_provider.into(),
         }
     }
+
+    /// Checks whether the last committed block is not empty and contains only
+    /// transactions of the time service.
+    fn is_time_only_block(context: &ServiceContext) -> bool {
+        let schema = Schema::new(context.snapshot());
+        let transactions = schema.transactions();
+        let block_transactions = schema.block_transactions(context.height());
+        !block_transactions.is_empty() && block_transactions.iter().all(|hash| {
+            transactions
+                .get(&hash)
+                .map_or(false, |raw| raw.service_id() == SERVICE_ID)
+        })
+    }
 }
 
 impl Service for TimeService {
@@ -121,6 +135,15 @@ impl Service for TimeService {
         if context.validator_id().is_none() {
             return;
         }
+        // With empty blocks suppression, a block containing only time transactions
+        // should not lead to another block.
+        if context
+            .actual_consensus_config()
+            .max_empty_block_interval
+            .is_some() && Self::is_time_only_block(context)
+        {
+            return;
+        }
         let (pub_key, sec_key) = (*context.public_key(), context.secret_key().clone());
         context
             .transaction_sender()
@@ -73,35 +73,48 @@ impl TxTime {
         let keys = Schema::new(&fork).actual_configuration().validator_keys;
         let mut schema = TimeSchema::new(fork);
 
-        // Find all known times for the validators.
+        // Find all known times for the validators together with their voting power.
         let validator_times = {
             let idx = schema.validators_times();
             let mut times = idx.iter()
                 .filter_map(|(public_key, time)| {
                     keys.iter()
                         .find(|validator| validator.service_key == public_key)
-                        .map(|_| time)
+                        .map(|validator| (time, validator.weight))
                 })
                 .collect::<Vec<_>>();
             // Ordering time from highest to lowest.
-            times.sort_by(|a, b| b.cmp(a));
+            times.sort_by(|a, b| b.0.cmp(&a.0));
             times
         };
 
-        // The largest number of Byzantine nodes.
-        let max_byzantine_nodes = (keys.len() - 1) / 3;
-        if validator_times.len() <= 2 * max_byzantine_nodes {
+        // The largest voting power of Byzantine nodes.
+        let total_weight: u64 = keys.iter().map(|validator| validator.weight).sum();
+        let max_byzantine_weight = (total_weight - 1) / 3;
+        let known_weight: u64 = validator_times.iter().map(|&(_, weight)| weight).sum();
+        if known_weight <= 2 * max_byzantine_weight {
             return;
         }
 
+        // Select the highest time which is confirmed by at least one honest validator.
+        let mut accumulated_weight = 0;
+        let selected_time = validator_times
+            .iter()
+            .find(|&&(_, weight)| {
+                accumulated_weight += weight;
+                accumulated_weight > max_byzantine_weight
+            })
+            .map(|&(time, _)| time)
+            .expect("Known voting power exceeds Byzantine one");
+
         match schema.time().get() {
             // Selected time should be greater than the time in the storage.
-            Some(current_time) if current_time >= validator_times[max_byzantine_nodes] => {
+            Some(current_time) if current_time >= selected_time => {
                 return;
             }
             _ => {
                 // Change the time in the storage.
-                schema.time_mut().set(validator_times[max_byzantine_nodes]);
+                schema.time_mut().set(selected_time);
             }
         }
     }
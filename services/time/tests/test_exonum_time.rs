@@ -296,6 +296,61 @@ fn test_exonum_time_service_with_7_validators() {
     }
 }
 
+#[test]
+fn test_exonum_time_service_with_weighted_validators() {
+    let mock_provider = MockTimeProvider::default();
+    let mut testkit = TestKitBuilder::validator()
+        .with_validators(4)
+        .with_service(TimeService::with_provider(mock_provider.clone()))
+        .create();
+
+    let cfg_change_height = Height(5);
+    let proposal = {
+        let mut cfg = testkit.configuration_change_proposal();
+        cfg.set_actual_from(cfg_change_height);
+        cfg.set_validator_weights(vec![3, 1, 1, 1]);
+        cfg
+    };
+    testkit.commit_configuration_change(proposal);
+    testkit.create_blocks_until(cfg_change_height);
+
+    let validators = testkit.network().validators().to_vec();
+
+    // Validators weights look like this:
+    // number | 0 | 1 | 2 | 3 |
+    // weight | 3 | 1 | 1 | 1 |
+    //
+    // max_byzantine_weight = (6 - 1) / 3 = 1.
+    //
+    // The time of the first validator alone has enough voting power to become
+    // the consolidated time.
+
+    mock_provider.set_time(Utc.timestamp(20, 0));
+    testkit.create_block();
+
+    assert_storage_times_eq(
+        testkit.snapshot(),
+        &validators,
+        Some(Utc.timestamp(20, 0)),
+        &[Some(Utc.timestamp(20, 0)), None, None, None],
+    );
+
+    // The time of a light validator is not confirmed by enough voting power.
+    let time1 = Utc.timestamp(30, 0);
+    let tx1 = {
+        let (pub_key, sec_key) = validators[1].service_keypair();
+        TxTime::new(time1, pub_key, sec_key)
+    };
+    testkit.create_block_with_transactions(txvec![tx1]);
+
+    assert_storage_times_eq(
+        testkit.snapshot(),
+        &validators,
+        Some(Utc.timestamp(20, 0)),
+        &[Some(Utc.timestamp(20, 0)), Some(time1), None, None],
+    );
+}
+
 #[test]
 fn test_mock_provider() {
     let mock_provider = MockTimeProvider::default();
@@ -339,6 +394,45 @@ fn test_mock_provider() {
     );
 }
 
+#[test]
+fn test_empty_blocks_suppression() {
+    let mock_provider = MockTimeProvider::default();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(TimeService::with_provider(mock_provider.clone()))
+        .create();
+
+    let cfg_change_height = Height(5);
+    let proposal = {
+        let mut cfg = testkit.configuration_change_proposal();
+        let mut consensus = cfg.consensus_configuration().clone();
+        consensus.max_empty_block_interval = Some(60_000);
+        cfg.set_consensus_configuration(consensus);
+        cfg.set_actual_from(cfg_change_height);
+        cfg
+    };
+    testkit.commit_configuration_change(proposal);
+    testkit.create_blocks_until(cfg_change_height);
+
+    // The block with a time transaction doesn't lead to a new time transaction.
+    mock_provider.add_time(Duration::seconds(10));
+    let block = testkit.create_block();
+    assert_eq!(block.len(), 1);
+    let block = testkit.create_block();
+    assert!(block.is_empty());
+
+    // The empty heartbeat block leads to a new time transaction.
+    let block = testkit.create_block();
+    assert_eq!(block.len(), 1);
+    assert_storage_times_eq(
+        testkit.snapshot(),
+        &testkit.network().validators().to_vec(),
+        Some(mock_provider.time()),
+        &[Some(mock_provider.time())],
+    );
+    let block = testkit.create_block();
+    assert!(block.is_empty());
+}
+
 #[test]
 fn test_selected_time_less_than_time_in_storage() {
     let mut testkit = TestKitBuilder::validator()
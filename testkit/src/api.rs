@@ -12,22 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bodyparser;
 use exonum::blockchain::{SharedNodeState, Transaction};
 use exonum::node::{create_private_api_handler, create_public_api_handler, ApiSender,
                    TransactionSend};
-use exonum::api::ApiError;
-use iron::{Chain, Handler, IronError, IronResult, Plugin, Request, Response};
-use iron::headers::{ContentType, Headers};
-use iron::status::{self, StatusClass};
-use iron_test::{request, response};
+use exonum::api::{ApiError, AsyncHandler, AsyncMount, HandlerFuture, HttpRequest, HttpResponse,
+                  IronAdapter};
+use futures::Future;
+use http::{self, Method, StatusCode};
+use iron::Handler;
 use log::Level;
-use mount::{Mount, OriginalUrl};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_json::Value as JsonValue;
 
 use std::fmt;
+use std::sync::Arc;
 
 use super::TestKit;
 
@@ -58,10 +57,17 @@ impl ApiKind {
 
 /// API encapsulation for the testkit. Allows to execute and synchronously retrieve results
 /// for REST-ful endpoints of services.
+///
+/// Requests are dispatched through the asynchronous handler interface of the node API,
+/// see [`public_api`] and [`private_api`].
+///
+/// [`public_api`]: #method.public_api
+/// [`private_api`]: #method.private_api
 pub struct TestKitApi {
-    public_handler: Chain,
-    private_handler: Chain,
+    public_handler: SharedHandler,
+    private_handler: SharedHandler,
     api_sender: ApiSender,
+    private_api_token: Option<String>,
 }
 
 impl fmt::Debug for TestKitApi {
@@ -78,58 +84,81 @@ impl TestKitApi {
 
         TestKitApi {
             public_handler: {
-                let mut handler = create_public_api_handler(
+                let handler = create_public_api_handler(
                     blockchain.clone(),
                     api_state.clone(),
                     &testkit.api_config,
                 );
-                handler.link_after(|req: &mut Request, resp| {
-                    log_request(&ApiAccess::Public, req, resp)
-                });
-                handler
+                SharedHandler::new(LoggedHandler::new(ApiAccess::Public, handler))
             },
 
             private_handler: {
-                let mut handler = create_private_api_handler(
+                let handler = create_private_api_handler(
                     blockchain.clone(),
                     api_state,
                     testkit.api_sender.clone(),
                     &testkit.api_config,
                 );
-                handler.link_after(|req: &mut Request, resp| {
-                    log_request(&ApiAccess::Private, req, resp)
-                });
-                handler
+                SharedHandler::new(LoggedHandler::new(ApiAccess::Private, handler))
             },
 
             api_sender: testkit.api_sender.clone(),
+
+            private_api_token: testkit
+                .api_config
+                .private_api_auth
+                .as_ref()
+                .and_then(|auth| auth.bearer_tokens.first().cloned()),
         }
     }
 
+    /// Sets the bearer token sent with the requests to the private API.
+    pub fn with_token<S: Into<String>>(mut self, token: S) -> Self {
+        self.private_api_token = Some(token.into());
+        self
+    }
+
+    /// Disables sending a bearer token with the requests to the private API.
+    pub fn without_token(mut self) -> Self {
+        self.private_api_token = None;
+        self
+    }
+
     /// Returns the mounting point for public APIs. Useful for intricate testing not covered
     /// by `get*` and `post*` functions.
-    pub fn public_handler(&self) -> &Chain {
-        &self.public_handler
+    pub fn public_handler(&self) -> &AsyncHandler {
+        &**self.public_handler.0
     }
 
     /// Returns the mounting point for private APIs. Useful for intricate testing not covered
     /// by `get*` and `post*` functions.
-    pub fn private_handler(&self) -> &Chain {
-        &self.private_handler
+    pub fn private_handler(&self) -> &AsyncHandler {
+        &**self.private_handler.0
     }
 
-    pub(crate) fn into_handlers<H: Handler>(self, testkit_handler: H) -> (Chain, Chain) {
-        let (public_handler, private_handler) = (self.public_handler, self.private_handler);
+    /// Returns the public APIs as an asynchronous handler. Useful for intricate testing
+    /// not covered by `get*` and `post*` functions.
+    pub fn public_api(&self) -> Box<AsyncHandler> {
+        Box::new(self.public_handler.clone())
+    }
 
-        let mut testkit_handler = Chain::new(testkit_handler);
-        testkit_handler
-            .link_after(|req: &mut Request, resp| log_request(&ApiAccess::Private, req, resp));
+    /// Returns the private APIs as an asynchronous handler. Useful for intricate testing
+    /// not covered by `get*` and `post*` functions.
+    pub fn private_api(&self) -> Box<AsyncHandler> {
+        Box::new(self.private_handler.clone())
+    }
 
-        let mut private_mount = Mount::new();
+    pub(crate) fn into_handlers<H: Handler>(
+        self,
+        testkit_handler: H,
+    ) -> (Box<AsyncHandler>, Box<AsyncHandler>) {
+        let testkit_handler =
+            LoggedHandler::new(ApiAccess::Private, IronAdapter::new(testkit_handler));
+        let mut private_mount = AsyncMount::new();
         private_mount.mount("api/testkit", testkit_handler);
-        private_mount.mount("", private_handler);
+        private_mount.mount("", self.private_handler);
 
-        (public_handler, Chain::new(private_mount))
+        (Box::new(self.public_handler), Box::new(private_mount))
     }
 
     /// Sends a transaction to the node via `ApiSender`.
@@ -142,40 +171,68 @@ impl TestKitApi {
             .expect("Cannot send transaction");
     }
 
-    fn get_internal<H, D>(handler: &H, endpoint: &str, expect_error: bool) -> D
+    fn private_token(&self) -> Option<&str> {
+        self.private_api_token.as_ref().map(String::as_str)
+    }
+
+    fn request(
+        handler: &AsyncHandler,
+        method: &str,
+        endpoint: &str,
+        token: Option<&str>,
+        body: Option<String>,
+    ) -> HttpResponse {
+        let mut builder = http::Request::builder();
+        builder.method(method).uri(format!("/{}", endpoint).as_str());
+        if let Some(token) = token {
+            builder.header("Authorization", format!("Bearer {}", token).as_str());
+        }
+        if body.is_some() {
+            builder.header("Content-Type", "application/json");
+        }
+        let request = builder
+            .body(body.map(String::into_bytes).unwrap_or_default())
+            .expect("Cannot build request");
+        handler
+            .call(request)
+            .wait()
+            .unwrap_or_else(HttpResponse::from)
+    }
+
+    fn post_request<T>(
+        handler: &AsyncHandler,
+        endpoint: &str,
+        token: Option<&str>,
+        data: &T,
+    ) -> HttpResponse
     where
-        H: Handler,
-        for<'de> D: Deserialize<'de>,
+        T: Serialize,
     {
-        let status_class = if expect_error {
-            StatusClass::ClientError
-        } else {
-            StatusClass::Success
-        };
+        let body = serde_json::to_string(&data).expect("Cannot serialize data to JSON");
+        TestKitApi::request(handler, "POST", endpoint, token, Some(body))
+    }
 
-        let url = format!("http://localhost:3000/{}", endpoint);
-        let resp = request::get(&url, Headers::new(), handler);
-        let resp = if expect_error {
-            // Support either "normal" or erroneous responses.
-            // For example, `Api.not_found_response()` returns the response as `Ok(..)`.
-            match resp {
-                Ok(resp) => resp,
-                Err(IronError { response, .. }) => response,
-            }
+    fn get_internal<D>(
+        handler: &AsyncHandler,
+        endpoint: &str,
+        token: Option<&str>,
+        expect_error: bool,
+    ) -> D
+    where
+        for<'de> D: Deserialize<'de>,
+    {
+        let response = TestKitApi::request(handler, "GET", endpoint, token, None);
+        let status = response.status();
+        let status_matches = if expect_error {
+            status.is_client_error()
         } else {
-            resp.expect("Got unexpected `Err(..)` response")
+            status.is_success()
         };
-
-        if let Some(ref status) = resp.status {
-            if status.class() != status_class {
-                panic!("Unexpected response status: {:?}", status);
-            }
-        } else {
-            panic!("Response status not set");
+        if !status_matches {
+            panic!("Unexpected response status: {}", status);
         }
 
-        let resp = response::extract_body_to_string(resp);
-        serde_json::from_str(&resp).unwrap()
+        serde_json::from_slice(response.body()).unwrap()
     }
 
     /// Gets information from a public endpoint of the node.
@@ -189,8 +246,9 @@ impl TestKitApi {
         for<'de> D: Deserialize<'de>,
     {
         TestKitApi::get_internal(
-            &self.public_handler,
+            &*self.public_api(),
             &format!("{}/{}", kind.into_prefix(), endpoint),
+            None,
             false,
         )
     }
@@ -206,8 +264,9 @@ impl TestKitApi {
         for<'de> D: Deserialize<'de>,
     {
         TestKitApi::get_internal(
-            &self.private_handler,
+            &*self.private_api(),
             &format!("{}/{}", kind.into_prefix(), endpoint),
+            self.private_token(),
             false,
         )
     }
@@ -218,34 +277,44 @@ impl TestKitApi {
     ///
     /// - Panics if the response has a non-error response status.
     pub fn get_err(&self, kind: ApiKind, endpoint: &str) -> ApiError {
-        let url = format!("http://localhost:3000/{}/{}", kind.into_prefix(), endpoint);
-        let response = match request::get(&url, Headers::new(), &self.public_handler) {
-            Ok(response) | Err(IronError { response, .. }) => response,
-        };
+        let response = TestKitApi::request(
+            &*self.public_api(),
+            "GET",
+            &format!("{}/{}", kind.into_prefix(), endpoint),
+            None,
+            None,
+        );
         TestKitApi::response_to_api_error(response)
     }
 
-    fn post_internal<H, T, D>(handler: &H, endpoint: &str, data: &T) -> D
+    /// Gets an error from a private endpoint of the node.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the response has a non-error response status.
+    pub fn get_private_err(&self, kind: ApiKind, endpoint: &str) -> ApiError {
+        let response = TestKitApi::request(
+            &*self.private_api(),
+            "GET",
+            &format!("{}/{}", kind.into_prefix(), endpoint),
+            self.private_token(),
+            None,
+        );
+        TestKitApi::response_to_api_error(response)
+    }
+
+    fn post_internal<T, D>(
+        handler: &AsyncHandler,
+        endpoint: &str,
+        token: Option<&str>,
+        data: &T,
+    ) -> D
     where
-        H: Handler,
         T: Serialize,
         for<'de> D: Deserialize<'de>,
     {
-        let url = format!("http://localhost:3000/{}", endpoint);
-        let body = serde_json::to_string(&data).expect("Cannot serialize data to JSON");
-        let resp = request::post(
-            &url,
-            {
-                let mut headers = Headers::new();
-                headers.set(ContentType::json());
-                headers
-            },
-            &body,
-            handler,
-        ).expect("Cannot send data");
-
-        let resp = response::extract_body_to_string(resp);
-        serde_json::from_str(&resp).expect("Cannot parse result")
+        let response = TestKitApi::post_request(handler, endpoint, token, data);
+        serde_json::from_slice(response.body()).expect("Cannot parse result")
     }
 
     /// Posts a transaction to the service using the public API. The returned value is the result
@@ -263,8 +332,9 @@ impl TestKitApi {
         for<'de> D: Deserialize<'de>,
     {
         TestKitApi::post_internal(
-            &self.public_handler,
+            &*self.public_api(),
             &format!("{}/{}", kind.into_prefix(), endpoint),
+            None,
             transaction,
         )
     }
@@ -284,18 +354,55 @@ impl TestKitApi {
         for<'de> D: Deserialize<'de>,
     {
         TestKitApi::post_internal(
-            &self.private_handler,
+            &*self.private_api(),
             &format!("{}/{}", kind.into_prefix(), endpoint),
+            self.private_token(),
             transaction,
         )
     }
 
-    /// Converts iron Response to ApiError.
+    /// Gets an error from a public endpoint of the node after posting the data to it.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the response has a non-error response status.
+    pub fn post_err<T>(&self, kind: ApiKind, endpoint: &str, data: &T) -> ApiError
+    where
+        T: Serialize,
+    {
+        let response = TestKitApi::post_request(
+            &*self.public_api(),
+            &format!("{}/{}", kind.into_prefix(), endpoint),
+            None,
+            data,
+        );
+        TestKitApi::response_to_api_error(response)
+    }
+
+    /// Gets an error from a private endpoint of the node after posting the data to it.
     ///
     /// # Panics
     ///
     /// - Panics if the response has a non-error response status.
-    fn response_to_api_error(response: Response) -> ApiError {
+    pub fn post_private_err<T>(&self, kind: ApiKind, endpoint: &str, data: &T) -> ApiError
+    where
+        T: Serialize,
+    {
+        let response = TestKitApi::post_request(
+            &*self.private_api(),
+            &format!("{}/{}", kind.into_prefix(), endpoint),
+            self.private_token(),
+            data,
+        );
+        TestKitApi::response_to_api_error(response)
+    }
+
+    /// Converts the HTTP response to ApiError.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the response has a non-error response status.
+    fn response_to_api_error(response: HttpResponse) -> ApiError {
         fn extract_description(body: &str) -> Option<String> {
             match serde_json::from_str::<JsonValue>(body).ok()? {
                 JsonValue::Object(ref object) if object.contains_key("description") => {
@@ -306,24 +413,45 @@ impl TestKitApi {
             }
         }
 
-        fn error(response: Response) -> String {
-            let body = response::extract_body_to_string(response);
-            extract_description(&body).unwrap_or(body)
+        let status = response.status();
+        let error = || {
+            let body = String::from_utf8_lossy(response.body());
+            extract_description(&body).unwrap_or_else(|| body.into_owned())
+        };
+
+        if status == StatusCode::FORBIDDEN {
+            ApiError::Unauthorized
+        } else if status == StatusCode::BAD_REQUEST {
+            ApiError::BadRequest(error())
+        } else if status == StatusCode::NOT_FOUND {
+            ApiError::NotFound(error())
+        } else if status == StatusCode::SERVICE_UNAVAILABLE {
+            ApiError::ServiceUnavailable(error())
+        } else if status.is_server_error() {
+            ApiError::InternalError(error().into())
+        } else {
+            panic!("Received non-error response status: {}", status.as_u16())
         }
+    }
+}
 
-        let status = response.status.expect("Status header is not set");
+// Handler shared between the testkit API and the handlers returned by it.
+#[derive(Clone)]
+struct SharedHandler(Arc<Box<AsyncHandler>>);
 
-        match status {
-            status::Forbidden => ApiError::Unauthorized,
-            status::BadRequest => ApiError::BadRequest(error(response)),
-            status::NotFound => ApiError::NotFound(error(response)),
-            s if s.is_server_error() => ApiError::InternalError(error(response).into()),
-            s => panic!("Received non-error response status: {}", s.to_u16()),
-        }
+impl SharedHandler {
+    fn new<H: AsyncHandler>(handler: H) -> SharedHandler {
+        SharedHandler(Arc::new(Box::new(handler)))
     }
 }
 
-#[derive(Debug)]
+impl AsyncHandler for SharedHandler {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        self.0.call(request)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 enum ApiAccess {
     Public,
     Private,
@@ -338,93 +466,83 @@ impl fmt::Display for ApiAccess {
     }
 }
 
-// Logging middleware for `TestKitApi`.
-fn log_request(
-    access: &ApiAccess,
-    request: &mut Request,
-    mut response: Response,
-) -> IronResult<Response> {
-    use iron::headers::ContentLength;
-    use iron::method::Method;
-    use iron::response::WriteBody;
-
-    fn has_body(method: &Method) -> bool {
-        match *method {
-            Method::Get | Method::Head | Method::Delete | Method::Trace => false,
-            _ => true,
-        }
+// Logging wrapper for the `TestKitApi` handlers.
+struct LoggedHandler<H> {
+    access: ApiAccess,
+    handler: H,
+}
+
+impl<H: AsyncHandler> LoggedHandler<H> {
+    fn new(access: ApiAccess, handler: H) -> Self {
+        LoggedHandler { access, handler }
     }
+}
 
-    if !log_enabled!(Level::Trace) {
-        // Avoid expensive string allocations.
-        return Ok(response);
-    }
-
-    let mut url = request
-        .extensions
-        .get::<OriginalUrl>()
-        .unwrap_or(&request.url)
-        .path()
-        .join("/");
-    if let Some(query) = request.url.query() {
-        url += "?";
-        url += query;
-    }
-
-    let req_body: Option<String> = if has_body(&request.method) {
-        request
-            .get::<bodyparser::Raw>()
-            .expect("cannot read request body")
-    } else {
-        None
-    };
-
-    let response_body: Option<String> = {
-        let len: u64 = response
-            .headers
-            .get::<ContentLength>()
-            .map(|len| len.0)
-            .unwrap_or_default();
-
-        response.body.take().map(|mut body| {
-            let mut buffer = Vec::with_capacity(len as usize);
-            body.write_body(&mut buffer)
-                .expect("cannot write response body to buffer");
-            String::from_utf8(buffer).expect("cannot decode response body")
-        })
-    };
-
-    trace!(
-        "{method} ({access}) /{url}{req_body_tag}{req_body}\n\
-         Response: {resp_status}\n{resp_body}\n",
-        method = request.method,
-        access = access,
-        url = url,
-        req_body_tag = if req_body.is_some() { "\nBody: " } else { "" },
-        req_body = req_body.unwrap_or_default(),
-        resp_status = response
-            .status
-            .as_ref()
-            .map(ToString::to_string)
-            .unwrap_or_else(|| "(no status)".to_string()),
-        resp_body = response_body
-            .as_ref()
-            .map(String::as_ref)
-            .unwrap_or("(no body)")
-    );
-
-    // Return the body to the response
-    response.body = response_body.map(|body| Box::new(body) as Box<WriteBody>);
-    Ok(response)
+impl<H: AsyncHandler> AsyncHandler for LoggedHandler<H> {
+    fn call(&self, request: HttpRequest) -> HandlerFuture {
+        fn has_body(method: &Method) -> bool {
+            match *method {
+                Method::GET | Method::HEAD | Method::DELETE | Method::TRACE => false,
+                _ => true,
+            }
+        }
+
+        if !log_enabled!(Level::Trace) {
+            // Avoid expensive string allocations.
+            return self.handler.call(request);
+        }
+
+        let method = request.method().clone();
+        let url = request
+            .uri()
+            .path_and_query()
+            .map_or("", |path| path.as_str())
+            .trim_left_matches('/')
+            .to_owned();
+        let req_body = if has_body(&method) {
+            Some(String::from_utf8_lossy(request.body()).into_owned())
+        } else {
+            None
+        };
+        let access = self.access;
+
+        let response = self.handler.call(request).then(move |result| -> Result<_, ApiError> {
+            let response = result.unwrap_or_else(HttpResponse::from);
+            trace!(
+                "{method} ({access}) /{url}{req_body_tag}{req_body}\n\
+                 Response: {resp_status}\n{resp_body}\n",
+                method = method,
+                access = access,
+                url = url,
+                req_body_tag = if req_body.is_some() { "\nBody: " } else { "" },
+                req_body = req_body.unwrap_or_default(),
+                resp_status = response.status(),
+                resp_body = if response.body().is_empty() {
+                    "(no body)".into()
+                } else {
+                    String::from_utf8_lossy(response.body())
+                }
+            );
+            Ok(response)
+        });
+        Box::new(response)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn response(status: u16, body: &str) -> HttpResponse {
+        http::Response::builder()
+            .status(status)
+            .body(body.as_bytes().to_vec())
+            .unwrap()
+    }
+
     #[test]
     fn test_get_err_non_json() {
-        let response = Response::with((status::NotFound, "Not found"));
+        let response = response(404, "Not found");
         assert_matches!(
             TestKitApi::response_to_api_error(response),
             ApiError::NotFound(ref body) if body == "Not found"
@@ -433,7 +551,7 @@ mod tests {
 
     #[test]
     fn test_get_err_json_string() {
-        let response = Response::with((status::NotFound, "\"Wallet not found\""));
+        let response = response(404, "\"Wallet not found\"");
         assert_matches!(
             TestKitApi::response_to_api_error(response),
             ApiError::NotFound(ref body) if body == "Wallet not found"
@@ -443,7 +561,7 @@ mod tests {
     #[test]
     fn test_get_err_json_object_with_description() {
         let response_body = r#"{ "debug": "Some debug info", "description": "Some description" }"#;
-        let response = Response::with((status::BadRequest, response_body));
+        let response = response(400, response_body);
         assert_matches!(
             TestKitApi::response_to_api_error(response),
             ApiError::BadRequest(ref body) if body == "Some description"
@@ -453,7 +571,7 @@ mod tests {
     #[test]
     fn test_get_err_json_object_without_description() {
         let response_body = r#"{ "type": "unknown" }"#;
-        let response = Response::with((status::BadRequest, response_body));
+        let response = response(400, response_body);
         assert_matches!(
             TestKitApi::response_to_api_error(response),
             ApiError::BadRequest(ref body) if body == response_body
@@ -463,7 +581,7 @@ mod tests {
     #[test]
     fn test_get_err_other_json() {
         let response_body = r#"[1, 2, 3]"#;
-        let response = Response::with((status::BadRequest, response_body));
+        let response = response(400, response_body);
         assert_matches!(
             TestKitApi::response_to_api_error(response),
             ApiError::BadRequest(ref body) if body == response_body
@@ -473,7 +591,7 @@ mod tests {
     #[test]
     #[should_panic(expected = "Received non-error response status")]
     fn test_get_err_non_error_status() {
-        let response = Response::with(status::Ok);
+        let response = response(200, "");
         TestKitApi::response_to_api_error(response);
     }
 }
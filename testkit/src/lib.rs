@@ -128,11 +128,12 @@ extern crate bodyparser;
 #[cfg_attr(test, macro_use)]
 extern crate exonum;
 extern crate futures;
+extern crate http;
 extern crate iron;
+#[cfg(test)]
 extern crate iron_test;
 #[macro_use]
 extern crate log;
-extern crate mount;
 extern crate router;
 extern crate serde;
 #[macro_use]
@@ -153,7 +154,6 @@ pub mod compare;
 
 use futures::{Future, Stream};
 use futures::sync::mpsc;
-use iron::Iron;
 use tokio_core::reactor::Core;
 
 use std::fmt;
@@ -161,6 +161,7 @@ use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 use std::thread;
 
+use exonum::api::{ApiAuthConfig, HttpServer};
 use exonum::blockchain::{Blockchain, Schema as CoreSchema, Service, StoredConfiguration,
                          Transaction};
 use exonum::crypto::{self, Hash};
@@ -269,6 +270,7 @@ pub struct TestKitBuilder {
     validator_count: Option<u16>,
     services: Vec<Box<Service>>,
     logger: bool,
+    private_api_auth: Option<ApiAuthConfig>,
 }
 
 impl fmt::Debug for TestKitBuilder {
@@ -300,6 +302,7 @@ impl TestKitBuilder {
             our_validator_id: Some(ValidatorId(0)),
             services: Vec::new(),
             logger: false,
+            private_api_auth: None,
         }
     }
 
@@ -310,6 +313,7 @@ impl TestKitBuilder {
             our_validator_id: None,
             services: Vec::new(),
             logger: false,
+            private_api_auth: None,
         }
     }
 
@@ -338,16 +342,26 @@ impl TestKitBuilder {
         self
     }
 
+    /// Enables authentication of the private API requests. Private calls of `TestKitApi`
+    /// send the first of the configured tokens unless it is changed with
+    /// [`TestKitApi::with_token`](struct.TestKitApi.html#method.with_token).
+    pub fn with_private_api_auth(mut self, auth: ApiAuthConfig) -> Self {
+        self.private_api_auth = Some(auth);
+        self
+    }
+
     /// Creates the testkit.
     pub fn create(self) -> TestKit {
         if self.logger {
             exonum::helpers::init_logger().ok();
         }
         crypto::init();
-        TestKit::assemble(
+        let mut testkit = TestKit::assemble(
             self.services,
             TestNetwork::with_our_role(self.our_validator_id, self.validator_count.unwrap_or(1)),
-        )
+        );
+        testkit.api_config.private_api_auth = self.private_api_auth;
+        testkit
     }
 
     /// Starts a testkit web server, which listens to public and private APIs exposed by
@@ -425,6 +439,11 @@ impl TestKit {
                                 schema.add_transaction_into_pool(tx.raw().clone());
                             }
                         }
+                        ExternalMessage::RemoveTransaction(hash) => {
+                            if schema.transactions_pool().contains(&hash) {
+                                schema.reject_transaction(&hash).unwrap();
+                            }
+                        }
                         ExternalMessage::PeerAdd(_)
                         | ExternalMessage::Enable(_)
                         | ExternalMessage::Shutdown => { /* Ignored */ }
@@ -943,13 +962,16 @@ impl TestKit {
         let (public_handler, private_handler) =
             api.into_handlers(create_testkit_handler(&testkit_ref));
 
-        let public_api_thread = thread::spawn(move || {
-            Iron::new(public_handler).http(public_api_address).unwrap();
-        });
-        let private_api_thread = thread::spawn(move || {
-            Iron::new(private_handler)
-                .http(private_api_address)
+        let api_thread = thread::spawn(move || {
+            let mut core = Core::new().unwrap();
+            let handle = core.handle();
+            let public_api = HttpServer::bind(&public_api_address, public_handler)
+                .and_then(|server| server.run(&handle))
+                .unwrap();
+            let private_api = HttpServer::bind(&private_api_address, private_handler)
+                .and_then(|server| server.run(&handle))
                 .unwrap();
+            core.run(public_api.join(private_api)).unwrap();
         });
 
         // Run the event stream in a separate thread in order to put transactions to mempool
@@ -958,8 +980,7 @@ impl TestKit {
         let mut core = Core::new().unwrap();
         core.run(events_stream).unwrap();
 
-        public_api_thread.join().unwrap();
-        private_api_thread.join().unwrap();
+        api_thread.join().unwrap();
     }
 
     /// Extracts the event stream from this testkit, replacing it with `futures::stream::empty()`.
@@ -200,6 +200,7 @@ impl TestNode {
         ValidatorKeys {
             consensus_key: self.consensus_public_key,
             service_key: self.service_public_key,
+            weight: ValidatorKeys::DEFAULT_WEIGHT,
         }
     }
 
@@ -279,9 +280,9 @@ impl TestNetworkConfiguration {
         self.stored_configuration.actual_from = actual_from;
     }
 
-    /// Modifies number of votes required to accept a new consensus configuration
+    /// Modifies the voting power required to accept a new consensus configuration
     /// (see majority_count field of the StoredConfiguration documentation).
-    pub fn set_majority_count(&mut self, majority_count: Option<u16>) {
+    pub fn set_majority_count(&mut self, majority_count: Option<u64>) {
         self.stored_configuration.majority_count = majority_count;
     }
 
@@ -311,6 +312,22 @@ impl TestNetworkConfiguration {
         self.update_our_role();
     }
 
+    /// Modifies voting power of the validators in the order they are listed.
+    ///
+    /// Note that `set_validators` resets weights to the default value.
+    pub fn set_validator_weights<I>(&mut self, weights: I)
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        for (keys, weight) in self.stored_configuration
+            .validator_keys
+            .iter_mut()
+            .zip(weights)
+        {
+            keys.weight = weight;
+        }
+    }
+
     /// Returns the configuration for service with the given identifier.
     pub fn service_config<D>(&self, id: &str) -> D
     where
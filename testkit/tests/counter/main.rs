@@ -18,6 +18,8 @@ extern crate assert_matches;
 extern crate exonum;
 #[macro_use]
 extern crate exonum_testkit;
+extern crate futures;
+extern crate http;
 #[macro_use]
 extern crate log;
 #[macro_use]
@@ -488,6 +490,202 @@ fn test_explorer_blocks() {
     assert_eq!(range.end, Height(5));
 }
 
+#[test]
+fn test_explorer_transactions() {
+    use exonum::api::public::TransactionsRange;
+
+    let (mut testkit, api) = init_testkit();
+    let (pubkey, key) = crypto::gen_keypair();
+    let tx_ok = TxIncrement::new(&pubkey, 5, &key);
+    let tx_failed = TxIncrement::new(&pubkey, 0, &key);
+    testkit.create_block_with_transactions(txvec![tx_ok.clone()]); // height == 1
+    testkit.create_block(); // height == 2
+    testkit.create_block_with_transactions(txvec![tx_failed.clone()]); // height == 3
+
+    let response: TransactionsRange<TxIncrement> =
+        api.get(ApiKind::Explorer, "v1/transactions?count=10");
+    let hashes: Vec<_> = response
+        .transactions
+        .iter()
+        .map(|tx| tx.content().hash())
+        .collect();
+    assert_eq!(hashes, vec![tx_failed.hash(), tx_ok.hash()]);
+    assert_eq!(response.range, Height(0)..Height(4));
+
+    // Check filtering by the execution status.
+    let response: TransactionsRange<TxIncrement> =
+        api.get(ApiKind::Explorer, "v1/transactions?count=10&status=failure");
+    assert_eq!(response.transactions.len(), 1);
+    assert_eq!(response.transactions[0].content().hash(), tx_failed.hash());
+    assert_eq!(
+        response.transactions[0].location().block_height(),
+        Height(3)
+    );
+    let response: TransactionsRange<TxIncrement> =
+        api.get(ApiKind::Explorer, "v1/transactions?count=10&status=success");
+    assert_eq!(response.transactions.len(), 1);
+    assert_eq!(response.transactions[0].content().hash(), tx_ok.hash());
+
+    // Check filtering by the service and the message type.
+    let response: TransactionsRange<TxIncrement> = api.get(
+        ApiKind::Explorer,
+        "v1/transactions?count=10&service_id=1&message_type=0",
+    );
+    assert_eq!(response.transactions.len(), 2);
+    let response: TransactionsRange<TxIncrement> =
+        api.get(ApiKind::Explorer, "v1/transactions?count=10&message_type=1");
+    assert!(response.transactions.is_empty());
+
+    // Check pagination and the height range.
+    let response: TransactionsRange<TxIncrement> =
+        api.get(ApiKind::Explorer, "v1/transactions?count=1");
+    assert_eq!(response.transactions.len(), 1);
+    assert_eq!(response.range, Height(3)..Height(4));
+    let response: TransactionsRange<TxIncrement> =
+        api.get(ApiKind::Explorer, "v1/transactions?count=1&latest=2");
+    assert_eq!(response.transactions[0].content().hash(), tx_ok.hash());
+    assert_eq!(response.range, Height(1)..Height(3));
+    let response: TransactionsRange<TxIncrement> = api.get(
+        ApiKind::Explorer,
+        "v1/transactions?count=10&earliest=2&latest=2",
+    );
+    assert!(response.transactions.is_empty());
+    assert_eq!(response.range, Height(2)..Height(3));
+
+    let error = api.get_err(ApiKind::Explorer, "v1/transactions?count=10&status=unknown");
+    assert_matches!(error, ApiError::BadRequest(_));
+    let error = api.get_err(ApiKind::Explorer, "v1/transactions?count=10000");
+    assert_matches!(error, ApiError::BadRequest(_));
+}
+
+#[test]
+fn test_explorer_submit_transaction() {
+    use exonum::api::AsyncHandler;
+    use exonum::api::public::{SubmittedTransaction, TransactionSubmission};
+    use exonum::encoding::serialize::ToHex;
+    use futures::Future;
+
+    fn submission<T: Message>(tx: &T, wait_for_commit: Option<u64>) -> TransactionSubmission {
+        TransactionSubmission {
+            tx_body: tx.raw().as_ref().to_hex(),
+            wait_for_commit,
+        }
+    }
+
+    let (mut testkit, api) = init_testkit();
+    let (pubkey, key) = crypto::gen_keypair();
+    let tx = TxIncrement::new(&pubkey, 5, &key);
+
+    let response: SubmittedTransaction<TxIncrement> =
+        api.post(ApiKind::Explorer, "v1/transactions", &submission(&tx, None));
+    assert_eq!(response.tx_hash, tx.hash());
+    assert!(response.committed.is_none());
+    testkit.create_block();
+    assert_eq!(CounterSchema::new(&testkit.snapshot()).count(), Some(5));
+
+    // The transaction is not committed while waiting.
+    let other_tx = TxIncrement::new(&pubkey, 3, &key);
+    let response: SubmittedTransaction<TxIncrement> =
+        api.post(ApiKind::Explorer, "v1/transactions", &submission(&other_tx, Some(10)));
+    assert_eq!(response.tx_hash, other_tx.hash());
+    assert!(response.committed.is_none());
+
+    // The transaction committed while waiting is returned in the response.
+    let committed_tx = TxIncrement::new(&pubkey, 2, &key);
+    let body = serde_json::to_vec(&submission(&committed_tx, Some(60_000))).unwrap();
+    let request = http::Request::post("/api/explorer/v1/transactions")
+        .body(body)
+        .unwrap();
+    let response = api.public_handler().call(request);
+    testkit.create_block();
+    let response = response.wait().unwrap();
+    let response: SubmittedTransaction<TxIncrement> =
+        serde_json::from_slice(response.body()).unwrap();
+    let committed = response.committed.expect("Transaction is not committed");
+    assert_eq!(committed.content().hash(), committed_tx.hash());
+    assert_eq!(committed.location().block_height(), Height(2));
+    assert_eq!(CounterSchema::new(&testkit.snapshot()).count(), Some(10));
+
+    let error = api.post_err(ApiKind::Explorer, "v1/transactions", &submission(&tx, None));
+    assert_eq!(
+        error.to_string(),
+        format!("Bad request: Transaction {} is already known", tx.hash())
+    );
+
+    let reset = TxReset::new(&pubkey, &key);
+    let error = api.post_err(ApiKind::Explorer, "v1/transactions", &submission(&reset, None));
+    assert_eq!(error.to_string(), "Bad request: Unable to verify transaction");
+
+    let malformed = TransactionSubmission {
+        tx_body: "deadbeef".to_owned(),
+        wait_for_commit: None,
+    };
+    let error = api.post_err(ApiKind::Explorer, "v1/transactions", &malformed);
+    assert_matches!(error, ApiError::BadRequest(_));
+}
+
+#[test]
+fn test_mempool_transactions() {
+    use exonum::api::public::{MemPoolTransaction, MemPoolTransactions};
+
+    let (mut testkit, api) = init_testkit();
+    let (pubkey, key) = crypto::gen_keypair();
+    let mut txs = vec![
+        TxIncrement::new(&pubkey, 5, &key),
+        TxIncrement::new(&pubkey, 3, &key),
+    ];
+    txs.sort_by_key(|tx| tx.hash());
+    for tx in &txs {
+        api.send(tx.clone());
+    }
+    testkit.poll_events();
+
+    let response: MemPoolTransactions =
+        api.get(ApiKind::System, "v1/mempool/transactions?count=1");
+    assert_eq!(response.transactions.len(), 1);
+    assert_eq!(response.transactions[0].tx_hash, txs[0].hash());
+    assert_eq!(response.transactions[0].content, txs[0].serialize_field().unwrap());
+    assert_eq!(response.next, Some(txs[1].hash()));
+
+    let response: MemPoolTransactions = api.get(
+        ApiKind::System,
+        &format!("v1/mempool/transactions?count=10&from={}", txs[1].hash()),
+    );
+    assert_eq!(response.transactions.len(), 1);
+    assert_eq!(response.transactions[0].tx_hash, txs[1].hash());
+    assert_eq!(response.next, None);
+
+    let endpoint = format!("v1/mempool/transaction?hash={}", txs[1].hash());
+    let info: MemPoolTransaction = api.get(ApiKind::System, &endpoint);
+    assert_eq!(info.tx_hash, txs[1].hash());
+
+    let _: String = api.post_private(
+        ApiKind::System,
+        "v1/mempool/remove",
+        &json!({ "tx_hash": txs[1].hash() }),
+    );
+    testkit.poll_events();
+    assert!(!testkit.is_tx_in_pool(&txs[1].hash()));
+    assert_matches!(
+        api.get_err(ApiKind::System, &endpoint),
+        ApiError::NotFound(_)
+    );
+    assert_matches!(
+        api.post_private_err(
+            ApiKind::System,
+            "v1/mempool/remove",
+            &json!({ "tx_hash": txs[1].hash() }),
+        ),
+        ApiError::NotFound(_)
+    );
+
+    testkit.create_block();
+    assert_eq!(
+        CounterSchema::new(&testkit.snapshot()).count(),
+        Some(txs[0].by())
+    );
+}
+
 #[test]
 fn test_explorer_single_block() {
     use std::collections::HashSet;
@@ -15,8 +15,9 @@
 #[macro_use]
 extern crate exonum;
 extern crate exonum_testkit;
+extern crate futures;
+extern crate http;
 extern crate iron;
-extern crate iron_test;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -227,17 +228,17 @@ fn test_post_transaction() {
 
 #[test]
 fn test_custom_request() {
-    use iron::headers::Headers;
-    use iron_test::request;
+    use futures::Future;
 
     init_log();
     let (mut testkit, api) = init_testkit();
 
-    request::get(
-        "http://localhost:3000/api/explorer/v1/blocks/0",
-        Headers::new(),
-        api.public_handler(),
-    ).unwrap();
+    let request = http::Request::builder()
+        .method("GET")
+        .uri("/api/explorer/v1/blocks/0")
+        .body(Vec::new())
+        .unwrap();
+    api.public_handler().call(request).wait().unwrap();
     testkit.poll_events();
 
     let s = LOG.pop_message().expect("no message received");
@@ -14,10 +14,17 @@
 
 extern crate exonum;
 extern crate exonum_testkit;
+extern crate futures;
+extern crate http;
 #[macro_use]
 extern crate pretty_assertions;
+extern crate serde_json;
+
+use futures::Future;
+use serde_json::Value;
 
 use exonum_testkit::{ApiKind, TestKitBuilder};
+use exonum::api::{ApiAuthConfig, ApiError, AsyncHandler};
 use exonum::api::public::HealthCheckInfo;
 use exonum::helpers::user_agent;
 
@@ -40,3 +47,79 @@ fn test_user_agent_info() {
     let expected = user_agent::get();
     assert_eq!(info, expected);
 }
+
+#[test]
+fn test_openapi_document() {
+    let testkit = TestKitBuilder::validator().with_validators(2).create();
+    let api = testkit.api();
+    let request = http::Request::get("/api/openapi.json")
+        .body(Vec::new())
+        .unwrap();
+    let response = api.public_api().call(request).wait().unwrap();
+    let document: Value = serde_json::from_slice(response.body()).unwrap();
+
+    assert_eq!(document["openapi"], "3.0.0");
+    let healthcheck = &document["paths"]["/api/system/v1/healthcheck"]["get"];
+    assert_eq!(
+        healthcheck["responses"]["200"]["content"]["application/json"]["schema"]["type"],
+        "object"
+    );
+
+    let transactions = &document["paths"]["/api/explorer/v1/transactions"];
+    assert!(transactions["get"]["parameters"].is_array());
+    assert!(transactions["post"]["requestBody"].is_object());
+    let block = &document["paths"]["/api/explorer/v1/blocks/{height}"]["get"];
+    assert_eq!(block["parameters"][0]["name"], "height");
+    assert_eq!(block["parameters"][0]["in"], "path");
+    assert_eq!(block["parameters"][0]["required"], true);
+}
+
+#[test]
+fn test_metrics() {
+    let testkit = TestKitBuilder::validator().with_validators(2).create();
+    let api = testkit.api();
+    testkit
+        .blockchain()
+        .metrics()
+        .counter("testkit.metrics_requests", &[("kind", "private")])
+        .inc();
+
+    let request = http::Request::get("/metrics").body(Vec::new()).unwrap();
+    let response = api.private_api().call(request).wait().unwrap();
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+    let text = String::from_utf8(response.into_body()).unwrap();
+    assert!(text.contains("# TYPE exonum_testkit_metrics_requests_total counter\n"));
+    assert!(text.contains("exonum_testkit_metrics_requests_total{kind=\"private\"} 1\n"));
+}
+
+#[test]
+fn test_private_api_auth() {
+    fn assert_unauthorized(error: ApiError) {
+        match error {
+            ApiError::Unauthorized => {}
+            e => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    let auth = ApiAuthConfig {
+        bearer_tokens: vec!["token".to_owned()],
+    };
+    let testkit = TestKitBuilder::validator()
+        .with_private_api_auth(auth)
+        .create();
+
+    let api = testkit.api();
+    let _: bool = api.get_private(ApiKind::System, "v1/consensus_enabled");
+
+    let api = testkit.api().without_token();
+    assert_unauthorized(api.get_private_err(ApiKind::System, "v1/consensus_enabled"));
+    assert_unauthorized(api.post_private_err(ApiKind::System, "v1/shutdown", &()));
+    // Public API does not require credentials.
+    let _: String = api.get(ApiKind::System, "v1/user_agent");
+
+    let api = testkit.api().with_token("other token");
+    assert_unauthorized(api.get_private_err(ApiKind::System, "v1/peers"));
+}